@@ -1,19 +1,282 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
 use defmt_rtt as _;
 use embassy_stm32::{
     adc::{Adc, AnyAdcChannel, SampleTime},
+    pac::{self, adc::vals::Adcaldif},
     peripherals::{self, ADC1},
     Peri,
 };
-use embassy_time::{Duration, Ticker};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use embassy_time::{with_timeout, Duration, Ticker};
 use panic_probe as _;
+use uom::si::{
+    electric_current::ampere,
+    electric_potential::volt,
+    f64::{ElectricCurrent, ElectricPotential, ThermodynamicTemperature},
+    thermodynamic_temperature::degree_celsius,
+};
+
+use crate::{
+    fault::{self, Fault},
+    shared::{ISN_MUL, SAMPLING_SETTINGS_CHANNEL, VREF, VSN_MUL},
+};
+
+/// 默认采样间隔，EMA 默认系数按此间隔整定
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 运行时可设置的采样间隔范围 (ms)，下限留出单次转换约 15ms 的时间
+pub const SAMPLE_INTERVAL_RANGE_MS: (u32, u32) = (20, 60_000);
+
+/// 硬件过采样配置：OVSR = 7 (×256)，右移 4 位
+pub const OVERSAMPLING_RATIO: u8 = 0x07;
+pub const OVERSAMPLING_SHIFT: u8 = 4;
+
+/// 单次 ADC 序列读取超时（4 通道 × 256 倍过采样正常约 15ms）
+const READ_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// 连续读取失败达到该次数后置位 ADC 故障
+const FAULT_THRESHOLD: u32 = 3;
+
+// 采样暂停标志及恢复信号
+static SAMPLING_PAUSED: AtomicBool = AtomicBool::new(false);
+static SAMPLING_RESUME: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// 暂停 ADC 采样，暂停期间不发布新数据
+pub fn pause_sampling() {
+    defmt::info!("ADC sampling paused");
+    SAMPLING_PAUSED.store(true, Ordering::SeqCst);
+}
+
+/// 恢复 ADC 采样
+pub fn resume_sampling() {
+    defmt::info!("ADC sampling resumed");
+    SAMPLING_PAUSED.store(false, Ordering::SeqCst);
+    SAMPLING_RESUME.signal(());
+}
+
+/// ADC 采样是否处于暂停状态（此时测量值不是最新数据）
+pub fn is_sampling_paused() -> bool {
+    SAMPLING_PAUSED.load(Ordering::SeqCst)
+}
+
+// 采样间隔修改请求，由 poll 在两次采样之间应用
+static SAMPLE_INTERVAL_REQUEST: Signal<CriticalSectionRawMutex, Duration> = Signal::new();
+
+/// 请求修改采样间隔，从下一次采样起生效；超出 `SAMPLE_INTERVAL_RANGE_MS` 时返回 false
+pub fn request_sample_interval(interval: Duration) -> bool {
+    let (min, max) = SAMPLE_INTERVAL_RANGE_MS;
+    if !(min as u64..=max as u64).contains(&interval.as_millis()) {
+        return false;
+    }
+    defmt::info!("ADC sample interval requested: {}ms", interval.as_millis());
+    SAMPLE_INTERVAL_REQUEST.signal(interval);
+    true
+}
+
+// EMA 系数修改请求，由 poll 在两次采样之间应用
+static FILTER_ALPHAS_REQUEST: Signal<CriticalSectionRawMutex, EmaAlphas> = Signal::new();
+
+/// 请求修改 EMA 滤波系数，从下一次采样起生效，超出 (0, 1] 的值会被限制
+pub fn request_filter_alphas(alphas: EmaAlphas) {
+    defmt::info!("ADC EMA alphas change requested");
+    FILTER_ALPHAS_REQUEST.signal(alphas);
+}
+
+// 原始数据模式标志，置位时 poll 返回未经 EMA 滤波的读数
+static RAW_MODE: AtomicBool = AtomicBool::new(false);
+
+/// 采样结果的表示方式
+#[derive(Clone, Copy, Debug, PartialEq, Eq, defmt::Format)]
+pub enum FilterMode {
+    /// EMA 平滑后的读数
+    Smoothed,
+    /// 未经滤波的单次读数，用于捕获负载阶跃时的瞬态跌落
+    Raw,
+}
+
+/// 切换采样结果的表示方式，从下一次采样起生效
+///
+/// 原始模式下 EMA 仍持续更新，切回平滑模式时不会出现跳变。
+pub fn set_filter_mode(mode: FilterMode) {
+    defmt::info!("ADC filter mode: {}", mode);
+    RAW_MODE.store(mode == FilterMode::Raw, Ordering::SeqCst);
+}
+
+/// 当前采样结果的表示方式
+pub fn filter_mode() -> FilterMode {
+    if RAW_MODE.load(Ordering::SeqCst) {
+        FilterMode::Raw
+    } else {
+        FilterMode::Smoothed
+    }
+}
+
+// 重新校准请求标志，由 adc_task 在两次采样之间执行
+static CALIBRATION_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// 请求在下一次采样前重新执行 ADC1 自校准（预热后补偿温漂）
+pub fn request_calibration() {
+    defmt::info!("ADC self-calibration requested");
+    CALIBRATION_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// 执行 ADC 单端自校准，返回校准系数 CALFACT_S
+///
+/// 校准修正 ADC 自身的偏移，应在外部两点电压/电流校准之前完成。
+/// 校准要求 ADC 处于关闭状态，完成后重新使能，采样时间、过采样等配置保持不变。
+pub fn self_calibrate(regs: pac::adc::Adc) -> u8 {
+    // 停止进行中的转换（例如超时中断的 DMA 序列）
+    if regs.cr().read().adstart() {
+        regs.cr().modify(|w| w.set_adstp(true));
+        while regs.cr().read().adstart() {}
+    }
+    if regs.cr().read().aden() {
+        regs.cr().modify(|w| w.set_addis(true));
+        while regs.cr().read().aden() {}
+    }
 
-use crate::shared::{VREF, VSN_MUL};
+    regs.cr().modify(|w| {
+        w.set_adcaldif(Adcaldif::SINGLEENDED);
+        w.set_adcal(true);
+    });
+    while regs.cr().read().adcal() {}
 
-// ADC校准参数结构体
+    // 校准结束后需等待至少 4 个 ADC 时钟周期才能重新使能
+    cortex_m::asm::delay(200);
+    regs.isr().write(|w| w.set_adrdy(true));
+    regs.cr().modify(|w| w.set_aden(true));
+    while !regs.isr().read().adrdy() {}
+    regs.isr().write(|w| w.set_adrdy(true));
+
+    regs.calfact().read().calfact_s()
+}
+
+/// ADC 校准参数
+///
+/// 电压在分压换算后再做单板修正：`voltage = raw * gain + offset`，
+/// 默认 gain = 1.0、offset = 0.0（不修正）。两点校准步骤：
+/// 1. 将校准系数恢复为默认值并重启；
+/// 2. 在接近量程两端的电压 V1、V2 下，分别记录读数 M1、M2 和参考表读数 R1、R2；
+/// 3. gain = (R2 - R1) / (M2 - M1)，offset = R1 - gain * M1；
+/// 4. 通过 `ConfigManager::write_voltage_calibration` 保存，重启后生效。
+///
+/// VOUT 与 VIN 各自独立校准。
 pub struct AdcCalibration {
     pub ts_cal1: f64,
     pub ts_cal2: f64,
     pub vrefint_cal: f64,
+    pub vout_gain: f64,
+    pub vout_offset: f64,
+    pub vin_gain: f64,
+    pub vin_offset: f64,
+}
+
+impl AdcCalibration {
+    /// 分压换算后的 VOUT 电压（V）加上单板修正
+    fn vout(&self, vout_sn: f64) -> f64 {
+        vout_sn * VSN_MUL * self.vout_gain + self.vout_offset
+    }
+
+    /// 分压换算后的 VIN 电压（V）加上单板修正
+    fn vin(&self, vin_sn: f64) -> f64 {
+        vin_sn * VSN_MUL * self.vin_gain + self.vin_offset
+    }
+}
+
+/// 各通道 EMA 滤波系数 (0 < alpha <= 1，1 表示不滤波)
+///
+/// 时间常数 τ = -T / ln(1 - alpha)，T 为采样间隔。在默认 5s 采样间隔下：
+/// - vout / vin / current: alpha = 0.1176 → τ ≈ 40s
+/// - temperature: alpha = 0.05 → τ ≈ 97s（温度变化慢，重度平滑）
+///
+/// 同一组系数在更短的采样间隔下时间常数等比例缩短，修改间隔时
+/// 需用 [`EmaAlphas::rescaled`] 换算以保持原有的平滑程度。
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EmaAlphas {
+    pub vout: f64,
+    pub vin: f64,
+    pub current: f64,
+    pub temperature: f64,
+}
+
+impl Default for EmaAlphas {
+    fn default() -> Self {
+        Self {
+            vout: 0.1176,
+            vin: 0.1176,
+            current: 0.1176,
+            temperature: 0.05,
+        }
+    }
+}
+
+impl EmaAlphas {
+    /// 允许的最小系数，更小的值会使时间常数趋于无穷
+    pub const MIN: f64 = 0.001;
+
+    /// 将各系数限制在 (0, 1] 内，NaN 视为不滤波
+    pub fn clamped(self) -> Self {
+        let clamp = |alpha: f64| {
+            if alpha.is_nan() {
+                1.0
+            } else {
+                alpha.clamp(Self::MIN, 1.0)
+            }
+        };
+        let clamped = Self {
+            vout: clamp(self.vout),
+            vin: clamp(self.vin),
+            current: clamp(self.current),
+            temperature: clamp(self.temperature),
+        };
+        if clamped != self {
+            defmt::warn!("EMA alphas out of range (0, 1], clamped");
+        }
+        clamped
+    }
+
+    /// 将按 `from` 间隔整定的系数换算到 `to` 间隔，保持时间常数不变
+    ///
+    /// alpha' = 1 - (1 - alpha)^(to / from)
+    pub fn rescaled(self, from: Duration, to: Duration) -> Self {
+        let ratio = to.as_micros() as f64 / from.as_micros() as f64;
+        let rescale = |alpha: f64| 1.0 - libm::pow(1.0 - alpha, ratio);
+        Self {
+            vout: rescale(self.vout),
+            vin: rescale(self.vin),
+            current: rescale(self.current),
+            temperature: rescale(self.temperature),
+        }
+    }
+}
+
+/// 当前生效的采样与滤波设置，供上位机解释数据带宽和时间常数
+#[derive(Clone, Copy, Debug)]
+pub struct SamplingSettings {
+    pub interval: Duration,
+    /// OVSR 寄存器值，过采样倍数为 2^(ratio + 1)
+    pub oversampling_ratio: u8,
+    pub oversampling_shift: u8,
+    pub alphas: EmaAlphas,
+}
+
+/// 一次采样的换算结果，单位在 ADC 边界处确定
+#[derive(Clone, Copy, Debug)]
+pub struct AdcSample {
+    pub vout: ElectricPotential,
+    pub vin: ElectricPotential,
+    pub current: ElectricCurrent,
+    pub temperature: ThermodynamicTemperature,
+    /// 上述读数是平滑值还是原始值
+    #[allow(dead_code)]
+    pub mode: FilterMode,
+    /// 未经滤波的 VOUT 电压，与 `mode` 无关，用于跟踪放电等快速变化
+    pub vout_raw: ElectricPotential,
+    /// 未经滤波的 VIN 电压，与 `mode` 无关，用于记录瞬态极值
+    pub vin_raw: ElectricPotential,
+    /// 未经滤波的输出电流，与 `mode` 无关，用于过流保护
+    pub current_raw: ElectricCurrent,
 }
 
 // ADC状态结构体
@@ -22,40 +285,57 @@ pub struct AdcReader<'a, const AVG_SIZE: usize> {
     dma_ch: Peri<'a, peripherals::DMA1_CH1>,
     vout_sn_ch: AnyAdcChannel<ADC1>,
     vin_sn_ch: AnyAdcChannel<ADC1>,
+    isn_ch: AnyAdcChannel<ADC1>,
     v_temp_ch: AnyAdcChannel<ADC1>,
     v_ref_int_ch: AnyAdcChannel<ADC1>,
-    buffer: [u16; 4],
+    buffer: [u16; 5],
     cal: AdcCalibration,
     ticker: Ticker,
+    sample_interval: Duration,
+    alphas: EmaAlphas,
 
     vout_sn_prev: f64,
     vin_sn_prev: f64,
+    isn_prev: f64,
+    temperature_prev: Option<f64>,
+    consecutive_failures: u32,
 }
 
 impl<'a, const AVG_SIZE: usize> AdcReader<'a, AVG_SIZE> {
-    pub async fn poll(&mut self) -> Option<(f64, f64, f64)> {
+    pub async fn poll(&mut self) -> Option<AdcSample> {
         self.ticker.next().await;
 
-        // ADC读取
-        self.adc
-            .read(
-                self.dma_ch.reborrow(),
-                [
-                    (&mut self.v_ref_int_ch, SampleTime::CYCLES640_5),
-                    (&mut self.vout_sn_ch, SampleTime::CYCLES640_5),
-                    (&mut self.v_temp_ch, SampleTime::CYCLES640_5), // 增加温度采样时间
-                    (&mut self.vin_sn_ch, SampleTime::CYCLES640_5),
-                ]
-                .into_iter(),
-                &mut self.buffer,
-            )
-            .await;
+        // 暂停时等待恢复信号，恢复后重新对齐采样节拍
+        if is_sampling_paused() {
+            while is_sampling_paused() {
+                SAMPLING_RESUME.wait().await;
+            }
+            self.ticker.reset();
+        }
+
+        if CALIBRATION_REQUESTED.swap(false, Ordering::SeqCst) {
+            let calfact = self_calibrate(pac::ADC1);
+            defmt::info!("ADC1 recalibrated, calibration factor: {}", calfact);
+        }
+
+        if let Some(interval) = SAMPLE_INTERVAL_REQUEST.try_take() {
+            self.set_sample_interval(interval);
+        }
+        if let Some(alphas) = FILTER_ALPHAS_REQUEST.try_take() {
+            self.set_filter_alphas(alphas);
+        }
+
+        // ADC读取，超时或数据无效时不发布新数据，由下游的过期判断接管
+        if !self.convert().await {
+            return None;
+        }
 
         // 数据换算
         let adc_ref = self.buffer[0] as f64;
         let adc_vout_sn = self.buffer[1] as f64;
         let adc_temp = self.buffer[2] as f64;
         let adc_vin_sn = self.buffer[3] as f64;
+        let adc_isn = self.buffer[4] as f64;
 
         let v_ref = VREF * self.cal.vrefint_cal / adc_ref;
         let vout_sn = v_ref / 4095.0 * adc_vout_sn;
@@ -63,16 +343,128 @@ impl<'a, const AVG_SIZE: usize> AdcReader<'a, AVG_SIZE> {
             * ((adc_temp * (v_ref / VREF)) - self.cal.ts_cal1)
             + 30.0;
         let vin_sn = v_ref / 4095.0 * adc_vin_sn;
+        // INA186 REF 接地（单向检测），输出电压与电流成正比
+        let isn = v_ref / 4095.0 * adc_isn;
 
-        let vout_sn_avg = self.ema(self.vout_sn_prev, vout_sn, 0.1176);
-        let vin_sn_avg = self.ema(self.vin_sn_prev, vin_sn, 0.1176);
+        let vout_sn_avg = self.ema(self.vout_sn_prev, vout_sn, self.alphas.vout);
+        let vin_sn_avg = self.ema(self.vin_sn_prev, vin_sn, self.alphas.vin);
+        let isn_avg = self.ema(self.isn_prev, isn, self.alphas.current);
+        // 温度首个样本直接作为初值，避免从 0°C 缓慢爬升
+        let temperature_avg = match self.temperature_prev {
+            Some(prev) => self.ema(prev, temperature, self.alphas.temperature),
+            None => temperature,
+        };
 
         self.vout_sn_prev = vout_sn_avg;
         self.vin_sn_prev = vin_sn_avg;
+        self.isn_prev = isn_avg;
+        self.temperature_prev = Some(temperature_avg);
+
+        let vout_raw = ElectricPotential::new::<volt>(self.cal.vout(vout_sn));
+        let vin_raw = ElectricPotential::new::<volt>(self.cal.vin(vin_sn));
+        let current_raw = ElectricCurrent::new::<ampere>(isn * ISN_MUL);
+        let mode = filter_mode();
+        let (vout_sn, vin_sn, isn, temperature) = match mode {
+            FilterMode::Smoothed => (vout_sn_avg, vin_sn_avg, isn_avg, temperature_avg),
+            FilterMode::Raw => (vout_sn, vin_sn, isn, temperature),
+        };
+        Some(AdcSample {
+            vout: ElectricPotential::new::<volt>(self.cal.vout(vout_sn)),
+            vin: ElectricPotential::new::<volt>(self.cal.vin(vin_sn)),
+            current: ElectricCurrent::new::<ampere>(isn * ISN_MUL),
+            temperature: ThermodynamicTemperature::new::<degree_celsius>(temperature),
+            mode,
+            vout_raw,
+            vin_raw,
+            current_raw,
+        })
+    }
+
+    /// 执行一次 DMA 转换，成功时结果在 `buffer` 中
+    async fn convert(&mut self) -> bool {
+        let read = with_timeout(
+            READ_TIMEOUT,
+            self.adc.read(
+                self.dma_ch.reborrow(),
+                [
+                    (&mut self.v_ref_int_ch, SampleTime::CYCLES640_5),
+                    (&mut self.vout_sn_ch, SampleTime::CYCLES640_5),
+                    (&mut self.v_temp_ch, SampleTime::CYCLES640_5), // 增加温度采样时间
+                    (&mut self.vin_sn_ch, SampleTime::CYCLES640_5),
+                    (&mut self.isn_ch, SampleTime::CYCLES640_5),
+                ]
+                .into_iter(),
+                &mut self.buffer,
+            ),
+        )
+        .await;
+        if read.is_err() {
+            defmt::error!("ADC read timed out after {}ms", READ_TIMEOUT.as_millis());
+            self.record_failure();
+            return false;
+        }
+        // VREFINT 读数为 0 说明 DMA 未写入有效数据，且会导致除零
+        if self.buffer[0] == 0 {
+            defmt::error!("ADC read returned invalid data: {}", self.buffer);
+            self.record_failure();
+            return false;
+        }
+        if self.consecutive_failures > 0 {
+            defmt::info!(
+                "ADC recovered after {} failed reads",
+                self.consecutive_failures
+            );
+            self.consecutive_failures = 0;
+            fault::set_active(Fault::AdcFailure, false);
+        }
+        true
+    }
 
-        let vout_voltage = vout_sn_avg * VSN_MUL;
-        let vin_voltage = vin_sn_avg * VSN_MUL;
-        Some((vout_voltage, vin_voltage, temperature))
+    /// 单次读取未经滤波的 VBUS 和 VIN 电压，用于启动时的安全状态检查
+    pub async fn read_rails(&mut self) -> Option<(ElectricPotential, ElectricPotential)> {
+        if !self.convert().await {
+            return None;
+        }
+        let v_ref = VREF * self.cal.vrefint_cal / self.buffer[0] as f64;
+        let to_sn = |raw: u16| v_ref / 4095.0 * raw as f64;
+        Some((
+            ElectricPotential::new::<volt>(self.cal.vout(to_sn(self.buffer[1]))),
+            ElectricPotential::new::<volt>(self.cal.vin(to_sn(self.buffer[3]))),
+        ))
+    }
+
+    /// 记录一次读取失败，连续失败达到阈值时置位 ADC 故障
+    fn record_failure(&mut self) {
+        self.consecutive_failures += 1;
+        if self.consecutive_failures == FAULT_THRESHOLD {
+            defmt::error!("ADC failed {} consecutive reads", FAULT_THRESHOLD);
+            fault::set_active(Fault::AdcFailure, true);
+        }
+    }
+
+    /// 修改采样间隔，从下一次采样起生效
+    ///
+    /// EMA 系数不随之调整，需要保持时间常数时由调用方换算后重新设置。
+    fn set_sample_interval(&mut self, interval: Duration) {
+        self.sample_interval = interval;
+        self.ticker = Ticker::every(interval);
+        self.publish_settings();
+    }
+
+    /// 修改 EMA 滤波系数，超出 (0, 1] 的值会被限制
+    fn set_filter_alphas(&mut self, alphas: EmaAlphas) {
+        self.alphas = alphas.clamped();
+        self.publish_settings();
+    }
+
+    /// 发布当前生效的采样与滤波设置
+    fn publish_settings(&self) {
+        SAMPLING_SETTINGS_CHANNEL.sender().send(SamplingSettings {
+            interval: self.sample_interval,
+            oversampling_ratio: OVERSAMPLING_RATIO,
+            oversampling_shift: OVERSAMPLING_SHIFT,
+            alphas: self.alphas,
+        });
     }
 
     #[inline(always)]
@@ -85,23 +477,67 @@ impl<'a, const AVG_SIZE: usize> AdcReader<'a, AVG_SIZE> {
         dma_ch: Peri<'a, peripherals::DMA1_CH1>,
         vout_sn_ch: AnyAdcChannel<ADC1>,
         vin_sn_ch: AnyAdcChannel<ADC1>,
+        isn_ch: AnyAdcChannel<ADC1>,
         v_temp_ch: AnyAdcChannel<ADC1>,
         v_ref_int_ch: AnyAdcChannel<ADC1>,
         cal: AdcCalibration,
+        alphas: EmaAlphas,
+        sample_interval: Duration,
     ) -> AdcReader<'a, AVG_SIZE> {
-        Self {
+        let reader = Self {
             adc,
             dma_ch,
             vout_sn_ch,
             vin_sn_ch,
+            isn_ch,
             v_temp_ch,
             v_ref_int_ch,
-            buffer: [0; 4],
+            buffer: [0; 5],
             cal,
-            ticker: Ticker::every(Duration::from_secs(5)),
+            ticker: Ticker::every(sample_interval),
+            sample_interval,
+            alphas: alphas.clamped(),
 
             vout_sn_prev: 0.0,
             vin_sn_prev: 0.0,
+            isn_prev: 0.0,
+            temperature_prev: None,
+            consecutive_failures: 0,
+        };
+        reader.publish_settings();
+        reader
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rescaled_alphas_keep_time_constant() {
+        let alphas = EmaAlphas::default();
+        let same = alphas.rescaled(SAMPLE_INTERVAL, SAMPLE_INTERVAL);
+        assert!((same.vout - alphas.vout).abs() < 1e-12);
+
+        // 采样间隔缩短 50 倍，50 次小步衰减应与一次原始衰减相同
+        let fast = alphas.rescaled(SAMPLE_INTERVAL, Duration::from_millis(100));
+        let decay = libm::pow(1.0 - fast.vout, 50.0);
+        assert!((decay - (1.0 - alphas.vout)).abs() < 1e-9);
+        assert!(fast.temperature < alphas.temperature);
+    }
+
+    #[test]
+    fn test_alphas_clamped_to_valid_range() {
+        let alphas = EmaAlphas {
+            vout: 0.0,
+            vin: 1.5,
+            current: f64::NAN,
+            temperature: 0.05,
         }
+        .clamped();
+        assert_eq!(alphas.vout, EmaAlphas::MIN);
+        assert_eq!(alphas.vin, 1.0);
+        assert_eq!(alphas.current, 1.0);
+        assert_eq!(alphas.temperature, 0.05);
     }
 }
@@ -1,12 +1,38 @@
 use alloc::sync::Arc;
-use embassy_stm32::{
-    gpio::Output, peripherals::TIM1, timer::simple_pwm::SimplePwm, timer::Channel,
-};
+use embassy_stm32::{gpio::Output, peripherals::TIM1, timer::simple_pwm::SimplePwm};
 use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex};
-use embassy_time::Timer;
-use embedded_hal_02::Pwm;
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::{
+    button::{ButtonId, InputEvent},
+    hal::{LedPwm, SwitchPin},
+    power_rail::{OutputTable, OutputTableError, PowerRail},
+    shared::{ticks_for_ms, MANAGER_TICK_MS},
+    vbus_manager::VbusState,
+    InputSubscriber,
+};
+
+/// 启动闪烁半周期 (150ms)
+const BOOTING_BLINK_HALF_TICKS: u32 = ticks_for_ms(150);
 
-use crate::{button::InputEvent, InputSubscriber};
+/// 故障闪码：每次闪烁亮/灭时长 (200ms)，每组之后的间隔 (1秒)
+const BLINK_CODE_ON: Duration = Duration::from_millis(200);
+const BLINK_CODE_OFF: Duration = Duration::from_millis(200);
+const BLINK_CODE_PAUSE: Duration = Duration::from_millis(1000);
+
+/// 关断时序：VBUS 关闭并泄放后，延时 60ms 再关断 VIN
+const VIN_OFF_DELAY: Duration = Duration::from_millis(60);
+/// 等待 VBUS 关闭的上限 (1秒)，超时仍关断 VIN，避免停留在 VIN 开启的待机状态
+const VBUS_OFF_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// VIN 关断时序状态
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+enum ShutdownStep {
+    Idle,                 // VIN 保持开启
+    WaitVbusOff(Instant), // 已请求关闭 VBUS，等待其关闭（开始等待的时刻）
+    Settle(Instant),      // VBUS 已关闭，延时后关断 VIN（VBUS 关闭的时刻）
+    Done,                 // VIN 已关断
+}
 
 /// 全局系统状态
 #[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
@@ -15,12 +41,33 @@ pub enum SystemState {
     Working, // 工作状态：VIN_EN=HIGH, VBUS_EN可切换, 电源LED根据VBUS状态
 }
 
+/// 进入待机状态的原因
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+#[repr(u8)]
+pub enum StandbyReason {
+    PowerOn = 0,          // 正常上电启动
+    UserRequest = 1,      // 用户长按按键
+    Reboot = 2,           // 受控重启（含重启后启动）
+    WatchdogRecovery = 3, // 看门狗复位后启动
+    Brownout = 4,         // VIN 掉电超过宽限期
+    IdleTimeout = 5,      // 闲置超时自动待机
+}
+
+impl StandbyReason {
+    /// 是否由故障引起（LED 使用不同的提示）
+    pub fn is_fault(self) -> bool {
+        matches!(self, Self::WatchdogRecovery | Self::Brownout)
+    }
+}
+
 /// 电源LED状态
 #[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
 pub enum PowerLedState {
-    Off,       // LED 熄灭
-    Breathing, // LED 呼吸效果（VIN 关闭时）
-    SolidOn,   // LED 常亮（VIN + VBUS 都开启时）
+    Off,            // LED 熄灭
+    Breathing,      // LED 呼吸效果（VIN 关闭时）
+    FaultBreathing, // LED 快速呼吸（故障引起的待机）
+    SolidOn,        // LED 常亮（VIN + VBUS 都开启时）
+    Booting,        // LED 快闪（启动稳定期）
 }
 
 impl Default for SystemState {
@@ -35,50 +82,253 @@ impl Default for PowerLedState {
     }
 }
 
+/// 呼吸灯波形
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum BreathingPattern {
+    Triangle,  // 线性渐亮渐暗
+    Sine,      // 正弦渐变，亮暗两端过渡更柔和
+    SlowPulse, // 周期开头短暂亮起，其余时间熄灭
+    FastBlink, // 亮灭各半的方波
+}
+
+impl BreathingPattern {
+    /// 周期内相位 `phase`（0.0..1.0）处的亮度 (%)
+    pub fn brightness(self, phase: f32) -> u8 {
+        let level = match self {
+            Self::Triangle => 1.0 - libm::fabsf(2.0 * phase - 1.0),
+            Self::Sine => 0.5 - 0.5 * libm::cosf(2.0 * core::f32::consts::PI * phase),
+            Self::SlowPulse if phase < SLOW_PULSE_ON_FRACTION => {
+                libm::sinf(core::f32::consts::PI * phase / SLOW_PULSE_ON_FRACTION)
+            }
+            Self::SlowPulse => 0.0,
+            Self::FastBlink if phase < 0.5 => 1.0,
+            Self::FastBlink => 0.0,
+        };
+        libm::roundf(level.clamp(0.0, 1.0) * 100.0) as u8
+    }
+}
+
+impl TryFrom<u8> for BreathingPattern {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Triangle),
+            1 => Ok(Self::Sine),
+            2 => Ok(Self::SlowPulse),
+            3 => Ok(Self::FastBlink),
+            _ => Err(()),
+        }
+    }
+}
+
+/// SlowPulse 在一个周期中亮起的比例
+const SLOW_PULSE_ON_FRACTION: f32 = 0.2;
+
+/// 故障闪码显示进度
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct BlinkCode {
+    count: u8,           // 每组闪烁次数
+    started_at: Instant, // 开始显示的时刻
+    duration: Duration,  // 显示总时长，至少一组完整闪码
+}
+
+impl BlinkCode {
+    fn new(count: u8, duration: Duration, now: Instant) -> Self {
+        let count = count.max(1);
+        Self {
+            count,
+            started_at: now,
+            duration: duration.max(Self::cycle(count)),
+        }
+    }
+
+    /// 一组闪码（N 次闪烁加间隔）的时长
+    fn cycle(count: u8) -> Duration {
+        (BLINK_CODE_ON + BLINK_CODE_OFF) * count as u32 + BLINK_CODE_PAUSE
+    }
+
+    /// `now` 时是否点亮
+    fn is_on(&self, now: Instant) -> bool {
+        let t = (now - self.started_at).as_millis() % Self::cycle(self.count).as_millis();
+        let blink = (BLINK_CODE_ON + BLINK_CODE_OFF).as_millis();
+        t < self.count as u64 * blink && t % blink < BLINK_CODE_ON.as_millis()
+    }
+
+    fn is_finished(&self, now: Instant) -> bool {
+        now - self.started_at >= self.duration
+    }
+}
+
+/// 呼吸灯效果：波形及周期
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Breathing {
+    pub pattern: BreathingPattern,
+    pub period: Duration,
+}
+
+/// 运行时可设置的呼吸周期范围 (ms)
+pub const BREATHING_PERIOD_RANGE_MS: (u32, u32) = (100, 10_000);
+
+/// 呼吸效果的应用场景
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum BreathingTarget {
+    Standby,
+    Fault,
+}
+
+impl Breathing {
+    pub const fn new(pattern: BreathingPattern, period: Duration) -> Self {
+        Self { pattern, period }
+    }
+
+    /// 周期对应的 tick 数，至少 2 个 tick
+    fn period_ticks(&self) -> u32 {
+        ((self.period.as_millis() / MANAGER_TICK_MS) as u32).max(2)
+    }
+}
+
+/// 运行模式
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Default, defmt::Format)]
+pub enum OperatingMode {
+    /// 交互模式：长按切换待机/工作，短按切换 VBUS
+    #[default]
+    Interactive,
+    /// 常开模式：启动后自动进入工作状态并开启 VBUS，仍受 PD 协商和各项保护约束
+    AlwaysOn(AlwaysOnButton),
+}
+
+impl OperatingMode {
+    pub fn is_always_on(self) -> bool {
+        matches!(self, Self::AlwaysOn(_))
+    }
+}
+
+/// 常开模式下按键的作用
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum AlwaysOnButton {
+    Ignore,     // 忽略所有按键
+    ToggleVbus, // 短按仍可切换 VBUS，长按忽略
+}
+
+/// 电源管理器配置
+#[derive(Debug, Clone, Copy)]
+pub struct PowerManagerConfig {
+    pub mode: OperatingMode,           // 运行模式
+    pub restore_working: bool,         // 启动稳定期后恢复到工作状态（上电恢复上次状态）
+    pub brownout_threshold: f64,       // VIN 掉电判定阈值 (V)
+    pub brownout_grace: Duration,      // VIN 低于阈值的容忍时间，超过后进入待机
+    pub brownout_hysteresis: f64,      // VIN 需高于阈值该值 (V) 才视为恢复
+    pub brownout_min_dwell: Duration,  // 掉电待机后至少保持该时间才自动恢复工作
+    pub dim_after: Option<Duration>,   // 无按键操作超过该时间后调暗电源 LED（None 表示不调暗）
+    pub dim_level_percent: u8,         // 调暗后的亮度比例 (%)
+    pub idle_standby: Duration,        // VBUS 关闭且无按键操作超过该时间后自动待机（0 表示不启用）
+    pub standby_breathing: Breathing,  // 待机时的呼吸效果
+    pub fault_breathing: Breathing,    // 故障引起的待机时的呼吸效果
+    pub blink_code_duration: Duration, // PD 错误闪码的显示时长
+}
+
+impl Default for PowerManagerConfig {
+    fn default() -> Self {
+        Self {
+            mode: OperatingMode::Interactive,
+            restore_working: false,
+            brownout_threshold: 4.0,
+            // 按 100ms 的未滤波 VIN 采样整定：需连续数个低读数，单次低读数不会触发
+            brownout_grace: Duration::from_millis(500),
+            brownout_hysteresis: 0.5,
+            brownout_min_dwell: Duration::from_secs(3),
+            dim_after: Some(Duration::from_secs(120)),
+            dim_level_percent: 20,
+            idle_standby: crate::config_manager::DEFAULT_IDLE_STANDBY,
+            standby_breathing: Breathing::new(BreathingPattern::Triangle, Duration::from_secs(3)),
+            fault_breathing: Breathing::new(BreathingPattern::Triangle, Duration::from_secs(1)),
+            blink_code_duration: Duration::from_secs(6),
+        }
+    }
+}
+
 /// 电源管理器上下文
-pub struct PowerManagerContext<'d> {
+///
+/// 硬件通过 `SwitchPin`/`LedPwm` 抽象，默认类型为实际外设，测试中可替换为模拟实现
+pub struct PowerManagerContext<'d, S = Output<'d>, L = SimplePwm<'d, TIM1>> {
     pub input_rx: Arc<Mutex<CriticalSectionRawMutex, InputSubscriber<'d>>>,
-    pub power_switch: Arc<Mutex<CriticalSectionRawMutex, Output<'d>>>, // PA15 控制电源开关
-    pub led_pwm: Arc<Mutex<CriticalSectionRawMutex, SimplePwm<'d, TIM1>>>, // PA8 PWM 控制LED
+    pub vin_rail: PowerRail<Mutex<CriticalSectionRawMutex, S>>, // PA15 控制电源开关
+    pub led_pwm: Arc<Mutex<CriticalSectionRawMutex, L>>,        // PA8 PWM 控制LED
+    pub output_table: OutputTable,                              // 各状态下的电源通路状态
+    pub config: PowerManagerConfig,
 }
 
 /// 全局系统管理器
-pub struct PowerManager<'d> {
-    context: PowerManagerContext<'d>,
+pub struct PowerManager<'d, S = Output<'d>, L = SimplePwm<'d, TIM1>> {
+    context: PowerManagerContext<'d, S, L>,
+    now: Instant, // 当前 tick 的时间，由 step 传入
     pub system_state: SystemState,
+    standby_reason: Option<StandbyReason>, // 最近一次进入待机的原因
     led_state: PowerLedState,
     current_vin_voltage: f64,
-    current_vbus_voltage: f64,
     current_vbus_enabled: bool,
+    vbus_output_on: bool,   // 实际输出状态（不被本地清除），供关断时序使用
     breathing_counter: u32, // 呼吸效果计数器
-    tick_counter: u32,      // 用于定期状态报告
+    vin_low_since: Option<Instant>, // VIN 开始低于掉电阈值的时刻
+    state_since: Instant,   // 进入当前系统状态的时刻
+    last_input: Instant,    // 最近一次按键操作的时刻
+    last_activity: Instant, // 工作状态下最近一次按键、状态切换或 VBUS 开启的时刻
+    dimmed: bool,           // 电源 LED 是否处于闲置调暗状态
+    auto_start_pending: bool, // 常开模式下等待首个 tick 进入工作状态
+    blink_code: Option<BlinkCode>, // 正在显示的 PD 错误闪码，优先于其他灯效
+    shutdown_step: ShutdownStep, // VIN 关断时序
 }
 
-impl<'d> PowerManager<'d> {
-    pub fn new(context: PowerManagerContext<'d>) -> Self {
+impl<'d, S: SwitchPin, L: LedPwm> PowerManager<'d, S, L> {
+    pub fn new(context: PowerManagerContext<'d, S, L>) -> Self {
         Self {
             context,
+            now: Instant::from_ticks(0),
             system_state: SystemState::default(),
+            standby_reason: None,
             led_state: PowerLedState::default(),
             current_vin_voltage: 0.0,
-            current_vbus_voltage: 0.0,
             current_vbus_enabled: false,
+            vbus_output_on: false,
             breathing_counter: 0,
-            tick_counter: 0,
+            vin_low_since: None,
+            state_since: Instant::from_ticks(0),
+            last_input: Instant::from_ticks(0),
+            last_activity: Instant::from_ticks(0),
+            dimmed: false,
+            auto_start_pending: false,
+            blink_code: None,
+            shutdown_step: ShutdownStep::Idle,
         }
     }
 
-    pub async fn init(&mut self) {
+    /// 校验输出表并进入待机状态；输出表违反上电时序时不操作任何开关
+    pub async fn init(&mut self, reason: StandbyReason) -> Result<(), OutputTableError> {
+        self.context.output_table.validate()?;
+
         // 初始化为待机状态
-        self.set_system_state(SystemState::Standby).await;
-        defmt::info!("PowerManager initialized in Standby state");
+        self.set_system_state(SystemState::Standby, reason).await;
+        defmt::info!("PowerManager initialized in Standby state ({:?})", reason);
+
+        // 常开模式在启动稳定期结束后的首个 tick 进入工作状态，稳定期内输出保持关闭
+        if self.context.config.mode.is_always_on() {
+            defmt::info!("Always-on mode: entering Working after startup settle");
+            self.auto_start_pending = true;
+        } else if self.context.config.restore_working {
+            defmt::info!("Restoring Working state after startup settle");
+            self.auto_start_pending = true;
+        }
+        Ok(())
     }
 
     /// 更新电压信息（仅用于监控和LED显示）
-    pub fn update_voltages(&mut self, vin_voltage: f64, vbus_voltage: f64, vbus_enabled: bool) {
+    pub fn update_voltages(&mut self, vin_voltage: f64, vbus_enabled: bool) {
         self.current_vin_voltage = vin_voltage;
-        self.current_vbus_voltage = vbus_voltage;
         self.current_vbus_enabled = vbus_enabled;
+        self.vbus_output_on = vbus_enabled;
     }
 
     /// 切换系统状态（由按键触发）
@@ -103,11 +353,151 @@ impl<'d> PowerManager<'d> {
             crate::shared::VBUS_RESET_CHANNEL.sender().send(true);
         }
 
-        self.set_system_state(new_state).await;
+        self.set_system_state(new_state, StandbyReason::UserRequest)
+            .await;
+    }
+
+    /// 强制进入待机状态（关闭VIN，用于重启等受控关机路径）
+    pub async fn enter_standby(&mut self, reason: StandbyReason) {
+        self.set_system_state(SystemState::Standby, reason).await;
+    }
+
+    /// VIN 掉电检测
+    ///
+    /// 工作状态下 VIN 低于阈值时开始计时，VIN 高于阈值加回差才清零；
+    /// 持续超过宽限期才判定为输入丢失，关闭 VBUS 并进入待机，避免线缆抖动引起的误关机。
+    async fn check_brownout(&mut self) {
+        let config = self.context.config;
+        if self.system_state != SystemState::Working {
+            self.vin_low_since = None;
+            return;
+        }
+        // 计时开始后需越过回差才算恢复，阈值附近的波动不会反复清零
+        let recovery_threshold = if self.vin_low_since.is_some() {
+            config.brownout_threshold + config.brownout_hysteresis
+        } else {
+            config.brownout_threshold
+        };
+        if self.current_vin_voltage >= recovery_threshold {
+            if let Some(since) = self.vin_low_since.take() {
+                defmt::info!(
+                    "VIN recovered after {}ms dropout",
+                    (self.now - since).as_millis()
+                );
+            }
+            return;
+        }
+
+        let since = *self.vin_low_since.get_or_insert_with(|| {
+            defmt::warn!(
+                "VIN {}V below {}V, tolerating for {}ms",
+                self.current_vin_voltage,
+                config.brownout_threshold,
+                config.brownout_grace.as_millis()
+            );
+            self.now
+        });
+
+        if self.now - since >= config.brownout_grace {
+            defmt::warn!("VIN lost beyond grace period - disabling VBUS and entering Standby");
+            self.current_vbus_enabled = false;
+            crate::shared::VBUS_RESET_CHANNEL.sender().send(true);
+            self.enter_standby(StandbyReason::Brownout).await;
+        }
+    }
+
+    /// 常开模式：启动后以及 VIN 掉电恢复后自动进入工作状态；上电恢复只在启动后进入一次
+    ///
+    /// 掉电保护仍然生效，只是恢复不需要按键。掉电后需 VIN 越过回差
+    /// 且待机满 `brownout_min_dwell` 才恢复，避免在阈值附近反复开关。
+    async fn check_always_on(&mut self) {
+        let config = self.context.config;
+        if self.system_state != SystemState::Standby {
+            return;
+        }
+        let dwell_elapsed = self.now - self.state_since >= config.brownout_min_dwell;
+        let brownout_recovered = config.mode.is_always_on()
+            && self.standby_reason == Some(StandbyReason::Brownout)
+            && self.current_vin_voltage >= config.brownout_threshold + config.brownout_hysteresis
+            && dwell_elapsed;
+        if self.auto_start_pending || brownout_recovered {
+            defmt::info!(
+                "Entering Working automatically (VIN={}V)",
+                self.current_vin_voltage
+            );
+            self.auto_start_pending = false;
+            self.set_system_state(SystemState::Working, StandbyReason::UserRequest)
+                .await;
+        }
+    }
+
+    /// 设置闲置自动待机超时（0 表示不启用），重新开始计时
+    pub fn set_idle_standby(&mut self, timeout: Duration) {
+        if self.context.config.idle_standby != timeout {
+            defmt::info!("Idle standby timeout set to {}s", timeout.as_secs());
+            self.context.config.idle_standby = timeout;
+            self.last_activity = self.now;
+        }
+    }
+
+    /// 闲置自动待机
+    ///
+    /// 工作状态下 VBUS 关闭且无按键操作超过 `idle_standby` 后进入待机；
+    /// 按键、状态切换或 VBUS 开启都会重新计时。常开模式不自动待机。
+    async fn check_idle_standby(&mut self) {
+        let config = self.context.config;
+        if self.system_state != SystemState::Working
+            || self.current_vbus_enabled
+            || config.mode.is_always_on()
+            || config.idle_standby.as_millis() == 0
+        {
+            self.last_activity = self.now;
+            return;
+        }
+
+        if self.now - self.last_activity >= config.idle_standby {
+            defmt::info!(
+                "No activity for {}s with VBUS off - entering Standby",
+                config.idle_standby.as_secs()
+            );
+            self.enter_standby(StandbyReason::IdleTimeout).await;
+        }
+    }
+
+    /// 更新闲置调暗状态
+    ///
+    /// 无按键操作超过 `dim_after` 后调暗电源 LED，下一次按键恢复全亮；
+    /// 故障提示（故障待机或存在活动故障）时不调暗。
+    fn update_dimming(&mut self) {
+        let config = self.context.config;
+        let idle = config
+            .dim_after
+            .is_some_and(|dim_after| self.now - self.last_input >= dim_after);
+        let fault_shown =
+            self.led_state == PowerLedState::FaultBreathing || crate::fault::faults().active.0 != 0;
+        let dimmed = idle && !fault_shown;
+
+        if dimmed != self.dimmed {
+            defmt::info!(
+                "Power LED {}",
+                if dimmed {
+                    "dimmed after inactivity"
+                } else {
+                    "back to full brightness"
+                }
+            );
+            self.dimmed = dimmed;
+        }
     }
 
     /// 设置系统状态
-    async fn set_system_state(&mut self, new_state: SystemState) {
+    ///
+    /// `reason` 仅在进入待机时记录，切换到工作状态时忽略
+    async fn set_system_state(&mut self, new_state: SystemState, reason: StandbyReason) {
+        if new_state == SystemState::Standby {
+            self.record_standby_reason(reason);
+        }
+
         if self.system_state != new_state {
             defmt::info!(
                 "System state changing from {:?} to {:?}",
@@ -115,46 +505,128 @@ impl<'d> PowerManager<'d> {
                 new_state
             );
             self.system_state = new_state;
+            self.state_since = self.now;
+            self.last_activity = self.now;
+            crate::shared::SYSTEM_STATE_CHANNEL.sender().send(new_state);
 
             // 同步更新硬件状态
             self.update_hardware_state().await;
         }
     }
 
+    /// 记录并发布待机原因
+    fn record_standby_reason(&mut self, reason: StandbyReason) {
+        if self.standby_reason != Some(reason) {
+            defmt::info!("Standby reason: {:?}", reason);
+        }
+        self.standby_reason = Some(reason);
+        crate::shared::STANDBY_REASON_CHANNEL.sender().send(reason);
+    }
+
     /// 更新硬件状态（LED和电源开关）
     async fn update_hardware_state(&mut self) {
+        let vbus_state = if self.current_vbus_enabled {
+            VbusState::Enabled
+        } else {
+            VbusState::Disabled
+        };
+        let rails = self
+            .context
+            .output_table
+            .lookup(self.system_state, vbus_state);
+
+        // 关断时序以实际输出状态为准：掉电等路径会提前清除 current_vbus_enabled，
+        // 此时 VBUS 仍然开启，必须先等待其关闭
+        let vbus_enabled = self.vbus_output_on;
+
+        // 表中要求关闭但 VBUS 仍开启时，通知 VbusManager 关闭输出
+        if self.current_vbus_enabled && !rails.vbus {
+            defmt::info!("Output table forces VBUS off in {:?}", self.system_state);
+            self.current_vbus_enabled = false;
+            crate::shared::VBUS_RESET_CHANNEL.sender().send(true);
+        }
+
         // 更新VIN开关状态 (PA15 - VIN_EN)
         // 根据硬件指南：高电平导通，低电平关断
-        match self.system_state {
-            SystemState::Standby => {
-                // 待机状态：VIN关闭，PA15输出低电平（关断）
-                {
-                    let mut power_switch = self.context.power_switch.lock().await;
-                    power_switch.set_low();
-                }
-                defmt::info!("VIN_EN (PA15) = LOW - Standby mode, VIN disabled");
+        let vin = self.sequence_vin(rails.vin, vbus_enabled);
+        self.context.vin_rail.set_enabled(vin).await;
+
+        // 更新LED状态
+        self.update_led_state().await;
+    }
+
+    /// VIN 关断时序，返回本 tick VIN 开关应处的状态
+    ///
+    /// VBUS 仍开启时先等待其关闭（VbusManager 关闭时同步泄放输出电容），再延时
+    /// 关断 VIN，避免下游设备在切换过程中经 VBUS 反向供电。
+    fn sequence_vin(&mut self, vin: bool, vbus_enabled: bool) -> bool {
+        if vin {
+            if !matches!(self.shutdown_step, ShutdownStep::Idle | ShutdownStep::Done) {
+                defmt::info!("Shutdown: cancelled, VIN stays on");
             }
-            SystemState::Working => {
-                // 工作状态：VIN开启，PA15输出高电平（导通）
-                {
-                    let mut power_switch = self.context.power_switch.lock().await;
-                    power_switch.set_high();
+            self.shutdown_step = ShutdownStep::Idle;
+            return true;
+        }
+
+        self.shutdown_step = match self.shutdown_step {
+            ShutdownStep::Idle if vbus_enabled => {
+                defmt::info!("Shutdown step 1: disabling VBUS, waiting for discharge");
+                ShutdownStep::WaitVbusOff(self.now)
+            }
+            ShutdownStep::WaitVbusOff(since) if vbus_enabled => {
+                if self.now - since < VBUS_OFF_TIMEOUT {
+                    ShutdownStep::WaitVbusOff(since)
+                } else {
+                    defmt::warn!(
+                        "Shutdown: VBUS still on after {}ms - dropping VIN_EN anyway",
+                        VBUS_OFF_TIMEOUT.as_millis()
+                    );
+                    ShutdownStep::Done
                 }
-                defmt::info!("VIN_EN (PA15) = HIGH - Working mode, VIN enabled");
             }
-        }
+            ShutdownStep::WaitVbusOff(_) => {
+                defmt::info!(
+                    "Shutdown step 2: VBUS off, waiting {}ms before dropping VIN_EN",
+                    VIN_OFF_DELAY.as_millis()
+                );
+                ShutdownStep::Settle(self.now)
+            }
+            ShutdownStep::Settle(since) if self.now - since < VIN_OFF_DELAY => {
+                ShutdownStep::Settle(since)
+            }
+            ShutdownStep::Settle(_) => {
+                defmt::info!("Shutdown step 3: dropping VIN_EN");
+                ShutdownStep::Done
+            }
+            ShutdownStep::Idle | ShutdownStep::Done => ShutdownStep::Done,
+        };
+        self.shutdown_step != ShutdownStep::Done
+    }
 
-        // 更新LED状态
-        self.update_led_state().await;
+    /// 推进进行中的 VIN 关断时序（等待 VBUS 关闭或延时阶段）
+    async fn advance_shutdown(&mut self) {
+        if matches!(
+            self.shutdown_step,
+            ShutdownStep::WaitVbusOff(_) | ShutdownStep::Settle(_)
+        ) {
+            let vin = self.sequence_vin(false, self.vbus_output_on);
+            self.context.vin_rail.set_enabled(vin).await;
+        }
     }
 
     /// 设置LED的PWM占空比
     async fn set_led_duty(&mut self, duty_percent: u8) {
+        // 闲置调暗：按比例缩放亮度
+        let duty_percent = if self.dimmed {
+            (duty_percent as u32 * self.context.config.dim_level_percent as u32 / 100) as u8
+        } else {
+            duty_percent
+        };
         let mut pwm = self.context.led_pwm.lock().await;
-        let max_duty = pwm.get_max_duty();
+        let max_duty = pwm.max_duty();
         // 计算实际占空比值，注意开漏输出是反向的（100% - duty_percent）
         let actual_duty = max_duty * (100 - duty_percent as u32) / 100;
-        pwm.set_duty(Channel::Ch1, actual_duty);
+        pwm.set_duty(actual_duty);
         // LED占空比已设置，不再打印日志以减少输出
     }
 
@@ -162,7 +634,10 @@ impl<'d> PowerManager<'d> {
     async fn update_led_state(&mut self) {
         // 根据系统状态和VBUS状态确定LED状态
         let new_led_state = match self.system_state {
-            SystemState::Standby => PowerLedState::Breathing,
+            SystemState::Standby => match self.standby_reason {
+                Some(reason) if reason.is_fault() => PowerLedState::FaultBreathing,
+                _ => PowerLedState::Breathing,
+            },
             SystemState::Working => {
                 if self.current_vbus_enabled {
                     PowerLedState::SolidOn
@@ -184,8 +659,32 @@ impl<'d> PowerManager<'d> {
         }
     }
 
+    /// 取出 PD 错误并开始显示对应的闪码，新错误覆盖正在显示的闪码
+    fn check_pd_errors(&mut self) {
+        while let Ok(error) = crate::shared::PD_ERROR_CHANNEL.try_receive() {
+            let count = error.blink_count();
+            defmt::warn!("PD error {}: showing {}-blink code", error.as_ref(), count);
+            self.blink_code = Some(BlinkCode::new(
+                count,
+                self.context.config.blink_code_duration,
+                self.now,
+            ));
+        }
+    }
+
     /// 更新LED显示
     async fn update_led_display(&mut self) {
+        if let Some(code) = self.blink_code {
+            if !code.is_finished(self.now) {
+                let duty = if code.is_on(self.now) { 100 } else { 0 };
+                self.set_led_duty(duty).await;
+                return;
+            }
+            // 闪码结束，呼吸效果从周期起点重新开始
+            self.blink_code = None;
+            self.breathing_counter = 0;
+        }
+
         match self.led_state {
             PowerLedState::Off => {
                 // LED熄灭
@@ -195,27 +694,78 @@ impl<'d> PowerManager<'d> {
                 // LED常亮
                 self.set_led_duty(100).await;
             }
-            PowerLedState::Breathing => {
-                // 呼吸效果：3秒周期 (150 * 20ms = 3000ms)
+            PowerLedState::Booting => {
+                // 启动快闪：复用呼吸计数器
                 self.breathing_counter += 1;
-                if self.breathing_counter >= 150 {
+                if self.breathing_counter >= BOOTING_BLINK_HALF_TICKS * 2 {
                     self.breathing_counter = 0;
                 }
-
-                // 简化的呼吸效果：三角波
-                let brightness = if self.breathing_counter < 75 {
-                    // 上升阶段：0% -> 100%
-                    (self.breathing_counter as f32 / 75.0) * 100.0
+                let duty = if self.breathing_counter < BOOTING_BLINK_HALF_TICKS {
+                    50
                 } else {
-                    // 下降阶段：100% -> 0%
-                    ((150 - self.breathing_counter) as f32 / 75.0) * 100.0
+                    0
                 };
-                self.set_led_duty(brightness as u8).await;
+                self.set_led_duty(duty).await;
             }
+            PowerLedState::Breathing => {
+                self.update_breathing(self.context.config.standby_breathing)
+                    .await;
+            }
+            PowerLedState::FaultBreathing => {
+                self.update_breathing(self.context.config.fault_breathing)
+                    .await;
+            }
+        }
+    }
+
+    /// 呼吸效果：按 tick 计数在周期内取相位，开漏反相由 `set_led_duty` 处理
+    async fn update_breathing(&mut self, breathing: Breathing) {
+        let period_ticks = breathing.period_ticks();
+        self.breathing_counter += 1;
+        if self.breathing_counter >= period_ticks {
+            self.breathing_counter = 0;
         }
+
+        let phase = self.breathing_counter as f32 / period_ticks as f32;
+        self.set_led_duty(breathing.pattern.brightness(phase)).await;
+    }
+
+    /// 设置待机时的呼吸效果
+    pub fn set_standby_breathing(&mut self, breathing: Breathing) {
+        self.context.config.standby_breathing = breathing;
+        self.breathing_counter = 0;
+    }
+
+    /// 设置故障待机时的呼吸效果
+    pub fn set_fault_breathing(&mut self, breathing: Breathing) {
+        self.context.config.fault_breathing = breathing;
+        self.breathing_counter = 0;
+    }
+
+    /// 启动稳定期的 tick：只显示启动灯效，不处理输入、不切换电源
+    pub async fn tick_booting(&mut self) {
+        self.led_state = PowerLedState::Booting;
+        self.update_led_display().await;
+        Timer::after_millis(MANAGER_TICK_MS).await;
     }
 
     pub async fn tick(&mut self) {
+        self.step(Instant::now()).await;
+
+        // 添加小延迟
+        Timer::after_millis(MANAGER_TICK_MS).await; // 50Hz更新频率，确保呼吸灯平滑
+    }
+
+    /// 执行一次 tick 的逻辑（不含延迟），时间由调用方传入以便测试中精确控制
+    ///
+    /// 超时均按传入的时间计算：主循环一次迭代包含两个管理器的 tick，
+    /// 实际周期长于 `MANAGER_TICK_MS`，按 tick 计数会使超时成倍延长。
+    pub async fn step(&mut self, now: Instant) {
+        self.now = now;
+
+        // 推进 VIN 关断时序：需在状态切换前执行，以使用本 tick 传入的 VBUS 状态
+        self.advance_shutdown().await;
+
         // 处理按键输入
         let event = {
             let mut input_rx = self.context.input_rx.lock().await;
@@ -223,9 +773,16 @@ impl<'d> PowerManager<'d> {
         };
 
         if let Some(event) = event {
+            self.last_input = self.now;
+            self.last_activity = self.now;
             defmt::info!("Button event received: {:?}", event);
             match event {
-                InputEvent::LongReleased => {
+                InputEvent::LongReleased(ButtonId::PRIMARY)
+                    if self.context.config.mode.is_always_on() =>
+                {
+                    defmt::info!("Always-on mode: ignoring long press");
+                }
+                InputEvent::LongReleased(ButtonId::PRIMARY) => {
                     defmt::info!("Power button long press released - toggling system state");
                     // PB8长按释放，切换系统状态
                     self.toggle_system_state().await;
@@ -236,28 +793,70 @@ impl<'d> PowerManager<'d> {
             }
         }
 
+        // 常开模式自动进入工作状态
+        self.check_always_on().await;
+
+        // 检查 VIN 掉电
+        self.check_brownout().await;
+
+        // 闲置自动待机
+        self.check_idle_standby().await;
+
         // 每个tick都更新LED状态，确保状态同步
         self.update_led_state().await;
 
+        // 闲置调暗
+        self.update_dimming();
+
+        // PD 错误闪码
+        self.check_pd_errors();
+
         // 更新LED显示
         self.update_led_display().await;
+    }
+}
 
-        // 定期状态报告（每5秒一次）
-        self.tick_counter += 1;
-        if self.tick_counter % 250 == 0 {
-            // 250 * 20ms = 5秒
-            defmt::info!(
-                "PowerManager status: State={:?}, LED={:?}, VIN={}V, VBUS={}V, VBUS_EN={}, Tick={}",
-                self.system_state,
-                self.led_state,
-                self.current_vin_voltage,
-                self.current_vbus_voltage,
-                self.current_vbus_enabled,
-                self.tick_counter
-            );
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_breathing_pattern_brightness() {
+        use BreathingPattern::*;
+
+        // 三角波和正弦波都在半周期达到最亮，两端熄灭
+        for pattern in [Triangle, Sine] {
+            assert_eq!(pattern.brightness(0.0), 0);
+            assert_eq!(pattern.brightness(0.5), 100);
         }
+        assert_eq!(Triangle.brightness(0.25), 50);
+        assert!(Sine.brightness(0.25) <= 50);
 
-        // 添加小延迟
-        Timer::after_millis(20).await; // 50Hz更新频率，确保呼吸灯平滑
+        assert_eq!(SlowPulse.brightness(SLOW_PULSE_ON_FRACTION / 2.0), 100);
+        assert_eq!(SlowPulse.brightness(0.6), 0);
+        assert_eq!(FastBlink.brightness(0.1), 100);
+        assert_eq!(FastBlink.brightness(0.9), 0);
+    }
+
+    #[test]
+    fn test_blink_code_sequence() {
+        let start = Instant::from_millis(1000);
+        let code = BlinkCode::new(2, Duration::from_millis(0), start);
+        // 时长不足时至少显示一组完整闪码
+        assert_eq!(code.duration, BlinkCode::cycle(2));
+
+        // 以 20ms 采样两次闪烁的时长
+        let mut lit = [false; 40];
+        for (i, slot) in lit.iter_mut().enumerate() {
+            *slot = code.is_on(start + Duration::from_millis(i as u64 * 20));
+        }
+        let edges = lit.windows(2).filter(|w| !w[0] && w[1]).count();
+        assert!(lit[0]);
+        assert_eq!(edges, 1); // 两次闪烁：开头一次，之后再亮起一次
+
+        // 间隔期间保持熄灭
+        assert!(!code.is_on(start + Duration::from_millis(800)));
+        assert!(!code.is_finished(start + Duration::from_millis(1780)));
+        assert!(code.is_finished(start + Duration::from_millis(1800)));
     }
 }
@@ -0,0 +1,81 @@
+use embassy_sync::{
+    blocking_mutex::raw::CriticalSectionRawMutex,
+    watch::{Receiver, Watch},
+};
+use uom::si::f64::{ElectricCurrent, ElectricPotential, ThermodynamicTemperature};
+
+/// Maximum number of counted receivers per topic
+pub(crate) const TOPIC_SUBS: usize = 4;
+
+pub(crate) type TopicReceiver<'a, T> = Receiver<'a, CriticalSectionRawMutex, T, TOPIC_SUBS>;
+
+/// Latest-value topic backed by a `Watch`
+///
+/// Publishing overwrites the previous value; readers always observe the most
+/// recent sample, never a backlog.
+pub(crate) struct Topic<T: Clone> {
+    watch: Watch<CriticalSectionRawMutex, T, TOPIC_SUBS>,
+}
+
+impl<T: Clone> Topic<T> {
+    pub const fn new() -> Self {
+        Self {
+            watch: Watch::new(),
+        }
+    }
+
+    /// Publish a new value, replacing the previous one
+    pub fn publish(&self, value: T) {
+        self.watch.sender().send(value);
+    }
+
+    /// Latest published value without registering a receiver
+    pub fn latest(&self) -> Option<T> {
+        self.watch.anon_receiver().try_get()
+    }
+
+    /// Register a receiver that can await changes
+    ///
+    /// Returns `None` when all `TOPIC_SUBS` receivers are in use.
+    pub fn subscribe(&self) -> Option<TopicReceiver<'_, T>> {
+        self.watch.receiver()
+    }
+}
+
+/// Measurement topics shared between the sampling tasks and the managers
+///
+/// Values carry their unit in the type; consumers pick the unit explicitly
+/// with `get::<volt>()` etc.
+pub(crate) struct Measurements {
+    /// VBUS (output) voltage
+    pub vbus_voltage: Topic<ElectricPotential>,
+    /// VBUS voltage of the latest sample, bypassing the EMA filter
+    pub vbus_voltage_raw: Topic<ElectricPotential>,
+    /// VIN (input) voltage
+    pub vin_voltage: Topic<ElectricPotential>,
+    /// VIN voltage of the latest sample, bypassing the EMA filter
+    pub vin_voltage_raw: Topic<ElectricPotential>,
+    /// VBUS output current
+    pub output_current: Topic<ElectricCurrent>,
+    /// Output current of the latest sample, bypassing the EMA filter
+    pub output_current_raw: Topic<ElectricCurrent>,
+    /// MCU die temperature from the internal sensor (the PB0 NTC is not sampled)
+    pub temperature: Topic<ThermodynamicTemperature>,
+    /// Fan speed in RPM
+    pub fan_rpm: Topic<u32>,
+}
+
+impl Measurements {
+    pub const fn new() -> Self {
+        Self {
+            vbus_voltage: Topic::new(),
+            vbus_voltage_raw: Topic::new(),
+            vin_voltage: Topic::new(),
+            vin_voltage_raw: Topic::new(),
+            output_current: Topic::new(),
+            output_current_raw: Topic::new(),
+            temperature: Topic::new(),
+            fan_rpm: Topic::new(),
+        }
+    }
+}
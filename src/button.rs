@@ -12,24 +12,74 @@ pub use button_internal::ButtonInternal;
 pub use real_impl::{RealButtonPin, RealTimeProvider};
 
 use alloc::sync::Arc;
+use core::future::pending;
+use embassy_futures::select::select_array;
 use embassy_stm32::exti::ExtiInput;
 use embassy_sync::pubsub::{PubSubBehavior, PubSubChannel};
 use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, pubsub::Subscriber};
 use embassy_time::Duration;
 
+use self::traits::{ButtonPin, TimeProvider};
 use crate::{INPUT_CAP, INPUT_PUB, INPUT_SUB};
 
-// 简化的输入事件类型 - 只支持单按钮
+// 输入事件类型 - 携带产生事件的按键编号
 #[derive(Debug, PartialEq, Clone, defmt::Format)]
 pub enum InputEvent {
     /// 按钮短按 (50ms-1000ms)
-    Click,
-    /// 按钮长按结束 (>=1000ms后释放)
-    LongReleased,
+    Click(ButtonId),
+    /// 按钮长按 (达到1000ms阈值时发布)
+    LongReleased(ButtonId),
+    /// 长按保持期间的周期性重复（需配置重复间隔）
+    LongPressRepeat(ButtonId),
+    /// 组合键：两个按键同时按住越过长按阈值，取代各自的长按事件
+    Chord(ButtonId, ButtonId),
+}
+
+/// 按键编号，即按键在 `InputManager` 中的注册顺序
+#[derive(Debug, PartialEq, Eq, Clone, Copy, defmt::Format)]
+pub struct ButtonId(pub u8);
+
+impl ButtonId {
+    /// PB8 电源按键，总是第一个注册
+    pub const PRIMARY: Self = Self(0);
+}
+
+/// `InputManager` 最多支持的按键数量
+pub const MAX_BUTTONS: usize = 4;
+
+/// 组合键配置
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub struct Chord {
+    pub buttons: (ButtonId, ButtonId),
+    /// 两个按键按下时刻允许的最大间隔
+    pub tolerance: Duration,
+}
+
+impl Chord {
+    /// `id` 在组合键中的另一个按键，`id` 不属于组合键时为 None
+    fn partner(&self, id: ButtonId) -> Option<ButtonId> {
+        match self.buttons {
+            (a, b) if a == id => Some(b),
+            (a, b) if b == id => Some(a),
+            _ => None,
+        }
+    }
+}
+
+/// 组合键识别状态
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+enum ChordState {
+    Idle,
+    /// 先越过阈值的按键，其长按事件暂缓发布，等待另一按键越过阈值
+    Pending(ButtonId),
+    /// 组合键已发布，屏蔽两个按键的后续长按事件直至全部释放
+    Active {
+        held: u8,
+    },
 }
 
 // 重新导出内部类型供外部使用
-pub use button_internal::ButtonEvent;
+pub use button_internal::{ButtonEvent, PressPrecedence, Thresholds};
 
 // 类型别名，使用真实硬件实现
 type RealButtonInternal = ButtonInternal<RealTimeProvider, RealButtonPin>;
@@ -40,25 +90,118 @@ type RealButtonInternal = ButtonInternal<RealTimeProvider, RealButtonPin>;
 
 // 旧的ButtonEvent枚举已移动到button_internal.rs模块
 
-// 简化的单按钮输入管理器
+// 输入管理器：最多 MAX_BUTTONS 个按键，全部并发轮询
 #[derive(Clone)]
-pub struct InputManager {
-    button: RealButtonInternal,
+pub struct InputManager<T: TimeProvider = RealTimeProvider, P: ButtonPin = RealButtonPin> {
+    buttons: [Option<ButtonInternal<T, P>>; MAX_BUTTONS],
+    chord: Option<Chord>,
+    chord_state: ChordState,
     channel:
         Arc<PubSubChannel<CriticalSectionRawMutex, InputEvent, INPUT_CAP, INPUT_SUB, INPUT_PUB>>,
 }
 
 impl InputManager {
-    // 简化构造函数，只接受单个按钮（PB8）
-    pub fn new(button_pin: ExtiInput<'static>, debounce: Duration, long_press: Duration) -> Self {
-        let time_provider = Arc::new(RealTimeProvider::new());
-        let pin = Arc::new(RealButtonPin::new(button_pin));
-        let button = ButtonInternal::new(time_provider, pin, debounce, long_press);
-
-        Self {
-            button,
+    // 单按钮便捷构造（PB8），编号为 ButtonId::PRIMARY
+    // pin_settle: 可选的驱动层消抖时间（None 表示仅使用状态机消抖）
+    pub fn new(
+        button_pin: ExtiInput<'static>,
+        debounce: Duration,
+        long_press: Duration,
+        pin_settle: Option<Duration>,
+    ) -> Self {
+        let button = real_button(button_pin, debounce, long_press, pin_settle);
+        Self::from_button(button)
+    }
+
+    /// 注册额外的按键，返回其编号；已满 MAX_BUTTONS 时返回 None
+    #[allow(dead_code)]
+    pub fn add_button(
+        &mut self,
+        button_pin: ExtiInput<'static>,
+        debounce: Duration,
+        long_press: Duration,
+        pin_settle: Option<Duration>,
+    ) -> Option<ButtonId> {
+        self.push(real_button(button_pin, debounce, long_press, pin_settle))
+    }
+}
+
+fn real_button(
+    button_pin: ExtiInput<'static>,
+    debounce: Duration,
+    long_press: Duration,
+    pin_settle: Option<Duration>,
+) -> RealButtonInternal {
+    let time_provider = Arc::new(RealTimeProvider::new());
+    let pin = Arc::new(RealButtonPin::new(button_pin, pin_settle));
+    ButtonInternal::new(
+        time_provider,
+        pin,
+        debounce,
+        long_press,
+        PressPrecedence::default(),
+        None, // 暂无需要长按重复的功能
+    )
+}
+
+impl<T: TimeProvider, P: ButtonPin> InputManager<T, P> {
+    /// 单按键构造，编号为 ButtonId::PRIMARY
+    pub fn from_button(button: ButtonInternal<T, P>) -> Self {
+        Self::from_buttons([button])
+    }
+
+    /// 按顺序注册按键，编号从 0 开始；超过 MAX_BUTTONS 的按键被忽略
+    pub fn from_buttons(buttons: impl IntoIterator<Item = ButtonInternal<T, P>>) -> Self {
+        let mut manager = Self {
+            buttons: core::array::from_fn(|_| None),
+            chord: None,
+            chord_state: ChordState::Idle,
             channel: Arc::new(PubSubChannel::new()),
+        };
+        for button in buttons {
+            if manager.push(button).is_none() {
+                defmt::warn!(
+                    "Input manager full ({} buttons), ignoring button",
+                    MAX_BUTTONS
+                );
+            }
         }
+        manager
+    }
+
+    /// 启用组合键识别；两个按键必须已注册且互不相同，否则忽略并返回 false
+    #[allow(dead_code)]
+    pub fn set_chord(&mut self, chord: Chord) -> bool {
+        let (a, b) = chord.buttons;
+        if a == b || self.button(a).is_none() || self.button(b).is_none() {
+            defmt::warn!("Ignoring invalid chord {:?}", chord);
+            return false;
+        }
+        self.chord = Some(chord);
+        self.chord_state = ChordState::Idle;
+        true
+    }
+
+    /// 修改指定按键的消抖与长按阈值，在该按键下一次按下时生效；
+    /// 按键未注册时忽略并返回 false
+    pub async fn set_thresholds(&self, id: ButtonId, thresholds: Thresholds) -> bool {
+        let Some(button) = self.button(id) else {
+            defmt::warn!("Ignoring thresholds for unregistered {:?}", id);
+            return false;
+        };
+        button.set_debounce(thresholds.debounce).await;
+        button.set_long_press(thresholds.long_press).await;
+        true
+    }
+
+    fn button(&self, id: ButtonId) -> Option<&ButtonInternal<T, P>> {
+        self.buttons.get(id.0 as usize)?.as_ref()
+    }
+
+    fn push(&mut self, button: ButtonInternal<T, P>) -> Option<ButtonId> {
+        let index = self.buttons.iter().position(Option::is_none)?;
+        self.buttons[index] = Some(button);
+        Some(ButtonId(index as u8))
     }
 
     // Get a receiver for input events
@@ -72,22 +215,105 @@ impl InputManager {
     }
 
     // Main loop tick function
+    //
+    // 所有按键同时轮询，先产生事件的一方返回；其余按键的 poll 被取消，
+    // 其状态保存在 ButtonInternal 中，下一次 tick 继续。
     pub async fn tick(&mut self) {
-        let event = self.button.poll().await;
-        self.handle_button_event(event).await;
+        let polls = self.buttons.each_ref().map(|button| async move {
+            match button {
+                Some(button) => button.poll().await,
+                None => pending().await,
+            }
+        });
+        let (event, index) = select_array(polls).await;
+        self.handle_button_event(ButtonId(index as u8), event).await;
     }
 
-    // 简化的单按钮事件处理
-    async fn handle_button_event(&mut self, event: ButtonEvent) {
+    // 组合键按键的事件先经过组合键状态机，其余按键直接发布
+    //
+    // 先越过长按阈值的按键若其搭档在容差内按下且仍按住，暂缓其长按事件；
+    // 搭档随后越过阈值则发布 Chord，否则（任一方提前释放）补发暂缓的长按。
+    async fn handle_button_event(&mut self, id: ButtonId, event: ButtonEvent) {
+        let Some((chord, partner)) = self
+            .chord
+            .and_then(|chord| Some((chord, chord.partner(id)?)))
+        else {
+            self.publish_button_event(id, event);
+            return;
+        };
+
+        match (self.chord_state, event) {
+            (ChordState::Idle, ButtonEvent::LongPressStart) => {
+                if self.pressed_together(id, partner, chord.tolerance).await {
+                    defmt::info!("{:?} held, waiting for chord partner {:?}", id, partner);
+                    self.chord_state = ChordState::Pending(id);
+                    return;
+                }
+            }
+            (ChordState::Pending(first), ButtonEvent::LongPressStart) if first == partner => {
+                let (a, b) = chord.buttons;
+                defmt::info!("Publishing chord event ({:?}, {:?})", a, b);
+                self.channel.publish_immediate(InputEvent::Chord(a, b));
+                self.chord_state = ChordState::Active { held: 2 };
+                return;
+            }
+            (ChordState::Pending(first), ButtonEvent::LongPressRepeat) if first == id => return,
+            (ChordState::Pending(first), _) => {
+                // 有一方未越过阈值即释放，组合键不成立
+                defmt::info!("Chord not completed, releasing held {:?}", first);
+                self.chord_state = ChordState::Idle;
+                self.publish_button_event(first, ButtonEvent::LongPressStart);
+            }
+            (ChordState::Active { .. }, ButtonEvent::LongPressRepeat) => return,
+            (ChordState::Active { held }, ButtonEvent::LongPressEnd) => {
+                self.chord_state = if held > 1 {
+                    ChordState::Active { held: held - 1 }
+                } else {
+                    ChordState::Idle
+                };
+                return;
+            }
+            _ => {}
+        }
+        self.publish_button_event(id, event);
+    }
+
+    /// 搭档按键仍按住，且两者按下时刻相差不超过 `tolerance`
+    async fn pressed_together(&self, id: ButtonId, partner: ButtonId, tolerance: Duration) -> bool {
+        let (Some(button), Some(partner)) = (self.button(id), self.button(partner)) else {
+            return false;
+        };
+        let (Some(start), Some(partner_start)) =
+            (button.press_start().await, partner.press_start().await)
+        else {
+            return false;
+        };
+        let gap = if start > partner_start {
+            start - partner_start
+        } else {
+            partner_start - start
+        };
+        gap <= tolerance
+    }
+
+    // 发布带按键编号的事件
+    fn publish_button_event(&mut self, id: ButtonId, event: ButtonEvent) {
         match event {
             ButtonEvent::ShortPress => {
-                defmt::info!("Publishing short press event (Click)");
-                self.channel.publish_immediate(InputEvent::Click);
+                defmt::info!("Publishing short press event ({:?})", id);
+                self.channel.publish_immediate(InputEvent::Click(id));
             }
             ButtonEvent::LongPressStart => {
                 // 长按开始事件 - 在1000ms时立即触发，立即执行长按动作
-                defmt::info!("Long press started (1000ms reached) - triggering immediate action");
-                self.channel.publish_immediate(InputEvent::LongReleased);
+                defmt::info!(
+                    "{:?} long press started (1000ms reached) - triggering immediate action",
+                    id
+                );
+                self.channel.publish_immediate(InputEvent::LongReleased(id));
+            }
+            ButtonEvent::LongPressRepeat => {
+                self.channel
+                    .publish_immediate(InputEvent::LongPressRepeat(id));
             }
             ButtonEvent::LongPressEnd => {
                 // 长按结束事件 - 但不发布，因为动作已经在LongPressStart时执行了
@@ -99,9 +325,11 @@ impl InputManager {
         }
     }
 
-    // 检查按钮是否处于激活状态（用于调试）
+    // 检查主按钮是否处于激活状态（用于调试）
     #[allow(dead_code)]
     pub fn is_button_active(&self) -> bool {
-        self.button.is_button_active()
+        self.buttons[0]
+            .as_ref()
+            .is_some_and(|button| button.is_button_active())
     }
 }
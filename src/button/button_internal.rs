@@ -17,19 +17,51 @@ pub enum ButtonState {
 pub enum ButtonEvent {
     None,
     ShortPress,
-    LongPressStart, // 新增：1000ms时立即触发
-    LongPressEnd,   // 长按释放时触发
+    LongPressStart,  // 新增：1000ms时立即触发
+    LongPressRepeat, // 长按保持期间按 repeat_interval 周期触发
+    LongPressEnd,    // 长按释放时触发
+}
+
+/// 短按/长按歧义时的优先级
+///
+/// 每次按键最多产生一个动作事件：ShortPress 或 LongPressStart（随后必有
+/// LongPressEnd），两者互斥。越过长按阈值的按键永远不会产生 ShortPress。
+/// 本枚举仅决定释放时刻已越过阈值、但长按定时器尚未触发时（两者同时
+/// 就绪）的处理方式。
+#[allow(dead_code)]
+#[derive(PartialEq, Clone, Copy, Debug, Default, defmt::Format)]
+pub enum PressPrecedence {
+    /// 长按优先：补发 LongPressStart，下一次 poll 返回 LongPressEnd
+    #[default]
+    LongPress,
+    /// 丢弃该次按键，不产生任何事件
+    Discard,
+}
+
+/// 按键时间阈值
+#[derive(PartialEq, Clone, Copy, Debug, defmt::Format)]
+pub struct Thresholds {
+    /// 短于该时长的按下视为抖动
+    pub debounce: Duration,
+    /// 达到该时长即触发长按
+    pub long_press: Duration,
 }
 
 /// 重构后的按键内部逻辑，支持依赖注入
 pub struct ButtonInternal<T: TimeProvider, P: ButtonPin> {
     time_provider: Arc<T>,
     pin: Arc<P>,
-    debounce: Duration,
-    long_press: Duration,
+    /// 当前按键周期使用的阈值
+    thresholds: Arc<Mutex<CriticalSectionRawMutex, Thresholds>>,
+    /// 运行时修改的阈值，在下一次按下开始时生效，避免影响进行中的按键
+    pending_thresholds: Arc<Mutex<CriticalSectionRawMutex, Option<Thresholds>>>,
+    precedence: PressPrecedence,
+    /// 长按保持期间的重复间隔（None 表示不产生重复事件）
+    repeat_interval: Option<Duration>,
     state: Arc<Mutex<CriticalSectionRawMutex, ButtonState>>,
     press_start: Arc<Mutex<CriticalSectionRawMutex, Option<Instant>>>,
     long_press_triggered: Arc<Mutex<CriticalSectionRawMutex, bool>>, // 防止重复触发
+    next_repeat: Arc<Mutex<CriticalSectionRawMutex, Option<Instant>>>, // 下一次重复事件的时间
 }
 
 impl<T: TimeProvider, P: ButtonPin> ButtonInternal<T, P> {
@@ -38,15 +70,24 @@ impl<T: TimeProvider, P: ButtonPin> ButtonInternal<T, P> {
         pin: Arc<P>,
         debounce: Duration,
         long_press: Duration,
+        precedence: PressPrecedence,
+        repeat_interval: Option<Duration>,
     ) -> Self {
         Self {
             time_provider,
             pin,
-            debounce,
-            long_press,
+            thresholds: Arc::new(Mutex::new(Thresholds {
+                debounce,
+                long_press,
+            })),
+            pending_thresholds: Arc::new(Mutex::new(None)),
+            precedence,
+            // 零间隔会在长按期间连续触发，视为不启用
+            repeat_interval: repeat_interval.filter(|interval| interval.as_ticks() > 0),
             state: Arc::new(Mutex::new(ButtonState::Idle)),
             press_start: Arc::new(Mutex::new(None)),
             long_press_triggered: Arc::new(Mutex::new(false)),
+            next_repeat: Arc::new(Mutex::new(None)),
         }
     }
 
@@ -68,6 +109,12 @@ impl<T: TimeProvider, P: ButtonPin> ButtonInternal<T, P> {
                     self.pin.wait_for_high().await;
                     defmt::info!("Button pressed! Recording start time...");
 
+                    // 新的按键周期开始，应用运行时修改的阈值
+                    if let Some(thresholds) = self.pending_thresholds.lock().await.take() {
+                        defmt::info!("Button thresholds updated: {:?}", thresholds);
+                        *self.thresholds.lock().await = thresholds;
+                    }
+
                     // 记录按键开始时间并进入等待释放状态
                     *self.press_start.lock().await = Some(self.time_provider.now());
                     *self.state.lock().await = ButtonState::WaitingRelease;
@@ -89,8 +136,13 @@ impl<T: TimeProvider, P: ButtonPin> ButtonInternal<T, P> {
                         }
                     };
 
-                    // 创建1000ms定时器
-                    let long_press_deadline = start_time + self.long_press;
+                    let Thresholds {
+                        debounce,
+                        long_press,
+                    } = *self.thresholds.lock().await;
+
+                    // 创建长按定时器
+                    let long_press_deadline = start_time + long_press;
 
                     // 同时等待按键释放和长按定时器
                     match select::select(
@@ -106,12 +158,12 @@ impl<T: TimeProvider, P: ButtonPin> ButtonInternal<T, P> {
 
                             defmt::info!("Button released after {}ms", duration_ms);
 
-                            if duration >= self.debounce && duration < self.long_press {
+                            if duration >= debounce && duration < long_press {
                                 // 有效短按 (50ms-1000ms)
                                 defmt::info!("Valid short press detected ({}ms)", duration_ms);
                                 self.reset().await;
                                 return ButtonEvent::ShortPress;
-                            } else if duration < self.debounce {
+                            } else if duration < debounce {
                                 // 抖动，忽略
                                 defmt::info!(
                                     "Button bounce detected ({}ms), ignoring",
@@ -120,12 +172,26 @@ impl<T: TimeProvider, P: ButtonPin> ButtonInternal<T, P> {
                                 self.reset().await;
                                 return ButtonEvent::None;
                             } else {
-                                // duration >= long_press，理论上不应该到这里，因为定时器会先触发
-                                defmt::warn!(
-                                    "Unexpected: button released after long press threshold"
-                                );
-                                self.reset().await;
-                                return ButtonEvent::None;
+                                // duration >= long_press：释放与长按定时器同时就绪，按优先级处理
+                                match self.precedence {
+                                    PressPrecedence::LongPress => {
+                                        defmt::info!(
+                                            "Release at long press threshold ({}ms), long press takes precedence",
+                                            duration_ms
+                                        );
+                                        // 进入 LongPressed，下一次 poll 立即返回 LongPressEnd
+                                        self.enter_long_pressed(long_press_deadline).await;
+                                        return ButtonEvent::LongPressStart;
+                                    }
+                                    PressPrecedence::Discard => {
+                                        defmt::warn!(
+                                            "Release at long press threshold ({}ms), discarding press",
+                                            duration_ms
+                                        );
+                                        self.reset().await;
+                                        return ButtonEvent::None;
+                                    }
+                                }
                             }
                         }
                         select::Either::Second(_) => {
@@ -133,18 +199,38 @@ impl<T: TimeProvider, P: ButtonPin> ButtonInternal<T, P> {
                             defmt::info!(
                                 "Long press threshold reached (1000ms) - triggering immediately!"
                             );
-                            *self.state.lock().await = ButtonState::LongPressed;
-                            *self.long_press_triggered.lock().await = true;
+                            self.enter_long_pressed(long_press_deadline).await;
                             return ButtonEvent::LongPressStart; // 立即返回长按开始事件
                         }
                     }
                 }
 
                 ButtonState::LongPressed => {
-                    defmt::info!("Button in long press state, waiting for release...");
+                    let next_repeat = *self.next_repeat.lock().await;
 
-                    // 等待按键释放
-                    self.pin.wait_for_low().await;
+                    // 等待按键释放；启用重复时同时等待下一次重复时间
+                    match next_repeat {
+                        Some(deadline) => {
+                            match select::select(
+                                self.pin.wait_for_low(),
+                                self.time_provider.sleep_until(deadline),
+                            )
+                            .await
+                            {
+                                select::Either::First(_) => {}
+                                select::Either::Second(_) => {
+                                    // 按截止时间推进，poll 调用延迟时不会丢失重复次数
+                                    let interval = self.repeat_interval.unwrap_or_default();
+                                    *self.next_repeat.lock().await = Some(deadline + interval);
+                                    return ButtonEvent::LongPressRepeat;
+                                }
+                            }
+                        }
+                        None => {
+                            defmt::info!("Button in long press state, waiting for release...");
+                            self.pin.wait_for_low().await;
+                        }
+                    }
 
                     let start_time = {
                         let start_mutex = self.press_start.lock().await;
@@ -161,10 +247,48 @@ impl<T: TimeProvider, P: ButtonPin> ButtonInternal<T, P> {
         }
     }
 
+    /// 进入 LongPressed 状态，并安排第一次重复事件
+    async fn enter_long_pressed(&self, long_press_deadline: Instant) {
+        *self.state.lock().await = ButtonState::LongPressed;
+        *self.long_press_triggered.lock().await = true;
+        *self.next_repeat.lock().await = self
+            .repeat_interval
+            .map(|interval| long_press_deadline + interval);
+    }
+
     async fn reset(&self) {
         *self.state.lock().await = ButtonState::Idle;
         *self.press_start.lock().await = None;
         *self.long_press_triggered.lock().await = false;
+        *self.next_repeat.lock().await = None;
+    }
+
+    /// 修改消抖时间，在下一次按下开始时生效
+    pub async fn set_debounce(&self, debounce: Duration) {
+        self.update_thresholds(|thresholds| thresholds.debounce = debounce)
+            .await;
+    }
+
+    /// 修改长按阈值，在下一次按下开始时生效
+    pub async fn set_long_press(&self, long_press: Duration) {
+        self.update_thresholds(|thresholds| thresholds.long_press = long_press)
+            .await;
+    }
+
+    /// 在尚未生效的修改（若有）基础上更新阈值
+    async fn update_thresholds(&self, update: impl FnOnce(&mut Thresholds)) {
+        let mut pending = self.pending_thresholds.lock().await;
+        let mut thresholds = match *pending {
+            Some(thresholds) => thresholds,
+            None => *self.thresholds.lock().await,
+        };
+        update(&mut thresholds);
+        *pending = Some(thresholds);
+    }
+
+    /// 当前按键的按下时刻，未按下时为 None
+    pub async fn press_start(&self) -> Option<Instant> {
+        *self.press_start.lock().await
     }
 
     // 检查按键当前状态（用于调试）
@@ -189,11 +313,14 @@ impl<T: TimeProvider, P: ButtonPin> Clone for ButtonInternal<T, P> {
         Self {
             time_provider: Arc::clone(&self.time_provider),
             pin: Arc::clone(&self.pin),
-            debounce: self.debounce,
-            long_press: self.long_press,
+            thresholds: Arc::clone(&self.thresholds),
+            pending_thresholds: Arc::clone(&self.pending_thresholds),
+            precedence: self.precedence,
+            repeat_interval: self.repeat_interval,
             state: Arc::clone(&self.state),
             press_start: Arc::clone(&self.press_start),
             long_press_triggered: Arc::clone(&self.long_press_triggered),
+            next_repeat: Arc::clone(&self.next_repeat),
         }
     }
 }
@@ -2,7 +2,7 @@ use alloc::sync::Arc;
 use embassy_stm32::exti::ExtiInput;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::mutex::Mutex;
-use embassy_time::{Instant, Timer};
+use embassy_time::{Duration, Instant, Timer};
 
 use super::traits::{ButtonPin, TimeProvider};
 
@@ -29,26 +29,53 @@ impl TimeProvider for RealTimeProvider {
 
 /// 真实硬件按键引脚
 /// 包装ExtiInput提供抽象的按键接口
+///
+/// `settle` 为可选的驱动层消抖：边沿触发后等待该时间再次采样，
+/// 电平仍一致才上报，否则继续等待下一个边沿。
 #[derive(Clone)]
 pub struct RealButtonPin {
     pin: Arc<Mutex<CriticalSectionRawMutex, ExtiInput<'static>>>,
+    settle: Option<Duration>,
 }
 
 impl RealButtonPin {
-    pub fn new(pin: ExtiInput<'static>) -> Self {
+    pub fn new(pin: ExtiInput<'static>, settle: Option<Duration>) -> Self {
         Self {
             pin: Arc::new(Mutex::new(pin)),
+            settle,
         }
     }
 }
 
 impl ButtonPin for RealButtonPin {
     async fn wait_for_high(&self) {
-        self.pin.lock().await.wait_for_high().await;
+        let mut pin = self.pin.lock().await;
+        loop {
+            pin.wait_for_high().await;
+            let Some(settle) = self.settle else {
+                return;
+            };
+            // 等待稳定后重新采样确认
+            Timer::after(settle).await;
+            if pin.is_high() {
+                return;
+            }
+        }
     }
 
     async fn wait_for_low(&self) {
-        self.pin.lock().await.wait_for_low().await;
+        let mut pin = self.pin.lock().await;
+        loop {
+            pin.wait_for_low().await;
+            let Some(settle) = self.settle else {
+                return;
+            };
+            // 等待稳定后重新采样确认
+            Timer::after(settle).await;
+            if pin.is_low() {
+                return;
+            }
+        }
     }
 
     fn is_high(&self) -> bool {
@@ -1,8 +1,12 @@
 #[cfg(test)]
 mod button_tests {
-    use super::super::button_internal::{ButtonEvent, ButtonInternal, ButtonState};
+    use super::super::button_internal::{
+        ButtonEvent, ButtonInternal, ButtonState, PressPrecedence,
+    };
     use super::super::mock_impl::{MockButtonPin, MockTimeProvider};
-    use alloc::sync::Arc;
+    use super::super::{ButtonId, Chord, InputEvent, InputManager, Thresholds, MAX_BUTTONS};
+    use alloc::{sync::Arc, vec::Vec};
+    use core::task::Poll;
     use embassy_time::Duration;
 
     type TestButtonInternal = ButtonInternal<MockTimeProvider, MockButtonPin>;
@@ -11,6 +15,16 @@ mod button_tests {
         TestButtonInternal,
         Arc<MockTimeProvider>,
         Arc<MockButtonPin>,
+    ) {
+        create_test_button_with(PressPrecedence::default())
+    }
+
+    fn create_test_button_with(
+        precedence: PressPrecedence,
+    ) -> (
+        TestButtonInternal,
+        Arc<MockTimeProvider>,
+        Arc<MockButtonPin>,
     ) {
         let time_provider = Arc::new(MockTimeProvider::new());
         let pin = Arc::new(MockButtonPin::new());
@@ -19,6 +33,8 @@ mod button_tests {
             Arc::clone(&pin),
             Duration::from_millis(50),   // 50ms debounce
             Duration::from_millis(1000), // 1000ms long press
+            precedence,
+            None,
         );
         (button, time_provider, pin)
     }
@@ -388,4 +404,396 @@ mod button_tests {
             "Should trigger LongPressEnd immediately after"
         );
     }
+
+    /// 完成一次按下-释放，收集该次按键产生的全部非 None 事件
+    async fn press_and_collect(
+        button: &TestButtonInternal,
+        time_provider: &MockTimeProvider,
+        pin: &MockButtonPin,
+        hold_ms: u64,
+    ) -> Vec<ButtonEvent> {
+        pin.set_high().await;
+        time_provider
+            .advance_time(Duration::from_millis(hold_ms))
+            .await;
+        pin.set_low().await;
+
+        let mut events = Vec::new();
+        loop {
+            let event = button.poll().await;
+            if event != ButtonEvent::None {
+                events.push(event);
+            }
+            if button.get_state().await == ButtonState::Idle {
+                return events;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_press_classification_is_exclusive() {
+        let (button, time_provider, pin) = create_test_button();
+
+        // 每次按键只能得到下列之一：无事件、单个短按、长按开始+长按结束
+        let cases: [(u64, &[ButtonEvent]); 6] = [
+            (10, &[]),
+            (49, &[]),
+            (50, &[ButtonEvent::ShortPress]),
+            (999, &[ButtonEvent::ShortPress]),
+            (
+                1000,
+                &[ButtonEvent::LongPressStart, ButtonEvent::LongPressEnd],
+            ),
+            (
+                5000,
+                &[ButtonEvent::LongPressStart, ButtonEvent::LongPressEnd],
+            ),
+        ];
+
+        for (hold_ms, expected) in cases {
+            let events = press_and_collect(&button, &time_provider, &pin, hold_ms).await;
+            assert_eq!(events.as_slice(), expected, "Hold of {}ms", hold_ms);
+            assert!(!button.is_long_press_triggered().await);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transitions_between_all_event_types() {
+        let (button, time_provider, pin) = create_test_button();
+
+        // 无事件（抖动）、短按、长按两两相接，前一次按键不得影响后一次的分类
+        let kinds: [(u64, &[ButtonEvent]); 3] = [
+            (20, &[]),
+            (200, &[ButtonEvent::ShortPress]),
+            (
+                1500,
+                &[ButtonEvent::LongPressStart, ButtonEvent::LongPressEnd],
+            ),
+        ];
+
+        for (first_ms, first_expected) in kinds {
+            for (second_ms, second_expected) in kinds {
+                let first = press_and_collect(&button, &time_provider, &pin, first_ms).await;
+                time_provider.advance_time(Duration::from_millis(100)).await;
+                let second = press_and_collect(&button, &time_provider, &pin, second_ms).await;
+                time_provider.advance_time(Duration::from_millis(100)).await;
+
+                assert_eq!(
+                    first.as_slice(),
+                    first_expected,
+                    "{}ms followed by {}ms",
+                    first_ms,
+                    second_ms
+                );
+                assert_eq!(
+                    second.as_slice(),
+                    second_expected,
+                    "{}ms followed by {}ms",
+                    first_ms,
+                    second_ms
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_late_release_discarded_when_configured() {
+        let (button, time_provider, pin) = create_test_button_with(PressPrecedence::Discard);
+
+        // 释放时已越过阈值但定时器尚未触发：丢弃，且绝不产生短按
+        let events = press_and_collect(&button, &time_provider, &pin, 1200).await;
+        assert!(events.is_empty());
+        assert_eq!(button.get_state().await, ButtonState::Idle);
+
+        // 丢弃后下一次短按正常识别
+        let events = press_and_collect(&button, &time_provider, &pin, 100).await;
+        assert_eq!(events.as_slice(), &[ButtonEvent::ShortPress]);
+    }
+
+    #[tokio::test]
+    async fn test_thresholds_change_between_press_cycles() {
+        let (button, time_provider, pin) = create_test_button();
+
+        // 缩短长按阈值后，600ms 按键成为长按
+        assert_eq!(
+            press_and_collect(&button, &time_provider, &pin, 600).await,
+            [ButtonEvent::ShortPress]
+        );
+        button.set_long_press(Duration::from_millis(500)).await;
+        assert_eq!(
+            press_and_collect(&button, &time_provider, &pin, 600).await,
+            [ButtonEvent::LongPressStart, ButtonEvent::LongPressEnd]
+        );
+
+        // 提高消抖时间后，80ms 按键被视为抖动
+        button.set_debounce(Duration::from_millis(100)).await;
+        assert!(press_and_collect(&button, &time_provider, &pin, 80)
+            .await
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_threshold_change_mid_press_waits_for_next_press() {
+        let (button, time_provider, pin) = create_test_button();
+
+        // 按下后修改阈值：进行中的按键仍按 1000ms 判定
+        pin.set_high().await;
+        assert!(embassy_futures::poll_once(button.poll()).is_pending());
+        button.set_long_press(Duration::from_millis(500)).await;
+        time_provider.advance_time(Duration::from_millis(600)).await;
+        assert!(embassy_futures::poll_once(button.poll()).is_pending());
+        pin.set_low().await;
+        assert_eq!(button.poll().await, ButtonEvent::ShortPress);
+        assert_eq!(button.get_state().await, ButtonState::Idle);
+
+        // 下一次按键使用新阈值
+        assert_eq!(
+            press_and_collect(&button, &time_provider, &pin, 600).await,
+            [ButtonEvent::LongPressStart, ButtonEvent::LongPressEnd]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_manager_sets_thresholds_of_registered_button() {
+        let (button, time_provider, pin) = create_test_button();
+        let manager = InputManager::from_button(button.clone());
+        let thresholds = Thresholds {
+            debounce: Duration::from_millis(50),
+            long_press: Duration::from_millis(500),
+        };
+
+        // 未注册的按键被忽略
+        assert!(!manager.set_thresholds(ButtonId(1), thresholds).await);
+        assert!(manager.set_thresholds(ButtonId::PRIMARY, thresholds).await);
+
+        // 克隆共享阈值状态，下一次按键使用新阈值
+        assert_eq!(
+            press_and_collect(&button, &time_provider, &pin, 600).await,
+            [ButtonEvent::LongPressStart, ButtonEvent::LongPressEnd]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_two_inputs_publish_distinct_events() {
+        let time_provider = Arc::new(MockTimeProvider::new());
+        let primary_pin = Arc::new(MockButtonPin::new());
+        let secondary_pin = Arc::new(MockButtonPin::new());
+        let button = |pin: &Arc<MockButtonPin>| {
+            ButtonInternal::new(
+                Arc::clone(&time_provider),
+                Arc::clone(pin),
+                Duration::from_millis(50),
+                Duration::from_millis(1000),
+                PressPrecedence::default(),
+                None,
+            )
+        };
+        let mut manager =
+            InputManager::from_buttons([button(&primary_pin), button(&secondary_pin)]);
+        let listener = manager.clone();
+        let mut events = listener.subscriber().unwrap();
+
+        // 两个按键同时按下：主按键 200ms 短按，副按键按住越过长按阈值
+        let run = async {
+            // 主按键短按、副按键长按开始、副按键长按结束
+            for _ in 0..3 {
+                manager.tick().await;
+            }
+        };
+        let drive = async {
+            primary_pin.set_high().await;
+            secondary_pin.set_high().await;
+            tokio::task::yield_now().await;
+            time_provider.advance_time(Duration::from_millis(200)).await;
+            primary_pin.set_low().await;
+            tokio::task::yield_now().await;
+            time_provider.advance_time(Duration::from_millis(900)).await;
+            tokio::task::yield_now().await;
+            secondary_pin.set_low().await;
+        };
+        embassy_futures::join::join(run, drive).await;
+
+        let mut published = Vec::new();
+        while let Some(event) = events.try_next_message_pure() {
+            published.push(event);
+        }
+        assert_eq!(
+            published.as_slice(),
+            &[
+                InputEvent::Click(ButtonId::PRIMARY),
+                InputEvent::LongReleased(ButtonId(1))
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_independent_buttons_keep_their_ids() {
+        let time_provider = Arc::new(MockTimeProvider::new());
+        let pins: Vec<_> = (0..MAX_BUTTONS + 1)
+            .map(|_| Arc::new(MockButtonPin::new()))
+            .collect();
+        let button = |pin: &Arc<MockButtonPin>| {
+            ButtonInternal::new(
+                Arc::clone(&time_provider),
+                Arc::clone(pin),
+                Duration::from_millis(50),
+                Duration::from_millis(1000),
+                PressPrecedence::default(),
+                None,
+            )
+        };
+        // 超出 MAX_BUTTONS 的按键被忽略
+        let mut manager = InputManager::from_buttons(pins.iter().map(button));
+        let listener = manager.clone();
+        let mut events = listener.subscriber().unwrap();
+
+        // 依次短按第三个和第二个按键，各自携带注册编号
+        for index in [2, 1] {
+            let run = manager.tick();
+            let drive = async {
+                pins[index].set_high().await;
+                tokio::task::yield_now().await;
+                time_provider.advance_time(Duration::from_millis(100)).await;
+                pins[index].set_low().await;
+            };
+            embassy_futures::join::join(run, drive).await;
+        }
+
+        // 第五个按键未注册，按下后不产生事件
+        pins[MAX_BUTTONS].set_high().await;
+        time_provider.advance_time(Duration::from_millis(100)).await;
+        pins[MAX_BUTTONS].set_low().await;
+        assert!(embassy_futures::poll_once(manager.tick()).is_pending());
+
+        let mut published = Vec::new();
+        while let Some(event) = events.try_next_message_pure() {
+            published.push(event);
+        }
+        assert_eq!(
+            published.as_slice(),
+            &[
+                InputEvent::Click(ButtonId(2)),
+                InputEvent::Click(ButtonId(1))
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_long_press_repeat_until_release() {
+        // (按住时长, 期望重复次数)：1000ms 长按开始后每 200ms 重复一次
+        for (hold_ms, expected_repeats) in [(1000u64, 0usize), (2000, 5), (3000, 10)] {
+            let time_provider = Arc::new(MockTimeProvider::new());
+            let pin = Arc::new(MockButtonPin::new());
+            let button = ButtonInternal::new(
+                Arc::clone(&time_provider),
+                Arc::clone(&pin),
+                Duration::from_millis(50),
+                Duration::from_millis(1000),
+                PressPrecedence::default(),
+                Some(Duration::from_millis(200)),
+            );
+
+            // 按下后先 poll 一次记录按下时间，再推进到按住时长
+            pin.set_high().await;
+            assert!(embassy_futures::poll_once(button.poll()).is_pending());
+            time_provider
+                .advance_time(Duration::from_millis(hold_ms))
+                .await;
+
+            // 收集按住期间已就绪的全部事件，然后释放
+            let mut events = Vec::new();
+            while let Poll::Ready(event) = embassy_futures::poll_once(button.poll()) {
+                events.push(event);
+            }
+            pin.set_low().await;
+            events.push(button.poll().await);
+
+            let repeats = events
+                .iter()
+                .filter(|event| **event == ButtonEvent::LongPressRepeat)
+                .count();
+            assert_eq!(repeats, expected_repeats, "hold {}ms", hold_ms);
+            assert_eq!(events.len(), expected_repeats + 2, "hold {}ms", hold_ms);
+            assert_eq!(events.first(), Some(&ButtonEvent::LongPressStart));
+            assert_eq!(events.last(), Some(&ButtonEvent::LongPressEnd));
+            assert_eq!(button.get_state().await, ButtonState::Idle);
+        }
+    }
+
+    /// A 在 0ms 按下，B 在 `stagger_ms` 按下并按住 `b_hold_ms`，2000ms 时全部释放；
+    /// 以 50ms 步长推进时间，每步处理完所有就绪事件，返回发布的事件
+    async fn run_chord(stagger_ms: u64, b_hold_ms: u64) -> Vec<InputEvent> {
+        let time_provider = Arc::new(MockTimeProvider::new());
+        let pins = [
+            Arc::new(MockButtonPin::new()),
+            Arc::new(MockButtonPin::new()),
+        ];
+        let mut manager = InputManager::from_buttons(pins.iter().map(|pin| {
+            ButtonInternal::new(
+                Arc::clone(&time_provider),
+                Arc::clone(pin),
+                Duration::from_millis(50),
+                Duration::from_millis(1000),
+                PressPrecedence::default(),
+                None,
+            )
+        }));
+        assert!(manager.set_chord(Chord {
+            buttons: (ButtonId(0), ButtonId(1)),
+            tolerance: Duration::from_millis(200),
+        }));
+        let listener = manager.clone();
+        let mut events = listener.subscriber().unwrap();
+
+        for t in (0..=2000).step_by(50) {
+            if t == 0 {
+                pins[0].set_high().await;
+            }
+            if t == stagger_ms {
+                pins[1].set_high().await;
+            }
+            if t == (stagger_ms + b_hold_ms).min(2000) {
+                pins[1].set_low().await;
+            }
+            if t == 2000 {
+                pins[0].set_low().await;
+            }
+            while embassy_futures::poll_once(manager.tick()).is_ready() {}
+            time_provider.advance_time(Duration::from_millis(50)).await;
+        }
+
+        let mut published = Vec::new();
+        while let Some(event) = events.try_next_message_pure() {
+            published.push(event);
+        }
+        published
+    }
+
+    #[tokio::test]
+    async fn test_chord_within_tolerance() {
+        let chord = InputEvent::Chord(ButtonId(0), ButtonId(1));
+        // 同时按下与容差内的先后按下都识别为组合键，且不产生单独的长按
+        assert_eq!(run_chord(0, 2000).await, [chord.clone()]);
+        assert_eq!(run_chord(150, 2000).await, [chord]);
+    }
+
+    #[tokio::test]
+    async fn test_chord_rejected_outside_tolerance_or_early_release() {
+        // 按下间隔超出容差：各自的长按
+        assert_eq!(
+            run_chord(400, 2000).await,
+            [
+                InputEvent::LongReleased(ButtonId(0)),
+                InputEvent::LongReleased(ButtonId(1))
+            ]
+        );
+        // B 未越过阈值即释放：补发 A 的长按，B 为短按
+        assert_eq!(
+            run_chord(100, 950).await,
+            [
+                InputEvent::LongReleased(ButtonId(0)),
+                InputEvent::Click(ButtonId(1))
+            ]
+        );
+    }
 }
@@ -8,19 +8,46 @@ use embassy_sync::{
     signal::Signal,
     watch,
 };
+use embassy_time::{Duration, Ticker, Timer};
+use embedded_hal_async::i2c::I2c;
 
-// use m24c64_driver::M24C64; // 暂时注释掉，因为不再使用 EEPROM
 use uom::si::{electric_current::milliampere, electric_potential::millivolt};
 use usbpd::protocol_layer::message::units::{ElectricCurrent, ElectricPotential};
 
+use crate::{
+    app_manager::{StandbyReason, SystemState},
+    power::RequestStrategy,
+    shared::CONFIG_SNAPSHOT_RECEIVERS,
+};
+
 #[derive(Debug, defmt::Format)]
 pub enum ConfigManagerError {
     I2CError,
+    InvalidValue,
 }
 
+/// 每个寄存器占 4 字节（大端），对齐存放不跨 EEPROM 页；
+/// 校验和集中存放在 `CHECKSUM_BASE` 起的校验和表中，每个寄存器 1 字节
+#[derive(Clone, Copy)]
 enum Register {
     TargetVoltage = 0x00,
     TargetCurrent = 0x04,
+    MinVoltage = 0x08,
+    RequestStrategy = 0x0C,
+    VoutGain = 0x10,
+    VoutOffset = 0x14,
+    VinGain = 0x18,
+    VinOffset = 0x1C,
+    IdleStandby = 0x20,
+    BootRestore = 0x24,
+    LastState = 0x28,
+}
+
+impl Register {
+    /// 该寄存器校验和在校验和表中的地址
+    fn checksum_address(self) -> u16 {
+        CHECKSUM_BASE + self as u16 / MAX_REGISTER_LEN as u16
+    }
 }
 
 impl From<Register> for usize {
@@ -29,27 +56,201 @@ impl From<Register> for usize {
     }
 }
 
-pub struct ConfigManager {
-    // 简化版本，不使用 EEPROM 存储
+/// 目标电压允许范围（mV），读取时钳位，USB 设置时超出范围拒绝
+pub const TARGET_VOLTAGE_RANGE_MV: (u32, u32) = (3_000, 48_000);
+/// 目标电流允许范围（mA）
+pub const TARGET_CURRENT_RANGE_MA: (u32, u32) = (100, 5_000);
+
+/// 电压校准增益允许范围，超出说明校准过程有误
+pub const VOLTAGE_GAIN_RANGE: (f64, f64) = (0.8, 1.2);
+/// 电压校准偏移允许的最大绝对值（V）
+pub const VOLTAGE_OFFSET_LIMIT: f64 = 1.0;
+
+/// 闲置自动待机超时的默认值
+pub const DEFAULT_IDLE_STANDBY: Duration = Duration::from_secs(10 * 60);
+/// 闲置自动待机超时允许的最大值（秒）
+pub const IDLE_STANDBY_MAX_S: u32 = 24 * 60 * 60;
+
+/// 单个寄存器的最大数据长度（不含校验和）
+const MAX_REGISTER_LEN: usize = 4;
+
+/// 配置存储抽象接口，按字节地址读写
+pub trait ConfigStorage {
+    async fn read(&mut self, address: u16, buffer: &mut [u8]) -> Result<(), ()>;
+    async fn write(&mut self, address: u16, data: &[u8]) -> Result<(), ()>;
+}
+
+/// 校验和表起始地址，位于第三页开头，与数据寄存器分开
+const CHECKSUM_BASE: u16 = 0x40;
+
+/// 配置区大小，覆盖数据寄存器和校验和表
+const STORAGE_SIZE: usize = 0x50;
+
+/// M24C64 EEPROM，16 位字节地址
+pub struct Eeprom<I> {
+    i2c: I,
+    address: u8,
+}
+
+impl<I: I2c> Eeprom<I> {
+    pub const DEFAULT_ADDRESS: u8 = 0x50;
+
+    pub fn new(i2c: I, address: u8) -> Self {
+        Self { i2c, address }
+    }
+}
+
+impl<I: I2c> ConfigStorage for Eeprom<I> {
+    async fn read(&mut self, address: u16, buffer: &mut [u8]) -> Result<(), ()> {
+        self.i2c
+            .write_read(self.address, &address.to_be_bytes(), buffer)
+            .await
+            .map_err(|_| ())
+    }
+
+    async fn write(&mut self, address: u16, data: &[u8]) -> Result<(), ()> {
+        let mut frame = [0u8; 2 + MAX_REGISTER_LEN];
+        frame[..2].copy_from_slice(&address.to_be_bytes());
+        frame[2..2 + data.len()].copy_from_slice(data);
+        self.i2c
+            .write(self.address, &frame[..2 + data.len()])
+            .await
+            .map_err(|_| ())
+    }
+}
+
+/// EEPROM 写入重试配置
+#[derive(Debug, Clone, Copy)]
+pub struct WriteRetryConfig {
+    /// 最多尝试次数（含首次写入）
+    pub attempts: u8,
+    /// 失败后再次尝试前的等待时间
+    pub retry_delay: Duration,
+    /// 写入后等待 EEPROM 内部写周期完成再回读
+    pub write_cycle: Duration,
 }
 
-impl ConfigManager {
-    pub fn new() -> Self {
-        ConfigManager {}
+impl Default for WriteRetryConfig {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            retry_delay: Duration::from_millis(10),
+            // M24C64 最大写周期 5ms
+            write_cycle: Duration::from_millis(5),
+        }
     }
+}
 
+/// 数据的 8 位校验和（字节和取反，全 0xFF 的空白 EEPROM 无法通过校验）
+fn checksum(data: &[u8]) -> u8 {
+    !data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+pub struct ConfigManager<S: ConfigStorage> {
+    storage: S,
+    retry: WriteRetryConfig,
+    integrity: ConfigIntegrity,
+}
+
+/// 存储配置完整性校验统计，用于长期观察 EEPROM 可靠性
+#[derive(Clone, Copy, Debug, Default, defmt::Format)]
+pub struct ConfigIntegrity {
+    pub checks: u32,
+    pub failures: u32,
+}
+
+/// 后台配置完整性校验的默认间隔（低频，6 小时）
+pub const INTEGRITY_CHECK_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+impl<S: ConfigStorage> ConfigManager<S> {
+    pub fn with_storage(storage: S, retry: WriteRetryConfig) -> Self {
+        ConfigManager {
+            storage,
+            retry,
+            integrity: ConfigIntegrity::default(),
+        }
+    }
+
+    /// 读取寄存器数据（不校验校验和，未写入过的 EEPROM 由各读取函数钳位/回退）
     async fn read(
         &mut self,
-        _register: Register,
-        _buffer: &mut [u8],
+        register: Register,
+        buffer: &mut [u8],
     ) -> Result<(), ConfigManagerError> {
-        // 简化版本：不实际读取 EEPROM
-        Ok(())
+        self.storage
+            .read(register as u16, buffer)
+            .await
+            .map_err(|_| ConfigManagerError::I2CError)
     }
 
-    async fn write(&mut self, _register: Register, _data: &[u8]) -> Result<(), ConfigManagerError> {
-        // 简化版本：不实际写入 EEPROM
-        Ok(())
+    /// 写入寄存器并回读校验，I2C 错误或回读不一致时按配置重试
+    ///
+    /// 重试用尽后才返回 `I2CError`，避免一次总线抖动就丢失配置修改。
+    async fn write(&mut self, register: Register, data: &[u8]) -> Result<(), ConfigManagerError> {
+        if data.len() > MAX_REGISTER_LEN {
+            return Err(ConfigManagerError::InvalidValue);
+        }
+        let sum = checksum(data);
+
+        for attempt in 1..=self.retry.attempts {
+            if attempt > 1 {
+                Timer::after(self.retry.retry_delay).await;
+            }
+            // 数据和校验和分两次写入，中途掉电时两者不匹配
+            let written = match self.storage.write(register as u16, data).await {
+                Ok(()) => {
+                    Timer::after(self.retry.write_cycle).await;
+                    self.storage
+                        .write(register.checksum_address(), &[sum])
+                        .await
+                }
+                Err(()) => Err(()),
+            };
+            if written.is_err() {
+                defmt::warn!(
+                    "EEPROM write to {=u8:#x} failed (attempt {}/{})",
+                    register as u8,
+                    attempt,
+                    self.retry.attempts
+                );
+                continue;
+            }
+
+            Timer::after(self.retry.write_cycle).await;
+            let mut readback = [0u8; MAX_REGISTER_LEN];
+            let readback = &mut readback[..data.len()];
+            let mut stored_sum = [0u8; 1];
+            let read = match self.storage.read(register as u16, readback).await {
+                Ok(()) => {
+                    self.storage
+                        .read(register.checksum_address(), &mut stored_sum)
+                        .await
+                }
+                Err(()) => Err(()),
+            };
+            match read {
+                Ok(()) if readback == data && stored_sum[0] == sum => return Ok(()),
+                Ok(()) => defmt::warn!(
+                    "EEPROM readback of {=u8:#x} mismatched (attempt {}/{})",
+                    register as u8,
+                    attempt,
+                    self.retry.attempts
+                ),
+                Err(()) => defmt::warn!(
+                    "EEPROM readback of {=u8:#x} failed (attempt {}/{})",
+                    register as u8,
+                    attempt,
+                    self.retry.attempts
+                ),
+            }
+        }
+
+        defmt::error!(
+            "EEPROM write to {=u8:#x} gave up after {} attempts",
+            register as u8,
+            self.retry.attempts
+        );
+        Err(ConfigManagerError::I2CError)
     }
 
     pub async fn read_target_voltage(&mut self) -> Result<ElectricPotential, ConfigManagerError> {
@@ -58,9 +259,8 @@ impl ConfigManager {
 
         let value = u32::from_be_bytes(data);
 
-        Ok(ElectricPotential::new::<millivolt>(
-            value.clamp(3000, 48_000),
-        ))
+        let (min, max) = TARGET_VOLTAGE_RANGE_MV;
+        Ok(ElectricPotential::new::<millivolt>(value.clamp(min, max)))
     }
 
     pub async fn write_target_voltage(
@@ -78,7 +278,8 @@ impl ConfigManager {
 
         let value = u32::from_be_bytes(data);
 
-        Ok(ElectricCurrent::new::<milliampere>(value.clamp(100, 5_000)))
+        let (min, max) = TARGET_CURRENT_RANGE_MA;
+        Ok(ElectricCurrent::new::<milliampere>(value.clamp(min, max)))
     }
 
     pub async fn write_target_current(
@@ -90,6 +291,170 @@ impl ConfigManager {
             .await
     }
 
+    pub async fn read_min_voltage(&mut self) -> Result<ElectricPotential, ConfigManagerError> {
+        let mut data = [0u8; 4];
+        self.read(Register::MinVoltage, &mut data).await?;
+
+        let value = u32::from_be_bytes(data);
+
+        Ok(ElectricPotential::new::<millivolt>(value.min(48_000)))
+    }
+
+    pub async fn write_min_voltage(
+        &mut self,
+        voltage: ElectricPotential,
+    ) -> Result<(), ConfigManagerError> {
+        let target_voltage = self.read_target_voltage().await?;
+        validate_min_voltage(voltage, target_voltage)?;
+
+        let value = voltage.get::<millivolt>();
+        self.write(Register::MinVoltage, &value.to_be_bytes()).await
+    }
+
+    pub async fn read_request_strategy(&mut self) -> Result<RequestStrategy, ConfigManagerError> {
+        let mut data = [0u8; 4];
+        self.read(Register::RequestStrategy, &mut data).await?;
+
+        let value = u32::from_be_bytes(data);
+
+        // 无法识别的值（如未写入过的 EEPROM）退回默认策略
+        Ok(RequestStrategy::try_from(value).unwrap_or(RequestStrategy::HighestPower))
+    }
+
+    pub async fn write_request_strategy(
+        &mut self,
+        strategy: RequestStrategy,
+    ) -> Result<(), ConfigManagerError> {
+        self.write(Register::RequestStrategy, &strategy.to_raw().to_be_bytes())
+            .await
+    }
+
+    /// 读取闲置自动待机超时
+    ///
+    /// 该槽位晚于其它配置加入，旧版本写入的 EEPROM 中为空白，校验和不匹配时使用默认值。
+    pub async fn read_idle_standby(&mut self) -> Result<Duration, ConfigManagerError> {
+        if !self.slot_intact(Register::IdleStandby).await? {
+            return Ok(DEFAULT_IDLE_STANDBY);
+        }
+        let mut data = [0u8; 4];
+        self.read(Register::IdleStandby, &mut data).await?;
+
+        let seconds = u32::from_be_bytes(data).min(IDLE_STANDBY_MAX_S);
+        Ok(Duration::from_secs(seconds as u64))
+    }
+
+    pub async fn write_idle_standby(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<(), ConfigManagerError> {
+        let seconds = validate_idle_standby(timeout)?;
+        self.write(Register::IdleStandby, &seconds.to_be_bytes())
+            .await
+    }
+
+    /// 读取上电恢复策略，空白或损坏的槽位视为不恢复
+    pub async fn read_boot_restore(&mut self) -> Result<BootRestore, ConfigManagerError> {
+        if !self.slot_intact(Register::BootRestore).await? {
+            return Ok(BootRestore::Off);
+        }
+        let mut data = [0u8; 4];
+        self.read(Register::BootRestore, &mut data).await?;
+
+        Ok(BootRestore::try_from(u32::from_be_bytes(data)).unwrap_or(BootRestore::Off))
+    }
+
+    pub async fn write_boot_restore(
+        &mut self,
+        restore: BootRestore,
+    ) -> Result<(), ConfigManagerError> {
+        self.write(Register::BootRestore, &restore.to_raw().to_be_bytes())
+            .await
+    }
+
+    /// 读取上次保存的运行状态，空白或损坏时返回 `None`（按待机启动）
+    pub async fn read_last_state(&mut self) -> Result<Option<LastState>, ConfigManagerError> {
+        if !self.slot_intact(Register::LastState).await? {
+            return Ok(None);
+        }
+        let mut data = [0u8; 4];
+        self.read(Register::LastState, &mut data).await?;
+
+        Ok(LastState::from_raw(u32::from_be_bytes(data)))
+    }
+
+    pub async fn write_last_state(&mut self, state: LastState) -> Result<(), ConfigManagerError> {
+        self.write(Register::LastState, &state.to_raw().to_be_bytes())
+            .await
+    }
+
+    /// 读取电压校准系数，未写入过、校验和不匹配或超出范围的值回退为默认（不修正）
+    pub async fn read_voltage_calibration(
+        &mut self,
+    ) -> Result<VoltageCalibration, ConfigManagerError> {
+        for register in [
+            Register::VoutGain,
+            Register::VoutOffset,
+            Register::VinGain,
+            Register::VinOffset,
+        ] {
+            if !self.slot_intact(register).await? {
+                // 空白槽位读出 NaN，属正常情况不告警
+                if !self.read_f32(register).await?.is_nan() {
+                    defmt::warn!(
+                        "Calibration slot {=u8:#x} checksum mismatch",
+                        register as u8
+                    );
+                }
+                return Ok(VoltageCalibration::default());
+            }
+        }
+
+        let calibration = VoltageCalibration {
+            vout_gain: self.read_f32(Register::VoutGain).await?,
+            vout_offset: self.read_f32(Register::VoutOffset).await?,
+            vin_gain: self.read_f32(Register::VinGain).await?,
+            vin_offset: self.read_f32(Register::VinOffset).await?,
+        };
+        if calibration.validate().is_err() {
+            // 空白槽位读出 NaN，属正常情况不告警
+            if !calibration.vout_gain.is_nan() {
+                defmt::warn!("Stored voltage calibration invalid, using defaults");
+            }
+            return Ok(VoltageCalibration::default());
+        }
+        Ok(calibration)
+    }
+
+    pub async fn write_voltage_calibration(
+        &mut self,
+        calibration: VoltageCalibration,
+    ) -> Result<(), ConfigManagerError> {
+        calibration.validate()?;
+
+        self.write_f32(Register::VoutGain, calibration.vout_gain)
+            .await?;
+        self.write_f32(Register::VoutOffset, calibration.vout_offset)
+            .await?;
+        self.write_f32(Register::VinGain, calibration.vin_gain)
+            .await?;
+        self.write_f32(Register::VinOffset, calibration.vin_offset)
+            .await
+    }
+
+    async fn read_f32(&mut self, register: Register) -> Result<f64, ConfigManagerError> {
+        let mut data = [0u8; 4];
+        self.read(register, &mut data).await?;
+        Ok(f32::from_be_bytes(data) as f64)
+    }
+
+    async fn write_f32(
+        &mut self,
+        register: Register,
+        value: f64,
+    ) -> Result<(), ConfigManagerError> {
+        self.write(register, &(value as f32).to_be_bytes()).await
+    }
+
     pub async fn exec(&mut self, req: ConfigRequest) -> Result<(), ConfigManagerError> {
         match req {
             ConfigRequest::WriteTargetVoltage(voltage, resp) => {
@@ -100,19 +465,143 @@ impl ConfigManager {
                 let res = self.write_target_current(current).await;
                 resp.signal(res);
             }
+            ConfigRequest::WriteMinVoltage(voltage, resp) => {
+                let res = self.write_min_voltage(voltage).await;
+                resp.signal(res);
+            }
+            ConfigRequest::WriteRequestStrategy(strategy, resp) => {
+                let res = self.write_request_strategy(strategy).await;
+                resp.signal(res);
+            }
+            ConfigRequest::WriteIdleStandby(timeout, resp) => {
+                let res = self.write_idle_standby(timeout).await;
+                resp.signal(res);
+            }
+            ConfigRequest::WriteBootRestore(restore, resp) => {
+                let res = self.write_boot_restore(restore).await;
+                resp.signal(res);
+            }
+            ConfigRequest::WriteLastState(state, resp) => {
+                let res = self.write_last_state(state).await;
+                resp.signal(res);
+            }
+            ConfigRequest::WriteVoltageCalibration(calibration, resp) => {
+                let res = self.write_voltage_calibration(calibration).await;
+                resp.signal(res);
+            }
+            ConfigRequest::VerifyStored(cached, resp) => {
+                let res = self.verify_stored(&cached).await;
+                resp.signal(res);
+            }
         }
 
         Ok(())
     }
 
-    pub async fn read_config(&mut self) -> Result<Config, ConfigManagerError> {
+    /// 检查寄存器的校验和，写入中途掉电会留下不匹配的数据
+    async fn slot_intact(&mut self, register: Register) -> Result<bool, ConfigManagerError> {
+        let mut data = [0u8; MAX_REGISTER_LEN];
+        self.read(register, &mut data).await?;
+        let mut sum = [0u8; 1];
+        self.storage
+            .read(register.checksum_address(), &mut sum)
+            .await
+            .map_err(|_| ConfigManagerError::I2CError)?;
+        Ok(checksum(&data) == sum[0])
+    }
+
+    /// 读取配置，任一槽位校验和不匹配时返回 `None`
+    async fn read_stored_config(&mut self) -> Result<Option<Config>, ConfigManagerError> {
+        for register in [
+            Register::TargetVoltage,
+            Register::TargetCurrent,
+            Register::MinVoltage,
+            Register::RequestStrategy,
+        ] {
+            if !self.slot_intact(register).await? {
+                defmt::warn!("Config slot {=u8:#x} checksum mismatch", register as u8);
+                return Ok(None);
+            }
+        }
+
         let target_voltage = self.read_target_voltage().await?;
         let target_current = self.read_target_current().await?;
+        let min_voltage = self.read_min_voltage().await?;
+        let request_strategy = self.read_request_strategy().await?;
+        let idle_standby = self.read_idle_standby().await?;
+        let boot_restore = self.read_boot_restore().await?;
 
-        Ok(Config {
+        let config = Config {
             target_voltage,
             target_current,
-        })
+            min_voltage,
+            request_strategy,
+            idle_standby,
+            boot_restore,
+        };
+        config.validate()?;
+
+        Ok(Some(config))
+    }
+
+    /// 读取配置；存储内容损坏（校验和不匹配）时回退到默认配置
+    pub async fn read_config(&mut self) -> Result<Config, ConfigManagerError> {
+        match self.read_stored_config().await? {
+            Some(config) => Ok(config),
+            None => {
+                defmt::warn!("Stored config corrupted, using defaults");
+                Ok(Config::default())
+            }
+        }
+    }
+
+    /// 启动时加载配置；空白 EEPROM（全 0xFF）回退到默认配置并写入
+    pub async fn load_config(&mut self) -> Result<Config, ConfigManagerError> {
+        let mut data = [0u8; MAX_REGISTER_LEN];
+        self.read(Register::TargetVoltage, &mut data).await?;
+        if data.iter().all(|b| *b == 0xFF) {
+            defmt::info!("EEPROM is blank, writing default config");
+            self.reset_config().await?;
+            return Ok(Config::default());
+        }
+
+        self.read_config().await
+    }
+
+    /// 重新读取存储的配置并与内存中的缓存比较
+    ///
+    /// 不一致（或读取失败）时只记录告警，调用方继续使用缓存值，避免重启后才发现损坏。
+    pub async fn verify_stored(
+        &mut self,
+        cached: &Config,
+    ) -> Result<ConfigIntegrity, ConfigManagerError> {
+        self.integrity.checks += 1;
+
+        let intact = match self.read_stored_config().await {
+            Ok(Some(stored)) if stored == *cached => true,
+            Ok(None) => {
+                defmt::warn!("Stored config corrupted - keeping cached");
+                false
+            }
+            Ok(Some(stored)) => {
+                defmt::warn!(
+                    "Stored config mismatch: stored {}, cached {} - keeping cached",
+                    stored,
+                    cached
+                );
+                false
+            }
+            Err(e) => {
+                defmt::warn!("Stored config unreadable: {} - keeping cached", e);
+                false
+            }
+        };
+        if !intact {
+            self.integrity.failures += 1;
+        }
+
+        defmt::info!("Config integrity: {}", self.integrity);
+        Ok(self.integrity)
     }
 
     pub async fn reset_config(&mut self) -> Result<(), ConfigManagerError> {
@@ -120,6 +609,10 @@ impl ConfigManager {
 
         self.write_target_voltage(config.target_voltage).await?;
         self.write_target_current(config.target_current).await?;
+        self.write_min_voltage(config.min_voltage).await?;
+        self.write_request_strategy(config.request_strategy).await?;
+        self.write_idle_standby(config.idle_standby).await?;
+        self.write_boot_restore(config.boot_restore).await?;
 
         Ok(())
     }
@@ -134,21 +627,144 @@ pub enum ConfigRequest {
         ElectricCurrent,
         Arc<Signal<CriticalSectionRawMutex, Result<(), ConfigManagerError>>>,
     ),
+    WriteMinVoltage(
+        ElectricPotential,
+        Arc<Signal<CriticalSectionRawMutex, Result<(), ConfigManagerError>>>,
+    ),
+    WriteRequestStrategy(
+        RequestStrategy,
+        Arc<Signal<CriticalSectionRawMutex, Result<(), ConfigManagerError>>>,
+    ),
+    WriteIdleStandby(
+        Duration,
+        Arc<Signal<CriticalSectionRawMutex, Result<(), ConfigManagerError>>>,
+    ),
+    WriteBootRestore(
+        BootRestore,
+        Arc<Signal<CriticalSectionRawMutex, Result<(), ConfigManagerError>>>,
+    ),
+    WriteLastState(
+        LastState,
+        Arc<Signal<CriticalSectionRawMutex, Result<(), ConfigManagerError>>>,
+    ),
+    WriteVoltageCalibration(
+        VoltageCalibration,
+        Arc<Signal<CriticalSectionRawMutex, Result<(), ConfigManagerError>>>,
+    ),
+    VerifyStored(
+        Config,
+        Arc<Signal<CriticalSectionRawMutex, Result<ConfigIntegrity, ConfigManagerError>>>,
+    ),
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Config {
     pub target_voltage: ElectricPotential,
     pub target_current: ElectricCurrent,
+    /// 负载要求的最低 VBUS 电压，输出开启时低于该值立即关闭（0 表示不启用）
+    pub min_voltage: ElectricPotential,
+    /// PD 请求策略
+    pub request_strategy: RequestStrategy,
+    /// 工作状态下 VBUS 关闭且无按键操作超过该时间后自动待机（0 表示不启用）
+    pub idle_standby: Duration,
+    /// 上电后恢复上次运行状态的范围
+    pub boot_restore: BootRestore,
+}
+
+/// 上电恢复策略
+#[derive(Clone, Copy, Debug, PartialEq, defmt::Format)]
+pub enum BootRestore {
+    /// 始终以待机状态启动
+    Off,
+    /// 恢复工作/待机状态，VBUS 保持关闭
+    Mode,
+    /// 同时恢复 VBUS 开启状态（需用户明确选择）
+    ModeAndVbus,
+}
+
+impl BootRestore {
+    /// 存储及 USB 使用的编码
+    pub fn to_raw(self) -> u32 {
+        match self {
+            Self::Off => 0,
+            Self::Mode => 1,
+            Self::ModeAndVbus => 2,
+        }
+    }
+}
+
+impl TryFrom<u32> for BootRestore {
+    type Error = ();
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Off),
+            1 => Ok(Self::Mode),
+            2 => Ok(Self::ModeAndVbus),
+            _ => Err(()),
+        }
+    }
+}
+
+/// 上次保存的运行状态，用于上电恢复
+#[derive(Clone, Copy, Debug, PartialEq, defmt::Format)]
+pub struct LastState {
+    pub working: bool,
+    pub vbus_enabled: bool,
+}
+
+impl LastState {
+    /// bit0：工作状态，bit1：VBUS 开启，其余位必须为 0
+    fn to_raw(self) -> u32 {
+        self.working as u32 | (self.vbus_enabled as u32) << 1
+    }
+
+    fn from_raw(raw: u32) -> Option<Self> {
+        (raw & !0b11 == 0).then_some(Self {
+            working: raw & 0b01 != 0,
+            vbus_enabled: raw & 0b10 != 0,
+        })
+    }
+}
+
+impl Config {
+    pub fn validate(&self) -> Result<(), ConfigManagerError> {
+        validate_min_voltage(self.min_voltage, self.target_voltage)?;
+        validate_idle_standby(self.idle_standby)?;
+        Ok(())
+    }
+}
+
+/// 闲置待机超时按整秒存储，超过上限拒绝
+fn validate_idle_standby(timeout: Duration) -> Result<u32, ConfigManagerError> {
+    u32::try_from(timeout.as_secs())
+        .ok()
+        .filter(|seconds| *seconds <= IDLE_STANDBY_MAX_S)
+        .ok_or(ConfigManagerError::InvalidValue)
+}
+
+/// 电压下限必须低于目标电压（0 表示不启用下限）
+fn validate_min_voltage(
+    min_voltage: ElectricPotential,
+    target_voltage: ElectricPotential,
+) -> Result<(), ConfigManagerError> {
+    if min_voltage.get::<millivolt>() > 0 && min_voltage >= target_voltage {
+        return Err(ConfigManagerError::InvalidValue);
+    }
+    Ok(())
 }
 
 impl defmt::Format for Config {
     fn format(&self, fmt: defmt::Formatter) {
         defmt::write!(
             fmt,
-            "target: {}mV, {}mA",
+            "target: {}mV, {}mA, floor: {}mV, strategy: {}, idle standby: {}s, boot restore: {}",
             self.target_voltage.get::<millivolt>(),
-            self.target_current.get::<milliampere>()
+            self.target_current.get::<milliampere>(),
+            self.min_voltage.get::<millivolt>(),
+            self.request_strategy,
+            self.idle_standby.as_secs(),
+            self.boot_restore
         );
     }
 }
@@ -158,20 +774,70 @@ impl Default for Config {
         Config {
             target_voltage: ElectricPotential::new::<millivolt>(5000),
             target_current: ElectricCurrent::new::<milliampere>(500),
+            min_voltage: ElectricPotential::new::<millivolt>(0),
+            request_strategy: RequestStrategy::HighestPower,
+            idle_standby: DEFAULT_IDLE_STANDBY,
+            boot_restore: BootRestore::Off,
+        }
+    }
+}
+
+/// 电压测量的单板校准系数，应用方式见 `AdcCalibration`
+///
+/// 与 `Config` 分开存储，恢复默认配置不会清除校准。
+#[derive(Clone, Copy, Debug, PartialEq, defmt::Format)]
+pub struct VoltageCalibration {
+    pub vout_gain: f64,
+    pub vout_offset: f64,
+    pub vin_gain: f64,
+    pub vin_offset: f64,
+}
+
+impl VoltageCalibration {
+    pub fn validate(&self) -> Result<(), ConfigManagerError> {
+        let (min_gain, max_gain) = VOLTAGE_GAIN_RANGE;
+        let gain_ok = |gain: f64| (min_gain..=max_gain).contains(&gain);
+        let offset_ok = |offset: f64| offset.abs() <= VOLTAGE_OFFSET_LIMIT;
+        if gain_ok(self.vout_gain)
+            && gain_ok(self.vin_gain)
+            && offset_ok(self.vout_offset)
+            && offset_ok(self.vin_offset)
+        {
+            Ok(())
+        } else {
+            Err(ConfigManagerError::InvalidValue)
+        }
+    }
+}
+
+impl Default for VoltageCalibration {
+    fn default() -> Self {
+        VoltageCalibration {
+            vout_gain: 1.0,
+            vout_offset: 0.0,
+            vin_gain: 1.0,
+            vin_offset: 0.0,
         }
     }
 }
 
 pub struct ConfigAgent<'a> {
     req_tx: Sender<'a, CriticalSectionRawMutex, ConfigRequest, 1>,
-    snapshot_rx:
-        Mutex<CriticalSectionRawMutex, watch::Receiver<'a, CriticalSectionRawMutex, Config, 1>>,
+    snapshot_rx: Mutex<
+        CriticalSectionRawMutex,
+        watch::Receiver<'a, CriticalSectionRawMutex, Config, CONFIG_SNAPSHOT_RECEIVERS>,
+    >,
 }
 
 impl<'a> ConfigAgent<'a> {
     pub fn new(
         req_tx: Sender<'a, CriticalSectionRawMutex, ConfigRequest, 1>,
-        snapshot_rx: watch::Receiver<'a, CriticalSectionRawMutex, Config, 1>,
+        snapshot_rx: watch::Receiver<
+            'a,
+            CriticalSectionRawMutex,
+            Config,
+            CONFIG_SNAPSHOT_RECEIVERS,
+        >,
     ) -> Self {
         ConfigAgent {
             req_tx,
@@ -181,11 +847,11 @@ impl<'a> ConfigAgent<'a> {
 
     pub fn create(
         req_ch: &'a Channel<CriticalSectionRawMutex, ConfigRequest, 1>,
-        snapshot_ch: &'a watch::Watch<CriticalSectionRawMutex, Config, 1>,
+        snapshot_ch: &'a watch::Watch<CriticalSectionRawMutex, Config, CONFIG_SNAPSHOT_RECEIVERS>,
     ) -> Result<Self, ()> {
         Ok(ConfigAgent::new(
             req_ch.sender(),
-            snapshot_ch.receiver().unwrap(),
+            snapshot_ch.receiver().ok_or(())?,
         ))
     }
 
@@ -205,6 +871,70 @@ impl<'a> ConfigAgent<'a> {
         signal.wait().await.ok();
     }
 
+    pub async fn write_min_voltage(&self, voltage: ElectricPotential) {
+        let signal = Arc::new(Signal::new());
+        self.req_tx
+            .send(ConfigRequest::WriteMinVoltage(voltage, signal.clone()))
+            .await;
+        signal.wait().await.ok();
+    }
+
+    pub async fn write_request_strategy(&self, strategy: RequestStrategy) {
+        let signal = Arc::new(Signal::new());
+        self.req_tx
+            .send(ConfigRequest::WriteRequestStrategy(
+                strategy,
+                signal.clone(),
+            ))
+            .await;
+        signal.wait().await.ok();
+    }
+
+    /// 保存电压校准系数，重启后生效
+    pub async fn write_voltage_calibration(
+        &self,
+        calibration: VoltageCalibration,
+    ) -> Result<(), ConfigManagerError> {
+        let signal = Arc::new(Signal::new());
+        self.req_tx
+            .send(ConfigRequest::WriteVoltageCalibration(
+                calibration,
+                signal.clone(),
+            ))
+            .await;
+        signal.wait().await
+    }
+
+    /// 保存闲置自动待机超时（0 表示不启用）
+    pub async fn write_idle_standby(&self, timeout: Duration) -> Result<(), ConfigManagerError> {
+        let signal = Arc::new(Signal::new());
+        self.req_tx
+            .send(ConfigRequest::WriteIdleStandby(timeout, signal.clone()))
+            .await;
+        signal.wait().await
+    }
+
+    /// 保存上电恢复策略
+    pub async fn write_boot_restore(&self, restore: BootRestore) -> Result<(), ConfigManagerError> {
+        let signal = Arc::new(Signal::new());
+        self.req_tx
+            .send(ConfigRequest::WriteBootRestore(restore, signal.clone()))
+            .await;
+        signal.wait().await
+    }
+
+    /// 校验存储的配置与当前缓存是否一致
+    pub async fn verify_stored(&self) -> Result<ConfigIntegrity, ConfigManagerError> {
+        let signal = Arc::new(Signal::new());
+        self.req_tx
+            .send(ConfigRequest::VerifyStored(
+                self.get_cached_config(),
+                signal.clone(),
+            ))
+            .await;
+        signal.wait().await
+    }
+
     pub async fn snapshot(&self) -> Config {
         let mut rx = self.snapshot_rx.lock().await;
         rx.get().await
@@ -218,3 +948,316 @@ impl<'a> ConfigAgent<'a> {
             .unwrap_or_default()
     }
 }
+
+/// 后台定期校验存储的配置（需与 config_task 一同运行）
+pub async fn integrity_check_task(agent: ConfigAgent<'static>, interval: Duration) {
+    let mut ticker = Ticker::every(interval);
+    loop {
+        ticker.next().await;
+        if let Err(e) = agent.verify_stored().await {
+            defmt::error!("Config integrity check failed: {}", e);
+        }
+    }
+}
+
+/// 上次运行状态的保存间隔：状态变化后最多延迟该时间写入
+pub const LAST_STATE_SAVE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// 在启用上电恢复时保存系统状态与 VBUS 状态（需与 config_task 一同运行）
+///
+/// 仅在状态变化时写入以减少 EEPROM 磨损；VIN 掉电引起的待机不保存，
+/// 否则断电前的掉电过程会覆盖用户实际选择的状态。
+pub async fn last_state_task(interval: Duration) {
+    let mut ticker = Ticker::every(interval);
+    let mut saved: Option<LastState> = None;
+    loop {
+        ticker.next().await;
+
+        let restore = crate::shared::CONFIG_SNAPSHOT_CHANNEL
+            .anon_receiver()
+            .try_get()
+            .map_or(BootRestore::Off, |config| config.boot_restore);
+        if restore == BootRestore::Off {
+            continue;
+        }
+        let Some(system_state) = crate::shared::SYSTEM_STATE_CHANNEL
+            .anon_receiver()
+            .try_get()
+        else {
+            continue;
+        };
+        let browned_out = crate::shared::STANDBY_REASON_CHANNEL
+            .anon_receiver()
+            .try_get()
+            == Some(StandbyReason::Brownout);
+        let working = system_state == SystemState::Working;
+        if !working && browned_out {
+            continue;
+        }
+
+        let state = LastState {
+            working,
+            vbus_enabled: working
+                && crate::shared::VBUS_STATE_CHANNEL
+                    .anon_receiver()
+                    .try_get()
+                    .unwrap_or(false),
+        };
+        if saved == Some(state) {
+            continue;
+        }
+
+        let signal = Arc::new(Signal::new());
+        crate::shared::CONFIG_REQUEST_CHANNEL
+            .send(ConfigRequest::WriteLastState(state, signal.clone()))
+            .await;
+        match signal.wait().await {
+            Ok(()) => {
+                defmt::info!("Last state saved: {}", state);
+                saved = Some(state);
+            }
+            Err(e) => defmt::warn!("Last state save failed: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_hal_async::i2c::{ErrorKind, ErrorType, Operation};
+
+    /// 模拟 M24C64：前 `fail_writes` 次写事务返回 NACK，
+    /// 随后 `torn_writes` 次写事务只写入前一半数据（模拟写入中途掉电）
+    struct MockI2c {
+        memory: [u8; STORAGE_SIZE],
+        fail_writes: u32,
+        torn_writes: u32,
+        writes: u32,
+    }
+
+    impl MockI2c {
+        fn new(fail_writes: u32) -> Self {
+            Self {
+                memory: [0xFF; STORAGE_SIZE],
+                fail_writes,
+                torn_writes: 0,
+                writes: 0,
+            }
+        }
+    }
+
+    impl ErrorType for MockI2c {
+        type Error = ErrorKind;
+    }
+
+    impl I2c for MockI2c {
+        async fn transaction(
+            &mut self,
+            _address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), Self::Error> {
+            let mut pointer = 0usize;
+            for op in operations {
+                match op {
+                    Operation::Write(bytes) => {
+                        pointer = u16::from_be_bytes([bytes[0], bytes[1]]) as usize;
+                        let data = &bytes[2..];
+                        if data.is_empty() {
+                            continue;
+                        }
+                        self.writes += 1;
+                        if self.writes <= self.fail_writes {
+                            return Err(ErrorKind::Other);
+                        }
+                        let len = if self.writes <= self.fail_writes + self.torn_writes {
+                            data.len() / 2
+                        } else {
+                            data.len()
+                        };
+                        self.memory[pointer..pointer + len].copy_from_slice(&data[..len]);
+                    }
+                    Operation::Read(buffer) => {
+                        buffer.copy_from_slice(&self.memory[pointer..pointer + buffer.len()]);
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    fn manager(i2c: MockI2c) -> ConfigManager<Eeprom<MockI2c>> {
+        let retry = WriteRetryConfig {
+            attempts: 3,
+            retry_delay: Duration::from_millis(1),
+            write_cycle: Duration::from_millis(1),
+        };
+        ConfigManager::with_storage(Eeprom::new(i2c, Eeprom::<MockI2c>::DEFAULT_ADDRESS), retry)
+    }
+
+    #[tokio::test]
+    async fn test_write_retries_then_succeeds() {
+        let mut config = manager(MockI2c::new(2));
+
+        let voltage = ElectricPotential::new::<millivolt>(9000);
+        assert!(config.write_target_voltage(voltage).await.is_ok());
+        // 两次失败后第三次尝试写入数据和校验和
+        assert_eq!(config.storage.i2c.writes, 4);
+
+        let memory = &config.storage.i2c.memory;
+        assert_eq!(&memory[0x00..0x04], &9000u32.to_be_bytes());
+        assert_eq!(memory[0x40], checksum(&9000u32.to_be_bytes()));
+        assert_eq!(config.read_target_voltage().await.unwrap(), voltage);
+    }
+
+    #[tokio::test]
+    async fn test_write_fails_after_exhausting_retries() {
+        let mut config = manager(MockI2c::new(3));
+
+        let current = ElectricCurrent::new::<milliampere>(2000);
+        assert!(matches!(
+            config.write_target_current(current).await,
+            Err(ConfigManagerError::I2CError)
+        ));
+        assert_eq!(config.storage.i2c.writes, 3);
+        // 数据和校验和保持空白
+        let memory = &config.storage.i2c.memory;
+        assert!(memory[0x04..0x08].iter().all(|b| *b == 0xFF));
+        assert_eq!(memory[0x41], 0xFF);
+    }
+
+    #[tokio::test]
+    async fn test_partial_write_is_detected_and_rewritten() {
+        let mut i2c = MockI2c::new(0);
+        i2c.torn_writes = 1;
+        let mut config = manager(i2c);
+
+        let voltage = ElectricPotential::new::<millivolt>(15_000);
+        assert!(config.write_target_voltage(voltage).await.is_ok());
+        // 回读不一致触发一次重写（每次尝试写数据和校验和两个事务）
+        assert_eq!(config.storage.i2c.writes, 4);
+        assert_eq!(config.read_target_voltage().await.unwrap(), voltage);
+    }
+
+    #[tokio::test]
+    async fn test_corrupted_slot_falls_back_to_defaults() {
+        let mut config = manager(MockI2c::new(0));
+        config.load_config().await.unwrap();
+        let current = ElectricCurrent::new::<milliampere>(3000);
+        config.write_target_current(current).await.unwrap();
+
+        // 掉电导致的半写：只有前两个数据字节更新，校验和仍是旧值
+        config.storage.i2c.memory[0x04..0x06].copy_from_slice(&[0x12, 0x34]);
+        assert_eq!(config.read_config().await.unwrap(), Config::default());
+
+        let integrity = config
+            .verify_stored(&Config {
+                target_current: current,
+                ..Config::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(integrity.failures, 1);
+    }
+
+    #[tokio::test]
+    async fn test_blank_eeprom_loads_and_writes_defaults() {
+        let mut config = manager(MockI2c::new(0));
+
+        assert_eq!(config.load_config().await.unwrap(), Config::default());
+        assert_eq!(
+            &config.storage.i2c.memory[0x00..0x04],
+            &5000u32.to_be_bytes()
+        );
+
+        let voltage = ElectricPotential::new::<millivolt>(12_000);
+        config.write_target_voltage(voltage).await.unwrap();
+        // 已初始化的 EEPROM 读回存储值而非默认值
+        assert_eq!(config.load_config().await.unwrap().target_voltage, voltage);
+    }
+
+    #[tokio::test]
+    async fn test_idle_standby_persists_and_defaults_on_blank_slot() {
+        let mut config = manager(MockI2c::new(0));
+
+        // 旧版本写入的 EEPROM：其它槽位有效，闲置待机槽位空白
+        config
+            .write_target_voltage(ElectricPotential::new::<millivolt>(9000))
+            .await
+            .unwrap();
+        assert_eq!(
+            config.read_idle_standby().await.unwrap(),
+            DEFAULT_IDLE_STANDBY
+        );
+
+        config
+            .write_idle_standby(Duration::from_secs(0))
+            .await
+            .unwrap();
+        assert_eq!(
+            config.read_idle_standby().await.unwrap(),
+            Duration::from_secs(0)
+        );
+
+        assert!(matches!(
+            config
+                .write_idle_standby(Duration::from_secs(IDLE_STANDBY_MAX_S as u64 + 1))
+                .await,
+            Err(ConfigManagerError::InvalidValue)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_last_state_round_trips_and_blank_restores_nothing() {
+        let mut config = manager(MockI2c::new(0));
+        assert_eq!(config.read_last_state().await.unwrap(), None);
+        assert_eq!(config.read_boot_restore().await.unwrap(), BootRestore::Off);
+
+        let state = LastState {
+            working: true,
+            vbus_enabled: true,
+        };
+        config.write_last_state(state).await.unwrap();
+        assert_eq!(config.read_last_state().await.unwrap(), Some(state));
+
+        // 未知位视为损坏，不恢复
+        assert_eq!(LastState::from_raw(0b100), None);
+    }
+
+    #[tokio::test]
+    async fn test_voltage_calibration_persists() {
+        let mut config = manager(MockI2c::new(0));
+
+        // 空白 EEPROM 读出 NaN，回退为不修正
+        assert_eq!(
+            config.read_voltage_calibration().await.unwrap(),
+            VoltageCalibration::default()
+        );
+
+        let calibration = VoltageCalibration {
+            vout_gain: 1.0125,
+            vout_offset: -0.05,
+            vin_gain: 0.995,
+            vin_offset: 0.02,
+        };
+        config.write_voltage_calibration(calibration).await.unwrap();
+        let stored = config.read_voltage_calibration().await.unwrap();
+        assert!((stored.vout_gain - calibration.vout_gain).abs() < 1e-6);
+        assert!((stored.vin_offset - calibration.vin_offset).abs() < 1e-6);
+
+        // 掉电导致的半写：VinGain 低位字节更新但校验和仍是旧值，数值仍在合法范围内
+        config.storage.i2c.memory[0x1A..0x1C].copy_from_slice(&[0x12, 0x34]);
+        assert_eq!(
+            config.read_voltage_calibration().await.unwrap(),
+            VoltageCalibration::default()
+        );
+
+        let bad = VoltageCalibration {
+            vout_gain: 2.0,
+            ..calibration
+        };
+        assert!(matches!(
+            config.write_voltage_calibration(bad).await,
+            Err(ConfigManagerError::InvalidValue)
+        ));
+    }
+}
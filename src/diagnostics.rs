@@ -0,0 +1,189 @@
+use uom::si::{electric_current::milliampere, electric_potential::millivolt};
+
+use crate::{
+    power::{self, PdStatus},
+    shared::{
+        CONFIG_SNAPSHOT_CHANNEL, STANDBY_REASON_CHANNEL, TELEMETRY_CHANNEL, VBUS_STATE_CHANNEL,
+    },
+    thermal::{self, ThermalStatus},
+    usb::BUILD_INFO,
+};
+
+/// Upper bound of a bundle, streamed to the host as 64-byte packets
+pub const MAX_BUNDLE_SIZE: usize = 512;
+
+const MAGIC: [u8; 4] = *b"SKDG";
+const FORMAT_VERSION: u8 = 1;
+const HEADER_LEN: usize = 6;
+const SECTION_HEADER_LEN: usize = 3;
+
+/// Section ids of the diagnostic bundle
+///
+/// Ids 0x10..=0x12 are reserved for the measurement, state-change and event
+/// histories; the firmware keeps no such history yet, so they never appear.
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+#[repr(u8)]
+pub enum Section {
+    /// Length-prefixed strings, see `BuildInfo::write_to`
+    BuildInfo = 0x01,
+    /// Active and latched fault flags (u32 LE each)
+    Faults = 0x02,
+    /// PD status, standby reason (0xFF if none), thermal status, VBUS enabled
+    Status = 0x03,
+    /// VBUS V, VIN V, current A, temperature °C (f32 LE each)
+    Telemetry = 0x04,
+    /// Target mV, target mA, minimum mV, request strategy (u32 LE each)
+    Config = 0x05,
+}
+
+/// Writer for the diagnostic bundle container
+///
+/// Layout: magic `SKDG`, format version (u8), section count (u8), then
+/// sections of `id (u8), length (u16 LE), payload`. A section that does not
+/// fit is dropped whole, so a bundle always parses.
+pub struct BundleWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> BundleWriter<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        buf[..4].copy_from_slice(&MAGIC);
+        buf[4] = FORMAT_VERSION;
+        buf[5] = 0;
+        Self {
+            buf,
+            len: HEADER_LEN,
+        }
+    }
+
+    /// Append a section, returns `false` if it was dropped for lack of space
+    pub fn section(&mut self, id: Section, payload: &[u8]) -> bool {
+        let start = self.len + SECTION_HEADER_LEN;
+        if start + payload.len() > self.buf.len() {
+            defmt::warn!("Diagnostic section {:?} dropped, bundle full", id);
+            return false;
+        }
+        self.buf[self.len] = id as u8;
+        self.buf[self.len + 1..start].copy_from_slice(&(payload.len() as u16).to_le_bytes());
+        self.buf[start..start + payload.len()].copy_from_slice(payload);
+        self.len = start + payload.len();
+        self.buf[5] += 1;
+        true
+    }
+
+    /// Total bundle length
+    pub fn finish(self) -> usize {
+        self.len
+    }
+}
+
+fn pd_status_code(status: PdStatus) -> u8 {
+    match status {
+        PdStatus::Detached => 0,
+        PdStatus::Attached(_) => 1,
+        PdStatus::Negotiated => 2,
+        PdStatus::NegotiationFailed => 3,
+    }
+}
+
+fn thermal_status_code(status: ThermalStatus) -> u8 {
+    match status {
+        ThermalStatus::Normal => 0,
+        ThermalStatus::Shutdown => 1,
+        ThermalStatus::Recovering => 2,
+    }
+}
+
+/// Capture build info, faults, status, telemetry and config into `buf`
+///
+/// Sections whose data is not available yet (no telemetry, no config
+/// snapshot) are omitted. Returns the bundle length.
+pub fn collect(buf: &mut [u8]) -> usize {
+    let mut bundle = BundleWriter::new(buf);
+
+    let mut build = [0u8; 128];
+    let n = BUILD_INFO.write_to(&mut build);
+    bundle.section(Section::BuildInfo, &build[..n]);
+
+    let faults = crate::fault::faults();
+    let mut payload = [0u8; 8];
+    payload[..4].copy_from_slice(&faults.active.0.to_le_bytes());
+    payload[4..].copy_from_slice(&faults.latched.0.to_le_bytes());
+    bundle.section(Section::Faults, &payload);
+
+    let standby_reason = STANDBY_REASON_CHANNEL
+        .anon_receiver()
+        .try_get()
+        .map_or(0xFF, |reason| reason as u8);
+    let vbus_enabled = VBUS_STATE_CHANNEL
+        .anon_receiver()
+        .try_get()
+        .unwrap_or(false);
+    bundle.section(
+        Section::Status,
+        &[
+            pd_status_code(power::pd_status()),
+            standby_reason,
+            thermal_status_code(thermal::thermal_status()),
+            vbus_enabled as u8,
+        ],
+    );
+
+    if let Some(snapshot) = TELEMETRY_CHANNEL.anon_receiver().try_get() {
+        let values = snapshot.streamed();
+        let mut payload = [0u8; 16];
+        for (i, value) in [
+            values.vbus_voltage,
+            values.vin_voltage,
+            values.output_current,
+            values.temperature,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            payload[i * 4..i * 4 + 4].copy_from_slice(&(value as f32).to_le_bytes());
+        }
+        bundle.section(Section::Telemetry, &payload);
+    }
+
+    if let Some(config) = CONFIG_SNAPSHOT_CHANNEL.anon_receiver().try_get() {
+        let mut payload = [0u8; 16];
+        for (i, value) in [
+            config.target_voltage.get::<millivolt>(),
+            config.target_current.get::<milliampere>(),
+            config.min_voltage.get::<millivolt>(),
+            config.request_strategy.to_raw(),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            payload[i * 4..i * 4 + 4].copy_from_slice(&value.to_le_bytes());
+        }
+        bundle.section(Section::Config, &payload);
+    }
+
+    bundle.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundle_framing_drops_oversized_section() {
+        let mut buf = [0u8; 16];
+        let mut bundle = BundleWriter::new(&mut buf);
+
+        assert!(bundle.section(Section::Status, &[1, 2, 3, 4]));
+        // 6 header + 7 status bytes used, 3 left: only an empty section fits
+        assert!(!bundle.section(Section::Faults, &[0; 8]));
+        assert!(bundle.section(Section::Config, &[]));
+        let len = bundle.finish();
+
+        assert_eq!(len, 16);
+        assert_eq!(&buf[..6], &[b'S', b'K', b'D', b'G', FORMAT_VERSION, 2]);
+        assert_eq!(&buf[6..13], &[0x03, 4, 0, 1, 2, 3, 4]);
+        assert_eq!(&buf[13..16], &[0x05, 0, 0]);
+    }
+}
@@ -1,13 +1,26 @@
-use crate::shared::{
-    CURRENT_FAN_RPM, FAN_MAX_DETECTION_TIME_MS, FAN_PULSES_PER_REVOLUTION, FAN_TIMER_FREQ_HZ,
-    MAX_FAN_RPM,
+use crate::{
+    bus::TopicReceiver,
+    fault::{self, Fault},
+    shared::{
+        FAN_CURVE_CHANNEL, FAN_MAX_DETECTION_TIME_MS, FAN_PULSES_PER_REVOLUTION,
+        FAN_TACH_TIMEOUT_MS, FAN_TIMER_FREQ_HZ, MAX_FAN_RPM, MEASUREMENTS,
+    },
+    thermal,
 };
 use defmt_rtt as _;
 use embassy_stm32::{
-    gpio::Output, gpio::Pull, peripherals::TIM3, time::Hertz, timer::pwm_input::PwmInput, Peri,
+    gpio::Pull,
+    peripherals::{TIM2, TIM3},
+    time::Hertz,
+    timer::{pwm_input::PwmInput, simple_pwm::SimplePwm, Channel},
+    Peri,
+};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, watch};
+use embassy_time::{Duration, Instant, Timer};
+use embedded_hal_02::Pwm;
+use uom::si::{
+    f64::ThermodynamicTemperature, power::watt, thermodynamic_temperature::degree_celsius,
 };
-use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, watch::Receiver};
-use embassy_time::{Instant, Timer};
 
 /// Fan manager state
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -16,21 +29,229 @@ enum FanManagerState {
     NormalOperation, // Normal operation phase
 }
 
+/// Weighting of the fan demand between heatsink temperature and output power
+///
+/// Demand is `temperature_weight * temperature_demand + power_weight * power_demand`:
+/// - temperature demand is 0 at the low threshold and 1 at the high threshold
+/// - power demand is 0 at `power_floor` and 1 at `power_full_scale`, clamped
+///
+/// The fan starts when demand reaches 1 and stops when it falls to 0. Setting
+/// `power_weight` to zero gives the plain 50°C/45°C temperature hysteresis.
+/// While running, the duty follows the demand from `min_duty` up to 100%.
+#[derive(Debug, Clone, Copy)]
+pub struct FanProfile {
+    pub temperature_weight: f64,
+    pub power_weight: f64,
+    /// Output power (W) below which power adds no demand
+    pub power_floor: f64,
+    /// Output power (W) at which power demand saturates
+    pub power_full_scale: f64,
+    /// Lowest running duty (%), kept high enough for the fan to keep spinning
+    pub min_duty: u8,
+    /// Time the fan may read 0 RPM while driven before it is reported stalled
+    pub stall_timeout: Duration,
+    /// Hold the commanded duty as a fraction of `MAX_FAN_RPM`; `None` drives the duty open-loop
+    pub rpm_gains: Option<RpmGains>,
+}
+
+impl Default for FanProfile {
+    fn default() -> Self {
+        Self {
+            temperature_weight: 1.0,
+            power_weight: 1.0,
+            power_floor: 20.0,
+            power_full_scale: 100.0,
+            min_duty: 30,
+            stall_timeout: Duration::from_secs(10),
+            rpm_gains: Some(RpmGains::default()),
+        }
+    }
+}
+
+impl FanProfile {
+    /// Combined fan demand for a temperature (°C) and output power (W)
+    fn demand(&self, temperature: f64, power: f64) -> f64 {
+        let temperature_demand = ((temperature - FanManager::LOW_TEMP_THRESHOLD)
+            / (FanManager::HIGH_TEMP_THRESHOLD - FanManager::LOW_TEMP_THRESHOLD))
+            .max(0.0);
+        let power_demand = ((power - self.power_floor)
+            / (self.power_full_scale - self.power_floor))
+            .clamp(0.0, 1.0);
+
+        self.temperature_weight * temperature_demand + self.power_weight * power_demand
+    }
+
+    /// Running duty (%) for a demand, linear from `min_duty` at 0 to 100% at 1
+    fn duty(&self, demand: f64) -> u8 {
+        let min_duty = self.min_duty.min(100) as f64;
+        (min_duty + (100.0 - min_duty) * demand.clamp(0.0, 1.0)) as u8
+    }
+}
+
+/// PI gains for holding a target fan RPM
+///
+/// Gains are in duty % per RPM of error; the integral term accumulates once per
+/// `FanManager::tick`.
+#[derive(Debug, Clone, Copy)]
+pub struct RpmGains {
+    pub kp: f64,
+    pub ki: f64,
+}
+
+impl Default for RpmGains {
+    fn default() -> Self {
+        Self {
+            kp: 0.01,
+            ki: 0.005,
+        }
+    }
+}
+
+/// PI loop correcting a feed-forward duty from the measured RPM
+#[derive(Debug, Default)]
+struct RpmController {
+    integral: f64,
+}
+
+impl RpmController {
+    /// Duty (%) that moves `rpm` towards `target_rpm`
+    ///
+    /// The output is clamped to `min_duty..=100` (or down to `feed_forward` if
+    /// that is lower). The integral only grows while the output is unsaturated or
+    /// the error pulls it back inside, so a saturated fan does not wind it up.
+    fn update(
+        &mut self,
+        gains: RpmGains,
+        feed_forward: u8,
+        target_rpm: f64,
+        rpm: f64,
+        min_duty: u8,
+    ) -> u8 {
+        let error = target_rpm - rpm;
+        let low = min_duty.min(feed_forward) as f64;
+        let proportional = feed_forward as f64 + gains.kp * error;
+
+        let candidate = proportional + self.integral + gains.ki * error;
+        let saturated_high = candidate >= 100.0 && error > 0.0;
+        let saturated_low = candidate <= low && error < 0.0;
+        if !saturated_high && !saturated_low {
+            self.integral += gains.ki * error;
+        }
+
+        ((proportional + self.integral).clamp(low, 100.0) + 0.5) as u8
+    }
+
+    fn reset(&mut self) {
+        self.integral = 0.0;
+    }
+}
+
+/// Maximum RPM learned during the startup test, the 100%-duty reference
+///
+/// Returns 0 until the detection phase of `fan_speed_sampling_task` completes.
+pub async fn max_fan_rpm() -> u32 {
+    *MAX_FAN_RPM.lock().await
+}
+
+/// User-defined temperature→duty curve
+///
+/// Up to `MAX_POINTS` `(temperature_c, duty_percent)` breakpoints with strictly
+/// increasing temperatures. Duty is interpolated linearly between breakpoints
+/// and clamped to the first/last duty outside them; 0% turns the fan off.
+/// A curve with no breakpoints selects the built-in `FanProfile` control.
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub struct FanCurve {
+    pub points: [(f32, u8); FanCurve::MAX_POINTS],
+    /// Number of valid entries in `points`
+    pub len: u8,
+}
+
+impl FanCurve {
+    pub const MAX_POINTS: usize = 4;
+
+    /// Build a curve from breakpoints, `None` if they are malformed
+    pub fn new(breakpoints: &[(f32, u8)]) -> Option<Self> {
+        if breakpoints.len() > Self::MAX_POINTS {
+            return None;
+        }
+        let mut points = [(0.0, 0); Self::MAX_POINTS];
+        points[..breakpoints.len()].copy_from_slice(breakpoints);
+        let curve = Self {
+            points,
+            len: breakpoints.len() as u8,
+        };
+        curve.is_valid().then_some(curve)
+    }
+
+    pub fn breakpoints(&self) -> &[(f32, u8)] {
+        &self.points[..(self.len as usize).min(Self::MAX_POINTS)]
+    }
+
+    /// Breakpoints fit, temperatures are finite and strictly increasing,
+    /// duties are percentages
+    pub fn is_valid(&self) -> bool {
+        if self.len as usize > Self::MAX_POINTS {
+            return false;
+        }
+        let points = self.breakpoints();
+        points
+            .iter()
+            .all(|(temperature, duty)| temperature.is_finite() && *duty <= 100)
+            && points.windows(2).all(|pair| pair[0].0 < pair[1].0)
+    }
+
+    /// Duty (%) at `temperature` (°C), `None` for an empty curve
+    fn duty(&self, temperature: f32) -> Option<u8> {
+        let points = self.breakpoints();
+        let &(first_temperature, first_duty) = points.first()?;
+        let &(last_temperature, last_duty) = points.last()?;
+        if temperature <= first_temperature {
+            return Some(first_duty);
+        }
+        if temperature >= last_temperature {
+            return Some(last_duty);
+        }
+
+        points.windows(2).find_map(|pair| {
+            let ((t0, d0), (t1, d1)) = (pair[0], pair[1]);
+            (temperature <= t1).then(|| {
+                let fraction = (temperature - t0) / (t1 - t0);
+                (d0 as f32 + (d1 as f32 - d0 as f32) * fraction + 0.5) as u8
+            })
+        })
+    }
+}
+
 /// Fan manager
 ///
-/// Responsible for automatically controlling fan on/off based on temperature, implementing 5°C hysteresis control:
-/// - First 5 seconds after startup: fan test run
-/// - Temperature ≥ 50°C: start fan
-/// - Temperature ≤ 45°C: stop fan
-/// - 5°C hysteresis prevents frequent switching
+/// Responsible for automatically controlling the fan PWM based on temperature and output power:
+/// - First 5 seconds after startup: fan test run at full duty
+/// - Fan demand ≥ 1: start fan (temperature alone: ≥ 50°C)
+/// - Fan demand ≤ 0: stop fan (temperature alone: ≤ 45°C)
+/// - The demand band gives 5°C hysteresis and prevents frequent switching
+/// - While running, duty ramps with demand between `FanProfile::min_duty` and 100%
+/// - Output power raises demand ahead of the heatsink temperature, see `FanProfile`
+/// - A `FanCurve` (initial or from `FAN_CURVE_CHANNEL`) replaces the above with
+///   a direct temperature→duty mapping; thermal protection still forces 100%
+/// - Driven but reading 0 RPM for `FanProfile::stall_timeout` raises `Fault::FanStall`
+/// - With `FanProfile::rpm_gains`, the duty becomes an RPM target (that fraction of
+///   `max_fan_rpm`) held by a PI loop; once no tach edge arrives for
+///   `FAN_TACH_TIMEOUT_MS` the speed reads 0 and it runs open-loop
 pub struct FanManager<'d> {
-    fan_pin: Output<'d>,
-    temperature_rx: Receiver<'d, CriticalSectionRawMutex, f64, 1>,
-    current_temperature: f64,
+    fan_pwm: SimplePwm<'d, TIM2>,
+    duty_percent: u8,
+    temperature_rx: TopicReceiver<'d, ThermodynamicTemperature>,
+    profile: FanProfile,
+    curve: Option<FanCurve>,
+    curve_rx: watch::Receiver<'d, CriticalSectionRawMutex, FanCurve, 1>,
     fan_enabled: bool,
-    tick_counter: u32,
     state: FanManagerState,
     startup_time: Instant,
+    /// When the fan was first seen driven at 0 RPM
+    stalled_since: Option<Instant>,
+    stalled: bool,
+    rpm_controller: RpmController,
+    closed_loop: bool,
 }
 
 impl<'d> FanManager<'d> {
@@ -43,39 +264,95 @@ impl<'d> FanManager<'d> {
     /// Temperature anomaly detection threshold (°C) - exceeding this temperature may indicate sensor failure
     const TEMP_ANOMALY_THRESHOLD: f64 = 100.0;
 
+    /// PB10 FAN_PWM2 is TIM2 channel 3
+    const FAN_CHANNEL: Channel = Channel::Ch3;
+
     /// Create new fan manager
     ///
     /// # Parameters
-    /// - `fan_pin`: Fan control GPIO pin (PB10)
+    /// - `fan_pwm`: Fan PWM timer with channel 3 on PB10
     /// - `temperature_rx`: Temperature data receiver
+    /// - `profile`: Temperature/power weighting and minimum duty
+    /// - `curve`: Optional user curve used instead of `profile`
+    /// - `curve_rx`: Runtime curve updates from `FAN_CURVE_CHANNEL`
     pub fn new(
-        mut fan_pin: Output<'d>,
-        temperature_rx: Receiver<'d, CriticalSectionRawMutex, f64, 1>,
+        mut fan_pwm: SimplePwm<'d, TIM2>,
+        temperature_rx: TopicReceiver<'d, ThermodynamicTemperature>,
+        profile: FanProfile,
+        curve: Option<FanCurve>,
+        curve_rx: watch::Receiver<'d, CriticalSectionRawMutex, FanCurve, 1>,
     ) -> Self {
         defmt::info!("🌀 Fan Manager initialized");
         defmt::info!("   High temp threshold: {}°C", Self::HIGH_TEMP_THRESHOLD);
         defmt::info!("   Low temp threshold: {}°C", Self::LOW_TEMP_THRESHOLD);
+        defmt::info!(
+            "   Power weight: {} ({}W..{}W)",
+            profile.power_weight,
+            profile.power_floor,
+            profile.power_full_scale
+        );
+        defmt::info!("   Minimum duty: {}%", profile.min_duty);
         defmt::info!("   Starting 5-second fan test...");
 
-        // Startup test: immediately start fan
-        fan_pin.set_high();
-
-        Self {
-            fan_pin,
+        fan_pwm.enable(Self::FAN_CHANNEL);
+        let mut manager = Self {
+            fan_pwm,
+            duty_percent: 0,
             temperature_rx,
-            current_temperature: 25.0, // Assume initial room temperature
-            fan_enabled: true,         // Fan enabled during startup test
-            tick_counter: 0,
+            profile,
+            curve: None,
+            curve_rx,
+            fan_enabled: true, // Fan enabled during startup test
             state: FanManagerState::StartupTest,
             startup_time: Instant::now(),
+            stalled_since: None,
+            stalled: false,
+            rpm_controller: RpmController::default(),
+            closed_loop: false,
+        };
+        if let Some(curve) = curve {
+            manager.apply_curve(curve);
         }
+        // Startup test: immediately start fan at full speed
+        manager.set_fan_duty(100);
+        manager
+    }
+
+    /// Switch to `curve`, keeping the previous one if it is malformed
+    fn apply_curve(&mut self, curve: FanCurve) {
+        if !curve.is_valid() {
+            defmt::warn!(
+                "🌀 Rejected malformed fan curve {}, keeping previous",
+                curve
+            );
+            return;
+        }
+        if curve.len == 0 {
+            defmt::info!("🌀 Fan curve cleared, using built-in profile");
+            self.curve = None;
+        } else {
+            defmt::info!("🌀 Fan curve set: {}", curve.breakpoints());
+            self.curve = Some(curve);
+        }
+    }
+
+    /// Drive the fan PWM at `percent` duty (clamped to 100)
+    pub fn set_fan_duty(&mut self, percent: u8) {
+        let percent = percent.min(100);
+        let duty = self.fan_pwm.get_max_duty() * percent as u32 / 100;
+        self.fan_pwm.set_duty(Self::FAN_CHANNEL, duty);
+        self.duty_percent = percent;
     }
 
     /// Execute one fan management check
     ///
     /// Should be called every 5 seconds, synchronized with ADC sampling frequency
     pub async fn tick(&mut self) {
-        self.tick_counter += 1;
+        if let Some(curve) = self.curve_rx.try_changed() {
+            self.apply_curve(curve);
+        }
+
+        self.check_stall();
 
         match self.state {
             FanManagerState::StartupTest => {
@@ -88,7 +365,7 @@ impl<'d> FanManager<'d> {
                         elapsed.as_secs()
                     );
                     self.state = FanManagerState::NormalOperation;
-                    self.fan_pin.set_low(); // Turn off fan
+                    self.set_fan_duty(0); // Turn off fan
                     self.fan_enabled = false;
                     defmt::info!("🛑 Fan DISABLED after startup test");
                 } else {
@@ -99,7 +376,7 @@ impl<'d> FanManager<'d> {
             FanManagerState::NormalOperation => {
                 // Normal operation phase: control fan based on temperature
                 if let Some(temperature) = self.temperature_rx.try_get() {
-                    self.current_temperature = temperature;
+                    let temperature = temperature.get::<degree_celsius>();
 
                     // Check for temperature anomaly
                     if temperature > Self::TEMP_ANOMALY_THRESHOLD {
@@ -115,47 +392,131 @@ impl<'d> FanManager<'d> {
                     // Update fan state
                     self.update_fan_state(temperature).await;
                 }
+            }
+        }
+    }
 
-                // Periodic status report (once per minute, i.e., 12 five-second cycles)
-                if self.tick_counter % 12 == 0 {
-                    defmt::info!(
-                        "🌡️ Temperature: {}°C, Fan: {}",
-                        self.current_temperature,
-                        if self.fan_enabled { "ON" } else { "OFF" }
-                    );
-                }
+    /// Report the fan stalled once it reads 0 RPM while driven for the stall timeout
+    ///
+    /// Published as `Fault::FanStall`, which lowers the thermal shutdown threshold.
+    fn check_stall(&mut self) {
+        let now = Instant::now();
+        let rpm = MEASUREMENTS.fan_rpm.latest();
+        // No tachometer reading yet is not a stall; a stopped fan reads 0 once
+        // no edge is captured for FAN_TACH_TIMEOUT_MS
+        let stalled = if self.duty_percent == 0 || rpm != Some(0) {
+            self.stalled_since = None;
+            false
+        } else {
+            let since = *self.stalled_since.get_or_insert(now);
+            now - since >= self.profile.stall_timeout
+        };
+
+        if stalled != self.stalled {
+            self.stalled = stalled;
+            if stalled {
+                defmt::error!(
+                    "🌀 Fan stalled: 0 RPM at {}% duty for {}s",
+                    self.duty_percent,
+                    self.profile.stall_timeout.as_secs()
+                );
+            } else {
+                defmt::info!("🌀 Fan stall cleared");
             }
+            fault::set_active(Fault::FanStall, stalled);
         }
     }
 
-    /// Update fan state based on temperature
+    /// Correct `duty` so the fan holds the same fraction of `max_fan_rpm`
     ///
-    /// Implement 5°C hysteresis control logic
+    /// Falls back to the open-loop duty while the maximum RPM is unknown or the
+    /// tachometer reads 0, which the sampling task reports once no edge has been
+    /// captured for `FAN_TACH_TIMEOUT_MS`; a lost tach never holds the last speed.
+    async fn closed_loop_duty(&mut self, duty: u8) -> u8 {
+        let Some(gains) = self.profile.rpm_gains else {
+            return duty;
+        };
+        let max_rpm = max_fan_rpm().await;
+        let rpm = MEASUREMENTS.fan_rpm.latest().unwrap_or(0);
+        let closed_loop = duty > 0 && max_rpm > 0 && rpm > 0;
+
+        if closed_loop != self.closed_loop {
+            self.closed_loop = closed_loop;
+            if closed_loop {
+                defmt::info!("🌀 Fan RPM control engaged (max {} RPM)", max_rpm);
+            } else if duty > 0 {
+                defmt::warn!("🌀 Fan tach signal lost, running open-loop at {}%", duty);
+            }
+        }
+        if !closed_loop {
+            self.rpm_controller.reset();
+            return duty;
+        }
+
+        let target_rpm = max_rpm as f64 * duty as f64 / 100.0;
+        self.rpm_controller
+            .update(gains, duty, target_rpm, rpm as f64, self.profile.min_duty)
+    }
+
+    /// Update fan state based on temperature and output power
+    ///
+    /// Implement hysteresis control logic on the combined demand
     async fn update_fan_state(&mut self, temperature: f64) {
-        let should_enable = if self.fan_enabled {
-            // Fan currently on, only turn off when temperature drops below 45°C
-            temperature > Self::LOW_TEMP_THRESHOLD
+        let power = match (
+            MEASUREMENTS.vbus_voltage.latest(),
+            MEASUREMENTS.output_current.latest(),
+        ) {
+            (Some(voltage), Some(current)) => (voltage * current).get::<watt>(),
+            _ => 0.0,
+        };
+        let demand = self.profile.demand(temperature, power);
+        let curve_duty = self.curve.and_then(|curve| curve.duty(temperature as f32));
+
+        let protecting = thermal::thermal_status().is_protecting();
+        let (should_enable, duty) = if protecting {
+            // Full speed throughout thermal shutdown and its recovery window
+            (true, 100)
+        } else if let Some(duty) = curve_duty {
+            // User curve maps temperature straight to duty
+            (duty > 0, duty)
+        } else if self.fan_enabled {
+            // Fan currently on, only turn off once demand drops to zero
+            let enable = demand > 0.0;
+            (enable, if enable { self.profile.duty(demand) } else { 0 })
         } else {
-            // Fan currently off, only turn on when temperature reaches 50°C or above
-            temperature >= Self::HIGH_TEMP_THRESHOLD
+            // Fan currently off, only turn on once demand reaches one
+            let enable = demand >= 1.0;
+            (enable, if enable { self.profile.duty(demand) } else { 0 })
         };
 
-        // Only update hardware and logs when state changes
+        let duty = if protecting {
+            // Thermal protection always drives the fan flat out
+            self.rpm_controller.reset();
+            duty
+        } else {
+            self.closed_loop_duty(duty).await
+        };
+
+        if duty != self.duty_percent {
+            self.set_fan_duty(duty);
+        }
+
+        // Only log when the on/off state changes
         if should_enable != self.fan_enabled {
             self.fan_enabled = should_enable;
 
             if should_enable {
-                self.fan_pin.set_high();
                 defmt::info!(
-                    "🌀 Fan ENABLED at {}°C (threshold: {}°C)",
+                    "🌀 Fan ENABLED at {}°C, {}W (threshold: {}°C)",
                     temperature,
+                    power,
                     Self::HIGH_TEMP_THRESHOLD
                 );
             } else {
-                self.fan_pin.set_low();
                 defmt::info!(
-                    "🛑 Fan DISABLED at {}°C (threshold: {}°C)",
+                    "🛑 Fan DISABLED at {}°C, {}W (threshold: {}°C)",
                     temperature,
+                    power,
                     Self::LOW_TEMP_THRESHOLD
                 );
             }
@@ -190,6 +551,32 @@ fn calculate_rpm(period_ticks: u32) -> u32 {
     rpm
 }
 
+/// Tachometer reading that drops to 0 RPM once the fan stops producing edges
+///
+/// The capture register keeps the last period after the fan stops, so a
+/// stopped fan would otherwise keep reporting its old speed.
+#[derive(Default)]
+struct TachReading {
+    rpm: u32,
+    last_capture: Option<Instant>,
+}
+
+impl TachReading {
+    /// Update with the RPM of a new capture, or `None` when no edge was captured
+    fn update(&mut self, now: Instant, captured_rpm: Option<u32>) -> u32 {
+        if let Some(rpm) = captured_rpm {
+            self.rpm = rpm;
+            self.last_capture = Some(now);
+        } else if self
+            .last_capture
+            .is_none_or(|at| now - at >= Duration::from_millis(FAN_TACH_TIMEOUT_MS))
+        {
+            self.rpm = 0;
+        }
+        self.rpm
+    }
+}
+
 /// Fan speed sampling task
 ///
 /// This task is responsible for:
@@ -216,12 +603,18 @@ pub async fn fan_speed_sampling_task(
     let start_time = Instant::now();
     let mut max_rpm_detected = 0u32;
     let mut sample_count = 0u32;
-    let mut log_counter = 0u32;
+    let mut max_rpm_saved = false;
+    let mut tach = TachReading::default();
 
     loop {
-        // Get period count and calculate speed
+        // CC1IF is set by every captured tach edge and cleared by reading CCR1,
+        // so it tells whether the period below is new since the last sample
+        let captured = embassy_stm32::pac::TIM3.sr().read().ccif(0);
         let period_ticks = pwm_input.get_period_ticks();
-        let current_rpm = calculate_rpm(period_ticks);
+        let current_rpm = tach.update(
+            Instant::now(),
+            captured.then(|| calculate_rpm(period_ticks)),
+        );
 
         sample_count += 1;
 
@@ -237,11 +630,8 @@ pub async fn fan_speed_sampling_task(
             }
         } else if sample_count > 0 && elapsed_ms >= FAN_MAX_DETECTION_TIME_MS {
             // Detection phase just ended, save maximum speed (execute only once)
-            static mut MAX_RPM_SAVED: bool = false;
-            if unsafe { !MAX_RPM_SAVED } {
-                unsafe {
-                    MAX_RPM_SAVED = true;
-                }
+            if !max_rpm_saved {
+                max_rpm_saved = true;
                 // Save maximum speed to global variable
                 *MAX_FAN_RPM.lock().await = max_rpm_detected;
                 defmt::info!(
@@ -253,24 +643,91 @@ pub async fn fan_speed_sampling_task(
         }
 
         // Update current speed to global variable
-        CURRENT_FAN_RPM.sender().send(current_rpm);
-
-        // Output speed log once per second (10 cycles of 100ms)
-        log_counter += 1;
-        if log_counter >= 10 {
-            log_counter = 0;
-            if is_max_detection_phase {
-                defmt::info!(
-                    "🌀 Fan RPM: {} (Max detection phase: {}ms remaining)",
-                    current_rpm,
-                    FAN_MAX_DETECTION_TIME_MS - elapsed_ms
-                );
-            } else {
-                defmt::info!("🌀 Fan RPM: {}", current_rpm);
-            }
-        }
+        MEASUREMENTS.fan_rpm.publish(current_rpm);
 
         // 100ms sampling interval
         Timer::after_millis(100).await;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fan_demand_weights() {
+        let temperature_only = FanProfile {
+            power_weight: 0.0,
+            ..FanProfile::default()
+        };
+        assert_eq!(temperature_only.demand(45.0, 150.0), 0.0);
+        assert_eq!(temperature_only.demand(50.0, 0.0), 1.0);
+
+        // Full-scale power alone starts the fan while the heatsink is still cool
+        let profile = FanProfile::default();
+        assert_eq!(profile.demand(25.0, 100.0), 1.0);
+        assert_eq!(profile.demand(47.5, 60.0), 1.0);
+        assert_eq!(profile.demand(25.0, 10.0), 0.0);
+    }
+
+    #[test]
+    fn test_fan_duty_ramps_from_minimum() {
+        let profile = FanProfile::default();
+        assert_eq!(profile.duty(0.0), 30);
+        assert_eq!(profile.duty(0.5), 65);
+        assert_eq!(profile.duty(1.0), 100);
+        // Combined demand can exceed one; duty saturates at full speed
+        assert_eq!(profile.duty(1.8), 100);
+    }
+
+    #[test]
+    fn test_fan_curve_interpolates_and_rejects_malformed() {
+        let curve = FanCurve::new(&[(40.0, 0), (50.0, 40), (70.0, 100)]).unwrap();
+        assert_eq!(curve.duty(20.0), Some(0));
+        assert_eq!(curve.duty(45.0), Some(20));
+        assert_eq!(curve.duty(60.0), Some(70));
+        assert_eq!(curve.duty(90.0), Some(100));
+        assert_eq!(FanCurve::new(&[]).unwrap().duty(50.0), None);
+
+        // Temperatures must strictly increase and duties stay within 100%
+        assert_eq!(FanCurve::new(&[(50.0, 40), (50.0, 60)]), None);
+        assert_eq!(FanCurve::new(&[(60.0, 40), (50.0, 60)]), None);
+        assert_eq!(FanCurve::new(&[(50.0, 120)]), None);
+        assert_eq!(FanCurve::new(&[(30.0, 0); 5]), None);
+    }
+
+    #[test]
+    fn test_rpm_controller_tracks_and_avoids_windup() {
+        let gains = RpmGains::default();
+        let mut controller = RpmController::default();
+
+        // Fan slower than the target: duty rises above the feed-forward
+        let duty = controller.update(gains, 50, 2000.0, 1800.0, 30);
+        assert!(duty > 50);
+
+        // Saturated at full duty with the fan unable to keep up
+        for _ in 0..100 {
+            assert_eq!(controller.update(gains, 90, 4000.0, 3000.0, 30), 100);
+        }
+        // Once the target is reached the duty leaves saturation immediately
+        assert!(controller.update(gains, 90, 3000.0, 3200.0, 30) < 100);
+
+        controller.reset();
+        assert_eq!(controller.update(gains, 40, 1000.0, 1000.0, 30), 40);
+        // Never below the minimum running duty
+        assert_eq!(controller.update(gains, 40, 1000.0, 9000.0, 30), 30);
+    }
+
+    #[test]
+    fn test_tach_reading_drops_to_zero_without_captures() {
+        let mut tach = TachReading::default();
+        let t0 = Instant::from_millis(0);
+        assert_eq!(tach.update(t0, None), 0);
+        assert_eq!(tach.update(t0, Some(1800)), 1800);
+
+        // A missed sample keeps the last speed until the timeout
+        assert_eq!(tach.update(t0 + Duration::from_millis(100), None), 1800);
+        assert_eq!(tach.update(t0 + Duration::from_millis(200), None), 0);
+        assert_eq!(tach.update(t0 + Duration::from_millis(300), Some(900)), 900);
+    }
+}
@@ -0,0 +1,119 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// Faults tracked by the central fault registry
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+#[repr(u8)]
+pub enum Fault {
+    /// Source attached but no PD contract within the negotiation timeout
+    PdNegotiation = 0,
+    /// VBUS dropped below the configured voltage floor while enabled
+    VoltageFloor = 1,
+    /// VBUS and VIN measurements inconsistent with the converter topology
+    TrackingMismatch = 2,
+    /// VBUS did not reach the target voltage within the rise timeout
+    OutputNoRise = 3,
+    /// VBUS enable refused after repeated failed attempts; cleared only by reset
+    EnableLockout = 4,
+    /// Repeated ADC conversion failures, measurements are stale
+    AdcFailure = 5,
+    /// Die temperature above the thermal shutdown threshold, output forced off
+    Overtemperature = 6,
+    /// Measured PD input deviates from the contract voltage, contract presumed lost
+    ContractDeviation = 7,
+    /// VBUS above the overvoltage ceiling; active until cleared by a short press
+    Overvoltage = 8,
+    /// Fan commanded on but its tachometer reads 0 RPM for the stall timeout
+    FanStall = 9,
+    /// Output current above the configured limit beyond the trip time
+    Overcurrent = 10,
+}
+
+impl Fault {
+    const fn bit(self) -> u32 {
+        1 << self as u8
+    }
+}
+
+/// Set of faults, serialized as a little-endian `u32` bitmask
+#[derive(Debug, Clone, Copy, PartialEq, Default, defmt::Format)]
+pub struct FaultFlags(pub u32);
+
+impl FaultFlags {
+    pub fn contains(self, fault: Fault) -> bool {
+        self.0 & fault.bit() != 0
+    }
+}
+
+/// Snapshot of the fault registry
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub struct FaultStatus {
+    /// Conditions currently present
+    pub active: FaultFlags,
+    /// Faults that tripped since the last successful clear
+    pub latched: FaultFlags,
+}
+
+/// Reason a clear request was refused
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum ClearFaultsError {
+    /// The underlying conditions of these latched faults are still present
+    ConditionPersists(FaultFlags),
+}
+
+static ACTIVE: AtomicU32 = AtomicU32::new(0);
+static LATCHED: AtomicU32 = AtomicU32::new(0);
+
+/// Record a fault trip: marks it latched until cleared
+pub fn raise(fault: Fault) {
+    let previous = LATCHED.fetch_or(fault.bit(), Ordering::SeqCst);
+    if previous & fault.bit() == 0 {
+        defmt::warn!("Fault latched: {:?}", fault);
+    }
+}
+
+/// Update whether the condition behind `fault` is currently present
+///
+/// Becoming active also latches the fault.
+pub fn set_active(fault: Fault, active: bool) {
+    if active {
+        ACTIVE.fetch_or(fault.bit(), Ordering::SeqCst);
+        raise(fault);
+    } else {
+        ACTIVE.fetch_and(!fault.bit(), Ordering::SeqCst);
+    }
+}
+
+/// Forget every active and latched fault, for tests that share the globals
+#[cfg(test)]
+pub fn reset() {
+    ACTIVE.store(0, Ordering::SeqCst);
+    LATCHED.store(0, Ordering::SeqCst);
+}
+
+/// Current active and latched faults
+pub fn faults() -> FaultStatus {
+    FaultStatus {
+        active: FaultFlags(ACTIVE.load(Ordering::SeqCst)),
+        latched: FaultFlags(LATCHED.load(Ordering::SeqCst)),
+    }
+}
+
+/// Clear all latched faults
+///
+/// Refused if any latched fault's condition is still present; nothing is
+/// cleared in that case.
+pub fn clear_faults() -> Result<(), ClearFaultsError> {
+    let status = faults();
+    let persisting = status.latched.0 & status.active.0;
+    if persisting != 0 {
+        defmt::warn!(
+            "Refusing to clear faults, conditions persist: {:?}",
+            FaultFlags(persisting)
+        );
+        return Err(ClearFaultsError::ConditionPersists(FaultFlags(persisting)));
+    }
+
+    LATCHED.fetch_and(!status.latched.0, Ordering::SeqCst);
+    defmt::info!("Latched faults cleared: {:?}", status.latched);
+    Ok(())
+}
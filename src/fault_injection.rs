@@ -0,0 +1,43 @@
+use core::sync::atomic::{AtomicU8, Ordering};
+
+/// Fault simulated by overriding a measurement
+///
+/// Only available with the `fault-injection` feature. The override replaces
+/// the measurement feeding a protection, so the full response (output
+/// disable, LEDs, fault flags, recovery) runs with real timing.
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+#[repr(u8)]
+pub enum SimulatedFault {
+    None = 0,
+    /// Die temperature reads 5°C above the thermal shutdown threshold
+    Overtemperature = 1,
+    /// VBUS reads 0V in the VBUS manager (voltage floor and rise checks)
+    Undervoltage = 2,
+}
+
+impl TryFrom<u8> for SimulatedFault {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::None),
+            1 => Ok(Self::Overtemperature),
+            2 => Ok(Self::Undervoltage),
+            _ => Err(()),
+        }
+    }
+}
+
+static SIMULATED: AtomicU8 = AtomicU8::new(SimulatedFault::None as u8);
+
+pub fn set_simulated(fault: SimulatedFault) {
+    match fault {
+        SimulatedFault::None => defmt::warn!("Simulated fault cleared"),
+        _ => defmt::error!("!!! SIMULATED FAULT ACTIVE: {:?} !!!", fault),
+    }
+    SIMULATED.store(fault as u8, Ordering::SeqCst);
+}
+
+pub fn is_simulated(fault: SimulatedFault) -> bool {
+    SIMULATED.load(Ordering::SeqCst) == fault as u8
+}
@@ -0,0 +1,104 @@
+use embassy_stm32::{
+    gpio::Output,
+    peripherals::TIM1,
+    timer::{simple_pwm::SimplePwm, Channel},
+};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex};
+use embassy_time::Duration;
+use embedded_hal_02::Pwm;
+
+use crate::{power_output::PowerOutput, vbus_manager::VbusLedColor};
+
+/// 数字输出引脚抽象接口（电源开关、双色 LED 等）
+/// 用于让管理器在测试中使用模拟引脚
+pub trait SwitchPin {
+    fn set_high(&mut self);
+    fn set_low(&mut self);
+}
+
+/// LED PWM 通道抽象接口（电源 LED 亮度）
+pub trait LedPwm {
+    fn max_duty(&self) -> u32;
+    fn set_duty(&mut self, duty: u32);
+}
+
+/// 双色 LED 抽象接口（VBUS LED），亮度为 0~100%，0 表示熄灭
+pub trait BicolorLed {
+    fn set_pwm(&mut self, color: VbusLedColor, brightness: u8);
+}
+
+/// 电源通路开关抽象接口（VBUS 输出、VIN 输入）
+pub trait OutputSwitch {
+    async fn set_on(&self);
+    async fn set_off(&self);
+
+    /// 软启动开启，在 `ramp` 时间内限制浪涌；不支持的开关直接开启
+    async fn set_on_soft(&self, _ramp: Duration) {
+        self.set_on().await
+    }
+
+    /// 关闭后主动泄放输出电容，返回泄放耗时；无泄放电路或超时返回 None
+    async fn discharge(&self, _timeout: Duration) -> Option<Duration> {
+        None
+    }
+}
+
+impl SwitchPin for Output<'_> {
+    fn set_high(&mut self) {
+        Output::set_high(self)
+    }
+
+    fn set_low(&mut self) {
+        Output::set_low(self)
+    }
+}
+
+/// 普通 GPIO 无法调光：只要亮度非 0 即全亮，琥珀色按红色输出，熄灭时输出低电平
+impl<P: SwitchPin> BicolorLed for P {
+    fn set_pwm(&mut self, color: VbusLedColor, brightness: u8) {
+        match color {
+            VbusLedColor::Red | VbusLedColor::Amber if brightness > 0 => self.set_high(),
+            _ => self.set_low(),
+        }
+    }
+}
+
+/// PA8 电源 LED 固定使用 TIM1 通道 1
+impl LedPwm for SimplePwm<'_, TIM1> {
+    fn max_duty(&self) -> u32 {
+        self.get_max_duty()
+    }
+
+    fn set_duty(&mut self, duty: u32) {
+        Pwm::set_duty(self, Channel::Ch1, duty)
+    }
+}
+
+impl OutputSwitch for PowerOutput<'_> {
+    async fn set_on(&self) {
+        PowerOutput::set_on(self).await
+    }
+
+    async fn set_off(&self) {
+        PowerOutput::set_off(self).await
+    }
+
+    async fn set_on_soft(&self, ramp: Duration) {
+        PowerOutput::set_on_soft(self, ramp).await
+    }
+
+    async fn discharge(&self, timeout: Duration) -> Option<Duration> {
+        PowerOutput::discharge(self, timeout).await
+    }
+}
+
+/// 直接由 GPIO 控制的开关（如 PA15 VIN_EN），高电平导通
+impl<P: SwitchPin> OutputSwitch for Mutex<CriticalSectionRawMutex, P> {
+    async fn set_on(&self) {
+        self.lock().await.set_high();
+    }
+
+    async fn set_off(&self) {
+        self.lock().await.set_low();
+    }
+}
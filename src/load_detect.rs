@@ -0,0 +1,120 @@
+use embassy_time::{Duration, Instant};
+
+/// Output load status derived from measured VBUS current
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum LoadStatus {
+    /// VBUS output disabled
+    Off,
+    /// VBUS enabled but no current measurement available yet
+    Unknown,
+    /// VBUS enabled, current essentially zero
+    NoLoad,
+    /// VBUS enabled and a load is drawing current
+    Active,
+}
+
+/// Load detection thresholds
+#[derive(Debug, Clone, Copy)]
+pub struct LoadDetectConfig {
+    /// Current (A) at or above which the output is considered loaded
+    pub load_on_current: f64,
+    /// Current (A) at or below which the output is considered unloaded
+    pub load_off_current: f64,
+    /// Time a crossing must persist before the status flips
+    pub debounce: Duration,
+}
+
+impl Default for LoadDetectConfig {
+    fn default() -> Self {
+        Self {
+            load_on_current: 0.050,
+            load_off_current: 0.020,
+            debounce: Duration::from_millis(300),
+        }
+    }
+}
+
+/// Debounced hysteresis detector for "is a load drawing current"
+pub struct LoadDetector {
+    config: LoadDetectConfig,
+    loaded: bool,
+    crossing_since: Option<Instant>,
+}
+
+impl LoadDetector {
+    pub fn new(config: LoadDetectConfig) -> Self {
+        Self {
+            config,
+            loaded: false,
+            crossing_since: None,
+        }
+    }
+
+    /// Current debounced decision
+    pub fn is_loaded(&self) -> bool {
+        self.loaded
+    }
+
+    /// Feed a current sample, returns the debounced decision
+    pub fn update(&mut self, current: f64, now: Instant) -> bool {
+        let crossing = if self.loaded {
+            current <= self.config.load_off_current
+        } else {
+            current >= self.config.load_on_current
+        };
+
+        if !crossing {
+            self.crossing_since = None;
+            return self.loaded;
+        }
+
+        let since = *self.crossing_since.get_or_insert(now);
+        if now - since >= self.config.debounce {
+            self.loaded = !self.loaded;
+            self.crossing_since = None;
+        }
+        self.loaded
+    }
+
+    /// Forget the previous decision (e.g. when the output is switched off)
+    pub fn reset(&mut self) {
+        self.loaded = false;
+        self.crossing_since = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(ms: u64) -> Instant {
+        Instant::from_millis(ms)
+    }
+
+    #[test]
+    fn test_load_requires_sustained_current() {
+        let mut detector = LoadDetector::new(LoadDetectConfig::default());
+
+        assert!(!detector.update(0.5, at(0)));
+        assert!(!detector.update(0.5, at(200)));
+        // A short dip restarts the debounce window
+        assert!(!detector.update(0.0, at(250)));
+        assert!(!detector.update(0.5, at(300)));
+        assert!(detector.update(0.5, at(600)));
+    }
+
+    #[test]
+    fn test_hysteresis_band_holds_state() {
+        let mut detector = LoadDetector::new(LoadDetectConfig::default());
+        detector.update(0.5, at(0));
+        detector.update(0.5, at(300));
+        assert!(detector.is_loaded());
+
+        // Inside the hysteresis band (20mA..50mA) the state holds
+        assert!(detector.update(0.030, at(400)));
+        assert!(detector.update(0.030, at(1000)));
+
+        assert!(detector.update(0.0, at(1100)));
+        assert!(!detector.update(0.0, at(1400)));
+    }
+}
@@ -1,20 +1,27 @@
 #![no_std]
 #![no_main]
 
-use adc_reader::{AdcCalibration, AdcReader};
+use adc_reader::{AdcCalibration, AdcReader, EmaAlphas};
 use alloc::sync::Arc;
-use app_manager::{PowerManager, PowerManagerContext};
+use app_manager::{
+    BreathingTarget, OperatingMode, PowerManager, PowerManagerConfig, PowerManagerContext,
+    StandbyReason,
+};
 use button::InputManager;
-use config_manager::ConfigManager;
-use vbus_manager::{VbusManager, VbusManagerContext};
+use config_manager::{ConfigAgent, ConfigManager};
+use vbus_led::SoftPwmLed;
+use vbus_manager::{VbusManager, VbusManagerConfig, VbusManagerContext};
 
 use core::{
+    alloc::{GlobalAlloc, Layout},
     mem::MaybeUninit,
     ptr::{read_volatile, write_volatile},
 };
 use defmt_rtt as _;
 
+use embassy_embedded_hal::shared_bus::asynch::i2c::I2cDevice;
 use embassy_executor::Spawner;
+use embassy_futures::select::{select, Either};
 use embassy_stm32::{
     adc::{
         vals::{Rovsm, Trovs},
@@ -29,8 +36,12 @@ use embassy_stm32::{
     timer::simple_pwm::{PwmPin, SimplePwm},
     timer::Channel,
     ucpd::{self},
+    wdg::IndependentWatchdog,
+};
+use embassy_sync::{
+    blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex, pubsub::PubSubBehavior,
+    watch::Receiver as WatchReceiver,
 };
-use embassy_sync::{mutex::Mutex, pubsub::PubSubBehavior};
 use embassy_time::Duration;
 use embedded_alloc::LlffHeap as Heap;
 use embedded_hal_02::Pwm;
@@ -38,20 +49,41 @@ use embedded_hal_02::Pwm;
 use panic_probe as _;
 use power::PowerInput;
 use power_output::PowerOutput;
+use power_rail::{OutputTable, OutputTableError, PowerRail, RailId, SafeStateCheck};
 use shared::*;
 use static_cell::StaticCell;
 use types::*;
+use uom::si::{electric_current::ampere, electric_potential::volt};
 
 mod adc_reader;
 mod app_manager;
+mod bus;
 mod button;
 mod config_manager;
+mod diagnostics;
 mod fan_manager;
+mod fault;
+#[cfg(feature = "fault-injection")]
+mod fault_injection;
+mod hal;
+mod load_detect;
+mod monitor;
+#[cfg(any(feature = "pd-trace", test))]
+#[cfg_attr(not(feature = "pd-trace"), allow(dead_code))]
+mod pd_trace;
+mod peak;
 mod power;
 mod power_output;
+mod power_rail;
 mod shared;
+mod source_health;
+mod status;
+mod system;
+mod telemetry;
+mod thermal;
 mod types;
 mod usb;
+mod vbus_led;
 mod vbus_manager;
 
 mod tests;
@@ -64,6 +96,17 @@ const VREFINT_DATA_ADDR: *mut u16 = 0x1FFF75AA as *mut u16;
 
 const ADC_READER_BUF_SIZE: usize = 8; // Minimum buffer size
 
+/// ADC sampling interval; fast enough for protection and live telemetry.
+/// The EMA alphas are rescaled so filtering keeps its default time constants.
+const ADC_SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Settle time after init before inputs and protections become active.
+/// Gives the INA186 and the analog front-end time to stabilize; outputs stay off.
+const STARTUP_SETTLE_DELAY: Duration = Duration::from_millis(500);
+
+/// Button-driven interactive operation, or always-on for fixed installations
+const OPERATING_MODE: OperatingMode = OperatingMode::Interactive;
+
 #[allow(dead_code)]
 static I2C_BUS_MUTEX: StaticCell<SharedI2cBus> = StaticCell::new();
 static mut ADC_READER: MaybeUninit<AdcReader<'static, ADC_READER_BUF_SIZE>> = MaybeUninit::uninit();
@@ -72,21 +115,171 @@ static POWER_OUTPUT: StaticCell<MaybeUninit<PowerOutput>> = StaticCell::new();
 
 extern crate alloc;
 
+/// Heap that shuts the outputs off instead of returning an allocation failure
+///
+/// Stable Rust has no `#[alloc_error_handler]`, so the allocator itself
+/// diverts into `system::out_of_memory` before the default handler can panic
+/// with VBUS possibly still enabled.
+struct SafeHeap(Heap);
+
+unsafe impl GlobalAlloc for SafeHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.0.alloc(layout);
+        if ptr.is_null() {
+            system::out_of_memory(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.0.dealloc(ptr, layout)
+    }
+}
+
 #[global_allocator]
-static HEAP: Heap = Heap::empty();
+static HEAP: SafeHeap = SafeHeap(Heap::empty());
 
 // This marks the entrypoint of our application.
 bind_interrupts!(
     struct Irqs {
         UCPD1 => ucpd::InterruptHandler<peripherals::UCPD1>;
         USB_LP => embassy_stm32::usb::InterruptHandler<peripherals::USB>;
-        I2C3_EV => i2c::EventInterruptHandler<peripherals::I2C3>;
-        I2C3_ER => i2c::ErrorInterruptHandler<peripherals::I2C3>;
+        I2C2_EV => i2c::EventInterruptHandler<peripherals::I2C2>;
+        I2C2_ER => i2c::ErrorInterruptHandler<peripherals::I2C2>;
     }
 );
 
+/// Initialization stage that failed, reported by LED blink count
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+enum InitError {
+    /// A watch channel or measurement topic has no free receiver slot
+    Receiver(&'static str),
+    /// The input event channel has no free subscriber slot
+    InputSubscriber(&'static str),
+    /// A task could not be spawned (pool exhausted)
+    Spawn(&'static str),
+    /// A rail still measures energized with its switch driven off
+    RailEnergized(RailId),
+    /// The ADC could not read the rails to confirm they are off
+    RailUnverified,
+    /// The power manager's output table violates the power sequencing rules
+    OutputTable(OutputTableError),
+    /// The PD input is configured with a CC termination the hardware cannot present
+    CcTermination(power::UnsupportedCcTermination),
+}
+
+impl InitError {
+    /// Number of VBUS LED blinks identifying the failure
+    fn blink_code(self) -> u8 {
+        match self {
+            Self::Receiver(_) => 2,
+            Self::InputSubscriber(_) => 3,
+            Self::Spawn(_) => 4,
+            Self::RailEnergized(_) => 5,
+            Self::RailUnverified => 6,
+            Self::OutputTable(_) => 7,
+            Self::CcTermination(_) => 8,
+        }
+    }
+}
+
+/// State handed from `init()` to the main loop
+struct AppContext {
+    power_manager: PowerManager<'static>,
+    vbus_manager: VbusManager<'static>,
+    vbus_state_rx: WatchReceiver<'static, CriticalSectionRawMutex, bool, 1>,
+    reboot_rx: WatchReceiver<'static, CriticalSectionRawMutex, bool, 1>,
+    config_rx: WatchReceiver<
+        'static,
+        CriticalSectionRawMutex,
+        config_manager::Config,
+        CONFIG_SNAPSHOT_RECEIVERS,
+    >,
+}
+
 #[embassy_executor::main]
 async fn main(spawner: Spawner) {
+    let AppContext {
+        mut power_manager,
+        mut vbus_manager,
+        mut vbus_state_rx,
+        mut reboot_rx,
+        mut config_rx,
+    } = match init(spawner).await {
+        Ok(app) => app,
+        Err(e) => {
+            defmt::error!("Initialization failed: {}", e);
+            system::boot_failure_loop(e.blink_code()).await
+        }
+    };
+
+    defmt::info!("Entering main loop");
+    system::feed();
+
+    // Get voltage listeners
+    let measurements = &shared::MEASUREMENTS;
+
+    loop {
+        // Handle controlled reboot: VBUS off first, then VIN, then reset
+        if reboot_rx.try_get() == Some(true) {
+            vbus_manager.force_disable().await;
+            power_manager.enter_standby(StandbyReason::Reboot).await;
+            embassy_time::Timer::after_millis(50).await;
+            system::reboot();
+        }
+
+        // Apply config changes made at runtime (e.g. over USB)
+        if let Some(config) = config_rx.try_changed() {
+            power_manager.set_idle_standby(config.idle_standby);
+        }
+        if let Ok((target, breathing)) = shared::LED_BREATHING_CHANNEL.try_receive() {
+            match target {
+                BreathingTarget::Standby => power_manager.set_standby_breathing(breathing),
+                BreathingTarget::Fault => power_manager.set_fault_breathing(breathing),
+            }
+        }
+
+        // Get latest voltage and status information. The managers' protection
+        // timings are sized for the unfiltered 100ms samples; the EMA (τ ≈ 40s)
+        // would delay a rise or a brownout by seconds.
+        let vbus_voltage = measurements
+            .vbus_voltage_raw
+            .latest()
+            .map_or(0.0, |v| v.get::<volt>());
+        let vin_voltage = measurements
+            .vin_voltage_raw
+            .latest()
+            .map_or(0.0, |v| v.get::<volt>());
+
+        // VBUS status is a latest-value watch, read it every iteration
+        let current_vbus_enabled = vbus_state_rx.try_get().unwrap_or(false);
+
+        // Update VbusManager voltage information
+        vbus_manager.update_voltages(vbus_voltage);
+        vbus_manager.update_system_state(power_manager.system_state);
+
+        // Execute VbusManager tick
+        vbus_manager.tick().await;
+
+        // Update PowerManager voltage information (for monitoring and LED display only)
+        power_manager.update_voltages(vin_voltage, current_vbus_enabled);
+
+        // Execute PowerManager tick
+        power_manager.tick().await;
+
+        // Pet the watchdog once per completed iteration
+        system::feed();
+
+        // Add small delay to avoid excessive CPU usage
+        embassy_time::Timer::after_millis(1).await;
+    }
+}
+
+/// Bring up peripherals, managers and tasks in order
+///
+/// Everything up to the main loop happens here, so a failure at any stage
+/// returns an error instead of panicking midway through the sequence.
+async fn init(spawner: Spawner) -> Result<AppContext, InitError> {
     // Initialize the allocator BEFORE you use it
     {
         use core::mem::MaybeUninit;
@@ -94,7 +287,7 @@ async fn main(spawner: Spawner) {
         static mut HEAP_MEM: [MaybeUninit<u8>; HEAP_SIZE] = [MaybeUninit::uninit(); HEAP_SIZE];
         #[allow(static_mut_refs)]
         unsafe {
-            HEAP.init(HEAP_MEM.as_mut_ptr() as usize, HEAP_SIZE)
+            HEAP.0.init(HEAP_MEM.as_mut_ptr() as usize, HEAP_SIZE)
         }
     }
 
@@ -116,11 +309,48 @@ async fn main(spawner: Spawner) {
         config.rcc.mux.adc12sel = mux::Adcsel::SYS;
         config.rcc.sys = Sysclk::PLL1_R;
         config.rcc.mux.clk48sel = mux::Clk48sel::HSI48;
-        // config.enable_ucpd1_dead_battery = true;
+        // Dead-battery Rd on CC1/CC2 (PA9/PA10 DBCC) stays active from reset until
+        // the UCPD driver takes over, so a source will provide vSafe5V to an
+        // unpowered board. Without it the CC lines float until firmware runs and
+        // sources that require Rd before enabling VBUS never power the board.
+        config.enable_ucpd1_dead_battery = cfg!(feature = "dead-battery");
     }
     let p = embassy_stm32::init(config);
     defmt::info!("STM32 initialized successfully");
 
+    // Drive both power switches off before the PD stack starts, so the handoff
+    // from dead-battery power to a negotiated contract never enables an output
+    // PA15: VIN_CE (input control enable)
+    let mut vin_ce_pin = Output::new(p.PA15, Level::Low, Speed::Low);
+    defmt::info!("VIN_CE pin PA15 configured");
+
+    // PB7: VBUS_EN (VBUS control enable) - USB-C power output switch control
+    let vbus_en_pin = Output::new(p.PB7, Level::Low, Speed::Low);
+    defmt::info!("VBUS_EN pin PB7 configured");
+
+    if cfg!(feature = "dead-battery") {
+        defmt::info!("UCPD1 dead-battery terminations enabled");
+    }
+
+    defmt::info!(
+        "Firmware v{} ({}), built at {}",
+        usb::BUILD_INFO.version,
+        usb::BUILD_INFO.git_hash,
+        usb::BUILD_INFO.build_timestamp
+    );
+
+    let watchdog_reset = system::take_watchdog_reset();
+    let clean_shutdown = system::take_clean_shutdown();
+    let boot_reason = if watchdog_reset {
+        defmt::warn!("Previous reset was caused by a watchdog");
+        StandbyReason::WatchdogRecovery
+    } else if clean_shutdown {
+        defmt::info!("Previous reset was a controlled reboot");
+        StandbyReason::Reboot
+    } else {
+        StandbyReason::PowerOn
+    };
+
     unsafe {
         write_volatile(VREFBUF_CSR_ADDR, 0x0000_0021_u32);
     }
@@ -133,6 +363,7 @@ async fn main(spawner: Spawner) {
         power_button,
         Duration::from_millis(50),
         Duration::from_millis(1000),
+        None, // No extra pin-level debounce
     );
     defmt::info!("Input manager created");
 
@@ -140,21 +371,88 @@ async fn main(spawner: Spawner) {
     defmt::info!("Input manager initialized");
     let input_manager = unsafe { input_mgr.assume_init_mut() };
 
-    // Temporarily skip I2C initialization to simplify debugging
-    defmt::info!("Skipping I2C initialization for debugging");
+    // PC4/PF0: I2C2 SCL/SDA - M24C64 config EEPROM. Without the chip fitted every
+    // access NACKs, so the config falls back to defaults and is not persisted.
+    let mut i2c_config = i2c::Config::default();
+    i2c_config.frequency = khz(400);
+    let i2c = i2c::I2c::new(
+        p.I2C2, p.PC4, p.PF0, Irqs, p.DMA1_CH2, p.DMA1_CH3, i2c_config,
+    );
+    let i2c_bus: &'static SharedI2cBus = I2C_BUS_MUTEX.init(Mutex::new(i2c));
+    defmt::info!("I2C2 configured for the config EEPROM");
 
-    defmt::info!("Skipping motion sensor and EEPROM for debugging");
+    defmt::info!("Skipping motion sensor for debugging");
 
+    let eeprom = config_manager::Eeprom::new(
+        I2cDevice::new(i2c_bus),
+        config_manager::Eeprom::<EepromBus>::DEFAULT_ADDRESS,
+    );
+    let mut config_manager =
+        ConfigManager::with_storage(eeprom, config_manager::WriteRetryConfig::default());
+    // Without working storage the defaults apply and nothing can be persisted
+    let (app_config, storage_available) = match config_manager.load_config().await {
+        Ok(config) => (config, true),
+        Err(e) => {
+            defmt::warn!("Config load failed: {}, using defaults", e);
+            (config_manager::Config::default(), false)
+        }
+    };
     let config_snapshot_tx = CONFIG_SNAPSHOT_CHANNEL.sender();
-    config_snapshot_tx.send(Default::default());
-    defmt::info!("Using default config");
+    config_snapshot_tx.send(app_config);
+    // No source attached yet, so this only selects the policy for the first request
+    power::set_request_strategy(app_config.request_strategy).ok();
+    defmt::info!("Config loaded: {}", app_config);
+    let voltage_calibration = match config_manager.read_voltage_calibration().await {
+        Ok(calibration) => calibration,
+        Err(e) => {
+            defmt::warn!("Voltage calibration load failed: {}, using defaults", e);
+            config_manager::VoltageCalibration::default()
+        }
+    };
+    defmt::info!("Voltage calibration: {}", voltage_calibration);
+    // Restore the last state only after a normal power-up or controlled reboot,
+    // never after a watchdog reset
+    let last_state = match (app_config.boot_restore, boot_reason) {
+        (config_manager::BootRestore::Off, _) | (_, StandbyReason::WatchdogRecovery) => None,
+        _ => config_manager.read_last_state().await.ok().flatten(),
+    };
+    let restore_working = last_state.is_some_and(|state| state.working);
+    // VBUS is only restored with the explicit opt-in
+    let restore_vbus = restore_working
+        && app_config.boot_restore == config_manager::BootRestore::ModeAndVbus
+        && last_state.is_some_and(|state| state.vbus_enabled);
+    if let Some(state) = last_state {
+        defmt::info!("Last state: {}, restoring VBUS: {}", state, restore_vbus);
+    }
+    spawner
+        .spawn(config_task(config_manager))
+        .map_err(|_| InitError::Spawn("config_task"))?;
+    // Boot restore needs the EEPROM: the saved state must survive the power cycle
+    if storage_available {
+        spawner
+            .spawn(last_state_task())
+            .map_err(|_| InitError::Spawn("last_state_task"))?;
+        let agent = ConfigAgent::create(&CONFIG_REQUEST_CHANNEL, &CONFIG_SNAPSHOT_CHANNEL)
+            .map_err(|_| InitError::Receiver("config agent snapshot"))?;
+        spawner
+            .spawn(integrity_check_task(agent))
+            .map_err(|_| InitError::Spawn("integrity_check_task"))?;
+    } else {
+        defmt::warn!("Config storage unavailable, boot restore and integrity checks disabled");
+    }
 
-    // Software undervoltage protection will start after power_output creation
-    defmt::info!("Software undervoltage protection will start later");
+    // Software undervoltage protection is the VBUS manager's voltage floor check:
+    // the threshold is `Config::min_voltage`, and tripping goes through the
+    // manager's own state change, so no second task drives VBUS_EN.
 
-    let power_device = power::Device::new(SINK_REQUEST_CHANNEL.receiver().unwrap());
+    let power_device = power::Device::new(
+        SINK_REQUEST_CHANNEL
+            .receiver()
+            .ok_or(InitError::Receiver("sink request"))?,
+        power::DeviceConfig::default(),
+    );
 
-    let _sink_agent = power::SinkAgent::new(SINK_REQUEST_CHANNEL.sender());
+    let sink_agent = power::SinkAgent::new(SINK_REQUEST_CHANNEL.sender());
 
     let pd_service = PowerInput::new(
         p.UCPD1,
@@ -165,25 +463,48 @@ async fn main(spawner: Spawner) {
         p.DMA2_CH4,
         p.DMA2_CH5,
         power_device,
+        power::PowerInputConfig::default(),
         PD_ERROR_CHANNEL.sender(),
-    );
-    spawner.spawn(pd_task(pd_service)).unwrap();
+    )
+    .map_err(InitError::CcTermination)?;
+    spawner
+        .spawn(pd_task(pd_service))
+        .map_err(|_| InitError::Spawn("pd_task"))?;
+    let source_caps_pd_status_rx = PD_STATUS_CHANNEL
+        .receiver()
+        .ok_or(InitError::Receiver("source caps PD status"))?;
+    spawner
+        .spawn(source_caps_task(sink_agent, source_caps_pd_status_rx))
+        .map_err(|_| InitError::Spawn("source_caps_task"))?;
+    let source_health_pd_status_rx = PD_STATUS_CHANNEL
+        .receiver()
+        .ok_or(InitError::Receiver("source health PD status"))?;
+    spawner
+        .spawn(source_health_task(source_health_pd_status_rx))
+        .map_err(|_| InitError::Spawn("source_health_task"))?;
 
     let mut adc1 = Adc::new(p.ADC1);
+    let calfact = adc_reader::self_calibrate(embassy_stm32::pac::ADC1);
+    defmt::info!("ADC1 self-calibration factor: {}", calfact);
     adc1.set_sample_time(SampleTime::CYCLES640_5); // Keep longer sampling time
-    adc1.set_oversampling_ratio(0x07); // ratio X256
-    adc1.set_oversampling_shift(4); // shift 4
+    adc1.set_oversampling_ratio(adc_reader::OVERSAMPLING_RATIO); // ratio X256
+    adc1.set_oversampling_shift(adc_reader::OVERSAMPLING_SHIFT); // shift 4
     adc1.enable_regular_oversampling_mode(Rovsm::RESUMED, Trovs::AUTOMATIC, true);
     let mut adc2 = Adc::new(p.ADC2);
+    let calfact = adc_reader::self_calibrate(embassy_stm32::pac::ADC2);
+    defmt::info!("ADC2 self-calibration factor: {}", calfact);
     adc2.set_sample_time(SampleTime::CYCLES640_5); // Keep longer sampling time
-    adc2.set_oversampling_ratio(0x07); // ratio X256 (corrected: should be adc2)
-    adc2.set_oversampling_shift(4); // shift 4
+    adc2.set_oversampling_ratio(adc_reader::OVERSAMPLING_RATIO); // ratio X256
+    adc2.set_oversampling_shift(adc_reader::OVERSAMPLING_SHIFT); // shift 4
     adc2.enable_regular_oversampling_mode(Rovsm::RESUMED, Trovs::AUTOMATIC, true);
     // Configure ADC channels according to .ioc file
     // PA0: VOUT_SN (ADC1_IN1) - output voltage detection
     // PA1: VIN_SN (ADC2_IN2) - input voltage detection
     let vout_sn_ch = p.PA0.degrade_adc(); // ADC1_IN1
     let vin_sn_ch = p.PA1.degrade_adc(); // ADC2_IN2
+                                         // INA186 output; not part of sk150c-kit.ioc, PB1 (ADC1_IN12) is assumed here
+                                         // and only used with the `current-sense` feature
+    let isn_ch = p.PB1.degrade_adc(); // ADC1_IN12
 
     let v_temp_ch = adc1.enable_temperature().degrade_adc();
     let v_ref_int_ch = adc1.enable_vrefint().degrade_adc();
@@ -197,7 +518,6 @@ async fn main(spawner: Spawner) {
     defmt::info!("vrefint_cal = {}", vrefint_cal);
 
     let dma_ch1 = p.DMA1_CH1;
-    let _dma_ch2 = p.DMA1_CH2;
 
     // Init INA186 REF
 
@@ -210,22 +530,23 @@ async fn main(spawner: Spawner) {
     let mut ina_ref_pin = Output::new(p.PA4, Level::Low, Speed::Low);
     ina_ref_pin.set_low();
 
-    // Configure hardware pins according to .ioc file
-    // PA15: VIN_CE (input control enable)
-    let vin_ce_pin = Output::new(p.PA15, Level::Low, Speed::Low);
-    defmt::info!("VIN_CE pin PA15 configured");
-
-    // PB7: VBUS_EN (VBUS control enable) - USB-C power output switch control
-    let vbus_en_pin = Output::new(p.PB7, Level::Low, Speed::Low);
-    defmt::info!("VBUS_EN pin PB7 configured");
-
-    // PB5: VBUS_LED (dual-color LED control) - changed to GPIO output mode
-    let vbus_led_pin = Output::new(p.PB5, Level::Low, Speed::Low);
+    // PB5: VBUS_LED (dual-color LED control) - GPIO driven by software PWM, floated when off
+    spawner
+        .spawn(vbus_led_task(p.PB5))
+        .map_err(|_| InitError::Spawn("vbus_led_task"))?;
     defmt::info!("VBUS_LED pin PB5 configured");
 
-    // PB10: FAN_PWM2 (fan control) - configured as GPIO output, high level starts fan
-    let fan_control_pin = Output::new(p.PB10, Level::Low, Speed::Low);
-    defmt::info!("FAN_PWM2 pin PB10 configured as GPIO output");
+    // PB10: FAN_PWM2 (TIM2_CH3) - fan speed PWM, duty set by the fan manager
+    let fan_pwm = SimplePwm::new(
+        p.TIM2,
+        None,
+        None,
+        Some(PwmPin::new(p.PB10, OutputType::PushPull)),
+        None,
+        khz(25), // 25kHz PWM frequency, above the audible range
+        Default::default(),
+    );
+    defmt::info!("FAN_PWM2 pin PB10 configured as TIM2_CH3 PWM");
 
     // PA8: POWER_LED (TIM1_CH1) - PWM breathing light control
     // Configure as open-drain output, low level lights up LED
@@ -252,6 +573,7 @@ async fn main(spawner: Spawner) {
     defmt::info!("PWM for PA8 (POWER_LED) configured, max_duty: {}", max_duty);
 
     // Create PowerOutput for power control - using PB7 (VBUS_EN)
+    // This board has no output discharge FET; boards that do attach it with `with_discharge`
     let power_output_instance = PowerOutput::new(vbus_en_pin);
     let power_output_static = POWER_OUTPUT.init(MaybeUninit::new(power_output_instance.clone()));
     let _power_output = unsafe { power_output_static.assume_init_mut() };
@@ -260,6 +582,10 @@ async fn main(spawner: Spawner) {
         ts_cal1,
         ts_cal2,
         vrefint_cal,
+        vout_gain: voltage_calibration.vout_gain,
+        vout_offset: voltage_calibration.vout_offset,
+        vin_gain: voltage_calibration.vin_gain,
+        vin_offset: voltage_calibration.vin_offset,
     };
 
     cortex_m::interrupt::free(|_| {
@@ -268,9 +594,12 @@ async fn main(spawner: Spawner) {
             dma_ch1,
             vout_sn_ch,
             vin_sn_ch,
+            isn_ch,
             v_temp_ch,
             v_ref_int_ch,
             adc_calibration,
+            EmaAlphas::default().rescaled(adc_reader::SAMPLE_INTERVAL, ADC_SAMPLE_INTERVAL),
+            ADC_SAMPLE_INTERVAL,
         );
         #[allow(static_mut_refs)]
         unsafe {
@@ -278,49 +607,77 @@ async fn main(spawner: Spawner) {
         }
     });
 
-    spawner.spawn(adc_task()).unwrap();
-    // Spawn input management task
-    spawner.spawn(input_task(input_manager)).unwrap();
+    // Verify the rails are really off before any manager can act on them
+    let safe_state = SafeStateCheck::default();
+    if safe_state.enabled {
+        vin_ce_pin.set_low();
+        power_output_instance.set_off().await;
+        embassy_time::Timer::after(safe_state.settle).await;
 
-    // Temporarily disable USB task to reduce code size
-    // let driver = embassy_stm32::usb::Driver::new(p.USB, Irqs, p.PA12, p.PA11);
-    // spawner.spawn(usb_task(driver)).unwrap();
+        #[allow(static_mut_refs)]
+        let adc_reader = unsafe { ADC_READER.assume_init_mut() };
+        let (vbus, vin) = adc_reader
+            .read_rails()
+            .await
+            .ok_or(InitError::RailUnverified)?;
+        let (vbus, vin) = (vbus.get::<volt>(), vin.get::<volt>());
+        defmt::info!("Boot rail readback: VBUS {}V, VIN {}V", vbus, vin);
+        safe_state
+            .verify(vbus, vin)
+            .map_err(InitError::RailEnergized)?;
+    }
 
-    // Get input event subscribers for both managers
+    spawner
+        .spawn(adc_task())
+        .map_err(|_| InitError::Spawn("adc_task"))?;
 
-    let power_input_subscriber = input_manager.subscriber();
-    if let Err(e) = power_input_subscriber {
-        defmt::panic!(
-            "Failed to subscribe to input events for power manager: {}",
-            e
-        );
-    }
+    // PA12/PA11: USB D+/D- - WebUSB control and telemetry interface
+    let driver = embassy_stm32::usb::Driver::new(p.USB, Irqs, p.PA12, p.PA11);
+    spawner
+        .spawn(usb::usb_task(driver))
+        .map_err(|_| InitError::Spawn("usb_task"))?;
 
-    let vbus_input_subscriber = input_manager.subscriber();
-    if let Err(e) = vbus_input_subscriber {
-        defmt::panic!(
-            "Failed to subscribe to input events for vbus manager: {}",
-            e
-        );
-    }
+    // Get input event subscribers for both managers
+
+    let power_input_subscriber = input_manager
+        .subscriber()
+        .map_err(|_| InitError::InputSubscriber("power manager"))?;
+    let vbus_input_subscriber = input_manager
+        .subscriber()
+        .map_err(|_| InitError::InputSubscriber("vbus manager"))?;
 
     // Create power manager context
     let power_ctx = PowerManagerContext {
-        input_rx: Arc::new(Mutex::new(power_input_subscriber.unwrap())),
-        power_switch: Arc::new(Mutex::new(vin_ce_pin)), // PA15 power switch control
-        led_pwm: Arc::new(Mutex::new(pwm)),             // PA8 PWM LED control
+        input_rx: Arc::new(Mutex::new(power_input_subscriber)),
+        vin_rail: PowerRail::new(RailId::Vin, Mutex::new(vin_ce_pin)), // PA15 power switch control
+        led_pwm: Arc::new(Mutex::new(pwm)),                            // PA8 PWM LED control
+        output_table: OutputTable::default(),
+        config: PowerManagerConfig {
+            mode: OPERATING_MODE,
+            restore_working,
+            idle_standby: app_config.idle_standby,
+            ..PowerManagerConfig::default()
+        },
     };
     let mut power_manager = PowerManager::new(power_ctx);
 
     defmt::info!("Initializing power manager...");
-    power_manager.init().await;
+    power_manager
+        .init(boot_reason)
+        .await
+        .map_err(InitError::OutputTable)?;
     defmt::info!("Power manager initialized successfully");
 
     // Create VBUS manager context
     let vbus_ctx = VbusManagerContext {
-        input_rx: Arc::new(Mutex::new(vbus_input_subscriber.unwrap())),
-        vbus_output: power_output_instance.clone(), // Use existing PowerOutput
-        vbus_led_pin: Arc::new(Mutex::new(vbus_led_pin)), // PB5 dual-color LED control
+        input_rx: Arc::new(Mutex::new(vbus_input_subscriber)),
+        vbus_rail: PowerRail::new(RailId::Vbus, power_output_instance.clone()), // Use existing PowerOutput
+        vbus_led_pin: Arc::new(Mutex::new(SoftPwmLed)), // PB5 dual-color LED control
+        config: VbusManagerConfig {
+            mode: OPERATING_MODE,
+            restore_vbus,
+            ..VbusManagerConfig::default()
+        },
     };
     let mut vbus_manager = VbusManager::new(vbus_ctx);
 
@@ -331,16 +688,33 @@ async fn main(spawner: Spawner) {
     // VBUS manager will run in main loop
 
     // Start VBUS ADC monitoring task
-    spawner.spawn(vbus_adc_task()).unwrap();
+    spawner
+        .spawn(vbus_adc_task())
+        .map_err(|_| InitError::Spawn("vbus_adc_task"))?;
 
     // Create fan manager and start task
-    let temperature_rx = shared::TEMPERATURE_CHANNEL.receiver().unwrap();
-    let fan_manager = fan_manager::FanManager::new(fan_control_pin, temperature_rx);
-    spawner.spawn(fan_task(fan_manager)).unwrap();
+    let temperature_rx = shared::MEASUREMENTS
+        .temperature
+        .subscribe()
+        .ok_or(InitError::Receiver("fan temperature"))?;
+    let fan_manager = fan_manager::FanManager::new(
+        fan_pwm,
+        temperature_rx,
+        fan_manager::FanProfile::default(),
+        None, // No user curve until one arrives on FAN_CURVE_CHANNEL
+        FAN_CURVE_CHANNEL
+            .receiver()
+            .ok_or(InitError::Receiver("fan curve"))?,
+    );
+    spawner
+        .spawn(fan_task(fan_manager))
+        .map_err(|_| InitError::Spawn("fan_task"))?;
     defmt::info!("Fan management task started");
 
     // Start fan speed sampling task
-    spawner.spawn(fan_speed_task(p.TIM3, p.PA6)).unwrap();
+    spawner
+        .spawn(fan_speed_task(p.TIM3, p.PA6))
+        .map_err(|_| InitError::Spawn("fan_speed_task"))?;
     defmt::info!("Fan speed sampling task started");
 
     // Run system state machine tests
@@ -350,72 +724,102 @@ async fn main(spawner: Spawner) {
         defmt::error!("Tests failed! System may have bugs.");
     }
 
-    defmt::info!("Entering main loop");
-    let mut counter = 0u32;
-
-    // Get voltage and status listeners
-    let mut vbus_voltage_rx = shared::VBUS_VOLTAGE_CHANNEL.receiver().unwrap();
-    let mut vin_voltage_rx = shared::VIN_VOLTAGE_CHANNEL.receiver().unwrap();
-    let mut vbus_state_rx = shared::VBUS_STATE_CHANNEL.receiver().unwrap();
-
-    // Keep latest VBUS status
-    let mut current_vbus_enabled = false;
-
-    loop {
-        // Get latest voltage and status information
-        let vbus_voltage = vbus_voltage_rx.try_get().unwrap_or(0.0);
-        let vin_voltage = vin_voltage_rx.try_get().unwrap_or(0.0);
-
-        // Update VBUS status, only update when there's new data
-        if let Some(new_vbus_enabled) = vbus_state_rx.try_get() {
-            current_vbus_enabled = new_vbus_enabled;
-        }
-
-        // Update VbusManager voltage information
-        vbus_manager.update_voltages(vbus_voltage, vin_voltage);
-
-        // Execute VbusManager tick
-        vbus_manager.tick().await;
-
-        // Update PowerManager voltage information (for monitoring and LED display only)
-        power_manager.update_voltages(vin_voltage, vbus_voltage, current_vbus_enabled);
-
-        // Execute PowerManager tick
-        power_manager.tick().await;
-
-        // Print debug info every 1000 loops
-        counter = counter.wrapping_add(1);
-        if counter % 1000 == 0 {
-            defmt::info!("Main loop running, counter: {}", counter);
-        }
-
-        // Add small delay to avoid excessive CPU usage
-        embassy_time::Timer::after_millis(1).await;
+    // Startup settle: outputs stay off, no input handling and no protection
+    // acting on readings from a still-settling analog front-end
+    defmt::info!(
+        "Startup settle delay: {}ms",
+        STARTUP_SETTLE_DELAY.as_millis()
+    );
+    let settle_start = embassy_time::Instant::now();
+    while settle_start.elapsed() < STARTUP_SETTLE_DELAY {
+        power_manager.tick_booting().await;
     }
+
+    // Spawn input management task
+    spawner
+        .spawn(input_task(input_manager))
+        .map_err(|_| InitError::Spawn("input_task"))?;
+
+    // Start status monitor (measurement sanity checks)
+    spawner
+        .spawn(monitor_task(monitor::MonitorConfig::default()))
+        .map_err(|_| InitError::Spawn("monitor_task"))?;
+
+    // Start thermal shutdown protection
+    spawner
+        .spawn(thermal_task(thermal::ThermalConfig::default()))
+        .map_err(|_| InitError::Spawn("thermal_task"))?;
+
+    // Start host telemetry (display smoothing only, no effect on protection)
+    spawner
+        .spawn(telemetry_task(telemetry::TelemetryConfig::default()))
+        .map_err(|_| InitError::Spawn("telemetry_task"))?;
+
+    // Periodic status line, replaces the per-manager throttled reports
+    spawner
+        .spawn(telemetry_log_task(telemetry::TelemetryLogConfig::default()))
+        .map_err(|_| InitError::Spawn("telemetry_log_task"))?;
+
+    // Publish targets next to the real VBUS_EN level for the host
+    spawner
+        .spawn(status_task(power_output_instance.clone()))
+        .map_err(|_| InitError::Spawn("status_task"))?;
+
+    // Get status listeners for the main loop
+    let vbus_state_rx = shared::VBUS_STATE_CHANNEL
+        .receiver()
+        .ok_or(InitError::Receiver("vbus state"))?;
+    let reboot_rx = shared::REBOOT_REQUEST_CHANNEL
+        .receiver()
+        .ok_or(InitError::Receiver("reboot request"))?;
+    let config_rx = CONFIG_SNAPSHOT_CHANNEL
+        .receiver()
+        .ok_or(InitError::Receiver("config snapshot"))?;
+
+    // Started after every fallible step so that slow initialization cannot trip
+    // it; the halt loops in `system` keep it fed if a later failure parks the board
+    spawner
+        .spawn(watchdog_task(p.IWDG))
+        .map_err(|_| InitError::Spawn("watchdog_task"))?;
+
+    Ok(AppContext {
+        power_manager,
+        vbus_manager,
+        vbus_state_rx,
+        reboot_rx,
+        config_rx,
+    })
 }
 
 #[embassy_executor::task]
 async fn input_task(input_manager: &'static InputManager) {
     let mut input_manager = input_manager.clone();
+    let thresholds = &shared::BUTTON_THRESHOLDS_CHANNEL;
     loop {
-        input_manager.tick().await;
+        // A cancelled tick keeps its press state in the buttons
+        if let Either::Second((id, new)) = select(input_manager.tick(), thresholds.receive()).await
+        {
+            if input_manager.set_thresholds(id, new).await {
+                defmt::info!("{:?} thresholds updated: {:?}", id, new);
+            }
+        }
     }
 }
 
 #[embassy_executor::task]
 async fn vbus_adc_task() {
     let mut adc_subscriber = ADC_PUBSUB.subscriber().unwrap();
-    let vbus_voltage_sender = shared::VBUS_VOLTAGE_CHANNEL.sender();
-    let vin_voltage_sender = shared::VIN_VOLTAGE_CHANNEL.sender();
+    let measurements = &shared::MEASUREMENTS;
 
     loop {
-        let (vout_voltage, vin_voltage) = adc_subscriber.next_message_pure().await;
+        let (vout, vin) = adc_subscriber.next_message_pure().await;
 
-        // Send VBUS voltage to shared channel
-        vbus_voltage_sender.send(vout_voltage);
+        // Publish VBUS and VIN voltage to the measurement bus
+        measurements.vbus_voltage.publish(vout);
+        measurements.vin_voltage.publish(vin);
 
-        // Send VIN voltage to shared channel
-        vin_voltage_sender.send(vin_voltage);
+        let vout_voltage = vout.get::<volt>();
+        let vin_voltage = vin.get::<volt>();
 
         // Log voltage status changes
         if vout_voltage >= 5.5 {
@@ -439,18 +843,54 @@ async fn adc_task() {
     #[allow(static_mut_refs)]
     let adc_reader = unsafe { ADC_READER.assume_init_mut() };
 
+    let power_info_tx = shared::POWER_INFO_CHANNEL.sender();
+    let mut reverse_flow = false;
+
     loop {
-        if let Some(values) = adc_reader.poll().await {
-            ADC_PUBSUB.publish_immediate((values.0, values.1));
-            // Publish temperature data to temperature channel
-            shared::TEMPERATURE_CHANNEL.sender().send(values.2);
+        if let Some(sample) = adc_reader.poll().await {
+            ADC_PUBSUB.publish_immediate((sample.vout, sample.vin));
+            shared::MEASUREMENTS
+                .vbus_voltage_raw
+                .publish(sample.vout_raw);
+            shared::MEASUREMENTS.vin_voltage_raw.publish(sample.vin_raw);
+            shared::MEASUREMENTS.temperature.publish(sample.temperature);
+            // The current sense pin is unconfirmed (see the `current-sense` feature);
+            // without it nothing current-based is published and consumers see no data
+            let current_sense = cfg!(feature = "current-sense");
+            peak::record(
+                sample.vout_raw.get::<volt>() as f32,
+                sample.vin_raw.get::<volt>() as f32,
+                if current_sense {
+                    sample.current_raw.get::<ampere>() as f32
+                } else {
+                    f32::NAN
+                },
+            );
+            if !current_sense {
+                continue;
+            }
+            shared::MEASUREMENTS.output_current.publish(sample.current);
+            shared::MEASUREMENTS
+                .output_current_raw
+                .publish(sample.current_raw);
+
+            let power = PowerInfo::new(sample.vout.get::<volt>(), sample.current.get::<ampere>());
+            if power.reverse_flow != reverse_flow {
+                reverse_flow = power.reverse_flow;
+                if reverse_flow {
+                    defmt::warn!("Reverse output current: {}A", power.amps);
+                } else {
+                    defmt::info!("Output current no longer reversed");
+                }
+            }
+            power_info_tx.send(power);
             // ADC logs removed to avoid spam
         }
     }
 }
 
 #[embassy_executor::task]
-async fn config_task(mut config_manager: ConfigManager) {
+async fn config_task(mut config_manager: ConfigManager<config_manager::Eeprom<EepromBus>>) {
     let config_req_rx = CONFIG_REQUEST_CHANNEL.receiver();
     loop {
         let req = config_req_rx.receive().await;
@@ -463,19 +903,102 @@ async fn config_task(mut config_manager: ConfigManager) {
     }
 }
 
+#[embassy_executor::task]
+async fn last_state_task() {
+    config_manager::last_state_task(config_manager::LAST_STATE_SAVE_INTERVAL).await;
+}
+
+#[embassy_executor::task]
+async fn integrity_check_task(agent: ConfigAgent<'static>) {
+    config_manager::integrity_check_task(agent, config_manager::INTEGRITY_CHECK_INTERVAL).await;
+}
+
 #[embassy_executor::task]
 async fn pd_task(mut pd_service: PowerInput<'static, UCPD1, Irqs, PB6, PB4, DMA2_CH4, DMA2_CH5>) {
     pd_service.run().await;
 }
 
+#[embassy_executor::task]
+async fn monitor_task(config: monitor::MonitorConfig) {
+    monitor::monitor_task(config).await;
+}
+
+#[embassy_executor::task]
+async fn source_health_task(
+    pd_status_rx: WatchReceiver<'static, CriticalSectionRawMutex, power::PdStatus, 2>,
+) {
+    source_health::source_health_task(pd_status_rx).await;
+}
+
+#[embassy_executor::task]
+async fn thermal_task(config: thermal::ThermalConfig) {
+    thermal::thermal_task(config).await;
+}
+
+#[embassy_executor::task]
+async fn status_task(output: PowerOutput<'static>) {
+    status::status_task(output).await;
+}
+
+#[embassy_executor::task]
+async fn telemetry_task(config: telemetry::TelemetryConfig) {
+    telemetry::telemetry_task(config).await;
+}
+
+#[embassy_executor::task]
+async fn telemetry_log_task(config: telemetry::TelemetryLogConfig) {
+    telemetry::telemetry_log_task(config).await;
+}
+
+#[embassy_executor::task]
+async fn source_caps_task(
+    sink_agent: power::SinkAgent<'static>,
+    pd_status_rx: WatchReceiver<'static, CriticalSectionRawMutex, power::PdStatus, 2>,
+) {
+    power::source_capabilities_task(sink_agent, pd_status_rx).await;
+}
+
 #[embassy_executor::task]
 async fn fan_task(mut fan_manager: fan_manager::FanManager<'static>) {
     loop {
         fan_manager.tick().await;
-        embassy_time::Timer::after_secs(5).await; // Check every 5 seconds, synchronized with ADC sampling
+        embassy_time::Timer::after_secs(5).await; // Check every 5 seconds
     }
 }
 
+/// Independent watchdog timeout
+///
+/// The IWDG runs from the LSI, so it keeps counting even if the main clock or
+/// the executor stops. One main loop iteration normally takes a few ms; the
+/// longest awaits in it are the VBUS discharge (500ms) and the 50ms delay
+/// before a controlled reboot, so 2s leaves ample margin.
+const WATCHDOG_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Pet the IWDG each time the main loop feeds it through `system::feed`
+///
+/// This task does nothing but wait for feeds, so it cannot be the thing that
+/// hangs; if the main loop deadlocks (e.g. on a manager mutex) feeds stop and
+/// the chip resets within `WATCHDOG_TIMEOUT`.
+#[embassy_executor::task]
+async fn watchdog_task(iwdg: embassy_stm32::Peri<'static, peripherals::IWDG>) {
+    let mut watchdog = IndependentWatchdog::new(iwdg, WATCHDOG_TIMEOUT.as_micros() as u32);
+    watchdog.unleash();
+    defmt::info!(
+        "Watchdog started, timeout {}ms",
+        WATCHDOG_TIMEOUT.as_millis()
+    );
+
+    loop {
+        shared::WATCHDOG_CHANNEL.receive().await;
+        watchdog.pet();
+    }
+}
+
+#[embassy_executor::task]
+async fn vbus_led_task(pin: embassy_stm32::Peri<'static, peripherals::PB5>) {
+    vbus_led::run(pin).await;
+}
+
 #[embassy_executor::task]
 async fn fan_speed_task(
     tim3: embassy_stm32::Peri<'static, peripherals::TIM3>,
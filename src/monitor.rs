@@ -0,0 +1,201 @@
+use embassy_time::{Duration, Instant, Ticker};
+use uom::si::electric_potential::volt;
+
+use crate::{
+    fault::{self, Fault},
+    power::{self, DeviceRequest, PdStatus},
+    shared::{MEASUREMENTS, PD_CONTRACT_CHANNEL, SINK_REQUEST_CHANNEL},
+};
+
+/// Expected relation between VIN and VBUS for the board topology
+///
+/// The SK150C stage is buck-boost, so VBUS may exceed VIN up to
+/// `max_boost_ratio`. For a buck-only build set the ratio to 1.0.
+#[derive(Debug, Clone, Copy)]
+pub struct TrackingCheckConfig {
+    pub enabled: bool,
+    /// Maximum VBUS / VIN ratio the converter can produce
+    pub max_boost_ratio: f64,
+    /// Absolute slack (V) to absorb measurement error
+    pub tolerance: f64,
+    /// Consecutive inconsistent samples before flagging a fault
+    pub trip_samples: u32,
+}
+
+impl Default for TrackingCheckConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_boost_ratio: 2.0,
+            tolerance: 1.0,
+            trip_samples: 3,
+        }
+    }
+}
+
+/// Periodic check that the source still honors the negotiated contract
+///
+/// The source's VBUS is measured as VIN on this board. Catches a source that
+/// silently drops the contract without sending a PD message.
+#[derive(Debug, Clone, Copy)]
+pub struct ContractCheckConfig {
+    pub enabled: bool,
+    /// Time between checks, at least one ADC sample interval
+    pub interval: Duration,
+    /// Allowed deviation as a fraction of the contract voltage
+    pub tolerance: f64,
+    /// Consecutive deviating checks before the contract is treated as lost
+    pub trip_samples: u32,
+}
+
+impl Default for ContractCheckConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval: Duration::from_secs(5),
+            tolerance: 0.1,
+            trip_samples: 3,
+        }
+    }
+}
+
+/// Status monitor settings
+#[derive(Debug, Clone, Copy)]
+pub struct MonitorConfig {
+    pub interval: Duration,
+    pub tracking: TrackingCheckConfig,
+    pub contract: ContractCheckConfig,
+}
+
+impl Default for MonitorConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            tracking: TrackingCheckConfig::default(),
+            contract: ContractCheckConfig::default(),
+        }
+    }
+}
+
+/// Whether VBUS is plausible for the measured VIN
+///
+/// VBUS above `VIN * max_boost_ratio + tolerance` cannot be produced by the
+/// converter and indicates a measurement or hardware fault.
+pub fn tracking_consistent(vbus: f64, vin: f64, config: &TrackingCheckConfig) -> bool {
+    vbus <= vin * config.max_boost_ratio + config.tolerance
+}
+
+/// Whether the measured input voltage is within tolerance of the contract
+pub fn contract_honored(vin: f64, contract_voltage: f64, config: &ContractCheckConfig) -> bool {
+    (vin - contract_voltage).abs() <= contract_voltage * config.tolerance
+}
+
+/// Periodic sanity checks on the published measurements
+pub async fn monitor_task(config: MonitorConfig) {
+    let mut ticker = Ticker::every(config.interval);
+    let mut tracking_violations = 0u32;
+    let mut contract_deviations = 0u32;
+    let mut next_contract_check = Instant::now();
+
+    loop {
+        ticker.next().await;
+
+        if config.contract.enabled && Instant::now() >= next_contract_check {
+            next_contract_check = Instant::now() + config.contract.interval;
+            let contract = PD_CONTRACT_CHANNEL
+                .anon_receiver()
+                .try_get()
+                .filter(|_| power::pd_status() == PdStatus::Negotiated);
+
+            match (contract, MEASUREMENTS.vin_voltage.latest()) {
+                (Some(contract), Some(vin)) => {
+                    let vin = vin.get::<volt>();
+                    if contract_honored(vin, contract.nearest_voltage(vin), &config.contract) {
+                        if contract_deviations >= config.contract.trip_samples {
+                            defmt::info!("Contract voltage honored again: VIN={}V", vin);
+                        }
+                        contract_deviations = 0;
+                    } else {
+                        contract_deviations = contract_deviations.saturating_add(1);
+                        if contract_deviations == config.contract.trip_samples {
+                            defmt::warn!(
+                                "Contract {}V not honored: VIN={}V - renegotiating",
+                                contract.voltage,
+                                vin
+                            );
+                            SINK_REQUEST_CHANNEL
+                                .sender()
+                                .send(DeviceRequest::Renegotiate);
+                        }
+                    }
+                }
+                // No contract to verify
+                _ => contract_deviations = 0,
+            }
+            fault::set_active(
+                Fault::ContractDeviation,
+                contract_deviations >= config.contract.trip_samples,
+            );
+        }
+
+        if config.tracking.enabled {
+            if let (Some(vbus), Some(vin)) = (
+                MEASUREMENTS.vbus_voltage.latest(),
+                MEASUREMENTS.vin_voltage.latest(),
+            ) {
+                let (vbus, vin) = (vbus.get::<volt>(), vin.get::<volt>());
+                if tracking_consistent(vbus, vin, &config.tracking) {
+                    if tracking_violations >= config.tracking.trip_samples {
+                        defmt::info!("VIN/VBUS tracking back to normal");
+                    }
+                    tracking_violations = 0;
+                } else {
+                    tracking_violations = tracking_violations.saturating_add(1);
+                    if tracking_violations == config.tracking.trip_samples {
+                        defmt::warn!(
+                            "VIN/VBUS tracking inconsistent: VBUS={}V, VIN={}V",
+                            vbus,
+                            vin
+                        );
+                    }
+                }
+                fault::set_active(
+                    Fault::TrackingMismatch,
+                    tracking_violations >= config.tracking.trip_samples,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tracking_rule() {
+        let config = TrackingCheckConfig {
+            max_boost_ratio: 1.0,
+            tolerance: 0.5,
+            ..Default::default()
+        };
+
+        assert!(tracking_consistent(12.0, 20.0, &config));
+        assert!(tracking_consistent(20.4, 20.0, &config));
+        assert!(!tracking_consistent(21.0, 20.0, &config));
+        // VBUS present without any input
+        assert!(!tracking_consistent(5.0, 0.0, &config));
+    }
+
+    #[test]
+    fn test_contract_deviation() {
+        let config = ContractCheckConfig::default();
+
+        assert!(contract_honored(20.0, 20.0, &config));
+        assert!(contract_honored(18.5, 20.0, &config));
+        assert!(contract_honored(5.4, 5.0, &config));
+        // Source fell back to vSafe5V without a PD message
+        assert!(!contract_honored(5.0, 20.0, &config));
+        assert!(!contract_honored(0.0, 9.0, &config));
+    }
+}
@@ -0,0 +1,154 @@
+/// Direction of a traced PD message
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum Direction {
+    Rx,
+    Tx,
+}
+
+/// Decoded 16-bit USB PD message header
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub struct Header {
+    pub message_type: u8,
+    pub object_count: u8,
+    pub message_id: u8,
+    /// Specification revision as encoded (0 = 1.0, 1 = 2.0, 2 = 3.x)
+    pub spec_revision: u8,
+    pub extended: bool,
+}
+
+impl Header {
+    pub fn parse(raw: u16) -> Self {
+        Self {
+            message_type: (raw & 0x1F) as u8,
+            spec_revision: ((raw >> 6) & 0x3) as u8,
+            message_id: ((raw >> 9) & 0x7) as u8,
+            object_count: ((raw >> 12) & 0x7) as u8,
+            extended: raw & 0x8000 != 0,
+        }
+    }
+
+    /// Message name per the PD 3.x message type tables
+    pub fn name(&self) -> &'static str {
+        if self.extended {
+            return "Extended";
+        }
+        if self.object_count == 0 {
+            match self.message_type {
+                1 => "GoodCRC",
+                2 => "GotoMin",
+                3 => "Accept",
+                4 => "Reject",
+                5 => "Ping",
+                6 => "PS_RDY",
+                7 => "Get_Source_Cap",
+                8 => "Get_Sink_Cap",
+                9 => "DR_Swap",
+                10 => "PR_Swap",
+                11 => "VCONN_Swap",
+                12 => "Wait",
+                13 => "Soft_Reset",
+                16 => "Not_Supported",
+                17 => "Get_Source_Cap_Extended",
+                18 => "Get_Status",
+                19 => "FR_Swap",
+                20 => "Get_PPS_Status",
+                _ => "Control(unknown)",
+            }
+        } else {
+            match self.message_type {
+                1 => "Source_Capabilities",
+                2 => "Request",
+                3 => "BIST",
+                4 => "Sink_Capabilities",
+                5 => "Battery_Status",
+                6 => "Alert",
+                7 => "Get_Country_Info",
+                8 => "Enter_USB",
+                9 => "EPR_Request",
+                10 => "EPR_Mode",
+                15 => "Vendor_Defined",
+                _ => "Data(unknown)",
+            }
+        }
+    }
+}
+
+/// Data objects of a message, little-endian after the header
+fn objects(message: &[u8], count: u8) -> impl Iterator<Item = u32> + '_ {
+    message
+        .get(2..)
+        .unwrap_or(&[])
+        .chunks_exact(4)
+        .take(count as usize)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// Log a PD message with its decoded header and key fields
+///
+/// High volume: every GoodCRC is logged too. Only built with the
+/// `pd-trace` feature.
+pub fn log(direction: Direction, message: &[u8]) {
+    let Some(raw) = message.get(..2) else {
+        defmt::info!("PD {}: runt message {:x}", direction, message);
+        return;
+    };
+    let header = Header::parse(u16::from_le_bytes([raw[0], raw[1]]));
+    defmt::info!(
+        "PD {}: {} id={} objects={} rev={}",
+        direction,
+        header.name(),
+        header.message_id,
+        header.object_count,
+        header.spec_revision
+    );
+
+    if header.extended {
+        return;
+    }
+    for (i, object) in objects(message, header.object_count).enumerate() {
+        match (header.name(), object >> 30) {
+            // Fixed supply PDO: 50mV / 10mA units
+            ("Source_Capabilities", 0) => defmt::info!(
+                "  PDO{} fixed {}mV {}mA",
+                i + 1,
+                ((object >> 10) & 0x3FF) * 50,
+                (object & 0x3FF) * 10
+            ),
+            ("Source_Capabilities", _) => defmt::info!("  PDO{} raw {:08x}", i + 1, object),
+            // Fixed RDO: object position, operating / max current in 10mA units
+            ("Request", _) => defmt::info!(
+                "  RDO position={} op={}mA max={}mA",
+                object >> 28,
+                ((object >> 10) & 0x3FF) * 10,
+                (object & 0x3FF) * 10
+            ),
+            _ => defmt::info!("  DO{} {:08x}", i + 1, object),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_decoding() {
+        // Source_Capabilities, rev 3.x, message id 0, 2 objects
+        let header = Header::parse(0x21A1);
+        assert_eq!(header.message_type, 1);
+        assert_eq!(header.spec_revision, 2);
+        assert_eq!(header.message_id, 0);
+        assert_eq!(header.object_count, 2);
+        assert_eq!(header.name(), "Source_Capabilities");
+
+        // GoodCRC carries no objects, so type 1 is a control message
+        assert_eq!(Header::parse(0x0241).name(), "GoodCRC");
+
+        let message = [0xA1, 0x21, 0x2C, 0x91, 0x01, 0x08, 0xC8, 0xD0, 0x02, 0x00];
+        let mut objects = objects(&message, 2);
+        // 5V 3A and 9V 2A fixed PDOs
+        assert_eq!(objects.next(), Some(0x0801_912C));
+        assert_eq!(objects.next(), Some(0x0002_D0C8));
+        assert_eq!(objects.next(), None);
+    }
+}
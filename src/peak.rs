@@ -0,0 +1,172 @@
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+
+/// Samples in the rolling window, 5 s at the 100 ms ADC interval
+pub const PEAK_WINDOW_SAMPLES: usize = 50;
+
+/// Lowest and highest value seen, NaN before the first sample
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub struct Extremes {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Extremes {
+    const EMPTY: Self = Self {
+        min: f32::NAN,
+        max: f32::NAN,
+    };
+
+    fn include(self, value: f32) -> Self {
+        // f32::min/max return the other operand when one is NaN
+        Self {
+            min: self.min.min(value),
+            max: self.max.max(value),
+        }
+    }
+}
+
+/// Rolling-window extremes plus a peak hold for one measurement
+///
+/// The window is a fixed ring buffer of the last `N` samples, so memory does
+/// not grow with the window. The hold keeps the extremes since the last
+/// `reset_hold`.
+pub struct PeakTracker<const N: usize> {
+    window: [f32; N],
+    len: usize,
+    next: usize,
+    hold: Extremes,
+}
+
+impl<const N: usize> PeakTracker<N> {
+    pub const fn new() -> Self {
+        Self {
+            window: [0.0; N],
+            len: 0,
+            next: 0,
+            hold: Extremes::EMPTY,
+        }
+    }
+
+    /// Add a sample, non-finite values are ignored
+    pub fn update(&mut self, value: f32) {
+        if !value.is_finite() || N == 0 {
+            return;
+        }
+        self.window[self.next] = value;
+        self.next = (self.next + 1) % N;
+        self.len = (self.len + 1).min(N);
+        self.hold = self.hold.include(value);
+    }
+
+    /// Extremes of the last `N` samples
+    pub fn window(&self) -> Extremes {
+        self.window[..self.len]
+            .iter()
+            .fold(Extremes::EMPTY, |extremes, &value| extremes.include(value))
+    }
+
+    /// Extremes since the last reset
+    pub fn hold(&self) -> Extremes {
+        self.hold
+    }
+
+    pub fn reset_hold(&mut self) {
+        self.hold = Extremes::EMPTY;
+    }
+}
+
+/// Window and hold extremes of one measurement
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub struct PeakValues {
+    pub window: Extremes,
+    pub hold: Extremes,
+}
+
+/// Transient extremes of the unfiltered VOUT, VIN and output current
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub struct PeakSnapshot {
+    /// Volts
+    pub vout: PeakValues,
+    /// Volts
+    pub vin: PeakValues,
+    /// Amperes
+    pub current: PeakValues,
+}
+
+struct Peaks {
+    vout: PeakTracker<PEAK_WINDOW_SAMPLES>,
+    vin: PeakTracker<PEAK_WINDOW_SAMPLES>,
+    current: PeakTracker<PEAK_WINDOW_SAMPLES>,
+}
+
+static PEAKS: Mutex<CriticalSectionRawMutex, RefCell<Peaks>> = Mutex::new(RefCell::new(Peaks {
+    vout: PeakTracker::new(),
+    vin: PeakTracker::new(),
+    current: PeakTracker::new(),
+}));
+
+/// Record one ADC sample
+pub fn record(vout: f32, vin: f32, current: f32) {
+    PEAKS.lock(|peaks| {
+        let mut peaks = peaks.borrow_mut();
+        peaks.vout.update(vout);
+        peaks.vin.update(vin);
+        peaks.current.update(current);
+    });
+}
+
+/// Clear the peak holds; the rolling windows are unaffected
+pub fn reset_peaks() {
+    defmt::info!("Peak hold values reset");
+    PEAKS.lock(|peaks| {
+        let mut peaks = peaks.borrow_mut();
+        peaks.vout.reset_hold();
+        peaks.vin.reset_hold();
+        peaks.current.reset_hold();
+    });
+}
+
+pub fn snapshot() -> PeakSnapshot {
+    PEAKS.lock(|peaks| {
+        let peaks = peaks.borrow();
+        let values = |tracker: &PeakTracker<PEAK_WINDOW_SAMPLES>| PeakValues {
+            window: tracker.window(),
+            hold: tracker.hold(),
+        };
+        PeakSnapshot {
+            vout: values(&peaks.vout),
+            vin: values(&peaks.vin),
+            current: values(&peaks.current),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window_rolls_and_hold_persists_until_reset() {
+        let mut tracker = PeakTracker::<3>::new();
+        assert!(tracker.window().min.is_nan());
+
+        for value in [5.0, 1.0, 9.0, f32::NAN] {
+            tracker.update(value);
+        }
+        assert_eq!(tracker.window(), Extremes { min: 1.0, max: 9.0 });
+
+        // The spike leaves the window after N newer samples but stays held
+        for value in [4.0, 4.0, 4.0] {
+            tracker.update(value);
+        }
+        assert_eq!(tracker.window(), Extremes { min: 4.0, max: 4.0 });
+        assert_eq!(tracker.hold(), Extremes { min: 1.0, max: 9.0 });
+
+        tracker.reset_hold();
+        assert!(tracker.hold().max.is_nan());
+        tracker.update(6.0);
+        assert_eq!(tracker.hold(), Extremes { min: 6.0, max: 6.0 });
+    }
+}
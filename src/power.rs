@@ -1,7 +1,11 @@
-use alloc::sync::Arc;
-use core::marker::PhantomData;
+use alloc::{sync::Arc, vec::Vec};
+use core::{
+    convert::Infallible,
+    marker::PhantomData,
+    sync::atomic::{AtomicU32, Ordering},
+};
 use defmt::{info, warn, Format};
-use embassy_futures::select::{select, Either};
+use embassy_futures::select::{select, select3, Either, Either3};
 use embassy_stm32::{
     interrupt,
     ucpd::{
@@ -13,20 +17,717 @@ use embassy_stm32::{
 use embassy_sync::{
     blocking_mutex::raw::CriticalSectionRawMutex, channel, mutex::Mutex, signal::Signal, watch,
 };
-use embassy_time::{with_timeout, Duration, Timer};
+use embassy_time::{with_timeout, Duration, Instant, Timer};
 
+use uom::si::{electric_current::milliampere, electric_potential::millivolt};
 use usbpd::{
     protocol_layer::message::{
-        pdo::SourceCapabilities,
-        request::{CurrentRequest, PowerSource, VoltageRequest},
+        pdo::{Augmented, PowerDataObject, SourceCapabilities},
+        request::{CurrentRequest, FixedVariableSupply, PowerSource, VoltageRequest},
+        units::{ElectricCurrent, ElectricPotential},
     },
     sink::{self, device_policy_manager::DevicePolicyManager},
     timers::Timer as SinkTimer,
 };
 use usbpd::{sink::policy_engine::Sink, Driver as SinkDriver};
 
-#[derive(Debug, Format)]
-enum CableOrientation {
+/// PD connection status published on `PD_STATUS_CHANNEL`
+#[derive(Debug, Clone, Copy, PartialEq, Format)]
+pub enum PdStatus {
+    /// No source attached
+    Detached,
+    /// Source attached, no contract established yet
+    Attached(CableOrientation),
+    /// Explicit contract established
+    Negotiated,
+    /// Source attached but negotiation did not complete within the timeout
+    NegotiationFailed,
+}
+
+/// Explicit contract accepted by the source, published on `PD_CONTRACT_CHANNEL`
+#[derive(Debug, Clone, Copy, PartialEq, Format)]
+pub struct PdContract {
+    /// Contract voltage in volts, the range maximum for a variable supply
+    pub voltage: f64,
+    /// Contract current in amps
+    pub current: f64,
+    /// Range minimum of a variable supply in volts, equal to `voltage` otherwise
+    pub min_voltage: f64,
+}
+
+impl PdContract {
+    /// Contract at a single voltage (fixed supply or PPS)
+    fn fixed(voltage_mv: u32, current_ma: u32) -> Self {
+        let voltage = voltage_mv as f64 / 1000.0;
+        Self {
+            voltage,
+            current: current_ma as f64 / 1000.0,
+            min_voltage: voltage,
+        }
+    }
+
+    /// Contracted voltage closest to `vin`; `vin` itself if inside a variable range
+    pub fn nearest_voltage(&self, vin: f64) -> f64 {
+        vin.max(self.min_voltage).min(self.voltage)
+    }
+}
+
+/// PDO selection policy applied by `Device::request`
+#[derive(Debug, Clone, Copy, PartialEq, Format)]
+pub enum RequestStrategy {
+    /// Highest fixed voltage at its maximum current
+    HighestPower,
+    /// A specific fixed voltage (mV) at its maximum current
+    FixedVoltage(u32),
+    /// Configured target: PPS when an APDO covers the target voltage,
+    /// otherwise the closest fixed voltage, limited to the target current
+    ConfigTarget,
+}
+
+/// Desired contract from the user config (`CONFIG_SNAPSHOT_CHANNEL`)
+#[derive(Debug, Clone, Copy, PartialEq, Format)]
+pub struct RequestTarget {
+    pub voltage_mv: u32,
+    pub current_ma: u32,
+}
+
+impl RequestTarget {
+    /// Target of the latest config snapshot, if one was published
+    fn from_snapshot() -> Option<Self> {
+        crate::shared::CONFIG_SNAPSHOT_CHANNEL
+            .anon_receiver()
+            .try_get()
+            .map(|config| Self {
+                voltage_mv: config.target_voltage.get::<millivolt>(),
+                current_ma: config.target_current.get::<milliampere>(),
+            })
+    }
+}
+
+/// Fixed supply PDOs as (voltage mV, max current mA)
+fn fixed_supplies(capabilities: &SourceCapabilities) -> impl Iterator<Item = (u32, u32)> + '_ {
+    capabilities.pdos().iter().filter_map(|pdo| match pdo {
+        PowerDataObject::FixedSupply(fixed) => Some((
+            fixed.voltage().get::<millivolt>(),
+            fixed.max_current().get::<milliampere>(),
+        )),
+        _ => None,
+    })
+}
+
+/// Variable supply PDOs as (object position, min mV, max mV, max current mA)
+fn variable_supplies(
+    capabilities: &SourceCapabilities,
+) -> impl Iterator<Item = (u8, u32, u32, u32)> + '_ {
+    capabilities
+        .pdos()
+        .iter()
+        .enumerate()
+        .filter_map(|(i, pdo)| match pdo {
+            PowerDataObject::VariableSupply(variable) => Some((
+                i as u8 + 1,
+                variable.min_voltage().get::<millivolt>(),
+                variable.max_voltage().get::<millivolt>(),
+                variable.max_current().get::<milliampere>(),
+            )),
+            _ => None,
+        })
+}
+
+/// Current requested from object position 1 when the source offers no fixed
+/// PDO to fall back on
+const FALLBACK_CURRENT_MA: u32 = 100;
+
+/// Request data object for a fixed or variable PDO at `current_ma`
+fn fixed_variable_rdo(object_position: u8, current_ma: u32) -> PowerSource {
+    // RDO currents are 10-bit fields in 10mA units
+    let raw_current = (current_ma / 10).min(0x3FF) as u16;
+    PowerSource::FixedVariableSupply(
+        FixedVariableSupply(0)
+            .with_object_position(object_position)
+            .with_usb_communications_capable(true)
+            .with_no_usb_suspend(true)
+            .with_raw_operating_current(raw_current)
+            .with_raw_max_operating_current(raw_current),
+    )
+}
+
+/// Variable supply request for sources that offer no fixed PDO
+///
+/// The first variable PDO whose range covers `target_mv` is used, wherever it
+/// sits in the list. The current is `current_limit_ma` (if any) limited to the
+/// PDO maximum.
+fn variable_power_source(
+    target_mv: u32,
+    current_limit_ma: Option<u32>,
+    capabilities: &SourceCapabilities,
+) -> Option<(PowerSource, PdContract)> {
+    let (object_position, min_mv, max_mv, max_current_ma) = variable_supplies(capabilities)
+        .find(|(_, min_mv, max_mv, _)| (*min_mv..=*max_mv).contains(&target_mv))?;
+    let current_ma = current_limit_ma.map_or(max_current_ma, |limit| limit.min(max_current_ma));
+
+    Some((
+        fixed_variable_rdo(object_position, current_ma),
+        PdContract {
+            voltage: max_mv as f64 / 1000.0,
+            current: current_ma as f64 / 1000.0,
+            min_voltage: min_mv as f64 / 1000.0,
+        },
+    ))
+}
+
+/// Offered voltage nearest to `target_mv`; ties resolve to the lower voltage
+fn closest_voltage(offered: &[u32], target_mv: u32) -> Option<u32> {
+    offered
+        .iter()
+        .copied()
+        .min_by_key(|voltage_mv| (voltage_mv.abs_diff(target_mv), *voltage_mv))
+}
+
+impl RequestStrategy {
+    /// Raw encoding used by config storage and USB: 0 = highest power,
+    /// 1 = config target, otherwise the fixed voltage in mV
+    pub fn to_raw(self) -> u32 {
+        match self {
+            Self::HighestPower => 0,
+            Self::ConfigTarget => 1,
+            Self::FixedVoltage(voltage_mv) => voltage_mv,
+        }
+    }
+
+    /// Contract this strategy would request from `capabilities`
+    fn contract(
+        self,
+        capabilities: &SourceCapabilities,
+        target: Option<RequestTarget>,
+    ) -> Option<PdContract> {
+        let mut fixed = fixed_supplies(capabilities);
+
+        match self {
+            Self::HighestPower => fixed.max_by_key(|(voltage_mv, _)| *voltage_mv),
+            Self::FixedVoltage(target_mv) => fixed.find(|(voltage_mv, _)| *voltage_mv == target_mv),
+            Self::ConfigTarget => {
+                let target = target?;
+                let offered: Vec<u32> = fixed.map(|(voltage_mv, _)| voltage_mv).collect();
+                let voltage_mv = closest_voltage(&offered, target.voltage_mv)?;
+                fixed_supplies(capabilities)
+                    .find(|(offered_mv, _)| *offered_mv == voltage_mv)
+                    .map(|(voltage_mv, current_ma)| (voltage_mv, current_ma.min(target.current_ma)))
+            }
+        }
+        .map(|(voltage_mv, current_ma)| PdContract::fixed(voltage_mv, current_ma))
+    }
+}
+
+impl TryFrom<u32> for RequestStrategy {
+    type Error = ();
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::HighestPower),
+            1 => Ok(Self::ConfigTarget),
+            3_000..=48_000 => Ok(Self::FixedVoltage(value)),
+            _ => Err(()),
+        }
+    }
+}
+
+/// PPS output voltage resolution
+pub const PPS_STEP_MV: u32 = 20;
+
+/// User-configurable safe range for PPS requests (mV)
+#[derive(Debug, Clone, Copy, PartialEq, Format)]
+pub struct PpsLimits {
+    pub min_mv: u32,
+    pub max_mv: u32,
+}
+
+impl Default for PpsLimits {
+    fn default() -> Self {
+        Self {
+            min_mv: 3_300,
+            max_mv: 21_000,
+        }
+    }
+}
+
+/// Reason a PPS target cannot be requested
+#[derive(Debug, Clone, Copy, PartialEq, Format)]
+pub enum PpsError {
+    /// The target lies outside every APDO range offered by the source
+    NoMatchingApdo,
+    /// The matching APDO has no 20mV step inside the configured safe range
+    OutsideSafeRange,
+}
+
+/// PPS request after clamping, with the voltage actually requested
+#[derive(Debug, Clone, Copy, PartialEq, Format)]
+pub struct PpsRequest {
+    /// 1-based object position of the APDO in the source capabilities
+    pub object_position: u8,
+    /// Granted target on the 20mV grid, for display
+    pub voltage_mv: u32,
+}
+
+/// Clamp a PPS target to its APDO and the safe range, snapped to 20mV
+///
+/// `apdos` holds `(object_position, min_mv, max_mv)` for each PPS APDO. The
+/// first APDO containing the target is used; the result is the valid step
+/// nearest to the target.
+fn clamp_pps_voltage(
+    target_mv: u32,
+    apdos: &[(u8, u32, u32)],
+    limits: PpsLimits,
+) -> Result<PpsRequest, PpsError> {
+    let &(object_position, apdo_min, apdo_max) = apdos
+        .iter()
+        .find(|(_, min, max)| (*min..=*max).contains(&target_mv))
+        .ok_or(PpsError::NoMatchingApdo)?;
+
+    let low = apdo_min.max(limits.min_mv).div_ceil(PPS_STEP_MV) * PPS_STEP_MV;
+    let high = apdo_max.min(limits.max_mv) / PPS_STEP_MV * PPS_STEP_MV;
+    if low > high {
+        return Err(PpsError::OutsideSafeRange);
+    }
+
+    let snapped = (target_mv + PPS_STEP_MV / 2) / PPS_STEP_MV * PPS_STEP_MV;
+    Ok(PpsRequest {
+        object_position,
+        voltage_mv: snapped.clamp(low, high),
+    })
+}
+
+/// Build a PPS request for `target_mv` against the source's APDOs
+pub fn pps_request(
+    target_mv: u32,
+    capabilities: &SourceCapabilities,
+    limits: PpsLimits,
+) -> Result<PpsRequest, PpsError> {
+    let apdos: Vec<(u8, u32, u32)> = capabilities
+        .pdos()
+        .iter()
+        .enumerate()
+        .filter_map(|(i, pdo)| match pdo {
+            PowerDataObject::Augmented(Augmented::Spr(pps)) => Some((
+                i as u8 + 1,
+                pps.min_voltage().get::<millivolt>(),
+                pps.max_voltage().get::<millivolt>(),
+            )),
+            _ => None,
+        })
+        .collect();
+
+    let request = clamp_pps_voltage(target_mv, &apdos, limits);
+    match request {
+        Ok(request) if request.voltage_mv != target_mv => info!(
+            "PPS target {}mV clamped to {}mV (APDO {})",
+            target_mv, request.voltage_mv, request.object_position
+        ),
+        Err(e) => warn!("PPS target {}mV rejected: {}", target_mv, e),
+        _ => {}
+    }
+    request
+}
+
+/// PPS power source for the config target, if an APDO covers it
+///
+/// The current is the configured target limited to the APDO maximum.
+fn pps_power_source(
+    target: RequestTarget,
+    capabilities: &SourceCapabilities,
+    limits: PpsLimits,
+) -> Option<(PowerSource, PdContract)> {
+    let has_pps = capabilities
+        .pdos()
+        .iter()
+        .any(|pdo| matches!(pdo, PowerDataObject::Augmented(Augmented::Spr(_))));
+    if !has_pps {
+        return None;
+    }
+
+    let pps = pps_request(target.voltage_mv, capabilities, limits).ok()?;
+    let max_current_ma = match capabilities.pdos().get(pps.object_position as usize - 1)? {
+        PowerDataObject::Augmented(Augmented::Spr(apdo)) => apdo.max_current().get::<milliampere>(),
+        _ => return None,
+    };
+    let current_ma = target.current_ma.min(max_current_ma);
+
+    let source = PowerSource::new_pps(
+        CurrentRequest::Specific(ElectricCurrent::new::<milliampere>(current_ma)),
+        ElectricPotential::new::<millivolt>(pps.voltage_mv),
+        capabilities,
+    )
+    .ok()?;
+    Some((source, PdContract::fixed(pps.voltage_mv, current_ma)))
+}
+
+static REQUEST_STRATEGY: AtomicU32 = AtomicU32::new(0);
+
+/// Strategy used for the next request
+pub fn request_strategy() -> RequestStrategy {
+    RequestStrategy::try_from(REQUEST_STRATEGY.load(Ordering::Relaxed))
+        .unwrap_or(RequestStrategy::HighestPower)
+}
+
+/// Select a new request strategy and renegotiate if a contract is active
+///
+/// Refused with `RequestError::Mismatch` when the attached source offers no
+/// matching PDO. Without a source the strategy is applied on the next attach.
+pub fn set_request_strategy(strategy: RequestStrategy) -> Result<(), RequestError> {
+    let capabilities = crate::shared::SOURCE_CAPABILITIES_CHANNEL
+        .anon_receiver()
+        .try_get();
+    if pd_status() != PdStatus::Detached {
+        if let Some(capabilities) = capabilities {
+            if strategy
+                .contract(&capabilities, RequestTarget::from_snapshot())
+                .is_none()
+            {
+                warn!("Request strategy {} not offered by the source", strategy);
+                return Err(RequestError::Mismatch);
+            }
+        }
+    }
+
+    info!("Request strategy: {}", strategy);
+    REQUEST_STRATEGY.store(strategy.to_raw(), Ordering::Relaxed);
+    if pd_status() == PdStatus::Negotiated {
+        crate::shared::SINK_REQUEST_CHANNEL
+            .sender()
+            .send(DeviceRequest::Renegotiate);
+    }
+    Ok(())
+}
+
+/// Renegotiate after the config target changed, if the strategy follows it
+pub fn target_changed() {
+    if request_strategy() == RequestStrategy::ConfigTarget && pd_status() == PdStatus::Negotiated {
+        info!("Config target changed, renegotiating");
+        crate::shared::SINK_REQUEST_CHANNEL
+            .sender()
+            .send(DeviceRequest::Renegotiate);
+    }
+}
+
+/// PHY errors (discarded/CRC/overrun) since the current attach
+static PHY_ERRORS: AtomicU32 = AtomicU32::new(0);
+
+/// PHY errors seen since the source was attached
+pub fn phy_error_count() -> u32 {
+    PHY_ERRORS.load(Ordering::Relaxed)
+}
+
+fn count_phy_error() {
+    PHY_ERRORS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// CC termination presented by the UCPD
+///
+/// The UCPD can present Rd or any of the Rp values, but the board has no path
+/// to source VBUS on its USB-C input. Rp is therefore only useful for
+/// accessory/cable detection with the default USB advertisement; advertising
+/// 1.5A or 3.0A would promise current the board cannot deliver and is refused.
+#[derive(Debug, Clone, Copy, PartialEq, Format)]
+#[allow(dead_code)]
+pub enum CcTermination {
+    /// Rd pull-down, normal PD sink operation
+    Sink,
+    /// Rp advertising default USB current
+    SourceDefaultUsb,
+    /// Rp advertising 1.5A (not supported by the hardware)
+    Source1_5A,
+    /// Rp advertising 3.0A (not supported by the hardware)
+    Source3_0A,
+}
+
+/// CC termination requested in `PowerInputConfig` that the hardware cannot present
+#[derive(Debug, Clone, Copy, PartialEq, Format)]
+pub struct UnsupportedCcTermination(pub CcTermination);
+
+impl CcTermination {
+    fn is_supported(self) -> bool {
+        matches!(self, Self::Sink | Self::SourceDefaultUsb)
+    }
+
+    fn pull(self) -> CcPull {
+        match self {
+            Self::Sink => CcPull::Sink,
+            Self::SourceDefaultUsb => CcPull::SourceDefaultUsb,
+            Self::Source1_5A => CcPull::Source1_5A,
+            Self::Source3_0A => CcPull::Source3_0A,
+        }
+    }
+}
+
+/// Sink policy settings for `PowerInput`
+#[derive(Debug, Clone, Copy)]
+pub struct PowerInputConfig {
+    /// CC termination, `Sink` unless the port is used for detection only
+    pub cc_termination: CcTermination,
+    /// Time allowed between attach and an established contract
+    pub negotiation_timeout: Duration,
+    /// Delay before the first sink restart after a recoverable error;
+    /// doubles with every further retry in the same window
+    pub retry_settle: Duration,
+    /// Upper bound of the doubling restart delay
+    pub retry_backoff_max: Duration,
+    /// Recoverable errors tolerated within `retry_window` before escalating
+    pub max_retries: u32,
+    pub retry_window: Duration,
+}
+
+impl Default for PowerInputConfig {
+    fn default() -> Self {
+        Self {
+            cc_termination: CcTermination::Sink,
+            negotiation_timeout: Duration::from_secs(5),
+            retry_settle: Duration::from_millis(500),
+            retry_backoff_max: Duration::from_secs(4),
+            max_retries: 3,
+            retry_window: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Whether the sink can be restarted after `err` on the same attachment
+///
+/// An unresponsive partner or a protocol hiccup (including a hard reset the
+/// source issued or we escalated to) is usually a marginal cable; everything
+/// else is treated as fatal for the session.
+fn is_recoverable(err: &sink::policy_engine::Error) -> bool {
+    matches!(
+        err,
+        sink::policy_engine::Error::PortPartnerUnresponsive
+            | sink::policy_engine::Error::Protocol(_)
+    )
+}
+
+/// Caps the number of retries within a time window
+struct RetryBudget {
+    max_retries: u32,
+    window: Duration,
+    window_start: Option<Instant>,
+    retries: u32,
+}
+
+impl RetryBudget {
+    fn new(max_retries: u32, window: Duration) -> Self {
+        Self {
+            max_retries,
+            window,
+            window_start: None,
+            retries: 0,
+        }
+    }
+
+    /// Consume one retry, returns false once the budget for the window is spent
+    fn try_consume(&mut self, now: Instant) -> bool {
+        match self.window_start {
+            Some(start) if now - start < self.window => {}
+            _ => {
+                self.window_start = Some(now);
+                self.retries = 0;
+            }
+        }
+
+        if self.retries >= self.max_retries {
+            return false;
+        }
+        self.retries += 1;
+        true
+    }
+
+    /// Delay before the retry just consumed: `base` doubled per earlier retry
+    /// in the window, capped at `max`
+    fn backoff(&self, base: Duration, max: Duration) -> Duration {
+        let doublings = self.retries.saturating_sub(1).min(16);
+        (base * (1 << doublings)).min(max)
+    }
+
+    fn reset(&mut self) {
+        self.window_start = None;
+        self.retries = 0;
+    }
+}
+
+/// Device policy settings
+#[derive(Debug, Clone, Copy)]
+pub struct DeviceConfig {
+    /// Minimum time between on-demand renegotiations; requests arriving
+    /// sooner are coalesced into one issued when the window expires
+    pub min_renegotiation_interval: Duration,
+    /// Unanswered requests at one voltage before de-rating to the next
+    /// lower fixed PDO; repeats follow the source/keep-alive cadence
+    pub max_request_attempts: u32,
+    /// Safe range for PPS requests made by `RequestStrategy::ConfigTarget`
+    pub pps_limits: PpsLimits,
+    /// Keep-alive interval while a PPS contract is active; must stay below
+    /// the source's 10s PPS request timeout
+    pub pps_keep_alive: Duration,
+}
+
+impl Default for DeviceConfig {
+    fn default() -> Self {
+        Self {
+            min_renegotiation_interval: Duration::from_secs(2),
+            max_request_attempts: 3,
+            pps_limits: PpsLimits::default(),
+            pps_keep_alive: Duration::from_secs(8),
+        }
+    }
+}
+
+/// Counts requests per target voltage and de-rates after repeated failures
+///
+/// A request counts as failed when the next request arrives without the
+/// previous one having been accepted (e.g. rejected by a source whose shared
+/// power budget is momentarily exhausted).
+struct RequestAttempts {
+    max_attempts: u32,
+    /// Last requested voltage (mV) not yet accepted, and how often it was sent
+    pending: Option<(u32, u32)>,
+    /// After de-rating, only voltages below this are requested
+    ceiling_mv: Option<u32>,
+}
+
+impl RequestAttempts {
+    fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts,
+            pending: None,
+            ceiling_mv: None,
+        }
+    }
+
+    /// Voltage to request for `target_mv` out of the `offered` fixed voltages
+    fn next(&mut self, target_mv: u32, offered: &[u32]) -> Option<u32> {
+        let highest_below = |limit: u32| offered.iter().copied().filter(|v| *v < limit).max();
+
+        let limit = target_mv
+            .saturating_add(1)
+            .min(self.ceiling_mv.unwrap_or(u32::MAX));
+        let mut voltage = highest_below(limit).or_else(|| offered.iter().copied().min())?;
+
+        let mut attempts = match self.pending {
+            Some((pending_mv, attempts)) if pending_mv == voltage => attempts + 1,
+            _ => 1,
+        };
+        if attempts > self.max_attempts {
+            if let Some(lower) = highest_below(voltage) {
+                warn!(
+                    "No contract at {}mV after {} attempts, de-rating to {}mV",
+                    voltage, self.max_attempts, lower
+                );
+                self.ceiling_mv = Some(voltage);
+                voltage = lower;
+                attempts = 1;
+            }
+        }
+
+        self.pending = Some((voltage, attempts));
+        Some(voltage)
+    }
+
+    fn accepted(&mut self) {
+        self.pending = None;
+    }
+
+    fn reset(&mut self) {
+        self.pending = None;
+        self.ceiling_mv = None;
+    }
+}
+
+/// Rate limits on-demand renegotiations
+struct RenegotiationGuard {
+    min_interval: Duration,
+    last_issued: Option<Instant>,
+    pending: bool,
+}
+
+impl RenegotiationGuard {
+    fn new(min_interval: Duration) -> Self {
+        Self {
+            min_interval,
+            last_issued: None,
+            pending: false,
+        }
+    }
+
+    /// Register a request, returns true if it may be issued now
+    ///
+    /// Otherwise the request is deferred until `deferred_deadline`; further
+    /// requests before then are folded into the same deferred one.
+    fn request(&mut self, now: Instant) -> bool {
+        match self.last_issued {
+            Some(last) if now - last < self.min_interval => {
+                self.pending = true;
+                false
+            }
+            _ => {
+                self.issue(now);
+                true
+            }
+        }
+    }
+
+    /// When the deferred renegotiation becomes due, if there is one
+    fn deferred_deadline(&self) -> Option<Instant> {
+        self.last_issued
+            .filter(|_| self.pending)
+            .map(|last| last + self.min_interval)
+    }
+
+    fn issue(&mut self, now: Instant) {
+        self.last_issued = Some(now);
+        self.pending = false;
+    }
+}
+
+fn publish_pd_status(status: PdStatus) {
+    crate::fault::set_active(
+        crate::fault::Fault::PdNegotiation,
+        status == PdStatus::NegotiationFailed,
+    );
+    crate::shared::PD_STATUS_CHANNEL.sender().send(status);
+}
+
+/// Contract in force, `None` before the first contract or once it is lost
+///
+/// Reads the published channels, so unlike `SinkAgent::get_active_contract`
+/// it never waits on the policy engine.
+pub fn active_contract() -> Option<PdContract> {
+    if pd_status() != PdStatus::Negotiated {
+        return None;
+    }
+    crate::shared::PD_CONTRACT_CHANNEL.anon_receiver().try_get()
+}
+
+/// Latest published PD status
+pub fn pd_status() -> PdStatus {
+    crate::shared::PD_STATUS_CHANNEL
+        .anon_receiver()
+        .try_get()
+        .unwrap_or(PdStatus::Detached)
+}
+
+// Flags the session as failed if no contract is established within `timeout`.
+// Never returns, so the sink keeps running in case a late contract arrives.
+async fn negotiation_watchdog(timeout: Duration) -> Infallible {
+    Timer::after(timeout).await;
+    if pd_status() != PdStatus::Negotiated {
+        warn!(
+            "No PD contract after {}ms, flagging negotiation failure",
+            timeout.as_millis()
+        );
+        publish_pd_status(PdStatus::NegotiationFailed);
+    }
+    core::future::pending().await
+}
+
+/// Which CC line the attached cable connects
+#[derive(Debug, Clone, Copy, PartialEq, Format)]
+pub enum CableOrientation {
     Normal,
     Flipped,
     DebugAccessoryMode,
@@ -49,15 +750,28 @@ impl<T: Instance> SinkDriver for UcpdSinkDriver<'_, T> {
     }
 
     async fn receive(&mut self, buffer: &mut [u8]) -> Result<usize, usbpd::DriverRxError> {
-        self.pd_phy.receive(buffer).await.map_err(|err| match err {
-            ucpd::RxError::Crc | ucpd::RxError::Overrun => usbpd::DriverRxError::Discarded,
+        let result = self.pd_phy.receive(buffer).await.map_err(|err| match err {
+            ucpd::RxError::Crc | ucpd::RxError::Overrun => {
+                count_phy_error();
+                usbpd::DriverRxError::Discarded
+            }
             ucpd::RxError::HardReset => usbpd::DriverRxError::HardReset,
-        })
+        });
+        #[cfg(feature = "pd-trace")]
+        if let Ok(n) = result {
+            crate::pd_trace::log(crate::pd_trace::Direction::Rx, &buffer[..n]);
+        }
+        result
     }
 
     async fn transmit(&mut self, data: &[u8]) -> Result<(), usbpd::DriverTxError> {
+        #[cfg(feature = "pd-trace")]
+        crate::pd_trace::log(crate::pd_trace::Direction::Tx, data);
         self.pd_phy.transmit(data).await.map_err(|err| match err {
-            ucpd::TxError::Discarded => usbpd::DriverTxError::Discarded,
+            ucpd::TxError::Discarded => {
+                count_phy_error();
+                usbpd::DriverTxError::Discarded
+            }
             ucpd::TxError::HardReset => usbpd::DriverTxError::HardReset,
         })
     }
@@ -123,19 +837,71 @@ impl SinkTimer for EmbassySinkTimer {
 #[allow(dead_code)]
 pub enum DeviceRequest {
     GetSourceCapabilities(Arc<Signal<CriticalSectionRawMutex, Option<SourceCapabilities>>>),
+    /// Contract accepted by the attached source, `None` before the first one
+    GetActiveContract(Arc<Signal<CriticalSectionRawMutex, Option<PdContract>>>),
+    /// Re-run the request policy against fresh source capabilities
+    Renegotiate,
 }
 
 #[derive(Clone, Debug, defmt::Format)]
 #[allow(dead_code)]
 pub enum RequestError {
+    /// The source offers no PDO matching the request
     Mismatch,
     Unsupported,
 }
 
+/// Errors published on `PD_ERROR_CHANNEL`
+#[derive(defmt::Format)]
+pub enum PdError {
+    /// The sink policy engine stopped with a fatal error
+    Sink(sink::policy_engine::Error),
+    /// A request could not be served as configured
+    Request(RequestError),
+}
+
+impl PdError {
+    /// Number of power LED blinks identifying the error
+    pub fn blink_count(&self) -> u8 {
+        match self {
+            // Negotiation failed: the source never answered our messages
+            Self::Sink(sink::policy_engine::Error::PortPartnerUnresponsive) => 2,
+            // Protocol failures end in a hard reset of the port
+            Self::Sink(sink::policy_engine::Error::Protocol(_)) => 3,
+            Self::Sink(_) => 4,
+            Self::Request(_) => 5,
+        }
+    }
+}
+
+/// Publish a request error without blocking the policy engine
+fn report_request_error(error: RequestError) {
+    if crate::shared::PD_ERROR_CHANNEL
+        .try_send(Arc::new(PdError::Request(error)))
+        .is_err()
+    {
+        warn!("PD error channel full, request error dropped");
+    }
+}
+
 struct DeviceCtx<'a> {
     active_power_source: Option<PowerSource>,
+    requested_contract: Option<PdContract>,
+    /// Contract accepted by the source for the current attachment
+    active_contract: Option<PdContract>,
+    /// Desired voltage/current, refreshed from the config snapshot per request
+    target: Option<RequestTarget>,
+    /// The active request is PPS and must be repeated before it times out
+    pps_active: bool,
+    pps_limits: PpsLimits,
+    pps_keep_alive: Duration,
     req_rx: watch::Receiver<'a, CriticalSectionRawMutex, DeviceRequest, 1>,
     source_capabilities: Option<SourceCapabilities>,
+    renegotiation: RenegotiationGuard,
+    request_attempts: RequestAttempts,
+    /// Target last reported as not offered, so re-requests for the same
+    /// attachment and target do not replay the Mismatch error
+    mismatch_reported: Option<RequestTarget>,
 }
 
 #[derive(Clone)]
@@ -144,17 +910,46 @@ pub struct Device<'a> {
 }
 
 impl<'a> Device<'a> {
-    pub fn new(req_rx: watch::Receiver<'a, CriticalSectionRawMutex, DeviceRequest, 1>) -> Self {
+    pub fn new(
+        req_rx: watch::Receiver<'a, CriticalSectionRawMutex, DeviceRequest, 1>,
+        config: DeviceConfig,
+    ) -> Self {
         Self {
             ctx: Arc::new(Mutex::new(DeviceCtx {
                 active_power_source: None,
+                requested_contract: None,
+                active_contract: None,
+                target: None,
+                pps_active: false,
+                pps_limits: config.pps_limits,
+                pps_keep_alive: config.pps_keep_alive,
                 req_rx,
                 source_capabilities: None,
+                renegotiation: RenegotiationGuard::new(config.min_renegotiation_interval),
+                request_attempts: RequestAttempts::new(config.max_request_attempts),
+                mismatch_reported: None,
             })),
         }
     }
 }
 
+impl Device<'_> {
+    /// Forget attempt counts and de-rating, e.g. for a new source
+    async fn reset_request_attempts(&self) {
+        self.ctx.lock().await.request_attempts.reset();
+    }
+
+    /// Drop the contract of a source that is gone
+    async fn clear_active_contract(&self) {
+        self.ctx.lock().await.active_contract = None;
+    }
+
+    /// Report a target mismatch again for the next source
+    async fn clear_reported_mismatch(&self) {
+        self.ctx.lock().await.mismatch_reported = None;
+    }
+}
+
 impl DevicePolicyManager for Device<'_> {
     async fn request(
         &mut self,
@@ -163,20 +958,148 @@ impl DevicePolicyManager for Device<'_> {
         let mut ctx = self.ctx.lock().await;
         ctx.source_capabilities = Some(source_capabilities.clone());
 
-        // 简化策略：总是请求最高电压和最大电流
-        let req = PowerSource::new_fixed(
-            CurrentRequest::Highest,
-            VoltageRequest::Highest,
-            source_capabilities,
-        )
-        .unwrap();
+        let highest = || {
+            PowerSource::new_fixed(
+                CurrentRequest::Highest,
+                VoltageRequest::Highest,
+                source_capabilities,
+            )
+            .unwrap_or_else(|_| {
+                // 不规范的源端可能不提供固定 PDO，退回请求首个 PDO 的最小电流
+                warn!("No fixed PDO offered, requesting object position 1");
+                fixed_variable_rdo(1, FALLBACK_CURRENT_MA)
+            })
+        };
+
+        if let Some(target) = RequestTarget::from_snapshot() {
+            ctx.target = Some(target);
+        }
+        let strategy = request_strategy();
 
-        defmt::info!("request: highest voltage and current");
+        // 配置目标落在 PPS APDO 范围内时优先协商 PPS
+        if strategy == RequestStrategy::ConfigTarget {
+            let pps = ctx
+                .target
+                .and_then(|target| pps_power_source(target, source_capabilities, ctx.pps_limits));
+            if let Some((req, contract)) = pps {
+                defmt::info!(
+                    "request: PPS {}mV {}mA",
+                    (contract.voltage * 1000.0) as u32,
+                    (contract.current * 1000.0) as u32
+                );
+                ctx.pps_active = true;
+                ctx.active_power_source = Some(req);
+                ctx.requested_contract = Some(contract);
+                return req;
+            }
+        }
+        ctx.pps_active = false;
+
+        // 按当前策略选择电压；同一电压多次未被接受时降额到更低的固定电压
+        let offered: Vec<u32> = fixed_supplies(source_capabilities)
+            .map(|(voltage_mv, _)| voltage_mv)
+            .collect();
+
+        // 可变 PDO 的输出电压可在其范围内浮动，只在源端没有可用的固定 PDO 时
+        // 使用；有固定 PDO 时取最接近目标的固定电压
+        let (wanted_mv, wanted_current_ma) = match strategy {
+            RequestStrategy::HighestPower => (None, None),
+            RequestStrategy::FixedVoltage(voltage_mv) => (Some(voltage_mv), None),
+            RequestStrategy::ConfigTarget => (
+                ctx.target.map(|target| target.voltage_mv),
+                ctx.target.map(|target| target.current_ma),
+            ),
+        };
+        let variable = wanted_mv
+            .filter(|_| offered.is_empty())
+            .and_then(|voltage_mv| {
+                variable_power_source(voltage_mv, wanted_current_ma, source_capabilities)
+            });
+        if let Some((req, contract)) = variable {
+            defmt::info!(
+                "request: variable {}-{}mV {}mA",
+                (contract.min_voltage * 1000.0) as u32,
+                (contract.voltage * 1000.0) as u32,
+                (contract.current * 1000.0) as u32
+            );
+            ctx.active_power_source = Some(req);
+            ctx.requested_contract = Some(contract);
+            return req;
+        }
+        let target_mv = match strategy {
+            RequestStrategy::HighestPower => Some(u32::MAX),
+            RequestStrategy::FixedVoltage(voltage_mv) => Some(voltage_mv),
+            // 取最接近配置目标的固定电压；未精确匹配时上报 Mismatch，
+            // 同一次连接内同一目标只上报一次，避免重复请求时反复显示闪码
+            RequestStrategy::ConfigTarget => ctx.target.and_then(|target| {
+                let closest = closest_voltage(&offered, target.voltage_mv);
+                if closest != Some(target.voltage_mv) && ctx.mismatch_reported != Some(target) {
+                    warn!(
+                        "Target {}mV not offered, using {}mV",
+                        target.voltage_mv, closest
+                    );
+                    report_request_error(RequestError::Mismatch);
+                    ctx.mismatch_reported = Some(target);
+                }
+                closest
+            }),
+        };
+        // 配置目标电流限制请求电流，其它策略请求 PDO 的最大电流
+        let current_limit_ma = match strategy {
+            RequestStrategy::ConfigTarget => ctx.target.map(|target| target.current_ma),
+            _ => None,
+        };
+
+        let selected = target_mv
+            .and_then(|target_mv| ctx.request_attempts.next(target_mv, &offered))
+            .map(RequestStrategy::FixedVoltage)
+            .unwrap_or(RequestStrategy::HighestPower);
+        let contract = selected
+            .contract(source_capabilities, None)
+            .map(|contract| PdContract {
+                current: current_limit_ma.map_or(contract.current, |ma| {
+                    contract.current.min(ma as f64 / 1000.0)
+                }),
+                ..contract
+            });
+        let req = match selected {
+            RequestStrategy::FixedVoltage(voltage_mv) => {
+                let current =
+                    match (current_limit_ma, contract) {
+                        (Some(_), Some(contract)) => CurrentRequest::Specific(
+                            ElectricCurrent::new::<milliampere>((contract.current * 1000.0) as u32),
+                        ),
+                        _ => CurrentRequest::Highest,
+                    };
+                PowerSource::new_fixed(
+                    current,
+                    VoltageRequest::Specific(ElectricPotential::new::<millivolt>(voltage_mv)),
+                    source_capabilities,
+                )
+                .unwrap_or_else(|_| highest())
+            }
+            _ => highest(),
+        };
+
+        defmt::info!("request: {} (strategy {})", selected, strategy);
         ctx.active_power_source = Some(req);
+        ctx.requested_contract = contract;
 
         req
     }
 
+    async fn transition_power(&mut self, _accepted: &PowerSource) {
+        info!("PD contract established");
+        let mut ctx = self.ctx.lock().await;
+        ctx.request_attempts.accepted();
+        ctx.active_contract = ctx.requested_contract;
+        if let Some(contract) = ctx.requested_contract {
+            info!("Contract: {}V {}A", contract.voltage, contract.current);
+            crate::shared::PD_CONTRACT_CHANNEL.sender().send(contract);
+        }
+        publish_pd_status(PdStatus::Negotiated);
+    }
+
     async fn get_event(
         &mut self,
         _: &SourceCapabilities,
@@ -184,17 +1107,52 @@ impl DevicePolicyManager for Device<'_> {
         use usbpd::sink::device_policy_manager::Event;
 
         let mut ctx = self.ctx.lock().await;
-        let keep_alive_ticker = Timer::after_secs(10);
+        let keep_alive_ticker = Timer::after(if ctx.pps_active {
+            ctx.pps_keep_alive
+        } else {
+            Duration::from_secs(10)
+        });
+        let deferred_renegotiation = Timer::at(
+            ctx.renegotiation
+                .deferred_deadline()
+                .unwrap_or(Instant::MAX),
+        );
 
-        let futures = select(ctx.req_rx.changed(), keep_alive_ticker);
+        let futures = select3(
+            ctx.req_rx.changed(),
+            keep_alive_ticker,
+            deferred_renegotiation,
+        );
 
         match futures.await {
-            Either::First(DeviceRequest::GetSourceCapabilities(resp_signal)) => {
+            Either3::First(DeviceRequest::GetSourceCapabilities(resp_signal)) => {
                 resp_signal.signal(ctx.source_capabilities.clone());
                 Event::None
             }
-            Either::Second(_) => {
+            Either3::First(DeviceRequest::GetActiveContract(resp_signal)) => {
+                resp_signal.signal(ctx.active_contract);
+                Event::None
+            }
+            Either3::First(DeviceRequest::Renegotiate) => {
+                // 主动重新协商时重新尝试目标电压
+                ctx.request_attempts.reset();
+                if ctx.renegotiation.request(Instant::now()) {
+                    info!("Renegotiating PD contract");
+                    Event::RequestSourceCapabilities
+                } else {
+                    info!("Renegotiation request coalesced");
+                    Event::None
+                }
+            }
+            Either3::Second(_) => match (ctx.pps_active, ctx.active_power_source) {
+                // PPS 合约需在源端超时前重发相同请求
+                (true, Some(source)) => Event::RequestPower(source),
                 // 定期保持连接活跃
+                _ => Event::RequestSourceCapabilities,
+            },
+            Either3::Third(_) => {
+                info!("Renegotiating PD contract (coalesced request)");
+                ctx.renegotiation.issue(Instant::now());
                 Event::RequestSourceCapabilities
             }
         }
@@ -211,7 +1169,6 @@ impl<'a> SinkAgent<'a> {
         Self { req_tx }
     }
 
-    #[allow(dead_code)]
     pub async fn get_source_capabilities(&self) -> Option<SourceCapabilities> {
         let resp = Arc::new(Signal::new());
         self.req_tx
@@ -219,6 +1176,59 @@ impl<'a> SinkAgent<'a> {
 
         resp.wait().await
     }
+
+    /// Contract accepted by the attached source, `None` before the first one
+    #[allow(dead_code)]
+    pub async fn get_active_contract(&self) -> Option<PdContract> {
+        let resp = Arc::new(Signal::new());
+        self.req_tx
+            .send(DeviceRequest::GetActiveContract(resp.clone()));
+
+        resp.wait().await
+    }
+
+    /// Ask for a new contract; rate limited by `DeviceConfig`
+    pub fn renegotiate(&self) {
+        self.req_tx.send(DeviceRequest::Renegotiate);
+    }
+}
+
+/// Request the source capabilities once per contract and publish them
+///
+/// Runs for the lifetime of the firmware; a new request is issued after every
+/// detach/re-attach cycle.
+pub async fn source_capabilities_task(
+    sink_agent: SinkAgent<'static>,
+    mut pd_status_rx: watch::Receiver<'static, CriticalSectionRawMutex, PdStatus, 2>,
+) {
+    let capabilities_tx = crate::shared::SOURCE_CAPABILITIES_CHANNEL.sender();
+
+    loop {
+        pd_status_rx
+            .changed_and(|status| *status == PdStatus::Negotiated)
+            .await;
+
+        match with_timeout(
+            Duration::from_secs(15),
+            sink_agent.get_source_capabilities(),
+        )
+        .await
+        {
+            Ok(Some(capabilities)) => {
+                info!(
+                    "Source capabilities cached: {}",
+                    crate::types::AvailableVoltCurr::from_capabilities(&capabilities)
+                );
+                capabilities_tx.send(capabilities);
+            }
+            Ok(None) => warn!("Source capabilities not available yet"),
+            Err(_) => warn!("Timed out requesting source capabilities"),
+        }
+
+        pd_status_rx
+            .changed_and(|status| *status == PdStatus::Detached)
+            .await;
+    }
 }
 
 pub struct PowerInput<'d, T, Irq, C1P, C2P, Rx, Tx>
@@ -238,8 +1248,8 @@ where
     rx_dma: Peri<'d, Rx>,
     tx_dma: Peri<'d, Tx>,
     device: Device<'d>,
-    pd_sink_error_tx:
-        channel::Sender<'d, CriticalSectionRawMutex, Arc<sink::policy_engine::Error>, 1>,
+    input_config: PowerInputConfig,
+    pd_sink_error_tx: channel::Sender<'d, CriticalSectionRawMutex, Arc<PdError>, 1>,
     _phantom: PhantomData<(&'d T, C1P, C2P, Rx, Tx)>,
 }
 
@@ -262,14 +1272,14 @@ where
         rx_dma: Peri<'d, Rx>,
         tx_dma: Peri<'d, Tx>,
         device: Device<'d>,
-        pd_sink_error_tx: channel::Sender<
-            'd,
-            CriticalSectionRawMutex,
-            Arc<sink::policy_engine::Error>,
-            1,
-        >,
-    ) -> Self {
-        Self {
+        input_config: PowerInputConfig,
+        pd_sink_error_tx: channel::Sender<'d, CriticalSectionRawMutex, Arc<PdError>, 1>,
+    ) -> Result<Self, UnsupportedCcTermination> {
+        if !input_config.cc_termination.is_supported() {
+            return Err(UnsupportedCcTermination(input_config.cc_termination));
+        }
+
+        Ok(Self {
             peri,
             irq,
             cc1,
@@ -278,12 +1288,22 @@ where
             rx_dma,
             tx_dma,
             device,
+            input_config,
             _phantom: PhantomData,
             pd_sink_error_tx,
-        }
+        })
     }
 
     pub async fn run(&mut self) {
+        let mut retry_budget = RetryBudget::new(
+            self.input_config.max_retries,
+            self.input_config.retry_window,
+        );
+
+        // Set while the sink restarts after a recoverable error. The cable is
+        // still attached then, so no detach is published.
+        let mut retrying = false;
+
         loop {
             let mut ucpd = Ucpd::new(
                 self.peri.reborrow(),
@@ -292,10 +1312,30 @@ where
                 self.cc2.reborrow(),
                 self.config,
             );
-            ucpd.cc_phy().set_pull(CcPull::Sink);
+            ucpd.cc_phy()
+                .set_pull(self.input_config.cc_termination.pull());
+            if !retrying {
+                publish_pd_status(PdStatus::Detached);
+                self.device.clear_reported_mismatch().await;
+            }
+
+            if self.input_config.cc_termination != CcTermination::Sink {
+                // Rp only advertises; there is no sink policy engine to run.
+                info!(
+                    "Presenting {} on CC, PD sink disabled",
+                    self.input_config.cc_termination
+                );
+                core::future::pending::<()>().await;
+            }
+
+            self.device.clear_active_contract().await;
             info!("Waiting for USB connection...");
             let cable_orientation = wait_attached(ucpd.cc_phy()).await;
             info!("USB cable attached, orientation: {}", cable_orientation);
+            retrying = false;
+            PHY_ERRORS.store(0, Ordering::Relaxed);
+            self.device.reset_request_attempts().await;
+            publish_pd_status(PdStatus::Attached(cable_orientation));
 
             let cc_sel = match cable_orientation {
                 CableOrientation::Normal => {
@@ -316,19 +1356,58 @@ where
                 Sink::new(driver, self.device.clone());
             info!("Run sink");
 
-            match select(sink.run(), wait_detached(&mut cc_phy)).await {
-                Either::First(result) => {
+            match select3(
+                sink.run(),
+                wait_detached(&mut cc_phy),
+                negotiation_watchdog(self.input_config.negotiation_timeout),
+            )
+            .await
+            {
+                Either3::First(result) => {
                     warn!("Sink loop broken with result: {}", result);
+                    let recoverable = match &result {
+                        Ok(()) => true,
+                        Err(err) => is_recoverable(err),
+                    };
+                    if recoverable && retry_budget.try_consume(Instant::now()) {
+                        let delay = retry_budget.backoff(
+                            self.input_config.retry_settle,
+                            self.input_config.retry_backoff_max,
+                        );
+                        info!(
+                            "Recoverable PD error, restarting sink in {}ms (retry {}/{})",
+                            delay.as_millis(),
+                            retry_budget.retries,
+                            self.input_config.max_retries
+                        );
+                        match select(Timer::after(delay), wait_detached(&mut cc_phy)).await {
+                            Either::First(()) => retrying = true,
+                            Either::Second(_) => {
+                                info!("Detached");
+                                retry_budget.reset();
+                            }
+                        }
+                        continue;
+                    }
+
                     if let Err(err) = result {
-                        self.pd_sink_error_tx.send(Arc::new(err)).await;
-                        // This is an unrecoverable error for this session.
-                        // Terminate the task to release the UCPD peripheral.
-                        warn!("Unrecoverable PD error. Terminating task.");
-                        return;
+                        self.pd_sink_error_tx
+                            .send(Arc::new(PdError::Sink(err)))
+                            .await;
                     }
+                    // Either fatal or retried too often within the window.
+                    // Stay off the bus until the cable is replugged, then start over
+                    // with a fresh budget so the port is not dead until reset.
+                    warn!("Unrecoverable PD error. Waiting for detach.");
+                    publish_pd_status(PdStatus::NegotiationFailed);
+                    wait_detached(&mut cc_phy).await;
+                    info!("Detached");
+                    retry_budget.reset();
+                    continue;
                 }
-                Either::Second(_) => {
+                Either3::Second(_) => {
                     info!("Detached");
+                    retry_budget.reset();
                     // Loop to wait for a new connection.
                     continue;
                 }
@@ -336,3 +1415,133 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pps_clamp_to_apdo_and_safe_range() {
+        let apdos = [(5, 3_300, 11_000), (6, 3_300, 21_000)];
+        let limits = PpsLimits {
+            min_mv: 5_000,
+            max_mv: 20_000,
+        };
+
+        // Snapped to the nearest 20mV step
+        assert_eq!(
+            clamp_pps_voltage(9_011, &apdos, limits),
+            Ok(PpsRequest {
+                object_position: 5,
+                voltage_mv: 9_020
+            })
+        );
+        // Clamped to the safe range on both ends
+        assert_eq!(
+            clamp_pps_voltage(4_000, &apdos, limits).map(|r| r.voltage_mv),
+            Ok(5_000)
+        );
+        assert_eq!(
+            clamp_pps_voltage(20_990, &apdos, limits),
+            Ok(PpsRequest {
+                object_position: 6,
+                voltage_mv: 20_000
+            })
+        );
+        assert_eq!(
+            clamp_pps_voltage(25_000, &apdos, limits),
+            Err(PpsError::NoMatchingApdo)
+        );
+        assert_eq!(
+            clamp_pps_voltage(4_000, &[(5, 3_300, 4_500)], limits),
+            Err(PpsError::OutsideSafeRange)
+        );
+    }
+
+    #[test]
+    fn test_retry_budget_caps_within_window() {
+        let mut budget = RetryBudget::new(2, Duration::from_secs(10));
+
+        assert!(budget.try_consume(Instant::from_secs(0)));
+        assert!(budget.try_consume(Instant::from_secs(1)));
+        assert!(!budget.try_consume(Instant::from_secs(2)));
+
+        // A new window restores the budget
+        assert!(budget.try_consume(Instant::from_secs(11)));
+    }
+
+    #[test]
+    fn test_retry_backoff_doubles_up_to_cap() {
+        let mut budget = RetryBudget::new(5, Duration::from_secs(60));
+        let base = Duration::from_millis(500);
+        let max = Duration::from_secs(3);
+
+        let delays: Vec<u64> = (0..5)
+            .map(|i| {
+                assert!(budget.try_consume(Instant::from_secs(i)));
+                budget.backoff(base, max).as_millis()
+            })
+            .collect();
+        assert_eq!(delays, [500, 1_000, 2_000, 3_000, 3_000]);
+    }
+
+    #[test]
+    fn test_request_attempts_derate_after_retries() {
+        let offered = [5_000, 9_000, 15_000, 20_000];
+        let mut attempts = RequestAttempts::new(2);
+
+        assert_eq!(attempts.next(u32::MAX, &offered), Some(20_000));
+        assert_eq!(attempts.next(u32::MAX, &offered), Some(20_000));
+        // Third unanswered request at 20V de-rates to the next lower PDO
+        assert_eq!(attempts.next(u32::MAX, &offered), Some(15_000));
+
+        attempts.accepted();
+        assert_eq!(attempts.next(u32::MAX, &offered), Some(15_000));
+
+        attempts.reset();
+        assert_eq!(attempts.next(12_000, &offered), Some(9_000));
+    }
+
+    #[test]
+    fn test_nearest_voltage_covers_variable_range() {
+        let fixed = PdContract::fixed(9_000, 3_000);
+        assert_eq!(fixed.nearest_voltage(8.5), 9.0);
+        assert_eq!(fixed.nearest_voltage(9.5), 9.0);
+
+        let variable = PdContract {
+            voltage: 21.0,
+            current: 2.0,
+            min_voltage: 5.0,
+        };
+        assert_eq!(variable.nearest_voltage(12.3), 12.3);
+        assert_eq!(variable.nearest_voltage(3.0), 5.0);
+        assert_eq!(variable.nearest_voltage(25.0), 21.0);
+    }
+
+    #[test]
+    fn test_closest_voltage_prefers_lower_on_tie() {
+        let offered = [5_000, 9_000, 15_000, 20_000];
+        assert_eq!(closest_voltage(&offered, 9_000), Some(9_000));
+        assert_eq!(closest_voltage(&offered, 12_000), Some(9_000));
+        assert_eq!(closest_voltage(&offered, 13_000), Some(15_000));
+        assert_eq!(closest_voltage(&offered, 48_000), Some(20_000));
+        assert_eq!(closest_voltage(&[], 5_000), None);
+    }
+
+    #[test]
+    fn test_renegotiation_guard_coalesces_within_interval() {
+        let mut guard = RenegotiationGuard::new(Duration::from_secs(2));
+
+        assert!(guard.request(Instant::from_secs(0)));
+        assert_eq!(guard.deferred_deadline(), None);
+
+        // Two requests inside the window collapse into one deferred renegotiation
+        assert!(!guard.request(Instant::from_millis(500)));
+        assert!(!guard.request(Instant::from_millis(900)));
+        assert_eq!(guard.deferred_deadline(), Some(Instant::from_secs(2)));
+
+        guard.issue(Instant::from_secs(2));
+        assert_eq!(guard.deferred_deadline(), None);
+        assert!(guard.request(Instant::from_secs(4)));
+    }
+}
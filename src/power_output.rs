@@ -1,53 +1,98 @@
 #![allow(dead_code)]
 
 use alloc::sync::Arc;
-use core::sync::atomic::AtomicBool;
+use core::sync::atomic::{AtomicBool, Ordering};
 use embassy_stm32::gpio::{Level, Output};
-use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex};
-use embassy_time::Timer;
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex, signal::Signal};
+use embassy_time::{with_timeout, Duration, Instant, Timer};
+use uom::si::electric_potential::volt;
 
 const OFF_LEVEL: Level = Level::Low;
 const ON_LEVEL: Level = Level::High;
 
+/// VBUS voltage at which the output counts as discharged
+const DISCHARGED_VOLTAGE: f64 = 1.0;
+
+/// How often the VBUS voltage is checked while discharging
+const DISCHARGE_POLL: Duration = Duration::from_millis(10);
+
+/// Period of the pulse train driven on the enable pin during soft-start
+const SOFT_START_PERIOD: Duration = Duration::from_millis(1);
+
+/// Output state shared between clones, with change notification
+#[derive(Clone, Default)]
+struct OutputState {
+    state: Arc<AtomicBool>,
+    prev_state: Arc<AtomicBool>,
+    changed: Arc<Signal<CriticalSectionRawMutex, ()>>,
+}
+
+impl OutputState {
+    fn get(&self) -> bool {
+        self.state.load(Ordering::SeqCst)
+    }
+
+    fn set(&self, state: bool) {
+        if self.state.swap(state, Ordering::SeqCst) != state {
+            self.changed.signal(());
+        }
+    }
+
+    async fn wait_change(&self) -> bool {
+        loop {
+            let old = self.prev_state.load(Ordering::SeqCst);
+            let state = self.state.load(Ordering::SeqCst);
+            if state != old {
+                self.prev_state.store(state, Ordering::SeqCst);
+                return state;
+            }
+            self.changed.wait().await;
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct PowerOutput<'d> {
     pin: Arc<Mutex<CriticalSectionRawMutex, Output<'d>>>,
-    state: Arc<AtomicBool>,
-    prev_state: Arc<AtomicBool>,
+    /// Active-high gate of the optional output discharge FET
+    discharge_pin: Option<Arc<Mutex<CriticalSectionRawMutex, Output<'d>>>>,
+    state: OutputState,
 }
 
 impl<'d> PowerOutput<'d> {
     pub fn new(pin: Output<'d>) -> Self {
         Self {
             pin: Arc::new(Mutex::new(pin)),
-            state: Arc::new(AtomicBool::new(false)),
-            prev_state: Arc::new(AtomicBool::new(false)),
+            discharge_pin: None,
+            state: OutputState::default(),
         }
     }
 
+    /// Enable active discharge through a FET driven by `pin`
+    pub fn with_discharge(mut self, mut pin: Output<'d>) -> Self {
+        pin.set_low();
+        self.discharge_pin = Some(Arc::new(Mutex::new(pin)));
+        self
+    }
+
+    /// Wait until the output state changes, returns the new state
     pub async fn wait_change(&self) -> bool {
-        let old = self.prev_state.load(core::sync::atomic::Ordering::SeqCst);
-        while self.state.load(core::sync::atomic::Ordering::SeqCst) == old {
-            Timer::after_millis(10).await;
-        }
-        self.prev_state
-            .store(!old, core::sync::atomic::Ordering::SeqCst);
-        !old
+        self.state.wait_change().await
+    }
+
+    /// Like `wait_change`, but gives up after `timeout` and returns `None`
+    pub async fn wait_change_timeout(&self, timeout: Duration) -> Option<bool> {
+        with_timeout(timeout, self.wait_change()).await.ok()
     }
 
     pub async fn get_state(&self) -> bool {
         let state = self.pin.lock().await.get_output_level() == ON_LEVEL;
-
-        if state != self.state.load(core::sync::atomic::Ordering::SeqCst) {
-            self.state
-                .store(state, core::sync::atomic::Ordering::SeqCst);
-        }
-
+        self.state.set(state);
         state
     }
 
     pub async fn toggle(&self) {
-        if self.state.load(core::sync::atomic::Ordering::SeqCst) {
+        if self.state.get() {
             defmt::info!("output off");
             self.set_off().await;
         } else {
@@ -58,8 +103,7 @@ impl<'d> PowerOutput<'d> {
 
     #[inline(always)]
     pub async fn set_state(&self, state: bool) {
-        self.state
-            .store(state, core::sync::atomic::Ordering::SeqCst);
+        self.state.set(state);
         self.pin
             .lock()
             .await
@@ -71,8 +115,90 @@ impl<'d> PowerOutput<'d> {
         self.set_state(true).await
     }
 
+    /// Turn on with a pulsed pre-charge spread over `ramp`
+    ///
+    /// The enable pin is a plain GPIO, so the ramp is a software pulse train
+    /// whose on-time grows linearly every `SOFT_START_PERIOD`; the switch's
+    /// gate charge smooths it into a limited inrush. Ramps shorter than one
+    /// period switch on at once.
+    pub async fn set_on_soft(&self, ramp: Duration) {
+        let steps = (ramp.as_ticks() / SOFT_START_PERIOD.as_ticks()) as u32;
+        self.state.set(true);
+        let mut pin = self.pin.lock().await;
+        for step in 1..steps {
+            let on_time = SOFT_START_PERIOD * step / steps;
+            pin.set_level(ON_LEVEL);
+            Timer::after(on_time).await;
+            pin.set_level(OFF_LEVEL);
+            Timer::after(SOFT_START_PERIOD - on_time).await;
+        }
+        pin.set_level(ON_LEVEL);
+    }
+
     #[inline(always)]
     pub async fn set_off(&self) {
         self.set_state(false).await
     }
+
+    /// Drain the output capacitance after turning off
+    ///
+    /// Asserts the discharge FET until VBUS falls below `DISCHARGED_VOLTAGE`
+    /// or `timeout` elapses. Returns how long discharge took, or `None` when
+    /// no discharge FET is fitted or VBUS stayed above the threshold.
+    pub async fn discharge(&self, timeout: Duration) -> Option<Duration> {
+        let discharge_pin = self.discharge_pin.as_ref()?;
+        if self.state.get() {
+            self.set_off().await;
+        }
+
+        let started = Instant::now();
+        discharge_pin.lock().await.set_high();
+        let discharged = with_timeout(timeout, async {
+            loop {
+                let vbus = crate::shared::MEASUREMENTS
+                    .vbus_voltage_raw
+                    .latest()
+                    .map(|v| v.get::<volt>());
+                if vbus.is_some_and(|v| v < DISCHARGED_VOLTAGE) {
+                    break;
+                }
+                Timer::after(DISCHARGE_POLL).await;
+            }
+        })
+        .await
+        .is_ok();
+        discharge_pin.lock().await.set_low();
+
+        if !discharged {
+            defmt::warn!(
+                "VBUS still above {}V after {}ms of discharge",
+                DISCHARGED_VOLTAGE,
+                timeout.as_millis()
+            );
+        }
+        discharged.then(|| started.elapsed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_wait_change_reports_new_state() {
+        let state = OutputState::default();
+        state.set(true);
+        assert!(state.wait_change().await);
+        state.set(false);
+        assert!(!state.wait_change().await);
+    }
+
+    #[tokio::test]
+    async fn test_wait_change_times_out_without_change() {
+        let state = OutputState::default();
+        state.set(false);
+
+        let result = with_timeout(Duration::from_millis(50), state.wait_change()).await;
+        assert!(result.is_err());
+    }
 }
@@ -0,0 +1,219 @@
+use embassy_time::Duration;
+
+use crate::{app_manager::SystemState, hal::OutputSwitch, vbus_manager::VbusState};
+
+/// Power-path rails controlled by the firmware
+///
+/// Shutdown order matters: VBUS must be cut before VIN.
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum RailId {
+    /// PA15 VIN_EN, input switch owned by `PowerManager`
+    Vin,
+    /// PB7 VBUS_EN, output switch owned by `VbusManager`
+    Vbus,
+}
+
+impl RailId {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Vin => "VIN (PA15 VIN_EN)",
+            Self::Vbus => "VBUS (PB7 VBUS_EN)",
+        }
+    }
+}
+
+/// A labelled power-path switch
+///
+/// Both managers drive their rail through this type so logging and generic
+/// safety logic treat VIN and VBUS uniformly.
+pub struct PowerRail<S> {
+    id: RailId,
+    switch: S,
+}
+
+impl<S: OutputSwitch> PowerRail<S> {
+    pub fn new(id: RailId, switch: S) -> Self {
+        Self { id, switch }
+    }
+
+    pub async fn set_enabled(&self, enabled: bool) {
+        if enabled {
+            self.switch.set_on().await;
+        } else {
+            self.switch.set_off().await;
+        }
+        defmt::info!(
+            "{} rail {}",
+            self.id.label(),
+            if enabled { "ON" } else { "OFF" }
+        );
+    }
+
+    /// Actively discharge the rail after it was turned off
+    ///
+    /// Returns the discharge time; `None` if the switch has no discharge
+    /// path or the voltage did not fall within `timeout`.
+    pub async fn discharge(&self, timeout: Duration) -> Option<Duration> {
+        self.switch.discharge(timeout).await
+    }
+
+    /// Turn the rail on with inrush limiting spread over `ramp`
+    pub async fn set_on_soft(&self, ramp: Duration) {
+        self.switch.set_on_soft(ramp).await;
+        defmt::info!(
+            "{} rail ON (soft-start {}ms)",
+            self.id.label(),
+            ramp.as_millis()
+        );
+    }
+}
+
+/// Desired state of both rails
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub struct RailStates {
+    pub vin: bool,
+    pub vbus: bool,
+}
+
+impl RailStates {
+    pub const OFF: Self = Self {
+        vin: false,
+        vbus: false,
+    };
+}
+
+/// Output table entry violating the power sequencing rules
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum OutputTableError {
+    /// VBUS on while VIN is off
+    VbusWithoutVin(SystemState, VbusState),
+    /// VBUS on although the VBUS manager has the output disabled
+    VbusNotRequested(SystemState, VbusState),
+}
+
+/// Rail states for each system state and VBUS manager state
+///
+/// Makes the power sequencing data rather than code. Combinations missing
+/// from the table resolve to both rails off.
+#[derive(Debug, Clone, Copy)]
+pub struct OutputTable {
+    pub entries: [(SystemState, VbusState, RailStates); 4],
+}
+
+impl Default for OutputTable {
+    fn default() -> Self {
+        use {SystemState::*, VbusState::*};
+
+        let rails = |vin, vbus| RailStates { vin, vbus };
+        Self {
+            entries: [
+                (Standby, Disabled, RailStates::OFF),
+                // Standby always cuts the output, even if it was left enabled
+                (Standby, Enabled, RailStates::OFF),
+                (Working, Disabled, rails(true, false)),
+                (Working, Enabled, rails(true, true)),
+            ],
+        }
+    }
+}
+
+impl OutputTable {
+    pub fn lookup(&self, system: SystemState, vbus: VbusState) -> RailStates {
+        self.entries
+            .iter()
+            .find(|(s, v, _)| *s == system && *v == vbus)
+            .map_or(RailStates::OFF, |(_, _, rails)| *rails)
+    }
+
+    /// Reject entries that could power the output unsafely
+    pub fn validate(&self) -> Result<(), OutputTableError> {
+        for &(system, vbus, rails) in &self.entries {
+            if rails.vbus && !rails.vin {
+                return Err(OutputTableError::VbusWithoutVin(system, vbus));
+            }
+            if rails.vbus && vbus == VbusState::Disabled {
+                return Err(OutputTableError::VbusNotRequested(system, vbus));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Boot-time check that both rails are really off before the managers start
+///
+/// The switch pins are driven low at reset, but an external pull-up or a
+/// latched driver could still leave a rail energized. After `settle` the
+/// measured voltages must be below the limits, otherwise bring-up stops.
+#[derive(Debug, Clone, Copy)]
+pub struct SafeStateCheck {
+    pub enabled: bool,
+    /// Time for the output capacitance to discharge after driving the pins low
+    pub settle: Duration,
+    /// Highest VBUS (VOUT_SN) reading accepted as off, in volts
+    pub vbus_max: f64,
+    /// Highest VIN reading accepted as off, in volts
+    ///
+    /// `None` skips VIN: VIN_SN measures the USB-C input that powers the
+    /// board, so it reads the source voltage regardless of VIN_EN.
+    pub vin_max: Option<f64>,
+}
+
+impl Default for SafeStateCheck {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            settle: Duration::from_millis(100),
+            vbus_max: 1.0,
+            vin_max: None,
+        }
+    }
+}
+
+impl SafeStateCheck {
+    /// Returns the first rail still energized, VBUS before VIN
+    pub fn verify(&self, vbus: f64, vin: f64) -> Result<(), RailId> {
+        if vbus > self.vbus_max {
+            return Err(RailId::Vbus);
+        }
+        if self.vin_max.is_some_and(|max| vin > max) {
+            return Err(RailId::Vin);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_table_validation() {
+        let mut table = OutputTable::default();
+        assert_eq!(table.validate(), Ok(()));
+        assert_eq!(
+            table.lookup(SystemState::Standby, VbusState::Enabled),
+            RailStates::OFF
+        );
+
+        table.entries[3].2.vin = false;
+        assert_eq!(
+            table.validate(),
+            Err(OutputTableError::VbusWithoutVin(
+                SystemState::Working,
+                VbusState::Enabled
+            ))
+        );
+    }
+
+    #[test]
+    fn test_safe_state_check() {
+        let mut check = SafeStateCheck::default();
+        // VIN carries the source voltage and is ignored by default
+        assert_eq!(check.verify(0.2, 5.1), Ok(()));
+        assert_eq!(check.verify(4.8, 5.1), Err(RailId::Vbus));
+
+        check.vin_max = Some(1.0);
+        assert_eq!(check.verify(0.2, 5.1), Err(RailId::Vin));
+        assert_eq!(check.verify(0.2, 0.3), Ok(()));
+    }
+}
@@ -1,61 +1,164 @@
 use crate::{
+    adc_reader::SamplingSettings,
+    app_manager::{Breathing, BreathingTarget, StandbyReason, SystemState},
+    bus::Measurements,
+    button::{ButtonId, Thresholds},
     config_manager::{Config, ConfigRequest},
+    fan_manager::FanCurve,
+    load_detect::LoadStatus,
     power,
+    source_health::SourceHealth,
+    telemetry::TelemetrySnapshot,
+    thermal::ThermalStatus,
+    types::{PowerInfo, StatusInfo},
+    vbus_manager::OutputRiseStatus,
 };
 use alloc::sync::Arc;
 use embassy_sync::{
     blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel, mutex::Mutex,
     pubsub::PubSubChannel, watch::Watch,
 };
+use uom::si::f64::ElectricPotential;
+use usbpd::protocol_layer::message::pdo::SourceCapabilities;
 
 #[allow(dead_code)]
 pub const VALUE_STEP_MILLIVOLTS: u32 = 100;
 pub const VREF: f64 = 3.0;
 
 pub const VSN_MUL: f64 = (130_000.0 + 10_000.0) / 10_000.0;
-#[allow(dead_code)]
+/// Amperes per volt of INA186 output: 10 mΩ shunt, gain 25 V/V
 pub const ISN_MUL: f64 = 1.0 / 0.010 / 25.0;
 
 // ADC and power constants
 
-pub(crate) static ADC_PUBSUB: PubSubChannel<CriticalSectionRawMutex, (f64, f64), 2, 1, 1> =
-    PubSubChannel::new();
+// Manager tick interval shared by PowerManager and VbusManager
+pub const MANAGER_TICK_MS: u64 = 20;
+
+/// Number of manager ticks spanning `period_ms`
+///
+/// Evaluated in const context, so a period shorter than one tick fails the build.
+pub const fn ticks_for_ms(period_ms: u64) -> u32 {
+    let ticks = period_ms / MANAGER_TICK_MS;
+    assert!(ticks > 0, "period shorter than one manager tick");
+    ticks as u32
+}
+
+/// (VBUS, VIN) voltages from the ADC reader
+pub(crate) static ADC_PUBSUB: PubSubChannel<
+    CriticalSectionRawMutex,
+    (ElectricPotential, ElectricPotential),
+    2,
+    1,
+    1,
+> = PubSubChannel::new();
 
-#[allow(dead_code)]
 pub(crate) static CONFIG_REQUEST_CHANNEL: Channel<CriticalSectionRawMutex, ConfigRequest, 1> =
     Channel::new();
 
-pub(crate) static CONFIG_SNAPSHOT_CHANNEL: Watch<CriticalSectionRawMutex, Config, 1> = Watch::new();
+/// Counted receivers of `CONFIG_SNAPSHOT_CHANNEL`: the main loop and the integrity check agent
+pub(crate) const CONFIG_SNAPSHOT_RECEIVERS: usize = 2;
+
+pub(crate) static CONFIG_SNAPSHOT_CHANNEL: Watch<
+    CriticalSectionRawMutex,
+    Config,
+    CONFIG_SNAPSHOT_RECEIVERS,
+> = Watch::new();
 
 pub(crate) static SINK_REQUEST_CHANNEL: Watch<CriticalSectionRawMutex, power::DeviceRequest, 1> =
     Watch::new();
 
-pub(crate) static PD_ERROR_CHANNEL: Channel<
-    CriticalSectionRawMutex,
-    Arc<usbpd::sink::policy_engine::Error>,
-    1,
-> = Channel::new();
+pub(crate) static PD_ERROR_CHANNEL: Channel<CriticalSectionRawMutex, Arc<power::PdError>, 1> =
+    Channel::new();
+
+// Measurement topics (VBUS/VIN voltage, output current, temperature, fan RPM)
+pub(crate) static MEASUREMENTS: Measurements = Measurements::new();
+
+// Output power of the latest ADC sample
+pub(crate) static POWER_INFO_CHANNEL: Watch<CriticalSectionRawMutex, PowerInfo, 1> = Watch::new();
 
-// VBUS voltage status channel
-pub(crate) static VBUS_VOLTAGE_CHANNEL: Watch<CriticalSectionRawMutex, f64, 1> = Watch::new();
+// Config targets and actual output state in one snapshot
+pub(crate) static STATUS_CHANNEL: Watch<CriticalSectionRawMutex, StatusInfo, 1> = Watch::new();
 
-// VIN voltage status channel
-pub(crate) static VIN_VOLTAGE_CHANNEL: Watch<CriticalSectionRawMutex, f64, 1> = Watch::new();
+// Filtered and display-smoothed measurements for the host
+pub(crate) static TELEMETRY_CHANNEL: Watch<CriticalSectionRawMutex, TelemetrySnapshot, 1> =
+    Watch::new();
+
+// PD connection status channel
+pub(crate) static PD_STATUS_CHANNEL: Watch<CriticalSectionRawMutex, power::PdStatus, 2> =
+    Watch::new();
+
+// Contract of the most recent negotiation; only valid while PD status is Negotiated
+pub(crate) static PD_CONTRACT_CHANNEL: Watch<CriticalSectionRawMutex, power::PdContract, 1> =
+    Watch::new();
+
+// Consolidated VIN source health for telemetry and UI
+pub(crate) static SOURCE_HEALTH_CHANNEL: Watch<CriticalSectionRawMutex, SourceHealth, 1> =
+    Watch::new();
+
+// Source capabilities of the attached PD source, refreshed on every new contract
+pub(crate) static SOURCE_CAPABILITIES_CHANNEL: Watch<
+    CriticalSectionRawMutex,
+    SourceCapabilities,
+    2,
+> = Watch::new();
+
+// System state (Standby/Working) of the power manager
+pub(crate) static SYSTEM_STATE_CHANNEL: Watch<CriticalSectionRawMutex, SystemState, 1> =
+    Watch::new();
 
 // VBUS switch status channel
 pub(crate) static VBUS_STATE_CHANNEL: Watch<CriticalSectionRawMutex, bool, 1> = Watch::new();
 
+// Result of the post-enable output rise check
+pub(crate) static OUTPUT_RISE_CHANNEL: Watch<CriticalSectionRawMutex, OutputRiseStatus, 1> =
+    Watch::new();
+
+// ADC sampling and filter settings in effect, published once by the ADC reader
+pub(crate) static SAMPLING_SETTINGS_CHANNEL: Watch<CriticalSectionRawMutex, SamplingSettings, 1> =
+    Watch::new();
+
+// Thermal shutdown status
+pub(crate) static THERMAL_STATUS_CHANNEL: Watch<CriticalSectionRawMutex, ThermalStatus, 1> =
+    Watch::new();
+
+// VBUS load status channel
+pub(crate) static LOAD_STATUS_CHANNEL: Watch<CriticalSectionRawMutex, LoadStatus, 1> = Watch::new();
+
 // VBUS reset signal channel
 pub(crate) static VBUS_RESET_CHANNEL: Watch<CriticalSectionRawMutex, bool, 1> = Watch::new();
 
-// Temperature data channel
-pub(crate) static TEMPERATURE_CHANNEL: Watch<CriticalSectionRawMutex, f64, 1> = Watch::new();
+// Reason for the most recent transition into Standby
+pub(crate) static STANDBY_REASON_CHANNEL: Watch<CriticalSectionRawMutex, StandbyReason, 1> =
+    Watch::new();
+
+// Controlled reboot request channel
+pub(crate) static REBOOT_REQUEST_CHANNEL: Watch<CriticalSectionRawMutex, bool, 1> = Watch::new();
+
+// Watchdog feed channel, sent to by the main loop and drained by the watchdog task
+pub(crate) static WATCHDOG_CHANNEL: Channel<CriticalSectionRawMutex, (), 1> = Channel::new();
+
+// Power LED breathing changes from the host, applied by the main loop
+pub(crate) static LED_BREATHING_CHANNEL: Channel<
+    CriticalSectionRawMutex,
+    (BreathingTarget, Breathing),
+    1,
+> = Channel::new();
+
+// Button threshold changes from the host, applied by the input task
+pub(crate) static BUTTON_THRESHOLDS_CHANNEL: Channel<
+    CriticalSectionRawMutex,
+    (ButtonId, Thresholds),
+    1,
+> = Channel::new();
 
 // Fan speed related constants
 pub const FAN_TIMER_FREQ_HZ: u32 = 1_000_000; // 1MHz timer frequency
 pub const FAN_PULSES_PER_REVOLUTION: u32 = 2; // Fan pulses per revolution
 pub const FAN_MAX_DETECTION_TIME_MS: u64 = 5000; // Max speed detection time (milliseconds)
+pub const FAN_TACH_TIMEOUT_MS: u64 = 200; // No tach edge captured for this long reads as 0 RPM
+
+// User fan curve from the host; an empty curve restores the built-in profile
+pub(crate) static FAN_CURVE_CHANNEL: Watch<CriticalSectionRawMutex, FanCurve, 1> = Watch::new();
 
 // Fan speed data storage
 pub(crate) static MAX_FAN_RPM: Mutex<CriticalSectionRawMutex, u32> = Mutex::new(0);
-pub(crate) static CURRENT_FAN_RPM: Watch<CriticalSectionRawMutex, u32, 1> = Watch::new();
@@ -0,0 +1,96 @@
+use embassy_futures::select::select;
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, watch};
+use embassy_time::{Duration, Ticker};
+use uom::si::electric_potential::{millivolt, volt};
+
+use crate::{
+    power::{self, PdContract, PdStatus},
+    shared::{CONFIG_SNAPSHOT_CHANNEL, MEASUREMENTS, PD_CONTRACT_CHANNEL, SOURCE_HEALTH_CHANNEL},
+};
+
+/// Consolidated view of the VIN (PD source) side
+///
+/// Input-side counterpart to the output status: connection, contract and
+/// link quality in one struct.
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub struct SourceHealth {
+    pub attached: bool,
+    pub negotiated: bool,
+    /// Active contract, `None` unless negotiated
+    pub contract: Option<PdContract>,
+    /// Measured VIN minus contract voltage (V)
+    pub regulation_error: Option<f64>,
+    /// PHY errors since attach
+    pub phy_errors: u32,
+    /// Contract voltage is below the configured target voltage
+    pub capability_mismatch: bool,
+}
+
+impl SourceHealth {
+    fn assemble(status: PdStatus, contract: Option<PdContract>) -> Self {
+        let attached = status != PdStatus::Detached;
+        let negotiated = status == PdStatus::Negotiated;
+        let contract = contract.filter(|_| negotiated);
+
+        let regulation_error = contract.and_then(|contract| {
+            MEASUREMENTS
+                .vin_voltage
+                .latest()
+                .map(|vin| vin.get::<volt>())
+                .map(|vin| vin - contract.nearest_voltage(vin))
+        });
+
+        let capability_mismatch = contract.is_some_and(|contract| {
+            CONFIG_SNAPSHOT_CHANNEL
+                .anon_receiver()
+                .try_get()
+                .is_some_and(|config| {
+                    config.target_voltage.get::<millivolt>() as f64 / 1000.0 > contract.voltage
+                })
+        });
+
+        Self {
+            attached,
+            negotiated,
+            contract,
+            regulation_error,
+            phy_errors: if attached {
+                power::phy_error_count()
+            } else {
+                0
+            },
+            capability_mismatch,
+        }
+    }
+}
+
+/// Republish `SourceHealth` on every PD status change and once per second
+/// for the measured parts (regulation error, PHY errors)
+pub async fn source_health_task(
+    mut pd_status_rx: watch::Receiver<'static, CriticalSectionRawMutex, PdStatus, 2>,
+) {
+    let mut ticker = Ticker::every(Duration::from_secs(1));
+    let health_tx = SOURCE_HEALTH_CHANNEL.sender();
+    let mut last: Option<SourceHealth> = None;
+
+    loop {
+        select(pd_status_rx.changed(), ticker.next()).await;
+
+        let health = SourceHealth::assemble(
+            power::pd_status(),
+            PD_CONTRACT_CHANNEL.anon_receiver().try_get(),
+        );
+
+        let link_changed = last.map_or(true, |last| {
+            last.attached != health.attached
+                || last.negotiated != health.negotiated
+                || last.contract != health.contract
+        });
+        if link_changed {
+            defmt::info!("Source health: {}", health);
+        }
+
+        last = Some(health);
+        health_tx.send(health);
+    }
+}
@@ -0,0 +1,47 @@
+use embassy_time::{Duration, Ticker};
+use uom::si::{electric_current::milliampere, electric_potential::millivolt};
+
+use crate::{
+    power_output::PowerOutput,
+    shared::{CONFIG_SNAPSHOT_CHANNEL, STATUS_CHANNEL, VBUS_STATE_CHANNEL},
+    types::StatusInfo,
+};
+
+/// How often targets and the output pin are sampled for changes
+const STATUS_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Publish `StatusInfo` whenever the targets or the output state change
+///
+/// Polls rather than awaiting the source channels: their receivers are
+/// already taken, and the VBUS_EN pin level has no change notification of
+/// its own.
+pub async fn status_task(output: PowerOutput<'static>) {
+    let mut ticker = Ticker::every(STATUS_POLL_INTERVAL);
+    let status_tx = STATUS_CHANNEL.sender();
+    let mut last: Option<StatusInfo> = None;
+
+    loop {
+        ticker.next().await;
+
+        let Some(config) = CONFIG_SNAPSHOT_CHANNEL.anon_receiver().try_get() else {
+            continue;
+        };
+        let status = StatusInfo {
+            target_volts: config.target_voltage.get::<millivolt>() as f64 / 1000.0,
+            limit_amps: config.target_current.get::<milliampere>() as f64 / 1000.0,
+            output: output.get_state().await,
+            output_requested: VBUS_STATE_CHANNEL
+                .anon_receiver()
+                .try_get()
+                .unwrap_or(false),
+        };
+
+        if last != Some(status) {
+            if status.output != status.output_requested {
+                defmt::debug!("Output pin differs from requested state: {}", status);
+            }
+            last = Some(status);
+            status_tx.send(status);
+        }
+    }
+}
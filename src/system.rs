@@ -0,0 +1,169 @@
+use core::{
+    mem::MaybeUninit,
+    ptr::{addr_of_mut, read_volatile, write_volatile},
+};
+
+/// Magic value written to the no-init RAM word before a controlled reset
+const CLEAN_SHUTDOWN_MAGIC: u32 = 0x5AFE_B007;
+
+/// Clean-shutdown flag, placed in `.uninit` so it survives a software reset
+#[link_section = ".uninit.CLEAN_SHUTDOWN_FLAG"]
+static mut CLEAN_SHUTDOWN_FLAG: MaybeUninit<u32> = MaybeUninit::uninit();
+
+/// Request a controlled reboot
+///
+/// The main loop owns both power rails, so the actual reset is performed there
+/// after VBUS and VIN have been commanded off.
+pub fn request_reboot() {
+    defmt::warn!("Reboot requested");
+    crate::shared::REBOOT_REQUEST_CHANNEL.sender().send(true);
+}
+
+/// Feed the independent watchdog
+///
+/// Only the main loop may call this: the watchdog task pets the IWDG once per
+/// feed, so the chip resets when the loop driving the managers stops running.
+/// Feeding from any other task would hide a stuck main loop.
+pub fn feed() {
+    // A pending feed already keeps the watchdog alive
+    let _ = crate::shared::WATCHDOG_CHANNEL.try_send(());
+}
+
+/// Record a clean shutdown and reset the MCU
+///
+/// Callers must make sure all outputs are already disabled.
+pub fn reboot() -> ! {
+    unsafe {
+        write_volatile(
+            addr_of_mut!(CLEAN_SHUTDOWN_FLAG) as *mut u32,
+            CLEAN_SHUTDOWN_MAGIC,
+        );
+    }
+    defmt::warn!("Performing software reset");
+    cortex_m::peripheral::SCB::sys_reset()
+}
+
+/// Check whether the previous reset was caused by a watchdog, clearing the
+/// RCC reset flags
+pub fn take_watchdog_reset() -> bool {
+    let rcc = embassy_stm32::pac::RCC;
+    let csr = rcc.csr().read();
+    let watchdog = csr.iwdgrstf() || csr.wwdgrstf();
+    rcc.csr().modify(|w| w.set_rmvf(true));
+    watchdog
+}
+
+/// Check whether the previous reset was a controlled reboot, clearing the flag
+pub fn take_clean_shutdown() -> bool {
+    unsafe {
+        let flag = addr_of_mut!(CLEAN_SHUTDOWN_FLAG) as *mut u32;
+        let clean = read_volatile(flag) == CLEAN_SHUTDOWN_MAGIC;
+        write_volatile(flag, 0);
+        clean
+    }
+}
+
+const VIN_EN: usize = 15; // PA15
+const VBUS_LED: usize = 5; // PB5, high = red, floating = off
+const VBUS_EN: usize = 7; // PB7
+
+/// Force both power switches off through the PAC and take over the VBUS LED
+///
+/// Used on fatal paths where the drivers are unavailable or their owners can
+/// no longer run.
+fn park_outputs() {
+    use embassy_stm32::pac::{gpio::vals::Moder, GPIOA, GPIOB, RCC};
+
+    RCC.ahb2enr().modify(|w| {
+        w.set_gpioaen(true);
+        w.set_gpioben(true);
+    });
+    GPIOA.bsrr().write(|w| w.set_br(VIN_EN, true));
+    GPIOA.moder().modify(|w| w.set_moder(VIN_EN, Moder::OUTPUT));
+    GPIOB.bsrr().write(|w| w.set_br(VBUS_EN, true));
+    GPIOB.moder().modify(|w| {
+        w.set_moder(VBUS_EN, Moder::OUTPUT);
+        w.set_moder(VBUS_LED, Moder::INPUT);
+    });
+}
+
+/// Reload the IWDG counter directly through the PAC
+///
+/// Used by the halt loops below so a watchdog started before the failure does
+/// not turn the halt into a reset loop. Harmless if the watchdog never started.
+fn pet_watchdog() {
+    use embassy_stm32::pac::{iwdg::vals::Key, IWDG};
+
+    IWDG.kr().write(|w| w.set_key(Key::RESET));
+}
+
+/// Show red or off on the VBUS LED
+///
+/// Goes through `SoftPwmLed` as well as the pin, so the software PWM task (if
+/// it was already spawned) drives the same level instead of overwriting it.
+fn set_vbus_led_red(on: bool) {
+    use crate::{hal::BicolorLed, vbus_led::SoftPwmLed, vbus_manager::VbusLedColor};
+
+    use embassy_stm32::pac::{gpio::vals::Moder, GPIOB};
+
+    SoftPwmLed.set_pwm(VbusLedColor::Red, if on { 100 } else { 0 });
+    if on {
+        GPIOB.bsrr().write(|w| w.set_bs(VBUS_LED, true));
+        GPIOB
+            .moder()
+            .modify(|w| w.set_moder(VBUS_LED, Moder::OUTPUT));
+    } else {
+        GPIOB
+            .moder()
+            .modify(|w| w.set_moder(VBUS_LED, Moder::INPUT));
+    }
+}
+
+/// Park the firmware after a failed bring-up, blinking the VBUS LED
+///
+/// The drivers created by `init()` have been dropped or were never created,
+/// so the switches are forced off directly. The LED blinks `blinks` times
+/// and pauses, so the failed stage can be read on a unit without a debug
+/// probe. The watchdog is kept fed so the code is not cut short by a reset.
+pub async fn boot_failure_loop(blinks: u8) -> ! {
+    use embassy_time::Timer;
+
+    park_outputs();
+    loop {
+        for _ in 0..blinks {
+            pet_watchdog();
+            set_vbus_led_red(true);
+            Timer::after_millis(200).await;
+            set_vbus_led_red(false);
+            Timer::after_millis(200).await;
+        }
+        pet_watchdog();
+        Timer::after_millis(1500).await;
+    }
+}
+
+/// Halt after a heap allocation failure
+///
+/// Interrupts are disabled so no task runs again, both power switches are
+/// forced off and the VBUS LED alternates red/green at 5 Hz until the
+/// board is reset or power-cycled. The watchdog is petted from the loop so
+/// the halt is not turned into a periodic reset. Never returns to the
+/// allocating code.
+pub fn out_of_memory(size: usize) -> ! {
+    cortex_m::interrupt::disable();
+    park_outputs();
+    defmt::error!(
+        "Heap exhausted allocating {} bytes, outputs forced off",
+        size
+    );
+
+    // Busy-wait at the 170 MHz system clock, the time driver no longer runs
+    const HALF_PERIOD_CYCLES: u32 = 17_000_000;
+    loop {
+        pet_watchdog();
+        set_vbus_led_red(true);
+        cortex_m::asm::delay(HALF_PERIOD_CYCLES);
+        set_vbus_led_red(false);
+        cortex_m::asm::delay(HALF_PERIOD_CYCLES);
+    }
+}
@@ -0,0 +1,429 @@
+use core::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+
+use embassy_time::{Duration, Ticker};
+use uom::si::{
+    electric_current::ampere, electric_potential::volt, thermodynamic_temperature::degree_celsius,
+};
+
+use crate::{
+    peak::PeakSnapshot,
+    power::PdContract,
+    shared::{
+        MEASUREMENTS, POWER_INFO_CHANNEL, SYSTEM_STATE_CHANNEL, TELEMETRY_CHANNEL,
+        VBUS_STATE_CHANNEL,
+    },
+    types::PowerInfo,
+};
+
+/// Which flavour of each measurement is streamed to the host
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+#[repr(u8)]
+pub enum DisplaySmoothing {
+    /// The EMA-filtered values also used for control and protection
+    Filtered = 0,
+    /// Additionally smoothed values for calm graphs
+    Smoothed = 1,
+}
+
+impl TryFrom<u8> for DisplaySmoothing {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Filtered),
+            1 => Ok(Self::Smoothed),
+            _ => Err(()),
+        }
+    }
+}
+
+static DISPLAY_SMOOTHING: AtomicU8 = AtomicU8::new(DisplaySmoothing::Filtered as u8);
+
+/// Select the streamed flavour; protection keeps using the filtered values
+pub fn set_display_smoothing(mode: DisplaySmoothing) {
+    defmt::info!("Telemetry display smoothing: {:?}", mode);
+    DISPLAY_SMOOTHING.store(mode as u8, Ordering::Relaxed);
+}
+
+pub fn display_smoothing() -> DisplaySmoothing {
+    DisplaySmoothing::try_from(DISPLAY_SMOOTHING.load(Ordering::Relaxed))
+        .unwrap_or(DisplaySmoothing::Filtered)
+}
+
+/// Minimum change before a streamed value is updated, per measurement
+///
+/// Zero disables the deadband for that measurement.
+#[derive(Debug, Clone, Copy, PartialEq, Default, defmt::Format)]
+pub struct DisplayDeadband {
+    /// VBUS and VIN, in volts
+    pub voltage: f32,
+    /// Output current, in amperes
+    pub current: f32,
+    /// Temperature, in °C
+    pub temperature: f32,
+}
+
+impl DisplayDeadband {
+    pub fn is_valid(&self) -> bool {
+        [self.voltage, self.current, self.temperature]
+            .iter()
+            .all(|band| band.is_finite() && *band >= 0.0)
+    }
+}
+
+// f32 bit patterns, 0 is 0.0
+static DEADBAND_VOLTAGE: AtomicU32 = AtomicU32::new(0);
+static DEADBAND_CURRENT: AtomicU32 = AtomicU32::new(0);
+static DEADBAND_TEMPERATURE: AtomicU32 = AtomicU32::new(0);
+
+/// Set the streamed deadband, returns `false` for negative or non-finite bands
+pub fn set_display_deadband(deadband: DisplayDeadband) -> bool {
+    if !deadband.is_valid() {
+        return false;
+    }
+    defmt::info!("Telemetry display deadband: {}", deadband);
+    DEADBAND_VOLTAGE.store(deadband.voltage.to_bits(), Ordering::Relaxed);
+    DEADBAND_CURRENT.store(deadband.current.to_bits(), Ordering::Relaxed);
+    DEADBAND_TEMPERATURE.store(deadband.temperature.to_bits(), Ordering::Relaxed);
+    true
+}
+
+pub fn display_deadband() -> DisplayDeadband {
+    DisplayDeadband {
+        voltage: f32::from_bits(DEADBAND_VOLTAGE.load(Ordering::Relaxed)),
+        current: f32::from_bits(DEADBAND_CURRENT.load(Ordering::Relaxed)),
+        temperature: f32::from_bits(DEADBAND_TEMPERATURE.load(Ordering::Relaxed)),
+    }
+}
+
+/// Detail of the periodic telemetry log line
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+#[repr(u8)]
+pub enum LogVerbosity {
+    /// No periodic line
+    Off = 0,
+    /// Measurements only
+    Summary = 1,
+    /// Measurements plus system, output, PD and fault state
+    Verbose = 2,
+}
+
+impl LogVerbosity {
+    /// Debug builds log everything, release builds only the measurements
+    pub const DEFAULT: Self = if cfg!(debug_assertions) {
+        Self::Verbose
+    } else {
+        Self::Summary
+    };
+
+    /// Parse a level sent by the host, rejecting unknown values
+    pub fn try_from_u8(value: u8) -> Option<Self> {
+        (value <= Self::Verbose as u8).then(|| Self::from_u8(value))
+    }
+
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => Self::Off,
+            1 => Self::Summary,
+            _ => Self::Verbose,
+        }
+    }
+}
+
+static LOG_VERBOSITY: AtomicU8 = AtomicU8::new(LogVerbosity::DEFAULT as u8);
+
+/// Change the periodic log detail at runtime
+pub fn set_log_verbosity(verbosity: LogVerbosity) {
+    defmt::info!("Telemetry log verbosity: {:?}", verbosity);
+    LOG_VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn log_verbosity() -> LogVerbosity {
+    LogVerbosity::from_u8(LOG_VERBOSITY.load(Ordering::Relaxed))
+}
+
+/// One value per measurement
+#[derive(Debug, Clone, Copy, PartialEq, Default, defmt::Format)]
+pub struct MeasurementSet {
+    pub vbus_voltage: f64,
+    pub vin_voltage: f64,
+    pub output_current: f64,
+    pub temperature: f64,
+}
+
+/// Filtered and display-smoothed measurements published on `TELEMETRY_CHANNEL`
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub struct TelemetrySnapshot {
+    pub filtered: MeasurementSet,
+    pub smoothed: MeasurementSet,
+    /// The flavour selected by the display smoothing mode, held by the deadband
+    pub display: MeasurementSet,
+    /// Output power of the latest ADC sample, unsmoothed
+    pub power: PowerInfo,
+    /// Negotiated PD contract, what the device asked for vs what is measured
+    pub contract: Option<PdContract>,
+    /// Unfiltered transient extremes, see `peak`
+    pub peaks: PeakSnapshot,
+}
+
+impl TelemetrySnapshot {
+    /// The set streamed to the host
+    pub fn streamed(&self) -> MeasurementSet {
+        self.display
+    }
+}
+
+/// Telemetry task settings
+#[derive(Debug, Clone, Copy)]
+pub struct TelemetryConfig {
+    pub interval: Duration,
+    /// EMA alpha of the display smoothing, applied once per `interval`
+    pub smoothing_alpha: f64,
+    /// Initial display deadband, can be changed at runtime
+    pub deadband: DisplayDeadband,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            smoothing_alpha: 0.2,
+            deadband: DisplayDeadband::default(),
+        }
+    }
+}
+
+/// Telemetry log task settings
+#[derive(Debug, Clone, Copy)]
+pub struct TelemetryLogConfig {
+    pub interval: Duration,
+}
+
+impl Default for TelemetryLogConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Display-only EMA, seeded from the first sample
+struct Smoother {
+    alpha: f64,
+    value: Option<MeasurementSet>,
+}
+
+impl Smoother {
+    fn new(alpha: f64) -> Self {
+        Self { alpha, value: None }
+    }
+
+    fn update(&mut self, sample: MeasurementSet) -> MeasurementSet {
+        let ema = |prev: f64, new: f64| prev + self.alpha * (new - prev);
+        let next = match self.value {
+            None => sample,
+            Some(prev) => MeasurementSet {
+                vbus_voltage: ema(prev.vbus_voltage, sample.vbus_voltage),
+                vin_voltage: ema(prev.vin_voltage, sample.vin_voltage),
+                output_current: ema(prev.output_current, sample.output_current),
+                temperature: ema(prev.temperature, sample.temperature),
+            },
+        };
+        self.value = Some(next);
+        next
+    }
+}
+
+/// Holds each displayed value until the input moves past the deadband
+#[derive(Default)]
+struct DeadbandHold {
+    held: Option<MeasurementSet>,
+}
+
+impl DeadbandHold {
+    fn update(&mut self, sample: MeasurementSet, band: DisplayDeadband) -> MeasurementSet {
+        let hold = |prev: f64, new: f64, band: f32| {
+            if (new - prev).abs() > band as f64 {
+                new
+            } else {
+                prev
+            }
+        };
+        let next = match self.held {
+            None => sample,
+            Some(prev) => MeasurementSet {
+                vbus_voltage: hold(prev.vbus_voltage, sample.vbus_voltage, band.voltage),
+                vin_voltage: hold(prev.vin_voltage, sample.vin_voltage, band.voltage),
+                output_current: hold(prev.output_current, sample.output_current, band.current),
+                temperature: hold(prev.temperature, sample.temperature, band.temperature),
+            },
+        };
+        self.held = Some(next);
+        next
+    }
+}
+
+/// Build telemetry snapshots from the measurement bus
+///
+/// The heavier smoothing lives here rather than in `AdcReader`, so display
+/// preferences never change protection timing.
+pub async fn telemetry_task(config: TelemetryConfig) {
+    let mut ticker = Ticker::every(config.interval);
+    let mut smoother = Smoother::new(config.smoothing_alpha);
+    let mut hold = DeadbandHold::default();
+    set_display_deadband(config.deadband);
+    let telemetry_tx = TELEMETRY_CHANNEL.sender();
+
+    loop {
+        ticker.next().await;
+
+        let filtered = MeasurementSet {
+            vbus_voltage: MEASUREMENTS
+                .vbus_voltage
+                .latest()
+                .map_or(0.0, |v| v.get::<volt>()),
+            vin_voltage: MEASUREMENTS
+                .vin_voltage
+                .latest()
+                .map_or(0.0, |v| v.get::<volt>()),
+            output_current: MEASUREMENTS
+                .output_current
+                .latest()
+                .map_or(0.0, |i| i.get::<ampere>()),
+            temperature: MEASUREMENTS
+                .temperature
+                .latest()
+                .map_or(0.0, |t| t.get::<degree_celsius>()),
+        };
+        let smoothed = smoother.update(filtered);
+        let selected = match display_smoothing() {
+            DisplaySmoothing::Filtered => filtered,
+            DisplaySmoothing::Smoothed => smoothed,
+        };
+        let display = hold.update(selected, display_deadband());
+
+        telemetry_tx.send(TelemetrySnapshot {
+            filtered,
+            smoothed,
+            display,
+            power: POWER_INFO_CHANNEL
+                .anon_receiver()
+                .try_get()
+                .unwrap_or_default(),
+            contract: crate::power::active_contract(),
+            peaks: crate::peak::snapshot(),
+        });
+    }
+}
+
+/// Emit one structured status line per `interval`
+///
+/// The single place for periodic status output; managers only log events.
+/// `LogVerbosity::Off` silences it without stopping the task.
+pub async fn telemetry_log_task(config: TelemetryLogConfig) {
+    let mut ticker = Ticker::every(config.interval);
+
+    loop {
+        ticker.next().await;
+
+        let verbosity = log_verbosity();
+        if verbosity == LogVerbosity::Off {
+            continue;
+        }
+
+        let vbus = MEASUREMENTS
+            .vbus_voltage
+            .latest()
+            .map_or(0.0, |v| v.get::<volt>());
+        let vin = MEASUREMENTS
+            .vin_voltage
+            .latest()
+            .map_or(0.0, |v| v.get::<volt>());
+        let current = MEASUREMENTS
+            .output_current
+            .latest()
+            .map_or(0.0, |i| i.get::<ampere>());
+        let temperature = MEASUREMENTS
+            .temperature
+            .latest()
+            .map_or(0.0, |t| t.get::<degree_celsius>());
+        let fan_rpm = MEASUREMENTS.fan_rpm.latest().unwrap_or(0);
+
+        if verbosity == LogVerbosity::Summary {
+            defmt::info!(
+                "Telemetry: VBUS={}V VIN={}V I={}A T={}°C fan={}RPM",
+                vbus,
+                vin,
+                current,
+                temperature,
+                fan_rpm
+            );
+            continue;
+        }
+
+        defmt::info!(
+            "Telemetry: VBUS={}V VIN={}V I={}A T={}°C fan={}RPM state={:?} vbus_en={:?} pd={:?} faults={:?}",
+            vbus,
+            vin,
+            current,
+            temperature,
+            fan_rpm,
+            SYSTEM_STATE_CHANNEL.anon_receiver().try_get(),
+            VBUS_STATE_CHANNEL.anon_receiver().try_get(),
+            crate::power::pd_status(),
+            crate::fault::faults()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_smoother_seeds_then_lags() {
+        let mut smoother = Smoother::new(0.5);
+        let sample = |v| MeasurementSet {
+            vbus_voltage: v,
+            ..Default::default()
+        };
+
+        assert_eq!(smoother.update(sample(10.0)).vbus_voltage, 10.0);
+        assert_eq!(smoother.update(sample(20.0)).vbus_voltage, 15.0);
+        assert_eq!(smoother.update(sample(20.0)).vbus_voltage, 17.5);
+    }
+
+    #[test]
+    fn test_log_verbosity_rejects_unknown_levels() {
+        assert_eq!(LogVerbosity::try_from_u8(0), Some(LogVerbosity::Off));
+        assert_eq!(LogVerbosity::try_from_u8(2), Some(LogVerbosity::Verbose));
+        assert_eq!(LogVerbosity::try_from_u8(3), None);
+    }
+
+    #[test]
+    fn test_deadband_holds_small_changes() {
+        let mut hold = DeadbandHold::default();
+        let band = DisplayDeadband {
+            voltage: 0.05,
+            ..Default::default()
+        };
+        let sample = |v| MeasurementSet {
+            vbus_voltage: v,
+            output_current: v,
+            ..Default::default()
+        };
+
+        assert_eq!(hold.update(sample(20.0), band).vbus_voltage, 20.0);
+        assert_eq!(hold.update(sample(20.03), band).vbus_voltage, 20.0);
+        assert_eq!(hold.update(sample(19.96), band).vbus_voltage, 20.0);
+        assert_eq!(hold.update(sample(20.1), band).vbus_voltage, 20.1);
+        // A zero band passes every change through
+        assert_eq!(hold.update(sample(20.11), band).output_current, 20.11);
+
+        assert!(!DisplayDeadband {
+            current: -0.1,
+            ..Default::default()
+        }
+        .is_valid());
+    }
+}
@@ -0,0 +1,618 @@
+// 管理器集成测试的确定性测试框架
+// 使用模拟引脚/PWM/输出开关驱动真实的 PowerManager 和 VbusManager，
+// 由测试按固定节拍推进时间并注入按键事件
+
+extern crate std;
+
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use embassy_sync::{
+    blocking_mutex::raw::CriticalSectionRawMutex,
+    mutex::Mutex,
+    pubsub::{PubSubBehavior, PubSubChannel},
+};
+use embassy_time::{Duration, Instant};
+
+use crate::{
+    app_manager::{
+        AlwaysOnButton, OperatingMode, PowerManager, PowerManagerConfig, PowerManagerContext,
+        StandbyReason, SystemState,
+    },
+    button::{ButtonId, InputEvent},
+    hal::{LedPwm, OutputSwitch, SwitchPin},
+    power::PdStatus,
+    power_rail::{OutputTable, PowerRail, RailId},
+    shared::{MANAGER_TICK_MS, PD_STATUS_CHANNEL, STANDBY_REASON_CHANNEL, VBUS_RESET_CHANNEL},
+    vbus_manager::{
+        VbusLedIndication, VbusManager, VbusManagerConfig, VbusManagerContext, VbusState,
+    },
+    INPUT_CAP, INPUT_PUB, INPUT_SUB,
+};
+
+type InputChannel =
+    PubSubChannel<CriticalSectionRawMutex, InputEvent, INPUT_CAP, INPUT_SUB, INPUT_PUB>;
+
+/// 模拟数字输出引脚，克隆之间共享电平
+#[derive(Clone, Default)]
+pub struct MockSwitchPin {
+    high: Arc<AtomicBool>,
+}
+
+impl MockSwitchPin {
+    pub fn is_high(&self) -> bool {
+        self.high.load(Ordering::SeqCst)
+    }
+}
+
+impl SwitchPin for MockSwitchPin {
+    fn set_high(&mut self) {
+        self.high.store(true, Ordering::SeqCst);
+    }
+
+    fn set_low(&mut self) {
+        self.high.store(false, Ordering::SeqCst);
+    }
+}
+
+/// 模拟 PWM 通道，克隆之间共享占空比
+#[derive(Clone, Default)]
+pub struct MockPwm {
+    duty: Arc<AtomicU32>,
+}
+
+impl MockPwm {
+    const MAX_DUTY: u32 = 1000;
+
+    /// LED 亮度百分比（开漏输出，占空比与亮度反向）
+    pub fn brightness_percent(&self) -> u32 {
+        100 - self.duty.load(Ordering::SeqCst) * 100 / Self::MAX_DUTY
+    }
+}
+
+impl LedPwm for MockPwm {
+    fn max_duty(&self) -> u32 {
+        Self::MAX_DUTY
+    }
+
+    fn set_duty(&mut self, duty: u32) {
+        self.duty.store(duty, Ordering::SeqCst);
+    }
+}
+
+/// 模拟 VBUS 输出开关
+#[derive(Clone, Default)]
+pub struct MockOutput {
+    on: Arc<AtomicBool>,
+}
+
+impl MockOutput {
+    pub fn is_on(&self) -> bool {
+        self.on.load(Ordering::SeqCst)
+    }
+}
+
+impl OutputSwitch for MockOutput {
+    async fn set_on(&self) {
+        self.on.store(true, Ordering::SeqCst);
+    }
+
+    async fn set_off(&self) {
+        self.on.store(false, Ordering::SeqCst);
+    }
+}
+
+/// 管理器通过进程级全局通道（PD 状态、VBUS 重置、待机原因等）通信，
+/// 而 tokio 测试在多个线程并行运行，同一时刻只允许一个测试框架存在
+static HARNESS_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// 按主循环顺序驱动两个管理器的测试框架
+///
+/// 创建时获取 `HARNESS_LOCK` 并重置全局状态，锁在框架销毁前一直持有。
+pub struct ManagerHarness {
+    _lock: std::sync::MutexGuard<'static, ()>,
+    pub power: PowerManager<'static, MockSwitchPin, MockPwm>,
+    pub vbus: VbusManager<'static, MockOutput, MockSwitchPin>,
+    input: &'static InputChannel,
+    now: Instant,
+    pub vin_switch: MockSwitchPin,
+    pub power_led: MockPwm,
+    pub vbus_output: MockOutput,
+    pub vbus_led: MockSwitchPin,
+    pub vbus_voltage: f64,
+    pub vin_voltage: f64,
+}
+
+impl ManagerHarness {
+    /// 创建并初始化管理器（PD 已协商完成）
+    pub async fn new() -> Self {
+        Self::with_vbus_config(VbusManagerConfig::default()).await
+    }
+
+    /// 使用指定的 VBUS 管理器配置创建
+    pub async fn with_vbus_config(vbus_config: VbusManagerConfig) -> Self {
+        Self::with_configs(PowerManagerConfig::default(), vbus_config).await
+    }
+
+    /// 使用指定的两个管理器配置创建
+    pub async fn with_configs(
+        power_config: PowerManagerConfig,
+        vbus_config: VbusManagerConfig,
+    ) -> Self {
+        // 前一个测试失败导致锁中毒时，全局状态随后即被重置，可以继续使用
+        let lock = HARNESS_LOCK
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        PD_STATUS_CHANNEL.sender().send(PdStatus::Negotiated);
+        VBUS_RESET_CHANNEL.sender().send(false);
+        STANDBY_REASON_CHANNEL.sender().send(StandbyReason::PowerOn);
+        crate::fault::reset();
+
+        let input: &'static InputChannel = Box::leak(Box::new(PubSubChannel::new()));
+
+        let vin_switch = MockSwitchPin::default();
+        let power_led = MockPwm::default();
+        let vbus_output = MockOutput::default();
+        let vbus_led = MockSwitchPin::default();
+
+        let mut power = PowerManager::new(PowerManagerContext {
+            input_rx: Arc::new(Mutex::new(input.subscriber().unwrap())),
+            vin_rail: PowerRail::new(RailId::Vin, Mutex::new(vin_switch.clone())),
+            led_pwm: Arc::new(Mutex::new(power_led.clone())),
+            output_table: OutputTable::default(),
+            config: power_config,
+        });
+        let mut vbus = VbusManager::new(VbusManagerContext {
+            input_rx: Arc::new(Mutex::new(input.subscriber().unwrap())),
+            vbus_rail: PowerRail::new(RailId::Vbus, vbus_output.clone()),
+            vbus_led_pin: Arc::new(Mutex::new(vbus_led.clone())),
+            config: vbus_config,
+        });
+
+        power.init(StandbyReason::PowerOn).await.unwrap();
+        vbus.init().await;
+
+        Self {
+            _lock: lock,
+            power,
+            vbus,
+            input,
+            now: Instant::from_millis(0),
+            vin_switch,
+            power_led,
+            vbus_output,
+            vbus_led,
+            vbus_voltage: 0.0,
+            vin_voltage: 0.0,
+        }
+    }
+
+    /// 注入按键事件，下一次 tick 时被两个管理器处理
+    pub fn press(&self, event: InputEvent) {
+        self.input.publish_immediate(event);
+    }
+
+    /// 按主循环顺序执行一次 tick，并推进一个 tick 的时间
+    pub async fn tick(&mut self) {
+        self.vbus.update_voltages(self.vbus_voltage);
+        self.vbus.update_system_state(self.power.system_state);
+        self.vbus.step(self.now).await;
+
+        let vbus_enabled = self.vbus.vbus_state == VbusState::Enabled;
+        self.power.update_voltages(self.vin_voltage, vbus_enabled);
+        self.power.step(self.now).await;
+
+        self.now += Duration::from_millis(MANAGER_TICK_MS);
+    }
+
+    /// 运行指定时长（按 tick 取整）
+    pub async fn run_for(&mut self, duration: Duration) {
+        let ticks = duration.as_millis() / MANAGER_TICK_MS;
+        for _ in 0..ticks {
+            self.tick().await;
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_boot_standby_breathes_with_outputs_off() {
+    let mut harness = ManagerHarness::new().await;
+
+    let mut brightness = [0u32; 2];
+    harness.run_for(Duration::from_millis(500)).await;
+    brightness[0] = harness.power_led.brightness_percent();
+    harness.run_for(Duration::from_millis(500)).await;
+    brightness[1] = harness.power_led.brightness_percent();
+
+    assert_eq!(harness.power.system_state, SystemState::Standby);
+    assert!(!harness.vin_switch.is_high());
+    assert!(!harness.vbus_output.is_on());
+    // 呼吸灯上升阶段亮度递增
+    assert!(brightness[1] > brightness[0]);
+}
+
+#[tokio::test]
+async fn test_long_press_then_click_enables_output() {
+    let mut harness = ManagerHarness::new().await;
+    harness.vin_voltage = 20.0;
+
+    // 长按释放：进入工作状态，VIN 打开，VBUS 仍关闭，电源 LED 熄灭
+    harness.press(InputEvent::LongReleased(ButtonId::PRIMARY));
+    harness.tick().await;
+    assert_eq!(harness.power.system_state, SystemState::Working);
+    assert!(harness.vin_switch.is_high());
+    assert!(!harness.vbus_output.is_on());
+    assert_eq!(harness.power_led.brightness_percent(), 0);
+
+    // 等待 VBUS 重置信号被 VbusManager 处理
+    harness.tick().await;
+
+    // 短按：VBUS 打开，电源 LED 常亮
+    harness.press(InputEvent::Click(ButtonId::PRIMARY));
+    harness.tick().await;
+    harness.vbus_voltage = 20.0;
+    harness.tick().await;
+    assert!(harness.vbus_output.is_on());
+    assert_eq!(harness.power_led.brightness_percent(), 100);
+    // 电压 >= 5.5V 时 VBUS LED 为红色（高电平）
+    assert!(harness.vbus_led.is_high());
+}
+
+#[tokio::test]
+async fn test_pattern_indication_blinks_instead_of_solid_color() {
+    let mut harness = ManagerHarness::with_vbus_config(VbusManagerConfig {
+        led_indication: VbusLedIndication::Pattern,
+        ..VbusManagerConfig::default()
+    })
+    .await;
+    harness.vin_voltage = 20.0;
+
+    harness.press(InputEvent::LongReleased(ButtonId::PRIMARY));
+    harness.run_for(Duration::from_millis(40)).await;
+    harness.press(InputEvent::Click(ButtonId::PRIMARY));
+    harness.tick().await;
+    harness.vbus_voltage = 20.0;
+
+    // 高电压快闪：亮灭各保持 200ms（10 tick），
+    // 区别于普通闪烁（500ms，25 tick）和快速闪烁（100ms，5 tick）
+    let half_period_ticks = (200 / MANAGER_TICK_MS) as u32;
+    let mut level = harness.vbus_led.is_high();
+    let mut runs = Vec::new();
+    let mut run = 0;
+    for _ in 0..half_period_ticks * 8 {
+        harness.tick().await;
+        run += 1;
+        if harness.vbus_led.is_high() != level {
+            level = !level;
+            runs.push(run);
+            run = 0;
+        }
+    }
+    assert!(harness.vbus_output.is_on());
+    // 第一段从开启时刻算起，不是完整的半周期
+    assert!(runs.len() >= 5, "runs: {:?}", runs);
+    assert!(
+        runs[1..].iter().all(|&run| run == half_period_ticks),
+        "runs: {:?}",
+        runs
+    );
+}
+
+#[tokio::test]
+async fn test_vin_dropout_within_grace_keeps_working() {
+    let mut harness = ManagerHarness::new().await;
+    harness.vin_voltage = 20.0;
+    harness.press(InputEvent::LongReleased(ButtonId::PRIMARY));
+    harness.tick().await;
+    assert_eq!(harness.power.system_state, SystemState::Working);
+
+    // 短暂掉电（小于 500ms 宽限期）后恢复：保持工作状态
+    harness.vin_voltage = 0.0;
+    harness.run_for(Duration::from_millis(400)).await;
+    harness.vin_voltage = 20.0;
+    harness.tick().await;
+    assert_eq!(harness.power.system_state, SystemState::Working);
+
+    // 恢复后计时清零，再次持续掉电超过宽限期才进入待机
+    harness.vin_voltage = 0.0;
+    harness.run_for(Duration::from_millis(400)).await;
+    assert_eq!(harness.power.system_state, SystemState::Working);
+    harness.run_for(Duration::from_millis(120)).await;
+    assert_eq!(harness.power.system_state, SystemState::Standby);
+    assert!(!harness.vin_switch.is_high());
+    assert_eq!(
+        STANDBY_REASON_CHANNEL.anon_receiver().try_get(),
+        Some(StandbyReason::Brownout)
+    );
+}
+
+#[tokio::test]
+async fn test_vin_collapse_uses_hysteresis_and_dwell() {
+    let mode = OperatingMode::AlwaysOn(AlwaysOnButton::ToggleVbus);
+    let mut harness = ManagerHarness::with_configs(
+        PowerManagerConfig {
+            mode,
+            ..PowerManagerConfig::default()
+        },
+        VbusManagerConfig {
+            mode,
+            ..VbusManagerConfig::default()
+        },
+    )
+    .await;
+    harness.vin_voltage = 20.0;
+    harness.vbus_voltage = 20.0;
+    harness.run_for(Duration::from_millis(40)).await;
+    assert!(harness.vbus_output.is_on());
+
+    // 掉电后 VIN 回到阈值与回差之间：不视为恢复，宽限期满后关闭 VBUS 进入待机
+    harness.vin_voltage = 0.0;
+    harness.run_for(Duration::from_millis(300)).await;
+    harness.vin_voltage = 4.2;
+    harness.run_for(Duration::from_millis(300)).await;
+    harness.tick().await;
+    assert_eq!(harness.power.system_state, SystemState::Standby);
+    assert!(!harness.vbus_output.is_on());
+
+    // 回差内不自动恢复
+    harness.run_for(Duration::from_secs(5)).await;
+    assert_eq!(harness.power.system_state, SystemState::Standby);
+
+    // 越过回差后立即恢复工作
+    harness.vin_voltage = 20.0;
+    harness.tick().await;
+    assert_eq!(harness.power.system_state, SystemState::Working);
+
+    // 掉电待机后 VIN 立即恢复：需待机满 3s 最短停留时间才重新工作
+    harness.vin_voltage = 0.0;
+    harness.run_for(Duration::from_millis(520)).await;
+    assert_eq!(harness.power.system_state, SystemState::Standby);
+    harness.vin_voltage = 20.0;
+    harness.run_for(Duration::from_millis(2500)).await;
+    assert_eq!(harness.power.system_state, SystemState::Standby);
+    harness.run_for(Duration::from_secs(1)).await;
+    assert_eq!(harness.power.system_state, SystemState::Working);
+}
+
+#[tokio::test]
+async fn test_power_led_dims_when_idle_and_restores_on_press() {
+    let mut harness = ManagerHarness::new().await;
+    harness.vin_voltage = 20.0;
+
+    harness.press(InputEvent::LongReleased(ButtonId::PRIMARY));
+    harness.run_for(Duration::from_millis(40)).await;
+    harness.press(InputEvent::Click(ButtonId::PRIMARY));
+    harness.tick().await;
+    harness.vbus_voltage = 20.0;
+    harness.tick().await;
+    assert_eq!(harness.power_led.brightness_percent(), 100);
+
+    // 默认 120s 无操作后调暗到 20%
+    harness.run_for(Duration::from_secs(120)).await;
+    assert_eq!(harness.power_led.brightness_percent(), 20);
+
+    // 按键恢复全亮（两次短按：关闭再打开 VBUS）
+    harness.press(InputEvent::Click(ButtonId::PRIMARY));
+    harness.run_for(Duration::from_millis(40)).await;
+    harness.press(InputEvent::Click(ButtonId::PRIMARY));
+    harness.run_for(Duration::from_millis(40)).await;
+    assert_eq!(harness.power_led.brightness_percent(), 100);
+}
+
+#[tokio::test]
+async fn test_idle_standby_only_while_vbus_off() {
+    let mut harness = ManagerHarness::with_configs(
+        PowerManagerConfig {
+            idle_standby: Duration::from_secs(5),
+            ..PowerManagerConfig::default()
+        },
+        VbusManagerConfig::default(),
+    )
+    .await;
+    harness.vin_voltage = 20.0;
+
+    harness.press(InputEvent::LongReleased(ButtonId::PRIMARY));
+    harness.run_for(Duration::from_millis(40)).await;
+    harness.press(InputEvent::Click(ButtonId::PRIMARY));
+    harness.tick().await;
+    harness.vbus_voltage = 20.0;
+    harness.tick().await;
+    assert!(harness.vbus_output.is_on());
+
+    // 输出开启时不计时
+    harness.run_for(Duration::from_secs(10)).await;
+    assert_eq!(harness.power.system_state, SystemState::Working);
+
+    // 关闭输出后计时，中途按键重新计时
+    harness.press(InputEvent::Click(ButtonId::PRIMARY));
+    harness.run_for(Duration::from_secs(4)).await;
+    harness.press(InputEvent::Chord(ButtonId(1), ButtonId(2)));
+    harness.run_for(Duration::from_secs(4)).await;
+    assert_eq!(harness.power.system_state, SystemState::Working);
+
+    harness.run_for(Duration::from_secs(2)).await;
+    assert_eq!(harness.power.system_state, SystemState::Standby);
+    assert!(!harness.vin_switch.is_high());
+}
+
+#[tokio::test]
+async fn test_restore_working_and_vbus_without_button() {
+    for restore_vbus in [false, true] {
+        let mut harness = ManagerHarness::with_configs(
+            PowerManagerConfig {
+                restore_working: true,
+                ..PowerManagerConfig::default()
+            },
+            VbusManagerConfig {
+                restore_vbus,
+                ..VbusManagerConfig::default()
+            },
+        )
+        .await;
+        harness.vin_voltage = 20.0;
+        harness.vbus_voltage = 20.0;
+
+        // 首个 tick 恢复工作状态，VBUS 只有明确要求时才恢复
+        harness.run_for(Duration::from_millis(40)).await;
+        assert_eq!(harness.power.system_state, SystemState::Working);
+        assert!(harness.vin_switch.is_high());
+        assert_eq!(harness.vbus_output.is_on(), restore_vbus);
+
+        // 恢复只发生一次：回到待机后保持待机
+        harness.press(InputEvent::LongReleased(ButtonId::PRIMARY));
+        harness.run_for(Duration::from_secs(5)).await;
+        assert_eq!(harness.power.system_state, SystemState::Standby);
+        assert!(!harness.vbus_output.is_on());
+    }
+}
+
+#[tokio::test]
+async fn test_vbus_never_enabled_in_standby() {
+    let mut harness = ManagerHarness::new().await;
+    harness.vin_voltage = 20.0;
+    harness.vbus_voltage = 20.0;
+    harness.tick().await;
+
+    // 待机状态下短按与直接切换都被拒绝
+    harness.press(InputEvent::Click(ButtonId::PRIMARY));
+    harness.tick().await;
+    harness.vbus.toggle_vbus().await;
+    assert_eq!(harness.vbus.vbus_state, VbusState::Disabled);
+    assert!(!harness.vbus_output.is_on());
+
+    // 外部强制的非法状态在一个 tick 内被纠正
+    harness.vbus.vbus_state = VbusState::Enabled;
+    harness.tick().await;
+    assert_eq!(harness.vbus.vbus_state, VbusState::Disabled);
+    assert!(!harness.vbus_output.is_on());
+    assert_eq!(harness.power.system_state, SystemState::Standby);
+}
+
+#[tokio::test]
+async fn test_always_on_starts_without_button_and_ignores_it() {
+    let mode = OperatingMode::AlwaysOn(AlwaysOnButton::Ignore);
+    let mut harness = ManagerHarness::with_configs(
+        PowerManagerConfig {
+            mode,
+            ..PowerManagerConfig::default()
+        },
+        VbusManagerConfig {
+            mode,
+            ..VbusManagerConfig::default()
+        },
+    )
+    .await;
+    harness.vin_voltage = 20.0;
+    harness.vbus_voltage = 20.0;
+
+    // 首个 tick 进入工作状态，下一个 tick 开启 VBUS
+    harness.run_for(Duration::from_millis(40)).await;
+    assert_eq!(harness.power.system_state, SystemState::Working);
+    assert!(harness.vin_switch.is_high());
+    assert!(harness.vbus_output.is_on());
+
+    // 长按和短按均被忽略
+    harness.press(InputEvent::LongReleased(ButtonId::PRIMARY));
+    harness.tick().await;
+    harness.press(InputEvent::Click(ButtonId::PRIMARY));
+    harness.tick().await;
+    assert_eq!(harness.power.system_state, SystemState::Working);
+    assert!(harness.vbus_output.is_on());
+
+    // 掉电保护仍生效，VIN 恢复后自动回到工作状态并重新开启 VBUS
+    harness.vin_voltage = 0.0;
+    harness.run_for(Duration::from_millis(520)).await;
+    assert_eq!(harness.power.system_state, SystemState::Standby);
+    harness.tick().await;
+    assert!(!harness.vbus_output.is_on());
+    harness.vin_voltage = 20.0;
+    harness.run_for(Duration::from_millis(60)).await;
+    assert_eq!(harness.power.system_state, SystemState::Working);
+    assert!(harness.vbus_output.is_on());
+}
+
+#[tokio::test]
+async fn test_standby_drops_vin_only_after_vbus_off() {
+    let mut harness = ManagerHarness::new().await;
+    harness.vin_voltage = 20.0;
+    harness.vbus_voltage = 20.0;
+
+    harness.press(InputEvent::LongReleased(ButtonId::PRIMARY));
+    harness.run_for(Duration::from_millis(40)).await;
+    harness.press(InputEvent::Click(ButtonId::PRIMARY));
+    harness.run_for(Duration::from_millis(40)).await;
+    assert!(harness.vbus_output.is_on());
+
+    // 长按进入待机：VBUS 尚未关闭时 VIN 保持开启
+    harness.press(InputEvent::LongReleased(ButtonId::PRIMARY));
+    harness.tick().await;
+    assert_eq!(harness.power.system_state, SystemState::Standby);
+    assert!(harness.vbus_output.is_on());
+    assert!(harness.vin_switch.is_high());
+
+    // 下一个 tick VBUS 关闭，VIN 延时后才关断
+    harness.tick().await;
+    assert!(!harness.vbus_output.is_on());
+    assert!(harness.vin_switch.is_high());
+    harness.run_for(Duration::from_millis(40)).await;
+    assert!(harness.vin_switch.is_high());
+    harness.tick().await;
+    assert!(!harness.vin_switch.is_high());
+}
+
+#[tokio::test]
+async fn test_output_rise_measured_from_pre_enable_voltage() {
+    let mut harness = ManagerHarness::new().await;
+    harness.vin_voltage = 20.0;
+    harness.vbus_voltage = 3.0;
+
+    harness.press(InputEvent::LongReleased(ButtonId::PRIMARY));
+    harness.run_for(Duration::from_millis(40)).await;
+    harness.press(InputEvent::Click(ButtonId::PRIMARY));
+    harness.tick().await;
+    assert!(harness.vbus_output.is_on());
+
+    // 输出停留在开启前的电压：超时后判定未上升并关闭
+    harness.run_for(Duration::from_millis(900)).await;
+    assert!(harness.vbus_output.is_on());
+    harness.run_for(Duration::from_millis(100)).await;
+    assert!(!harness.vbus_output.is_on());
+
+    // 输出只需比开启前高出 rise_min_delta，与 PD 目标电压无关
+    harness.vbus_voltage = 0.0;
+    harness.tick().await;
+    harness.press(InputEvent::Click(ButtonId::PRIMARY));
+    harness.tick().await;
+    assert!(harness.vbus_output.is_on());
+    harness.vbus_voltage = 5.0;
+    harness.run_for(Duration::from_secs(2)).await;
+    assert!(harness.vbus_output.is_on());
+}
+
+#[tokio::test]
+async fn test_brownout_drops_vin_only_after_vbus_off() {
+    let mut harness = ManagerHarness::new().await;
+    harness.vin_voltage = 20.0;
+
+    harness.press(InputEvent::LongReleased(ButtonId::PRIMARY));
+    harness.run_for(Duration::from_millis(40)).await;
+    harness.press(InputEvent::Click(ButtonId::PRIMARY));
+    harness.tick().await;
+    harness.vbus_voltage = 20.0;
+    harness.tick().await;
+    assert!(harness.vbus_output.is_on());
+
+    // 掉电超过宽限期进入待机：VBUS 仍开启，VIN 保持开启
+    harness.vin_voltage = 0.0;
+    while harness.power.system_state == SystemState::Working {
+        harness.tick().await;
+    }
+    assert!(harness.vbus_output.is_on());
+    assert!(harness.vin_switch.is_high());
+
+    // VBUS 关闭后延时才关断 VIN
+    harness.tick().await;
+    assert!(!harness.vbus_output.is_on());
+    assert!(harness.vin_switch.is_high());
+    harness.run_for(Duration::from_millis(60)).await;
+    assert!(!harness.vin_switch.is_high());
+}
@@ -1 +1,4 @@
 pub mod system_state_tests;
+
+#[cfg(test)]
+mod manager_harness;
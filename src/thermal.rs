@@ -0,0 +1,216 @@
+use embassy_time::{Duration, Instant, Ticker};
+use uom::si::thermodynamic_temperature::degree_celsius;
+
+use crate::{
+    fault::{self, Fault},
+    shared::{MEASUREMENTS, THERMAL_STATUS_CHANNEL},
+};
+
+/// Thermal shutdown state published on `THERMAL_STATUS_CHANNEL`
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum ThermalStatus {
+    Normal,
+    /// Above the trip threshold (or not yet below the recovery point)
+    Shutdown,
+    /// Below the recovery point, waiting for `recovery_hold` to elapse
+    Recovering,
+}
+
+impl ThermalStatus {
+    /// Output must stay off and the fan at full speed
+    pub fn is_protecting(self) -> bool {
+        self != Self::Normal
+    }
+}
+
+/// Die temperature (°C) at which the output is shut down
+pub const OTP_TRIP_C: f64 = 90.0;
+
+/// Degrees below `OTP_TRIP_C` required before recovery starts
+pub const OTP_RECOVERY_HYSTERESIS_C: f64 = 15.0;
+
+/// Time the temperature must stay below the recovery point before clearing
+pub const OTP_RECOVERY_HOLD: Duration = Duration::from_secs(30);
+
+/// Reduced shutdown threshold (°C) while the fan is stalled
+pub const OTP_STALLED_FAN_TRIP_C: f64 = 75.0;
+
+/// Thermal shutdown settings
+#[derive(Debug, Clone, Copy)]
+pub struct ThermalConfig {
+    /// Shutdown threshold (°C)
+    pub trip: f64,
+    /// Shutdown threshold (°C) used instead of `trip` while `Fault::FanStall` is active
+    pub stalled_fan_trip: f64,
+    /// Degrees below `trip` the temperature must fall before recovery starts
+    pub recovery_hysteresis: f64,
+    /// Time the temperature must stay below the recovery point
+    pub recovery_hold: Duration,
+    pub interval: Duration,
+}
+
+impl Default for ThermalConfig {
+    fn default() -> Self {
+        Self {
+            trip: OTP_TRIP_C,
+            stalled_fan_trip: OTP_STALLED_FAN_TRIP_C,
+            recovery_hysteresis: OTP_RECOVERY_HYSTERESIS_C,
+            recovery_hold: OTP_RECOVERY_HOLD,
+            interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Thermal shutdown state machine with hysteresis and a recovery hold
+struct ThermalGuard {
+    config: ThermalConfig,
+    status: ThermalStatus,
+    recovering_since: Option<Instant>,
+    /// Fan reported stalled, shut down at `stalled_fan_trip` instead
+    fan_stalled: bool,
+}
+
+impl ThermalGuard {
+    fn new(config: ThermalConfig) -> Self {
+        Self {
+            config,
+            status: ThermalStatus::Normal,
+            recovering_since: None,
+            fan_stalled: false,
+        }
+    }
+
+    /// Active shutdown threshold (°C)
+    fn trip(&self) -> f64 {
+        if self.fan_stalled {
+            self.config.trip.min(self.config.stalled_fan_trip)
+        } else {
+            self.config.trip
+        }
+    }
+
+    fn update(&mut self, temperature: f64, now: Instant) -> ThermalStatus {
+        let trip = self.trip();
+        let recovery_point = trip - self.config.recovery_hysteresis;
+
+        self.status = match self.status {
+            ThermalStatus::Normal if temperature >= trip => ThermalStatus::Shutdown,
+            ThermalStatus::Normal => ThermalStatus::Normal,
+            // A transient rise above the recovery point restarts the hold
+            _ if temperature >= recovery_point => {
+                self.recovering_since = None;
+                ThermalStatus::Shutdown
+            }
+            _ => {
+                let since = *self.recovering_since.get_or_insert(now);
+                if now - since >= self.config.recovery_hold {
+                    self.recovering_since = None;
+                    ThermalStatus::Normal
+                } else {
+                    ThermalStatus::Recovering
+                }
+            }
+        };
+        self.status
+    }
+}
+
+/// Evaluate the die temperature and publish the thermal status
+///
+/// Consumers react to the status: `VbusManager` keeps the output off and
+/// `FanManager` runs the fan while protecting. The output is not re-enabled
+/// automatically after recovery. A stalled fan lowers the shutdown threshold
+/// to `stalled_fan_trip`.
+pub async fn thermal_task(config: ThermalConfig) {
+    let mut ticker = Ticker::every(config.interval);
+    let mut guard = ThermalGuard::new(config);
+    let status_tx = THERMAL_STATUS_CHANNEL.sender();
+    status_tx.send(ThermalStatus::Normal);
+
+    loop {
+        ticker.next().await;
+
+        let Some(temperature) = MEASUREMENTS.temperature.latest() else {
+            continue;
+        };
+        let temperature = temperature.get::<degree_celsius>();
+        #[cfg(feature = "fault-injection")]
+        let temperature = if crate::fault_injection::is_simulated(
+            crate::fault_injection::SimulatedFault::Overtemperature,
+        ) {
+            config.trip + 5.0
+        } else {
+            temperature
+        };
+
+        let fan_stalled = fault::faults().active.contains(Fault::FanStall);
+        if fan_stalled != guard.fan_stalled {
+            guard.fan_stalled = fan_stalled;
+            defmt::info!("Thermal trip point now {}°C", guard.trip());
+        }
+
+        let previous = guard.status;
+        let status = guard.update(temperature, Instant::now());
+        if status != previous {
+            match status {
+                ThermalStatus::Shutdown => {
+                    defmt::warn!("Thermal shutdown at {}°C ({:?})", temperature, previous)
+                }
+                ThermalStatus::Recovering => defmt::info!(
+                    "Thermal recovery started at {}°C, holding {}s",
+                    temperature,
+                    config.recovery_hold.as_secs()
+                ),
+                ThermalStatus::Normal => {
+                    defmt::info!("Thermal shutdown cleared at {}°C", temperature)
+                }
+            }
+            status_tx.send(status);
+        }
+        fault::set_active(Fault::Overtemperature, status.is_protecting());
+    }
+}
+
+/// Latest thermal status, `Normal` before the first evaluation
+pub fn thermal_status() -> ThermalStatus {
+    THERMAL_STATUS_CHANNEL
+        .anon_receiver()
+        .try_get()
+        .unwrap_or(ThermalStatus::Normal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thermal_hysteresis_and_hold() {
+        let mut guard = ThermalGuard::new(ThermalConfig::default());
+        let at = Instant::from_secs;
+
+        assert_eq!(guard.update(89.0, at(0)), ThermalStatus::Normal);
+        assert_eq!(guard.update(90.0, at(1)), ThermalStatus::Shutdown);
+        // Inside the hysteresis band the shutdown persists
+        assert_eq!(guard.update(80.0, at(2)), ThermalStatus::Shutdown);
+
+        assert_eq!(guard.update(74.0, at(3)), ThermalStatus::Recovering);
+        // A transient rise restarts the hold
+        assert_eq!(guard.update(76.0, at(10)), ThermalStatus::Shutdown);
+        assert_eq!(guard.update(74.0, at(11)), ThermalStatus::Recovering);
+        assert_eq!(guard.update(74.0, at(40)), ThermalStatus::Recovering);
+        assert_eq!(guard.update(74.0, at(41)), ThermalStatus::Normal);
+    }
+
+    #[test]
+    fn test_stalled_fan_lowers_trip() {
+        let mut guard = ThermalGuard::new(ThermalConfig::default());
+        let at = Instant::from_secs;
+
+        assert_eq!(guard.update(80.0, at(0)), ThermalStatus::Normal);
+        guard.fan_stalled = true;
+        assert_eq!(guard.update(80.0, at(1)), ThermalStatus::Shutdown);
+        // Recovery is measured from the lowered trip point
+        assert_eq!(guard.update(61.0, at(2)), ThermalStatus::Shutdown);
+        assert_eq!(guard.update(59.0, at(3)), ThermalStatus::Recovering);
+    }
+}
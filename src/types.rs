@@ -1,13 +1,19 @@
+use embassy_embedded_hal::shared_bus::asynch::i2c::I2cDevice;
 use embassy_stm32::i2c::{I2c, Master};
 use embassy_stm32::mode;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::mutex::Mutex;
 use embassy_sync::pubsub;
 
+use uom::si::{electric_current::milliampere, electric_potential::millivolt};
+use usbpd::protocol_layer::message::pdo::{PowerDataObject, SourceCapabilities};
+
 use crate::button::InputEvent;
 
 pub(crate) type I2cBus = I2c<'static, mode::Async, Master>;
 pub(crate) type SharedI2cBus = Mutex<CriticalSectionRawMutex, I2cBus>;
+/// Device handle on the shared bus used by the config EEPROM
+pub(crate) type EepromBus = I2cDevice<'static, CriticalSectionRawMutex, I2cBus>;
 
 pub(crate) const INPUT_CAP: usize = 2;
 pub(crate) const INPUT_PUB: usize = 1;
@@ -39,4 +45,58 @@ impl AvailableVoltCurr {
             _20v: None,
         }
     }
+
+    /// Collect the maximum current (mA) of each standard fixed PDO
+    pub fn from_capabilities(capabilities: &SourceCapabilities) -> Self {
+        let mut available = Self::default();
+        for pdo in capabilities.pdos() {
+            if let PowerDataObject::FixedSupply(fixed) = pdo {
+                let current = Some(fixed.max_current().get::<milliampere>());
+                match fixed.voltage().get::<millivolt>() {
+                    5000 => available._5v = current,
+                    9000 => available._9v = current,
+                    12000 => available._12v = current,
+                    15000 => available._15v = current,
+                    18000 => available._18v = current,
+                    20000 => available._20v = current,
+                    _ => {}
+                }
+            }
+        }
+        available
+    }
+}
+
+/// Output power computed from one ADC sample
+#[derive(Clone, Copy, Debug, Default, PartialEq, defmt::Format)]
+pub struct PowerInfo {
+    pub volts: f64,
+    pub amps: f64,
+    /// Never negative; reverse flow is reported through `reverse_flow`
+    pub watts: f64,
+    /// Current measured flowing back into the output
+    pub reverse_flow: bool,
+}
+
+impl PowerInfo {
+    pub fn new(volts: f64, amps: f64) -> Self {
+        let reverse_flow = amps < 0.0;
+        Self {
+            volts,
+            amps,
+            watts: if reverse_flow { 0.0 } else { volts * amps },
+            reverse_flow,
+        }
+    }
+}
+
+/// Targets from the config next to the actual output state
+#[derive(Clone, Copy, Debug, PartialEq, defmt::Format)]
+pub struct StatusInfo {
+    pub target_volts: f64,
+    pub limit_amps: f64,
+    /// VBUS_EN pin level
+    pub output: bool,
+    /// Output state requested by the VBUS manager
+    pub output_requested: bool,
 }
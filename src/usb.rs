@@ -1,11 +1,198 @@
+use crate::{
+    adc_reader::{EmaAlphas, FilterMode},
+    app_manager::{
+        Breathing, BreathingPattern, BreathingTarget, SystemState, BREATHING_PERIOD_RANGE_MS,
+    },
+    button::{ButtonId, Thresholds, MAX_BUTTONS},
+    config_manager::{
+        BootRestore, Config, ConfigManagerError, ConfigRequest, IDLE_STANDBY_MAX_S,
+        TARGET_CURRENT_RANGE_MA, TARGET_VOLTAGE_RANGE_MV,
+    },
+    fan_manager::FanCurve,
+    peak::Extremes,
+    power::RequestStrategy,
+    telemetry::{DisplayDeadband, DisplaySmoothing, LogVerbosity},
+};
+use alloc::{sync::Arc, vec::Vec};
 use embassy_futures::join::join;
 use embassy_stm32::{peripherals, usb};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use embassy_time::{with_timeout, Duration};
 use embassy_usb::driver::{Driver, Endpoint, EndpointIn, EndpointOut};
 use embassy_usb::{
     class::web_usb::{self, Url, WebUsb},
     driver::EndpointError,
     Builder,
 };
+use uom::si::{
+    electric_current::{ampere, milliampere},
+    electric_potential::{millivolt, volt},
+    power::milliwatt,
+    thermodynamic_temperature::degree_celsius,
+};
+use usbpd::protocol_layer::message::{
+    pdo::{Augmented, PowerDataObject, SourceCapabilities},
+    units::{ElectricCurrent, ElectricPotential},
+};
+
+/// Request opcodes (first byte of each packet sent by the host)
+const OP_GET_TELEMETRY: u8 = 0x01;
+const OP_SET_TARGET: u8 = 0x02;
+const OP_SOURCE_CAPABILITIES: u8 = 0x03;
+const OP_STATUS: u8 = 0x04;
+const OP_REBOOT: u8 = 0x10;
+const OP_BUILD_INFO: u8 = 0x11;
+const OP_ADC_SAMPLING: u8 = 0x12;
+const OP_FAULTS: u8 = 0x13;
+const OP_CLEAR_FAULTS: u8 = 0x14;
+const OP_STANDBY_REASON: u8 = 0x15;
+const OP_TELEMETRY: u8 = 0x16;
+const OP_DISPLAY_SMOOTHING: u8 = 0x17;
+const OP_REQUEST_STRATEGY: u8 = 0x18;
+#[cfg(feature = "fault-injection")]
+const OP_INJECT_FAULT: u8 = 0x19;
+const OP_ADC_CALIBRATE: u8 = 0x1A;
+const OP_SAMPLING_SETTINGS: u8 = 0x1B;
+const OP_DIAGNOSTICS: u8 = 0x1C;
+const OP_DISPLAY_DEADBAND: u8 = 0x1D;
+const OP_FAN_CURVE: u8 = 0x1E;
+const OP_PEAKS: u8 = 0x1F;
+const OP_RENEGOTIATE: u8 = 0x20;
+const OP_IDLE_STANDBY: u8 = 0x21;
+const OP_BOOT_RESTORE: u8 = 0x22;
+const OP_BUTTON_THRESHOLDS: u8 = 0x23;
+const OP_FILTER_MODE: u8 = 0x24;
+const OP_LED_BREATHING: u8 = 0x25;
+const OP_LOG_VERBOSITY: u8 = 0x26;
+
+/// Status bytes returned after the echoed opcode
+const STATUS_OK: u8 = 0x00;
+const STATUS_INVALID: u8 = 0x01;
+const STATUS_REFUSED: u8 = 0x02;
+
+/// Raw measurements and states returned for `OP_GET_TELEMETRY`
+///
+/// Framing: the opcode followed by the fields in declaration order,
+/// little-endian, no padding. Measurements not sampled yet are NaN.
+/// Unlike `OP_TELEMETRY` the voltages and the current bypass the EMA filter;
+/// the temperature is always filtered.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TelemetryFrame {
+    /// VBUS (output) voltage in volts
+    pub vbus_voltage: f32,
+    /// VIN (input) voltage in volts
+    pub vin_voltage: f32,
+    /// Output current in amps
+    pub current: f32,
+    /// MCU die temperature in °C from the internal sensor, not the PB0 NTC
+    pub temperature: f32,
+    pub fan_rpm: u32,
+    /// 0 = disabled, 1 = enabled
+    pub vbus_state: u8,
+    /// 0 = standby, 1 = working
+    pub system_state: u8,
+}
+
+impl TelemetryFrame {
+    /// Encoded length including the opcode
+    pub const LEN: usize = 1 + 4 * 5 + 2;
+
+    /// Latest values from the measurement bus and state channels
+    pub fn capture() -> Self {
+        let measurements = &crate::shared::MEASUREMENTS;
+        let system_state = crate::shared::SYSTEM_STATE_CHANNEL
+            .anon_receiver()
+            .try_get()
+            .unwrap_or(SystemState::Standby);
+        Self {
+            vbus_voltage: measurements
+                .vbus_voltage_raw
+                .latest()
+                .map_or(f32::NAN, |v| v.get::<volt>() as f32),
+            vin_voltage: measurements
+                .vin_voltage_raw
+                .latest()
+                .map_or(f32::NAN, |v| v.get::<volt>() as f32),
+            current: measurements
+                .output_current_raw
+                .latest()
+                .map_or(f32::NAN, |i| i.get::<ampere>() as f32),
+            temperature: measurements
+                .temperature
+                .latest()
+                .map_or(f32::NAN, |t| t.get::<degree_celsius>() as f32),
+            fan_rpm: measurements.fan_rpm.latest().unwrap_or(0),
+            vbus_state: crate::shared::VBUS_STATE_CHANNEL
+                .anon_receiver()
+                .try_get()
+                .unwrap_or(false) as u8,
+            system_state: match system_state {
+                SystemState::Standby => 0,
+                SystemState::Working => 1,
+            },
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut buf = [0u8; Self::LEN];
+        buf[0] = OP_GET_TELEMETRY;
+        for (i, value) in [
+            self.vbus_voltage.to_le_bytes(),
+            self.vin_voltage.to_le_bytes(),
+            self.current.to_le_bytes(),
+            self.temperature.to_le_bytes(),
+            self.fan_rpm.to_le_bytes(),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            buf[1 + i * 4..5 + i * 4].copy_from_slice(&value);
+        }
+        buf[21] = self.vbus_state;
+        buf[22] = self.system_state;
+        buf
+    }
+}
+
+/// Firmware build information embedded at compile time (see `build.rs`)
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub git_hash: &'static str,
+    /// Build time in seconds since the Unix epoch
+    pub build_timestamp: &'static str,
+    /// Comma separated list of enabled cargo features
+    pub features: &'static str,
+}
+
+pub static BUILD_INFO: BuildInfo = BuildInfo {
+    version: env!("CARGO_PKG_VERSION"),
+    git_hash: env!("SK150C_GIT_HASH"),
+    build_timestamp: env!("SK150C_BUILD_TIMESTAMP"),
+    features: env!("SK150C_FEATURES"),
+};
+
+impl BuildInfo {
+    /// Serialize as consecutive length-prefixed strings
+    /// (version, git hash, timestamp, features), truncated to fit `buf`.
+    pub fn write_to(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        for field in [
+            self.version,
+            self.git_hash,
+            self.build_timestamp,
+            self.features,
+        ] {
+            if len >= buf.len() {
+                break;
+            }
+            let n = field.len().min(buf.len() - len - 1).min(u8::MAX as usize);
+            buf[len] = n as u8;
+            buf[len + 1..len + 1 + n].copy_from_slice(&field.as_bytes()[..n]);
+            len += 1 + n;
+        }
+        len
+    }
+}
 
 #[embassy_executor::task]
 pub async fn usb_task(driver: usb::Driver<'static, peripherals::USB>) {
@@ -52,14 +239,14 @@ pub async fn usb_task(driver: usb::Driver<'static, peripherals::USB>) {
         loop {
             endpoints.wait_connected().await;
             defmt::info!("Connected");
-            endpoints.echo().await;
+            endpoints.serve().await.ok();
             defmt::info!("Disconnected");
         }
     };
 
     join(usb_fut, echo_fut).await;
 }
-#[allow(dead_code)]
+
 struct Disconnected {}
 
 impl From<EndpointError> for Disconnected {
@@ -71,13 +258,11 @@ impl From<EndpointError> for Disconnected {
     }
 }
 
-#[allow(dead_code)]
 struct WebEndpoints<'d, D: Driver<'d>> {
     write_ep: D::EndpointIn,
     read_ep: D::EndpointOut,
 }
 
-#[allow(dead_code)]
 impl<'d, D: Driver<'d>> WebEndpoints<'d, D> {
     fn new(builder: &mut Builder<'d, D>, config: &'d web_usb::Config<'d>) -> Self {
         let mut func = builder.function(0xff, 0x00, 0x00);
@@ -95,14 +280,794 @@ impl<'d, D: Driver<'d>> WebEndpoints<'d, D> {
         self.read_ep.wait_enabled().await
     }
 
-    // Echo data back to the host.
-    async fn echo(&mut self) {
+    // Handle host requests until the endpoints are disabled.
+    // Unknown opcodes are echoed back to the host.
+    async fn serve(&mut self) -> Result<(), Disconnected> {
         let mut buf = [0; 64];
         loop {
-            let n = self.read_ep.read(&mut buf).await.unwrap();
+            let n = self.read_ep.read(&mut buf).await?;
             let data = &buf[..n];
             defmt::info!("Data read: {:x}", data);
-            self.write_ep.write(data).await.unwrap();
+
+            match data.first() {
+                Some(&OP_GET_TELEMETRY) => {
+                    self.write_ep
+                        .write(&TelemetryFrame::capture().to_bytes())
+                        .await?;
+                }
+                Some(&OP_SET_TARGET) => {
+                    // Payload: target voltage mV (u32 LE), target current mA (u32 LE)
+                    // Response: status
+                    let status = if data.len() == 9 {
+                        let voltage_mv = u32::from_le_bytes(data[1..5].try_into().unwrap());
+                        let current_ma = u32::from_le_bytes(data[5..9].try_into().unwrap());
+                        set_target(voltage_mv, current_ma).await
+                    } else {
+                        STATUS_INVALID
+                    };
+                    self.write_ep.write(&[OP_SET_TARGET, status]).await?;
+                }
+                Some(&OP_SOURCE_CAPABILITIES) => {
+                    let capabilities = source_capabilities().await;
+                    let mut resp = [0u8; 64];
+                    resp[0] = OP_SOURCE_CAPABILITIES;
+                    resp[1] = STATUS_OK;
+                    let n = write_source_capabilities(capabilities.as_ref(), &mut resp[2..]);
+                    self.write_ep.write(&resp[..2 + n]).await?;
+                }
+                Some(&OP_RENEGOTIATE) => {
+                    // Request a fresh contract from the latest config; refused while detached
+                    let status = if crate::power::pd_status() == crate::power::PdStatus::Detached {
+                        STATUS_REFUSED
+                    } else {
+                        crate::power::SinkAgent::new(crate::shared::SINK_REQUEST_CHANNEL.sender())
+                            .renegotiate();
+                        STATUS_OK
+                    };
+                    self.write_ep.write(&[OP_RENEGOTIATE, status]).await?;
+                }
+                Some(&OP_STATUS) => {
+                    // Response: target V, current limit A (f32 LE each),
+                    // VBUS_EN pin level, requested output state
+                    let Some(status) = crate::shared::STATUS_CHANNEL.anon_receiver().try_get()
+                    else {
+                        self.write_ep.write(&[OP_STATUS, STATUS_REFUSED]).await?;
+                        continue;
+                    };
+                    let mut resp = [0u8; 12];
+                    resp[0] = OP_STATUS;
+                    resp[1] = STATUS_OK;
+                    resp[2..6].copy_from_slice(&(status.target_volts as f32).to_le_bytes());
+                    resp[6..10].copy_from_slice(&(status.limit_amps as f32).to_le_bytes());
+                    resp[10] = status.output as u8;
+                    resp[11] = status.output_requested as u8;
+                    self.write_ep.write(&resp).await?;
+                }
+                Some(&OP_REBOOT) => {
+                    self.write_ep.write(&[OP_REBOOT, STATUS_OK]).await?;
+                    crate::system::request_reboot();
+                }
+                Some(&OP_ADC_SAMPLING) => {
+                    // Payload: 1 = pause, 0 = resume
+                    let status = match data.get(1) {
+                        Some(1) => {
+                            crate::adc_reader::pause_sampling();
+                            STATUS_OK
+                        }
+                        Some(0) => {
+                            crate::adc_reader::resume_sampling();
+                            STATUS_OK
+                        }
+                        _ => STATUS_INVALID,
+                    };
+                    self.write_ep.write(&[OP_ADC_SAMPLING, status]).await?;
+                }
+                Some(&OP_FAULTS) => {
+                    // Response: active (u32 LE), latched (u32 LE)
+                    let faults = crate::fault::faults();
+                    let mut resp = [0u8; 10];
+                    resp[0] = OP_FAULTS;
+                    resp[1] = STATUS_OK;
+                    resp[2..6].copy_from_slice(&faults.active.0.to_le_bytes());
+                    resp[6..10].copy_from_slice(&faults.latched.0.to_le_bytes());
+                    self.write_ep.write(&resp).await?;
+                }
+                Some(&OP_CLEAR_FAULTS) => {
+                    // Response on refusal carries the persisting faults (u32 LE)
+                    let (status, persisting) = match crate::fault::clear_faults() {
+                        Ok(()) => (STATUS_OK, 0),
+                        Err(crate::fault::ClearFaultsError::ConditionPersists(flags)) => {
+                            (STATUS_REFUSED, flags.0)
+                        }
+                    };
+                    let mut resp = [0u8; 6];
+                    resp[0] = OP_CLEAR_FAULTS;
+                    resp[1] = status;
+                    resp[2..6].copy_from_slice(&persisting.to_le_bytes());
+                    self.write_ep.write(&resp).await?;
+                }
+                Some(&OP_STANDBY_REASON) => {
+                    // Response: reason code, 0xFF if Standby was never entered
+                    let reason = crate::shared::STANDBY_REASON_CHANNEL
+                        .anon_receiver()
+                        .try_get()
+                        .map_or(0xFF, |reason| reason as u8);
+                    self.write_ep
+                        .write(&[OP_STANDBY_REASON, STATUS_OK, reason])
+                        .await?;
+                }
+                Some(&OP_TELEMETRY) => {
+                    // Response: VBUS V, VIN V, current A, temperature °C, power W
+                    // (f32 LE each), 1 if reverse current was measured, the
+                    // contract voltage V and current A (f32 LE, NaN without a contract),
+                    // then the held VBUS, VIN and current min/max (f32 LE, NaN before
+                    // the first sample after a reset, see `OP_PEAKS`)
+                    let Some(snapshot) = crate::shared::TELEMETRY_CHANNEL.anon_receiver().try_get()
+                    else {
+                        self.write_ep.write(&[OP_TELEMETRY, STATUS_REFUSED]).await?;
+                        continue;
+                    };
+                    let values = snapshot.streamed();
+                    let mut resp = [0u8; 55];
+                    resp[0] = OP_TELEMETRY;
+                    resp[1] = STATUS_OK;
+                    for (i, value) in [
+                        values.vbus_voltage,
+                        values.vin_voltage,
+                        values.output_current,
+                        values.temperature,
+                        snapshot.power.watts,
+                    ]
+                    .into_iter()
+                    .enumerate()
+                    {
+                        let offset = 2 + i * 4;
+                        resp[offset..offset + 4].copy_from_slice(&(value as f32).to_le_bytes());
+                    }
+                    resp[22] = snapshot.power.reverse_flow as u8;
+                    let (contract_v, contract_a) = snapshot
+                        .contract
+                        .map_or((f64::NAN, f64::NAN), |c| (c.voltage, c.current));
+                    resp[23..27].copy_from_slice(&(contract_v as f32).to_le_bytes());
+                    resp[27..31].copy_from_slice(&(contract_a as f32).to_le_bytes());
+                    let peaks = snapshot.peaks;
+                    write_extremes(
+                        [peaks.vout.hold, peaks.vin.hold, peaks.current.hold],
+                        &mut resp[31..],
+                    );
+                    self.write_ep.write(&resp).await?;
+                }
+                Some(&OP_PEAKS) => {
+                    // Payload: none to query, 1 to reset the peak holds first.
+                    // Response: status, then the rolling-window VBUS, VIN and
+                    // current min/max followed by the held ones (f32 LE each)
+                    let status = match data.get(1) {
+                        None => STATUS_OK,
+                        Some(1) => {
+                            crate::peak::reset_peaks();
+                            STATUS_OK
+                        }
+                        Some(_) => STATUS_INVALID,
+                    };
+                    let peaks = crate::peak::snapshot();
+                    let mut resp = [0u8; 50];
+                    resp[0] = OP_PEAKS;
+                    resp[1] = status;
+                    write_extremes(
+                        [peaks.vout.window, peaks.vin.window, peaks.current.window],
+                        &mut resp[2..26],
+                    );
+                    write_extremes(
+                        [peaks.vout.hold, peaks.vin.hold, peaks.current.hold],
+                        &mut resp[26..],
+                    );
+                    self.write_ep.write(&resp).await?;
+                }
+                Some(&OP_DISPLAY_SMOOTHING) => {
+                    // Payload: 0 = filtered, 1 = smoothed
+                    let status = match data.get(1).copied().map(DisplaySmoothing::try_from) {
+                        Some(Ok(mode)) => {
+                            crate::telemetry::set_display_smoothing(mode);
+                            STATUS_OK
+                        }
+                        _ => STATUS_INVALID,
+                    };
+                    self.write_ep.write(&[OP_DISPLAY_SMOOTHING, status]).await?;
+                }
+                Some(&OP_DISPLAY_DEADBAND) => {
+                    // Payload: none to query, or V, A, °C bands (f32 LE each) to set.
+                    // Response: status, active bands (f32 LE each)
+                    let status = match data.len() {
+                        1 => STATUS_OK,
+                        13 => {
+                            let band = |i: usize| {
+                                f32::from_le_bytes(data[1 + i * 4..5 + i * 4].try_into().unwrap())
+                            };
+                            let deadband = DisplayDeadband {
+                                voltage: band(0),
+                                current: band(1),
+                                temperature: band(2),
+                            };
+                            if crate::telemetry::set_display_deadband(deadband) {
+                                STATUS_OK
+                            } else {
+                                STATUS_INVALID
+                            }
+                        }
+                        _ => STATUS_INVALID,
+                    };
+                    let active = crate::telemetry::display_deadband();
+                    let mut resp = [0u8; 14];
+                    resp[0] = OP_DISPLAY_DEADBAND;
+                    resp[1] = status;
+                    for (i, band) in [active.voltage, active.current, active.temperature]
+                        .into_iter()
+                        .enumerate()
+                    {
+                        let offset = 2 + i * 4;
+                        resp[offset..offset + 4].copy_from_slice(&band.to_le_bytes());
+                    }
+                    self.write_ep.write(&resp).await?;
+                }
+                Some(&OP_FAN_CURVE) => {
+                    // Payload: none to query, or a count followed by that many
+                    // (°C f32 LE, duty % u8) breakpoints to set; count 0 clears.
+                    // Response: status, count, 4 breakpoints (unused ones zeroed)
+                    let status = match data.get(1) {
+                        None => STATUS_OK,
+                        Some(&len) if data.len() == 2 + len as usize * 5 => {
+                            let breakpoints: Vec<(f32, u8)> = data[2..]
+                                .chunks_exact(5)
+                                .map(|b| (f32::from_le_bytes([b[0], b[1], b[2], b[3]]), b[4]))
+                                .collect();
+                            match FanCurve::new(&breakpoints) {
+                                Some(curve) => {
+                                    crate::shared::FAN_CURVE_CHANNEL.sender().send(curve);
+                                    STATUS_OK
+                                }
+                                None => STATUS_INVALID,
+                            }
+                        }
+                        Some(_) => STATUS_INVALID,
+                    };
+                    let active = crate::shared::FAN_CURVE_CHANNEL
+                        .anon_receiver()
+                        .try_get()
+                        .unwrap_or(FanCurve {
+                            points: [(0.0, 0); FanCurve::MAX_POINTS],
+                            len: 0,
+                        });
+                    let mut resp = [0u8; 3 + FanCurve::MAX_POINTS * 5];
+                    resp[0] = OP_FAN_CURVE;
+                    resp[1] = status;
+                    resp[2] = active.len;
+                    for (i, (temperature, duty)) in active.breakpoints().iter().enumerate() {
+                        let offset = 3 + i * 5;
+                        resp[offset..offset + 4].copy_from_slice(&temperature.to_le_bytes());
+                        resp[offset + 4] = *duty;
+                    }
+                    self.write_ep.write(&resp).await?;
+                }
+                Some(&OP_REQUEST_STRATEGY) => {
+                    // Payload: none to query, or the raw strategy (u32 LE) to set.
+                    // Response: status, active raw strategy (u32 LE)
+                    let status = match data.len() {
+                        1 => STATUS_OK,
+                        5 => {
+                            let raw = u32::from_le_bytes(data[1..5].try_into().unwrap());
+                            match RequestStrategy::try_from(raw) {
+                                Ok(strategy) => {
+                                    match crate::power::set_request_strategy(strategy) {
+                                        Ok(()) => {
+                                            persist_request_strategy(strategy);
+                                            STATUS_OK
+                                        }
+                                        Err(_) => STATUS_REFUSED,
+                                    }
+                                }
+                                Err(()) => STATUS_INVALID,
+                            }
+                        }
+                        _ => STATUS_INVALID,
+                    };
+                    let mut resp = [0u8; 6];
+                    resp[0] = OP_REQUEST_STRATEGY;
+                    resp[1] = status;
+                    resp[2..6]
+                        .copy_from_slice(&crate::power::request_strategy().to_raw().to_le_bytes());
+                    self.write_ep.write(&resp).await?;
+                }
+                Some(&OP_IDLE_STANDBY) => {
+                    // Payload: none to query, or the timeout in seconds (u32 LE,
+                    // 0 = disabled) to set. Response: status, active timeout (u32 LE)
+                    let status = match data.len() {
+                        1 => STATUS_OK,
+                        5 => {
+                            let seconds = u32::from_le_bytes(data[1..5].try_into().unwrap());
+                            set_idle_standby(seconds).await
+                        }
+                        _ => STATUS_INVALID,
+                    };
+                    let seconds = crate::shared::CONFIG_SNAPSHOT_CHANNEL
+                        .anon_receiver()
+                        .try_get()
+                        .map_or(0, |config| config.idle_standby.as_secs() as u32);
+                    let mut resp = [0u8; 6];
+                    resp[0] = OP_IDLE_STANDBY;
+                    resp[1] = status;
+                    resp[2..6].copy_from_slice(&seconds.to_le_bytes());
+                    self.write_ep.write(&resp).await?;
+                }
+                Some(&OP_BOOT_RESTORE) => {
+                    // Payload: none to query, or the policy (u32 LE: 0 = off,
+                    // 1 = mode, 2 = mode and VBUS) to set. Applies from the next boot.
+                    // Response: status, active policy (u32 LE)
+                    let status = match data.len() {
+                        1 => STATUS_OK,
+                        5 => {
+                            let raw = u32::from_le_bytes(data[1..5].try_into().unwrap());
+                            set_boot_restore(raw).await
+                        }
+                        _ => STATUS_INVALID,
+                    };
+                    let raw = crate::shared::CONFIG_SNAPSHOT_CHANNEL
+                        .anon_receiver()
+                        .try_get()
+                        .map_or(0, |config| config.boot_restore.to_raw());
+                    let mut resp = [0u8; 6];
+                    resp[0] = OP_BOOT_RESTORE;
+                    resp[1] = status;
+                    resp[2..6].copy_from_slice(&raw.to_le_bytes());
+                    self.write_ep.write(&resp).await?;
+                }
+                Some(&OP_BUTTON_THRESHOLDS) => {
+                    // Payload: button id (u8), debounce ms (u16 LE), long-press ms
+                    // (u16 LE). Applies from the button's next press. Response: status
+                    let status = match data.len() {
+                        6 => {
+                            let id = ButtonId(data[1]);
+                            let debounce = u16::from_le_bytes([data[2], data[3]]);
+                            let long_press = u16::from_le_bytes([data[4], data[5]]);
+                            if (id.0 as usize) < MAX_BUTTONS
+                                && 0 < debounce
+                                && debounce < long_press
+                            {
+                                let thresholds = Thresholds {
+                                    debounce: Duration::from_millis(debounce as u64),
+                                    long_press: Duration::from_millis(long_press as u64),
+                                };
+                                match crate::shared::BUTTON_THRESHOLDS_CHANNEL
+                                    .try_send((id, thresholds))
+                                {
+                                    Ok(()) => STATUS_OK,
+                                    Err(_) => STATUS_REFUSED,
+                                }
+                            } else {
+                                STATUS_INVALID
+                            }
+                        }
+                        _ => STATUS_INVALID,
+                    };
+                    self.write_ep.write(&[OP_BUTTON_THRESHOLDS, status]).await?;
+                }
+                Some(&OP_LED_BREATHING) => {
+                    // Payload: target (0 = standby, 1 = fault standby), pattern
+                    // (0 = triangle, 1 = sine, 2 = slow pulse, 3 = fast blink),
+                    // period ms (u16 LE). Not persisted. Response: status
+                    let status = match data.len() {
+                        5 => {
+                            let target = match data[1] {
+                                0 => Some(BreathingTarget::Standby),
+                                1 => Some(BreathingTarget::Fault),
+                                _ => None,
+                            };
+                            let period = u16::from_le_bytes([data[3], data[4]]) as u32;
+                            let (min, max) = BREATHING_PERIOD_RANGE_MS;
+                            match (target, BreathingPattern::try_from(data[2])) {
+                                (Some(target), Ok(pattern)) if (min..=max).contains(&period) => {
+                                    let breathing = Breathing::new(
+                                        pattern,
+                                        Duration::from_millis(period as u64),
+                                    );
+                                    match crate::shared::LED_BREATHING_CHANNEL
+                                        .try_send((target, breathing))
+                                    {
+                                        Ok(()) => STATUS_OK,
+                                        Err(_) => STATUS_REFUSED,
+                                    }
+                                }
+                                _ => STATUS_INVALID,
+                            }
+                        }
+                        _ => STATUS_INVALID,
+                    };
+                    self.write_ep.write(&[OP_LED_BREATHING, status]).await?;
+                }
+                Some(&OP_LOG_VERBOSITY) => {
+                    // Payload: none to query, or the level (0 = off, 1 = summary,
+                    // 2 = verbose) to set. Not persisted. Response: status, active level
+                    let status = match data.get(1) {
+                        None => STATUS_OK,
+                        Some(&raw) => match LogVerbosity::try_from_u8(raw) {
+                            Some(verbosity) => {
+                                crate::telemetry::set_log_verbosity(verbosity);
+                                STATUS_OK
+                            }
+                            None => STATUS_INVALID,
+                        },
+                    };
+                    let level = crate::telemetry::log_verbosity() as u8;
+                    self.write_ep
+                        .write(&[OP_LOG_VERBOSITY, status, level])
+                        .await?;
+                }
+                Some(&OP_ADC_CALIBRATE) => {
+                    // Runs before the next ADC sample, e.g. after warm-up
+                    crate::adc_reader::request_calibration();
+                    self.write_ep.write(&[OP_ADC_CALIBRATE, STATUS_OK]).await?;
+                }
+                Some(&OP_SAMPLING_SETTINGS) => {
+                    // Payload: none to query, the interval ms (u32 LE) to set, or the
+                    // interval followed by the four EMA alphas (f32 LE, same order as the
+                    // response) to set both. Changes apply from the next sample; alphas
+                    // outside (0, 1] are clamped and kept as is when only the interval is set.
+                    // Response: status, interval ms (u32 LE), oversampling ratio (OVSR)
+                    // and shift, EMA alphas for VBUS, VIN, temperature, current (f32 LE each)
+                    let status = match data.len() {
+                        1 => STATUS_OK,
+                        5 => {
+                            let ms = u32::from_le_bytes(data[1..5].try_into().unwrap());
+                            if crate::adc_reader::request_sample_interval(Duration::from_millis(
+                                ms as u64,
+                            )) {
+                                STATUS_OK
+                            } else {
+                                STATUS_INVALID
+                            }
+                        }
+                        21 => {
+                            let ms = u32::from_le_bytes(data[1..5].try_into().unwrap());
+                            let alpha = |i: usize| {
+                                let offset = 5 + i * 4;
+                                f32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+                                    as f64
+                            };
+                            if crate::adc_reader::request_sample_interval(Duration::from_millis(
+                                ms as u64,
+                            )) {
+                                crate::adc_reader::request_filter_alphas(EmaAlphas {
+                                    vout: alpha(0),
+                                    vin: alpha(1),
+                                    temperature: alpha(2),
+                                    current: alpha(3),
+                                });
+                                STATUS_OK
+                            } else {
+                                STATUS_INVALID
+                            }
+                        }
+                        _ => STATUS_INVALID,
+                    };
+                    let Some(settings) = crate::shared::SAMPLING_SETTINGS_CHANNEL
+                        .anon_receiver()
+                        .try_get()
+                    else {
+                        self.write_ep
+                            .write(&[OP_SAMPLING_SETTINGS, STATUS_REFUSED])
+                            .await?;
+                        continue;
+                    };
+                    let mut resp = [0u8; 24];
+                    resp[0] = OP_SAMPLING_SETTINGS;
+                    resp[1] = status;
+                    resp[2..6]
+                        .copy_from_slice(&(settings.interval.as_millis() as u32).to_le_bytes());
+                    resp[6] = settings.oversampling_ratio;
+                    resp[7] = settings.oversampling_shift;
+                    for (i, alpha) in [
+                        settings.alphas.vout,
+                        settings.alphas.vin,
+                        settings.alphas.temperature,
+                        settings.alphas.current,
+                    ]
+                    .into_iter()
+                    .enumerate()
+                    {
+                        let offset = 8 + i * 4;
+                        resp[offset..offset + 4].copy_from_slice(&(alpha as f32).to_le_bytes());
+                    }
+                    self.write_ep.write(&resp).await?;
+                }
+                Some(&OP_FILTER_MODE) => {
+                    // Payload: none to query, or 0 = smoothed / 1 = raw readings to
+                    // set; applies from the next sample. Response: status, active mode
+                    let status = match data.get(1) {
+                        None => STATUS_OK,
+                        Some(0) => {
+                            crate::adc_reader::set_filter_mode(FilterMode::Smoothed);
+                            STATUS_OK
+                        }
+                        Some(1) => {
+                            crate::adc_reader::set_filter_mode(FilterMode::Raw);
+                            STATUS_OK
+                        }
+                        Some(_) => STATUS_INVALID,
+                    };
+                    let mode = match crate::adc_reader::filter_mode() {
+                        FilterMode::Smoothed => 0,
+                        FilterMode::Raw => 1,
+                    };
+                    self.write_ep.write(&[OP_FILTER_MODE, status, mode]).await?;
+                }
+                Some(&OP_DIAGNOSTICS) => {
+                    // Response: bundle length (u16 LE), followed by the bundle
+                    // (see `diagnostics::BundleWriter`) in 64-byte packets
+                    let mut bundle = [0u8; crate::diagnostics::MAX_BUNDLE_SIZE];
+                    let len = crate::diagnostics::collect(&mut bundle);
+                    let mut resp = [0u8; 4];
+                    resp[0] = OP_DIAGNOSTICS;
+                    resp[1] = STATUS_OK;
+                    resp[2..4].copy_from_slice(&(len as u16).to_le_bytes());
+                    self.write_ep.write(&resp).await?;
+                    for packet in bundle[..len].chunks(64) {
+                        self.write_ep.write(packet).await?;
+                    }
+                }
+                #[cfg(feature = "fault-injection")]
+                Some(&OP_INJECT_FAULT) => {
+                    // Payload: simulated fault code, 0 = clear
+                    use crate::fault_injection::{set_simulated, SimulatedFault};
+                    let status = match data.get(1).copied().map(SimulatedFault::try_from) {
+                        Some(Ok(fault)) => {
+                            set_simulated(fault);
+                            STATUS_OK
+                        }
+                        _ => STATUS_INVALID,
+                    };
+                    self.write_ep.write(&[OP_INJECT_FAULT, status]).await?;
+                }
+                Some(&OP_BUILD_INFO) => {
+                    let mut resp = [0u8; 64];
+                    resp[0] = OP_BUILD_INFO;
+                    resp[1] = STATUS_OK;
+                    let n = BUILD_INFO.write_to(&mut resp[2..]);
+                    self.write_ep.write(&resp[..2 + n]).await?;
+                }
+                _ => self.write_ep.write(data).await?,
+            }
+        }
+    }
+}
+
+/// Longest wait for the sink to answer a source capabilities query
+const SOURCE_CAPABILITIES_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// PDO type codes in the `OP_SOURCE_CAPABILITIES` response
+const PDO_FIXED: u8 = 0;
+const PDO_BATTERY: u8 = 1;
+const PDO_VARIABLE: u8 = 2;
+const PDO_PPS: u8 = 3;
+const PDO_OTHER: u8 = 0xFF;
+
+/// Bytes per PDO entry: type, min voltage mV, max voltage mV, limit (u16 LE each)
+///
+/// The limit is the max current in mA, or the max power in 10mW units for
+/// battery PDOs.
+const PDO_ENTRY_LEN: usize = 7;
+
+/// Source capabilities held by the sink, `None` while detached or on timeout
+async fn source_capabilities() -> Option<SourceCapabilities> {
+    if crate::power::pd_status() == crate::power::PdStatus::Detached {
+        return None;
+    }
+    let sink_agent = crate::power::SinkAgent::new(crate::shared::SINK_REQUEST_CHANNEL.sender());
+    with_timeout(
+        SOURCE_CAPABILITIES_TIMEOUT,
+        sink_agent.get_source_capabilities(),
+    )
+    .await
+    .ok()
+    .flatten()
+}
+
+/// Serialize as a PDO count followed by one `PDO_ENTRY_LEN` entry per PDO,
+/// in object position order
+///
+/// Fixed PDOs report the same min and max voltage. Any SPR list (at most 7
+/// PDOs) fits a single 64-byte packet; longer lists are cut at the last entry
+/// that fits, and the count reflects what was written.
+fn write_source_capabilities(capabilities: Option<&SourceCapabilities>, buf: &mut [u8]) -> usize {
+    let mut len = 1;
+    for pdo in capabilities.into_iter().flat_map(|caps| caps.pdos().iter()) {
+        if len + PDO_ENTRY_LEN > buf.len() {
+            break;
         }
+        let (kind, min_mv, max_mv, limit) = match pdo {
+            PowerDataObject::FixedSupply(fixed) => {
+                let voltage_mv = fixed.voltage().get::<millivolt>();
+                (
+                    PDO_FIXED,
+                    voltage_mv,
+                    voltage_mv,
+                    fixed.max_current().get::<milliampere>(),
+                )
+            }
+            PowerDataObject::Battery(battery) => (
+                PDO_BATTERY,
+                battery.min_voltage().get::<millivolt>(),
+                battery.max_voltage().get::<millivolt>(),
+                battery.max_power().get::<milliwatt>() / 10,
+            ),
+            PowerDataObject::VariableSupply(variable) => (
+                PDO_VARIABLE,
+                variable.min_voltage().get::<millivolt>(),
+                variable.max_voltage().get::<millivolt>(),
+                variable.max_current().get::<milliampere>(),
+            ),
+            PowerDataObject::Augmented(Augmented::Spr(pps)) => (
+                PDO_PPS,
+                pps.min_voltage().get::<millivolt>(),
+                pps.max_voltage().get::<millivolt>(),
+                pps.max_current().get::<milliampere>(),
+            ),
+            _ => (PDO_OTHER, 0, 0, 0),
+        };
+        buf[len] = kind;
+        for (i, value) in [min_mv, max_mv, limit].into_iter().enumerate() {
+            let at = len + 1 + 2 * i;
+            buf[at..at + 2].copy_from_slice(&(value.min(u16::MAX as u32) as u16).to_le_bytes());
+        }
+        len += PDO_ENTRY_LEN;
+    }
+    buf[0] = ((len - 1) / PDO_ENTRY_LEN) as u8;
+    len
+}
+
+/// Serialize VBUS, VIN and current extremes as min/max f32 LE pairs
+fn write_extremes(extremes: [Extremes; 3], buf: &mut [u8]) {
+    for (extremes, chunk) in extremes.iter().zip(buf.chunks_exact_mut(8)) {
+        chunk[..4].copy_from_slice(&extremes.min.to_le_bytes());
+        chunk[4..].copy_from_slice(&extremes.max.to_le_bytes());
+    }
+}
+
+/// Completion signal of a config store request
+type ConfigDone = Arc<Signal<CriticalSectionRawMutex, Result<(), ConfigManagerError>>>;
+
+/// Longest wait for the config store to persist a USB change
+const CONFIG_WRITE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Validate, persist and apply a new target voltage/current
+///
+/// Out-of-range values and targets at or below the configured minimum
+/// voltage are rejected rather than clamped.
+async fn set_target(voltage_mv: u32, current_ma: u32) -> u8 {
+    let in_range = |(min, max): (u32, u32), value: u32| (min..=max).contains(&value);
+    if !in_range(TARGET_VOLTAGE_RANGE_MV, voltage_mv)
+        || !in_range(TARGET_CURRENT_RANGE_MA, current_ma)
+    {
+        return STATUS_INVALID;
+    }
+    let Some(mut config) = crate::shared::CONFIG_SNAPSHOT_CHANNEL
+        .anon_receiver()
+        .try_get()
+    else {
+        return STATUS_REFUSED;
+    };
+    config.target_voltage = ElectricPotential::new::<millivolt>(voltage_mv);
+    config.target_current = ElectricCurrent::new::<milliampere>(current_ma);
+    if config.validate().is_err() {
+        return STATUS_INVALID;
+    }
+
+    let voltage_done = Arc::new(Signal::new());
+    let current_done = Arc::new(Signal::new());
+    let store = async {
+        let requests = &crate::shared::CONFIG_REQUEST_CHANNEL;
+        requests
+            .send(ConfigRequest::WriteTargetVoltage(
+                config.target_voltage,
+                voltage_done.clone(),
+            ))
+            .await;
+        requests
+            .send(ConfigRequest::WriteTargetCurrent(
+                config.target_current,
+                current_done.clone(),
+            ))
+            .await;
+        join(voltage_done.wait(), current_done.wait()).await
+    };
+    match with_timeout(CONFIG_WRITE_TIMEOUT, store).await {
+        Ok((Ok(()), Ok(()))) => {}
+        _ => {
+            defmt::warn!(
+                "Config store failed, target {}mV {}mA not applied",
+                voltage_mv,
+                current_ma
+            );
+            return STATUS_REFUSED;
+        }
+    }
+
+    defmt::info!("Target set over USB: {}mV {}mA", voltage_mv, current_ma);
+    crate::shared::CONFIG_SNAPSHOT_CHANNEL.sender().send(config);
+    crate::power::target_changed();
+    STATUS_OK
+}
+
+/// Store one config setting, then publish the updated snapshot
+///
+/// `apply` edits the snapshot; `request` builds the matching store request.
+/// Refused when no snapshot exists yet or the store fails.
+async fn store_setting(
+    apply: impl FnOnce(&mut Config),
+    request: impl FnOnce(ConfigDone) -> ConfigRequest,
+) -> u8 {
+    let Some(mut config) = crate::shared::CONFIG_SNAPSHOT_CHANNEL
+        .anon_receiver()
+        .try_get()
+    else {
+        return STATUS_REFUSED;
+    };
+    apply(&mut config);
+
+    let done = Arc::new(Signal::new());
+    let store = async {
+        crate::shared::CONFIG_REQUEST_CHANNEL
+            .send(request(done.clone()))
+            .await;
+        done.wait().await
+    };
+    if !matches!(with_timeout(CONFIG_WRITE_TIMEOUT, store).await, Ok(Ok(()))) {
+        defmt::warn!("Config store failed, setting not applied");
+        return STATUS_REFUSED;
+    }
+
+    crate::shared::CONFIG_SNAPSHOT_CHANNEL.sender().send(config);
+    STATUS_OK
+}
+
+/// Validate, persist and apply a new idle standby timeout
+async fn set_idle_standby(seconds: u32) -> u8 {
+    if seconds > IDLE_STANDBY_MAX_S {
+        return STATUS_INVALID;
+    }
+    let timeout = Duration::from_secs(seconds as u64);
+    let status = store_setting(
+        |config| config.idle_standby = timeout,
+        |done| ConfigRequest::WriteIdleStandby(timeout, done),
+    )
+    .await;
+    if status == STATUS_OK {
+        defmt::info!("Idle standby set over USB: {}s", seconds);
+    }
+    status
+}
+
+/// Validate, persist and apply a new boot restore policy
+async fn set_boot_restore(raw: u32) -> u8 {
+    let Ok(restore) = BootRestore::try_from(raw) else {
+        return STATUS_INVALID;
+    };
+    let status = store_setting(
+        |config| config.boot_restore = restore,
+        |done| ConfigRequest::WriteBootRestore(restore, done),
+    )
+    .await;
+    if status == STATUS_OK {
+        defmt::info!("Boot restore set over USB: {}", restore);
+    }
+    status
+}
+
+/// Queue the strategy for storage without blocking the USB loop
+fn persist_request_strategy(strategy: RequestStrategy) {
+    let request = ConfigRequest::WriteRequestStrategy(strategy, Arc::new(Signal::new()));
+    if crate::shared::CONFIG_REQUEST_CHANNEL
+        .try_send(request)
+        .is_err()
+    {
+        defmt::warn!("Config store busy, request strategy not persisted");
     }
 }
@@ -0,0 +1,150 @@
+use core::sync::atomic::{AtomicU8, Ordering};
+use embassy_stm32::{
+    gpio::{Flex, Pull, Speed},
+    peripherals::PB5,
+    Peri,
+};
+use embassy_time::{Duration, Ticker};
+
+use crate::{hal::BicolorLed, vbus_manager::VbusLedColor};
+
+/// Number of PWM steps per period; brightness is quantised to 100 / PWM_STEPS percent
+const PWM_STEPS: u8 = 10;
+
+/// Length of one PWM step, giving a 100Hz period that does not visibly flicker
+const PWM_STEP: Duration = Duration::from_millis(1);
+
+/// State of PB5 during one PWM step
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Drive {
+    /// Driven high, lights red
+    Red,
+    /// Driven low, lights green
+    Green,
+    /// Floating input: the two LEDs in series between 3V3 and GND need more
+    /// than 3.3V to conduct, so both stay dark
+    Off,
+}
+
+/// State of PB5 during `step` of the PWM period
+///
+/// Brightness is the share of lit steps; the remaining steps float the pin so
+/// the LED is really off rather than green. Amber alternates red and green on
+/// every lit step.
+fn drive_for_step(color: VbusLedColor, brightness: u8, step: u8) -> Drive {
+    let lit_steps = (u16::from(brightness.min(100)) * u16::from(PWM_STEPS) + 50) / 100;
+    if u16::from(step) >= lit_steps {
+        return Drive::Off;
+    }
+    match color {
+        VbusLedColor::Red => Drive::Red,
+        VbusLedColor::Amber if step % 2 == 0 => Drive::Red,
+        VbusLedColor::Amber | VbusLedColor::Green => Drive::Green,
+    }
+}
+
+// Colour and brightness are stored separately; a torn update lasts one step at most
+static COLOR: AtomicU8 = AtomicU8::new(VbusLedColor::Green as u8);
+static BRIGHTNESS: AtomicU8 = AtomicU8::new(0);
+
+/// Handle to the software PWM driven VBUS LED; the pin itself is owned by [`run`]
+///
+/// Anything that wants to show something on the LED while the task runs
+/// (including the boot failure blink code) must go through this handle, or
+/// the task overwrites the pin within one step.
+#[derive(Default)]
+pub struct SoftPwmLed;
+
+impl BicolorLed for SoftPwmLed {
+    fn set_pwm(&mut self, color: VbusLedColor, brightness: u8) {
+        COLOR.store(color as u8, Ordering::Relaxed);
+        BRIGHTNESS.store(brightness, Ordering::Relaxed);
+    }
+}
+
+fn current_color() -> VbusLedColor {
+    match COLOR.load(Ordering::Relaxed) {
+        x if x == VbusLedColor::Red as u8 => VbusLedColor::Red,
+        x if x == VbusLedColor::Amber as u8 => VbusLedColor::Amber,
+        _ => VbusLedColor::Green,
+    }
+}
+
+/// Drive PB5 from the colour and brightness last set through [`SoftPwmLed`]
+pub async fn run(pin: Peri<'static, PB5>) -> ! {
+    let mut pin = Flex::new(pin);
+    pin.set_as_input(Pull::None);
+
+    let mut ticker = Ticker::every(PWM_STEP);
+    let mut step = 0;
+    let mut last = Drive::Off;
+    loop {
+        let brightness = BRIGHTNESS.load(Ordering::Relaxed);
+        let drive = drive_for_step(current_color(), brightness, step);
+        if drive != last {
+            // Set the output level before enabling the driver to avoid a glitch
+            match drive {
+                Drive::Red => {
+                    pin.set_high();
+                    pin.set_as_output(Speed::Low);
+                }
+                Drive::Green => {
+                    pin.set_low();
+                    pin.set_as_output(Speed::Low);
+                }
+                Drive::Off => pin.set_as_input(Pull::None),
+            }
+            last = drive;
+        }
+        step = (step + 1) % PWM_STEPS;
+        ticker.next().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lit_steps(color: VbusLedColor, brightness: u8, lit: Drive) -> usize {
+        (0..PWM_STEPS)
+            .filter(|&step| drive_for_step(color, brightness, step) == lit)
+            .count()
+    }
+
+    #[test]
+    fn test_brightness_sets_lit_share_of_period() {
+        assert_eq!(lit_steps(VbusLedColor::Red, 100, Drive::Red), 10);
+        assert_eq!(lit_steps(VbusLedColor::Red, 30, Drive::Red), 3);
+        assert_eq!(lit_steps(VbusLedColor::Red, 55, Drive::Red), 6);
+        assert_eq!(lit_steps(VbusLedColor::Red, 0, Drive::Red), 0);
+        assert_eq!(lit_steps(VbusLedColor::Red, 255, Drive::Red), 10);
+        assert_eq!(lit_steps(VbusLedColor::Green, 100, Drive::Green), 10);
+        assert_eq!(lit_steps(VbusLedColor::Green, 30, Drive::Green), 3);
+    }
+
+    #[test]
+    fn test_unlit_steps_float_the_pin() {
+        for color in [VbusLedColor::Red, VbusLedColor::Green, VbusLedColor::Amber] {
+            // The unlit share is off, never driven green
+            assert_eq!(lit_steps(color, 30, Drive::Off), 7);
+            assert_eq!(lit_steps(color, 0, Drive::Off), 10);
+        }
+        assert_eq!(lit_steps(VbusLedColor::Red, 30, Drive::Green), 0);
+    }
+
+    #[test]
+    fn test_amber_alternates_colors() {
+        let drives: [Drive; 5] =
+            core::array::from_fn(|step| drive_for_step(VbusLedColor::Amber, 40, step as u8));
+        assert_eq!(
+            drives,
+            [
+                Drive::Red,
+                Drive::Green,
+                Drive::Red,
+                Drive::Green,
+                Drive::Off
+            ]
+        );
+    }
+}
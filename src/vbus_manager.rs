@@ -1,12 +1,84 @@
 use alloc::sync::Arc;
-use embassy_stm32::gpio::Output;
 use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex};
-use embassy_time::Timer;
+use embassy_time::{Duration, Instant, Timer};
 
-use crate::{button::InputEvent, power_output::PowerOutput, InputSubscriber};
+use uom::si::{
+    electric_current::ampere,
+    electric_potential::{millivolt, volt},
+};
+
+use crate::{
+    app_manager::{AlwaysOnButton, OperatingMode, SystemState},
+    button::{ButtonId, InputEvent},
+    fault::{self, Fault},
+    hal::{BicolorLed, OutputSwitch},
+    load_detect::{LoadDetectConfig, LoadDetector, LoadStatus},
+    power::{self, PdStatus},
+    power_output::PowerOutput,
+    power_rail::PowerRail,
+    shared::{ticks_for_ms, MANAGER_TICK_MS},
+    thermal,
+    vbus_led::SoftPwmLed,
+    InputSubscriber,
+};
 
 /// VBUS 电压阈值 (5.5V)
 const VBUS_VOLTAGE_THRESHOLD: f64 = 5.5;
+/// LED 颜色切换的回差：高于阈值 +0.1V 变红，低于阈值 -0.1V 变绿
+const VBUS_COLOR_HYSTERESIS: f64 = 0.1;
+
+/// 负载状态变化时 LED 熄灭脉冲长度 (200ms)
+const LOAD_PULSE_TICKS: u32 = ticks_for_ms(200);
+
+/// LED 闪烁半周期：普通 500ms，快速 100ms
+const BLINK_HALF_PERIOD_TICKS: u32 = ticks_for_ms(500);
+const FAST_BLINK_HALF_PERIOD_TICKS: u32 = ticks_for_ms(100);
+
+/// 闪烁指示模式下 VBUS 开启时的闪烁半周期：低电压慢闪 1.5s，高电压快闪 200ms，
+/// 与普通闪烁 (500ms) 和快速闪烁 (100ms) 均至少相差 2 倍，不会混淆
+const PATTERN_LOW_HALF_PERIOD_TICKS: u32 = ticks_for_ms(1500);
+const PATTERN_HIGH_HALF_PERIOD_TICKS: u32 = ticks_for_ms(200);
+
+/// 等待 PD 协商时的短闪：每 1s 点亮 100ms
+const WAITING_PD_PERIOD_TICKS: u32 = ticks_for_ms(1000);
+const WAITING_PD_ON_TICKS: u32 = ticks_for_ms(100);
+
+/// VBUS 状态发布的最小间隔，突发切换时合并为一次发布
+const VBUS_STATE_PUBLISH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 状态发布限流器：只发布变化的值，且两次发布之间至少间隔 `interval`
+///
+/// 被限流的值不会丢失，后续调用 `poll` 时会发布最新值。
+pub struct PublishLimiter<T: Copy + PartialEq> {
+    interval: Duration,
+    published: Option<T>,
+    last_publish: Option<Instant>,
+}
+
+impl<T: Copy + PartialEq> PublishLimiter<T> {
+    pub const fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            published: None,
+            last_publish: None,
+        }
+    }
+
+    /// 返回需要发布的值（无需发布时返回 None）
+    pub fn poll(&mut self, value: T, now: Instant) -> Option<T> {
+        if self.published == Some(value) {
+            return None;
+        }
+        if let Some(last) = self.last_publish {
+            if now - last < self.interval {
+                return None;
+            }
+        }
+        self.published = Some(value);
+        self.last_publish = Some(now);
+        Some(value)
+    }
+}
 
 /// VBUS 管理器状态
 #[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
@@ -24,57 +96,278 @@ impl Default for VbusState {
 /// VBUS LED 颜色状态
 #[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
 pub enum VbusLedColor {
-    Green, // 绿色 LED (电压 < 5.5V)
-    Red,   // 红色 LED (电压 >= 5.5V)
+    Green, // 绿色 LED (电压低于 5.4V，回差带内保持)
+    Red,   // 红色 LED (电压高于 5.6V，回差带内保持)
+    Amber, // 琥珀色 (红绿快速交替，等待 PD 协商)
+}
+
+impl VbusLedColor {
+    /// 带回差的颜色判定：越过阈值加减回差才切换，阈值附近的波动保持当前颜色
+    pub fn for_voltage(voltage: f64, current: Self) -> Self {
+        if voltage > VBUS_VOLTAGE_THRESHOLD + VBUS_COLOR_HYSTERESIS {
+            Self::Red
+        } else if voltage < VBUS_VOLTAGE_THRESHOLD - VBUS_COLOR_HYSTERESIS {
+            Self::Green
+        } else {
+            current
+        }
+    }
 }
 
 /// VBUS LED 显示模式
 #[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
 pub enum VbusLedMode {
-    Blinking, // 闪烁 (VBUS 关闭时)
-    Solid,    // 常亮 (VBUS 开启时)
+    Blinking,     // 闪烁 (VBUS 关闭时)
+    Solid,        // 常亮 (VBUS 开启时)
+    FastBlinking, // 快速闪烁 (PD 协商失败)
+    WaitingPd,    // 短闪 (已连接，等待 PD 协商)
+    Lockout,      // 红灯常亮 (连续开启失败，已锁定)
+}
+
+/// VBUS 电压高低的指示方式
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum VbusLedIndication {
+    Color,   // 颜色区分：绿色为低电压，红色为高电压（5.5V 阈值，带回差）
+    Pattern, // 闪烁速率区分（色盲友好）：VBUS 开启时低电压慢闪，高电压快闪
+}
+
+/// LED 颜色和模式的判定，不访问硬件，便于单独测试
+///
+/// 颜色按 VBUS 电压带回差判定（`previous_color` 为当前颜色）；VBUS 关闭时的模式
+/// 依次反映开启锁定和 PD 协商状态。
+pub fn decide_led(
+    vbus_voltage: f64,
+    vbus_state: VbusState,
+    pd_status: PdStatus,
+    enable_lockout: bool,
+    previous_color: VbusLedColor,
+) -> (VbusLedColor, VbusLedMode) {
+    let color = VbusLedColor::for_voltage(vbus_voltage, previous_color);
+    let mode = match vbus_state {
+        VbusState::Disabled if enable_lockout => VbusLedMode::Lockout,
+        VbusState::Disabled => match pd_status {
+            PdStatus::NegotiationFailed => VbusLedMode::FastBlinking,
+            PdStatus::Attached(_) => VbusLedMode::WaitingPd,
+            PdStatus::Detached | PdStatus::Negotiated => VbusLedMode::Blinking,
+        },
+        VbusState::Enabled => VbusLedMode::Solid,
+    };
+    (color, mode)
+}
+
+/// 输出上升检查结果
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum OutputRiseStatus {
+    Idle,    // VBUS 关闭
+    Pending, // 已开启，等待达到目标电压
+    Ok,      // 在预期上升时间内达到目标
+    Slow,    // 达到目标但超过预期上升时间（源端偏弱或容性负载过大）
+    NoRise,  // 超时仍未达到目标，已关闭输出
+}
+
+/// 过压保护配置
+#[derive(Debug, Clone, Copy)]
+pub struct OvpConfig {
+    pub enabled: bool,
+    pub max_voltage: f64, // 输出过压上限 (V)
+}
+
+impl Default for OvpConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            // PA0 测量的是 SK150C 升降压模块之后的输出，可高于 PD 输入电压，
+            // 因此按模块最高输出 36V 留出余量，且低于 ADC 满量程 42V
+            max_voltage: 38.0,
+        }
+    }
+}
+
+/// 过流后的处理方式
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum OcpMode {
+    /// 关闭并锁定，需短按按键解除后才能重新开启
+    Latched,
+    /// 关闭后等待 `delay` 自动重新开启
+    AutoRetry { delay: Duration },
+}
+
+/// 过流保护配置
+#[derive(Debug, Clone, Copy)]
+pub struct OcpConfig {
+    pub enabled: bool,
+    pub limit_ratio: f64,        // 过流上限 = 配置的目标电流 × 该比例
+    pub inrush_window: Duration, // 开启后该时间内的电流尖峰视为浪涌，不计入
+    pub trip_time: Duration,     // 电流持续超过上限该时间才判定过流
+    pub mode: OcpMode,
+}
+
+impl Default for OcpConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            limit_ratio: 1.10,
+            inrush_window: Duration::from_millis(200),
+            // 需覆盖多个 ADC 采样周期，单个尖峰不会触发
+            trip_time: Duration::from_millis(300),
+            mode: OcpMode::Latched,
+        }
+    }
+}
+
+/// 过流判定：浪涌窗口内忽略，之后电流持续超过上限 `trip_time` 才判定过流
+#[derive(Debug, Default)]
+struct OvercurrentFilter {
+    over_since: Option<Instant>, // 电流开始超过上限的时刻
+}
+
+impl OvercurrentFilter {
+    /// 返回是否判定为持续过流
+    fn update(
+        &mut self,
+        config: &OcpConfig,
+        enabled_at: Instant,
+        now: Instant,
+        current: f64,
+        limit: f64,
+    ) -> bool {
+        if now - enabled_at < config.inrush_window || current <= limit {
+            self.over_since = None;
+            return false;
+        }
+        let since = *self.over_since.get_or_insert(now);
+        now - since >= config.trip_time
+    }
+
+    fn reset(&mut self) {
+        self.over_since = None;
+    }
+}
+
+/// VBUS 管理器配置
+#[derive(Debug, Clone, Copy)]
+pub struct VbusManagerConfig {
+    pub mode: OperatingMode,               // 运行模式（需与 PowerManager 一致）
+    pub restore_vbus: bool,                // 上电恢复：允许开启时自动开启一次 VBUS
+    pub load_detect: LoadDetectConfig,     // 负载检测阈值
+    pub floor_arm_delay: Duration,         // 开启后电压下限保护生效前的等待时间
+    pub load_indication: bool,             // 负载接入/断开时 LED 短暂熄灭提示
+    pub led_indication: VbusLedIndication, // 电压高低的 LED 指示方式
+    pub led_brightness: u8,                // VBUS 开启及锁定时的 LED 亮度 (%)
+    pub blink_brightness: u8,              // VBUS 关闭时闪烁的 LED 亮度 (%)
+    pub rise_time: Duration,               // 预期上升时间，超过则告警
+    pub rise_timeout: Duration,            // 上升超时，超过仍未达到目标则判定故障
+    pub rise_min_delta: f64,               // 比开启前电压高出该值 (V) 即视为上升完成
+    pub max_enable_attempts: u32,          // 连续开启失败次数上限，达到后锁定直到复位
+    pub discharge_timeout: Duration,       // 关闭后主动泄放的最长时间（需硬件泄放电路）
+    pub ovp: OvpConfig,                    // 过压保护
+    pub ocp: OcpConfig,                    // 过流保护
+    pub soft_start: Duration, // 开启时的软启动时间，限制下游电容的浪涌（0 表示直接开启）
+}
+
+impl Default for VbusManagerConfig {
+    fn default() -> Self {
+        Self {
+            mode: OperatingMode::Interactive,
+            restore_vbus: false,
+            load_detect: LoadDetectConfig::default(),
+            floor_arm_delay: Duration::from_secs(1),
+            load_indication: false,
+            led_indication: VbusLedIndication::Color,
+            led_brightness: 100,
+            blink_brightness: 100,
+            // 按 100ms 的未滤波 VBUS 采样整定：超时前至少有数个采样
+            rise_time: Duration::from_millis(300),
+            rise_timeout: Duration::from_secs(1),
+            // PA0 测量的是升降压模块之后的输出，与 PD 目标电压无关，只看开启后是否明显上升
+            rise_min_delta: 1.0,
+            max_enable_attempts: 3,
+            discharge_timeout: Duration::from_millis(500),
+            ovp: OvpConfig::default(),
+            ocp: OcpConfig::default(),
+            // 默认直接开启，需要限制浪涌时再按硬件设置软启动时间
+            soft_start: Duration::from_ticks(0),
+        }
+    }
 }
 
 /// VBUS 管理器上下文
-pub struct VbusManagerContext<'d> {
+///
+/// 硬件通过 `OutputSwitch`/`BicolorLed` 抽象，默认类型为实际外设，测试中可替换为模拟实现
+pub struct VbusManagerContext<'d, O = PowerOutput<'d>, P = SoftPwmLed> {
     pub input_rx: Arc<Mutex<CriticalSectionRawMutex, InputSubscriber<'d>>>,
-    pub vbus_output: PowerOutput<'d>, // PB7 VBUS 开关控制 (使用现有的 PowerOutput)
-    pub vbus_led_pin: Arc<Mutex<CriticalSectionRawMutex, Output<'d>>>, // PB5 双色 LED 控制
+    pub vbus_rail: PowerRail<O>, // PB7 VBUS 开关控制 (使用现有的 PowerOutput)
+    pub vbus_led_pin: Arc<Mutex<CriticalSectionRawMutex, P>>, // PB5 双色 LED 控制
+    pub config: VbusManagerConfig,
 }
 
 /// VBUS 管理器
-pub struct VbusManager<'d> {
-    context: VbusManagerContext<'d>,
+pub struct VbusManager<'d, O = PowerOutput<'d>, P = SoftPwmLed> {
+    context: VbusManagerContext<'d, O, P>,
+    now: Instant, // 当前 tick 的时间，由 step 传入
     pub vbus_state: VbusState,
     current_vbus_voltage: f64,
-    current_vin_voltage: f64,
+    system_state: SystemState, // PowerManager 的系统状态，由外部每个 tick 更新
     led_color: VbusLedColor,
     led_mode: VbusLedMode,
     led_blink_state: bool,  // LED 闪烁状态
     led_blink_counter: u32, // LED 闪烁计数器
-    tick_counter: u32,      // 用于定期状态报告
+    state_publisher: PublishLimiter<bool>,
+    load_detector: LoadDetector,
+    load_status: LoadStatus,
+    enabled_at: Option<Instant>, // VBUS 开启时刻
+    load_pulse_ticks: u32,       // 负载提示脉冲剩余 tick 数
+    rise_status: OutputRiseStatus,
+    rise_baseline: f64,  // 开启前的 VBUS 电压，上升检查以此为基准
+    failed_enables: u32, // 连续开启失败次数
+    enable_lockout: bool,
+    ovp_latched: bool, // 过压后锁定，需短按按键解除后才能重新开启
+    vbus_peak: f64,    // 本次开启以来的 VBUS 峰值（未滤波）
+    overcurrent: OvercurrentFilter,
+    ocp_latched: bool, // 过流后锁定（OcpMode::Latched），需短按按键解除后才能重新开启
+    ocp_retry_at: Option<Instant>, // 过流后自动重试的时刻（OcpMode::AutoRetry）
+    auto_enable_pending: bool, // 常开模式下等待条件满足后自动开启 VBUS
 }
 
-impl<'d> VbusManager<'d> {
-    pub fn new(context: VbusManagerContext<'d>) -> Self {
+impl<'d, O: OutputSwitch, P: BicolorLed> VbusManager<'d, O, P> {
+    pub fn new(context: VbusManagerContext<'d, O, P>) -> Self {
+        let load_detector = LoadDetector::new(context.config.load_detect);
+        let auto_enable_pending = context.config.mode.is_always_on() || context.config.restore_vbus;
         Self {
             context,
+            now: Instant::from_ticks(0),
             vbus_state: VbusState::default(),
             current_vbus_voltage: 0.0,
-            current_vin_voltage: 0.0,
+            system_state: SystemState::Standby,
             led_color: VbusLedColor::Green,
             led_mode: VbusLedMode::Blinking,
             led_blink_state: false,
             led_blink_counter: 0,
-            tick_counter: 0,
+            state_publisher: PublishLimiter::new(VBUS_STATE_PUBLISH_INTERVAL),
+            load_detector,
+            load_status: LoadStatus::Off,
+            enabled_at: None,
+            load_pulse_ticks: 0,
+            rise_status: OutputRiseStatus::Idle,
+            rise_baseline: 0.0,
+            failed_enables: 0,
+            enable_lockout: false,
+            ovp_latched: false,
+            vbus_peak: 0.0,
+            overcurrent: OvercurrentFilter::default(),
+            ocp_latched: false,
+            ocp_retry_at: None,
+            auto_enable_pending,
         }
     }
 
     pub async fn init(&mut self) {
         // 初始化为关闭状态
         self.set_vbus_state(VbusState::Disabled).await;
-        // 初始化 LED 状态（绿色，熄灭）
-        self.set_led_hardware_off().await;
+        // 初始化 LED 状态（熄灭）
+        self.set_led_pwm(VbusLedColor::Green, 0).await;
         defmt::info!("VbusManager initialized in Disabled state");
     }
 
@@ -86,6 +379,8 @@ impl<'d> VbusManager<'d> {
                 if reset_signal {
                     defmt::info!("VBUS reset signal received - forcing VBUS to Disabled");
                     self.set_vbus_state(VbusState::Disabled).await;
+                    // 常开模式下由 PowerManager 关闭后重新开启
+                    self.auto_enable_pending = self.context.config.mode.is_always_on();
                     // 清除重置信号
                     crate::shared::VBUS_RESET_CHANNEL.sender().send(false);
                 }
@@ -93,14 +388,362 @@ impl<'d> VbusManager<'d> {
         }
     }
 
+    /// 返回当前禁止开启 VBUS 的原因（None 表示允许开启）
+    fn enable_blocked_reason(&self) -> Option<&'static str> {
+        if self.system_state == SystemState::Standby {
+            return Some("system in Standby");
+        }
+        if self.enable_lockout {
+            return Some("too many failed enable attempts, reset required");
+        }
+        if self.ovp_latched {
+            return Some("overvoltage latched, short press to clear");
+        }
+        if self.ocp_latched {
+            return Some("overcurrent latched, short press to clear");
+        }
+        if self.ocp_retry_at.is_some() {
+            return Some("overcurrent, waiting to retry");
+        }
+        match power::pd_status() {
+            PdStatus::Negotiated => {}
+            PdStatus::NegotiationFailed => return Some("PD negotiation failed"),
+            // 协商完成前源端可能仅提供默认 5V，不允许输出
+            PdStatus::Attached(_) | PdStatus::Detached => return Some("no PD contract yet"),
+        }
+        if thermal::thermal_status().is_protecting() {
+            return Some("thermal shutdown");
+        }
+        if crate::adc_reader::is_sampling_paused() {
+            // 采样暂停时保护逻辑无法获得新数据
+            return Some("ADC sampling paused");
+        }
+        None
+    }
+
+    /// PD 合约失效（协商失败、重新连接或断开）时强制关闭 VBUS
+    async fn check_pd_status(&mut self) {
+        let pd_status = power::pd_status();
+        if pd_status != PdStatus::Negotiated && self.vbus_state == VbusState::Enabled {
+            defmt::warn!(
+                "VBUS: PD contract not valid ({:?}) - forcing VBUS to Disabled",
+                pd_status
+            );
+            self.set_vbus_state(VbusState::Disabled).await;
+            // 常开模式下新的合约建立后重新开启
+            self.auto_enable_pending = self.context.config.mode.is_always_on();
+        }
+    }
+
+    /// 待机状态下 VBUS 必须关闭，外部造成的非法状态在本 tick 内纠正
+    async fn check_system_state(&mut self) {
+        if self.system_state == SystemState::Standby && self.vbus_state == VbusState::Enabled {
+            defmt::warn!("VBUS: enabled in Standby - forcing VBUS to Disabled");
+            self.set_vbus_state(VbusState::Disabled).await;
+            // 常开模式下回到工作状态后重新开启
+            self.auto_enable_pending = self.context.config.mode.is_always_on();
+        }
+    }
+
+    /// 常开模式：允许开启时自动开启 VBUS
+    ///
+    /// 仅在启动、PD 合约重建和待机恢复后开启一次；过温、电压下限、上升失败等保护
+    /// 关闭输出后不自动重新开启，与交互模式一致。上电恢复 VBUS 只在启动后开启一次。
+    async fn check_always_on(&mut self) {
+        if !self.auto_enable_pending || self.vbus_state == VbusState::Enabled {
+            return;
+        }
+        if self.enable_blocked_reason().is_none() {
+            defmt::info!("VBUS: enabling automatically");
+            self.auto_enable_pending = false;
+            self.set_vbus_state(VbusState::Enabled).await;
+        }
+    }
+
+    /// 过温保护期间（含恢复等待期）强制关闭 VBUS，恢复后不自动重新开启
+    async fn check_thermal(&mut self) {
+        let status = thermal::thermal_status();
+        if status.is_protecting() && self.vbus_state == VbusState::Enabled {
+            defmt::warn!("VBUS: thermal {:?} - forcing VBUS to Disabled", status);
+            self.set_vbus_state(VbusState::Disabled).await;
+        }
+    }
+
+    /// 过压保护：VBUS（未滤波）超过输出上限时立即关闭并锁定
+    ///
+    /// 锁定期间故障保持激活，`clear_faults` 无法清除，只有短按按键解除。
+    async fn check_overvoltage(&mut self) {
+        let ovp = self.context.config.ovp;
+        if !ovp.enabled || self.vbus_state != VbusState::Enabled {
+            return;
+        }
+        let Some(vbus) = crate::shared::MEASUREMENTS.vbus_voltage_raw.latest() else {
+            return;
+        };
+        let vbus = vbus.get::<volt>();
+        self.vbus_peak = self.vbus_peak.max(vbus);
+
+        let ceiling = ovp.max_voltage;
+        if vbus > ceiling {
+            defmt::error!(
+                "VBUS overvoltage: {}V > {}V, peak {}V - forcing VBUS to Disabled until short press",
+                vbus,
+                ceiling,
+                self.vbus_peak
+            );
+            self.ovp_latched = true;
+            fault::set_active(Fault::Overvoltage, true);
+            self.set_vbus_state(VbusState::Disabled).await;
+        }
+    }
+
+    /// 短按按键解除过压/过流锁定
+    fn clear_protection_latches(&mut self) {
+        if self.ovp_latched {
+            defmt::info!("VBUS: overvoltage latch cleared by short press");
+            self.ovp_latched = false;
+            fault::set_active(Fault::Overvoltage, false);
+        }
+        if self.ocp_latched {
+            defmt::info!("VBUS: overcurrent latch cleared by short press");
+            self.ocp_latched = false;
+            fault::set_active(Fault::Overcurrent, false);
+        }
+    }
+
+    /// 过流保护：输出电流（未滤波）持续超过配置目标电流的上限时关闭
+    ///
+    /// 开启后的浪涌窗口内不判定。按 `OcpMode` 锁定等待短按，或延时后自动重新开启；
+    /// 等待期间故障保持激活。
+    async fn check_overcurrent(&mut self) {
+        let ocp = self.context.config.ocp;
+        if !ocp.enabled {
+            return;
+        }
+
+        if let Some(retry_at) = self.ocp_retry_at {
+            if self.now >= retry_at {
+                self.ocp_retry_at = None;
+                fault::set_active(Fault::Overcurrent, false);
+                match self.enable_blocked_reason() {
+                    None => {
+                        defmt::info!("VBUS: retrying after overcurrent");
+                        self.set_vbus_state(VbusState::Enabled).await;
+                    }
+                    Some(reason) => {
+                        defmt::warn!("VBUS: overcurrent retry skipped - {}", reason);
+                    }
+                }
+            }
+            return;
+        }
+
+        let Some(enabled_at) = self.enabled_at else {
+            self.overcurrent.reset();
+            return;
+        };
+        let Some(config) = crate::shared::CONFIG_SNAPSHOT_CHANNEL
+            .anon_receiver()
+            .try_get()
+        else {
+            return;
+        };
+        let Some(current) = crate::shared::MEASUREMENTS.output_current_raw.latest() else {
+            return;
+        };
+        let current = current.get::<ampere>();
+        let limit = config.target_current.get::<ampere>() * ocp.limit_ratio;
+        if limit <= 0.0
+            || !self
+                .overcurrent
+                .update(&ocp, enabled_at, self.now, current, limit)
+        {
+            return;
+        }
+
+        self.overcurrent.reset();
+        fault::set_active(Fault::Overcurrent, true);
+        match ocp.mode {
+            OcpMode::Latched => {
+                defmt::error!(
+                    "VBUS overcurrent: {}A > {}A - forcing VBUS to Disabled until short press",
+                    current,
+                    limit
+                );
+                self.ocp_latched = true;
+            }
+            OcpMode::AutoRetry { delay } => {
+                defmt::error!(
+                    "VBUS overcurrent: {}A > {}A - forcing VBUS to Disabled, retrying in {}ms",
+                    current,
+                    limit,
+                    delay.as_millis()
+                );
+                self.ocp_retry_at = Some(self.now + delay);
+            }
+        }
+        self.set_vbus_state(VbusState::Disabled).await;
+    }
+
+    /// 固件强制的电压下限：输出开启后 VBUS 低于配置下限时立即关闭
+    async fn check_voltage_floor(&mut self) {
+        let Some(enabled_at) = self.enabled_at else {
+            return;
+        };
+        if self.now - enabled_at < self.context.config.floor_arm_delay {
+            return;
+        }
+        let Some(config) = crate::shared::CONFIG_SNAPSHOT_CHANNEL
+            .anon_receiver()
+            .try_get()
+        else {
+            return;
+        };
+        let floor = config.min_voltage.get::<millivolt>() as f64 / 1000.0;
+        if floor > 0.0 && self.current_vbus_voltage < floor {
+            defmt::warn!(
+                "VBUS {}V below floor {}V - forcing VBUS to Disabled",
+                self.current_vbus_voltage,
+                floor
+            );
+            fault::raise(Fault::VoltageFloor);
+            self.set_vbus_state(VbusState::Disabled).await;
+        }
+    }
+
+    /// 开启后验证输出在预期时间内比开启前至少上升 `rise_min_delta`
+    ///
+    /// 慢于 `rise_time` 仅告警；超过 `rise_timeout` 仍未上升则判定故障并关闭。
+    async fn check_output_rise(&mut self) {
+        if self.rise_status != OutputRiseStatus::Pending {
+            return;
+        }
+        let Some(enabled_at) = self.enabled_at else {
+            return;
+        };
+
+        let elapsed = self.now - enabled_at;
+        let target = self.rise_baseline + self.context.config.rise_min_delta;
+
+        let status = if self.current_vbus_voltage >= target {
+            if elapsed <= self.context.config.rise_time {
+                OutputRiseStatus::Ok
+            } else {
+                defmt::warn!(
+                    "VBUS slow rise: reached {}V after {}ms",
+                    self.current_vbus_voltage,
+                    elapsed.as_millis()
+                );
+                OutputRiseStatus::Slow
+            }
+        } else if elapsed >= self.context.config.rise_timeout {
+            defmt::error!(
+                "VBUS did not rise: {}V < {}V after {}ms - forcing VBUS to Disabled",
+                self.current_vbus_voltage,
+                target,
+                elapsed.as_millis()
+            );
+            fault::raise(Fault::OutputNoRise);
+            self.set_vbus_state(VbusState::Disabled).await;
+            OutputRiseStatus::NoRise
+        } else {
+            return;
+        };
+        self.set_rise_status(status);
+    }
+
+    fn set_rise_status(&mut self, status: OutputRiseStatus) {
+        match status {
+            // 验证通过的开启清零失败计数
+            OutputRiseStatus::Ok | OutputRiseStatus::Slow => self.failed_enables = 0,
+            OutputRiseStatus::NoRise => self.record_failed_enable(),
+            OutputRiseStatus::Idle | OutputRiseStatus::Pending => {}
+        }
+        if self.rise_status != status {
+            self.rise_status = status;
+            crate::shared::OUTPUT_RISE_CHANNEL.sender().send(status);
+        }
+    }
+
+    /// 记录一次开启失败，连续失败达到上限后锁定（仅复位可解除）
+    fn record_failed_enable(&mut self) {
+        self.failed_enables += 1;
+        defmt::warn!(
+            "VBUS enable failed ({}/{})",
+            self.failed_enables,
+            self.context.config.max_enable_attempts
+        );
+        if self.failed_enables >= self.context.config.max_enable_attempts && !self.enable_lockout {
+            defmt::error!("VBUS enable locked out until reset");
+            self.enable_lockout = true;
+            fault::set_active(Fault::EnableLockout, true);
+        }
+    }
+
+    /// 根据输出电流更新负载状态，并在变化时发布
+    fn update_load_status(&mut self) {
+        let new_status = match self.vbus_state {
+            VbusState::Disabled => {
+                self.load_detector.reset();
+                LoadStatus::Off
+            }
+            VbusState::Enabled => match crate::shared::MEASUREMENTS.output_current.latest() {
+                Some(current) => {
+                    if self.load_detector.update(current.get::<ampere>(), self.now) {
+                        LoadStatus::Active
+                    } else {
+                        LoadStatus::NoLoad
+                    }
+                }
+                None => LoadStatus::Unknown,
+            },
+        };
+
+        if self.load_status != new_status {
+            defmt::info!(
+                "VBUS load status changing from {:?} to {:?}",
+                self.load_status,
+                new_status
+            );
+            // 负载跨越迟滞阈值时触发 LED 提示脉冲
+            let crossed = matches!(
+                (self.load_status, new_status),
+                (LoadStatus::NoLoad, LoadStatus::Active) | (LoadStatus::Active, LoadStatus::NoLoad)
+            );
+            if crossed && self.context.config.load_indication {
+                self.load_pulse_ticks = LOAD_PULSE_TICKS;
+            }
+            self.load_status = new_status;
+            crate::shared::LOAD_STATUS_CHANNEL.sender().send(new_status);
+        }
+    }
+
     /// 更新电压信息（由外部调用）
-    pub fn update_voltages(&mut self, vbus_voltage: f64, vin_voltage: f64) {
+    pub fn update_voltages(&mut self, vbus_voltage: f64) {
+        #[cfg(feature = "fault-injection")]
+        let vbus_voltage = if crate::fault_injection::is_simulated(
+            crate::fault_injection::SimulatedFault::Undervoltage,
+        ) {
+            0.0
+        } else {
+            vbus_voltage
+        };
         self.current_vbus_voltage = vbus_voltage;
-        self.current_vin_voltage = vin_voltage;
+    }
+
+    /// 更新系统状态（由外部调用，VBUS 只允许在工作状态下开启）
+    pub fn update_system_state(&mut self, system_state: SystemState) {
+        self.system_state = system_state;
     }
 
     /// 设置 VBUS 开关状态
+    ///
+    /// 待机状态下拒绝开启，所有开启路径都经过这里
     async fn set_vbus_state(&mut self, new_state: VbusState) {
+        if new_state == VbusState::Enabled && self.system_state == SystemState::Standby {
+            defmt::error!("VBUS: refusing to enable VBUS in Standby");
+            return;
+        }
         if self.vbus_state != new_state {
             defmt::info!(
                 "VBUS state changing from {:?} to {:?}",
@@ -108,28 +751,54 @@ impl<'d> VbusManager<'d> {
                 new_state
             );
             self.vbus_state = new_state;
+            self.enabled_at = match new_state {
+                VbusState::Enabled => Some(self.now),
+                VbusState::Disabled => None,
+            };
+            if new_state == VbusState::Enabled {
+                self.vbus_peak = 0.0;
+                self.rise_baseline = self.current_vbus_voltage;
+            }
+            self.set_rise_status(match new_state {
+                VbusState::Enabled => OutputRiseStatus::Pending,
+                VbusState::Disabled => OutputRiseStatus::Idle,
+            });
 
             // 更新硬件状态
             self.update_vbus_hardware().await;
 
-            // 发送状态到共享通道
-            let vbus_enabled = matches!(new_state, VbusState::Enabled);
-            crate::shared::VBUS_STATE_CHANNEL
-                .sender()
-                .send(vbus_enabled);
+            // 发送状态到共享通道（限流）
+            self.publish_vbus_state();
+        }
+    }
+
+    /// 将最新 VBUS 状态发布到共享通道
+    ///
+    /// VBUS_STATE_CHANNEL 是 Watch（只保留最新值），突发切换时被限流的中间状态
+    /// 直接丢弃，最终状态会在后续 tick 中发布。
+    fn publish_vbus_state(&mut self) {
+        let vbus_enabled = matches!(self.vbus_state, VbusState::Enabled);
+        if let Some(value) = self.state_publisher.poll(vbus_enabled, self.now) {
+            crate::shared::VBUS_STATE_CHANNEL.sender().send(value);
         }
     }
 
     /// 更新 VBUS 硬件开关状态
+    ///
+    /// 开启时经软启动限制浪涌，开启后的上升检查由 `check_output_rise` 负责；
+    /// 关闭时若有泄放电路则主动泄放输出电容。
     async fn update_vbus_hardware(&mut self) {
         match self.vbus_state {
-            VbusState::Disabled => {
-                self.context.vbus_output.set_off().await;
-                defmt::info!("VBUS output DISABLED (PB7 = LOW)");
-            }
             VbusState::Enabled => {
-                self.context.vbus_output.set_on().await;
-                defmt::info!("VBUS output ENABLED (PB7 = HIGH)");
+                let ramp = self.context.config.soft_start;
+                self.context.vbus_rail.set_on_soft(ramp).await;
+            }
+            VbusState::Disabled => {
+                self.context.vbus_rail.set_enabled(false).await;
+                let timeout = self.context.config.discharge_timeout;
+                if let Some(elapsed) = self.context.vbus_rail.discharge(timeout).await {
+                    defmt::info!("VBUS discharged in {}ms", elapsed.as_millis());
+                }
             }
         }
     }
@@ -143,10 +812,31 @@ impl<'d> VbusManager<'d> {
         self.set_vbus_state(new_state).await;
     }
 
+    /// 强制关闭 VBUS 输出（用于重启等受控关机路径）
+    pub async fn force_disable(&mut self) {
+        self.now = Instant::now();
+        self.set_vbus_state(VbusState::Disabled).await;
+    }
+
     /// 处理按键事件
     async fn handle_button_event(&mut self, event: InputEvent) {
         match event {
-            InputEvent::Click => {
+            // 锁定期间短按只解除锁定、不开启输出；长按留给 PowerManager 切换系统状态
+            InputEvent::Click(ButtonId::PRIMARY) if self.ovp_latched || self.ocp_latched => {
+                self.clear_protection_latches()
+            }
+            InputEvent::Click(ButtonId::PRIMARY)
+                if self.context.config.mode == OperatingMode::AlwaysOn(AlwaysOnButton::Ignore) =>
+            {
+                defmt::info!("VBUS: always-on mode - ignoring short press");
+            }
+            InputEvent::Click(ButtonId::PRIMARY) => {
+                if self.vbus_state == VbusState::Disabled {
+                    if let Some(reason) = self.enable_blocked_reason() {
+                        defmt::warn!("VBUS: refusing to enable VBUS - {}", reason);
+                        return;
+                    }
+                }
                 defmt::info!("VBUS: Short press detected - toggling VBUS state");
                 self.toggle_vbus().await;
             }
@@ -159,6 +849,16 @@ impl<'d> VbusManager<'d> {
 
     /// 主循环 tick
     pub async fn tick(&mut self) {
+        self.step(Instant::now()).await;
+
+        // 添加小延迟
+        Timer::after_millis(MANAGER_TICK_MS).await; // 50Hz更新频率
+    }
+
+    /// 执行一次 tick 的逻辑（不含延迟），时间由调用方传入以便测试中精确控制
+    pub async fn step(&mut self, now: Instant) {
+        self.now = now;
+
         // 处理按键输入
         let event = {
             let mut input_rx = self.context.input_rx.lock().await;
@@ -174,42 +874,49 @@ impl<'d> VbusManager<'d> {
         // 检查VBUS重置信号
         self.check_vbus_reset().await;
 
-        // 更新 LED 状态
-        self.update_led_display().await;
+        // 检查PD协商状态
+        self.check_pd_status().await;
 
-        // 定期状态报告（每10秒一次）
-        self.tick_counter += 1;
-        if self.tick_counter % 500 == 0 {
-            // 500 * 20ms = 10秒
-            defmt::info!(
-                "VbusManager status: State={:?}, VBUS={}V, VIN={}V, LED={:?}/{:?}, Tick={}",
-                self.vbus_state,
-                self.current_vbus_voltage,
-                self.current_vin_voltage,
-                self.led_color,
-                self.led_mode,
-                self.tick_counter
-            );
-        }
+        // 检查系统状态（待机时不允许 VBUS 开启）
+        self.check_system_state().await;
 
-        // 添加小延迟
-        Timer::after_millis(20).await; // 50Hz更新频率
+        // 检查过温保护
+        self.check_thermal().await;
+
+        // 检查过压
+        self.check_overvoltage().await;
+
+        // 检查过流
+        self.check_overcurrent().await;
+
+        // 检查电压下限
+        self.check_voltage_floor().await;
+
+        // 检查开启后的输出上升
+        self.check_output_rise().await;
+
+        // 常开模式自动开启
+        self.check_always_on().await;
+
+        // 补发被限流的最终状态
+        self.publish_vbus_state();
+
+        // 更新负载检测状态
+        self.update_load_status();
+
+        // 更新 LED 状态
+        self.update_led_display().await;
     }
 
     /// 更新 LED 显示状态
     async fn update_led_display(&mut self) {
-        // 确定 LED 颜色
-        let new_led_color = if self.current_vbus_voltage < VBUS_VOLTAGE_THRESHOLD {
-            VbusLedColor::Green
-        } else {
-            VbusLedColor::Red
-        };
-
-        // 确定 LED 模式
-        let new_led_mode = match self.vbus_state {
-            VbusState::Disabled => VbusLedMode::Blinking,
-            VbusState::Enabled => VbusLedMode::Solid,
-        };
+        let (new_led_color, new_led_mode) = decide_led(
+            self.current_vbus_voltage,
+            self.vbus_state,
+            power::pd_status(),
+            self.enable_lockout,
+            self.led_color,
+        );
 
         // 更新 LED 颜色状态
         if self.led_color != new_led_color {
@@ -239,49 +946,195 @@ impl<'d> VbusManager<'d> {
 
     /// 更新 LED 硬件显示
     async fn update_led_hardware(&mut self) {
+        let brightness = self.context.config.led_brightness;
+        let blink_brightness = self.context.config.blink_brightness;
         match self.led_mode {
             VbusLedMode::Solid => {
-                // 常亮模式
-                self.set_led_hardware_color(self.led_color).await;
-            }
-            VbusLedMode::Blinking => {
-                // 闪烁模式
-                self.led_blink_counter += 1;
-                if self.led_blink_counter >= 25 {
-                    // 25 * 20ms = 500ms，切换闪烁状态
-                    self.led_blink_state = !self.led_blink_state;
-                    self.led_blink_counter = 0;
+                // 常亮模式，负载提示脉冲期间短暂熄灭
+                if self.load_pulse_ticks > 0 {
+                    self.load_pulse_ticks -= 1;
+                    self.set_led_pwm(self.led_color, 0).await;
+                } else if self.context.config.led_indication == VbusLedIndication::Pattern {
+                    // 以闪烁速率代替颜色指示电压高低，常亮仅用于锁定状态
+                    let half_period = match self.led_color {
+                        VbusLedColor::Green | VbusLedColor::Amber => PATTERN_LOW_HALF_PERIOD_TICKS,
+                        VbusLedColor::Red => PATTERN_HIGH_HALF_PERIOD_TICKS,
+                    };
+                    self.advance_blink(half_period);
+                    let level = if self.led_blink_state { brightness } else { 0 };
+                    self.set_led_pwm(self.led_color, level).await;
+                } else {
+                    self.set_led_pwm(self.led_color, brightness).await;
                 }
+            }
+            VbusLedMode::Lockout => {
+                self.set_led_pwm(VbusLedColor::Red, brightness).await;
+            }
+            VbusLedMode::WaitingPd => {
+                // 琥珀色短闪，与电压颜色区分
+                self.led_blink_counter = (self.led_blink_counter + 1) % WAITING_PD_PERIOD_TICKS;
+                let level = if self.led_blink_counter < WAITING_PD_ON_TICKS {
+                    blink_brightness
+                } else {
+                    0
+                };
+                self.set_led_pwm(VbusLedColor::Amber, level).await;
+            }
+            VbusLedMode::Blinking | VbusLedMode::FastBlinking => {
+                // 闪烁模式：普通 500ms，快速 100ms
+                let half_period = match self.led_mode {
+                    VbusLedMode::FastBlinking => FAST_BLINK_HALF_PERIOD_TICKS,
+                    _ => BLINK_HALF_PERIOD_TICKS,
+                };
+                self.advance_blink(half_period);
 
-                if self.led_blink_state {
-                    self.set_led_hardware_color(self.led_color).await;
+                let level = if self.led_blink_state {
+                    blink_brightness
                 } else {
-                    self.set_led_hardware_off().await;
-                }
+                    0
+                };
+                self.set_led_pwm(self.led_color, level).await;
             }
         }
     }
 
-    /// 设置 LED 硬件颜色
-    async fn set_led_hardware_color(&mut self, color: VbusLedColor) {
-        let mut vbus_led_pin = self.context.vbus_led_pin.lock().await;
-        match color {
-            VbusLedColor::Green => {
-                // 绿色 LED: PB5 输出低电平
-                vbus_led_pin.set_low();
-            }
-            VbusLedColor::Red => {
-                // 红色 LED: PB5 输出高电平
-                vbus_led_pin.set_high();
-            }
+    /// 推进闪烁计数，每 `half_period` 个 tick 翻转一次亮灭
+    fn advance_blink(&mut self, half_period: u32) {
+        self.led_blink_counter += 1;
+        if self.led_blink_counter >= half_period {
+            self.led_blink_state = !self.led_blink_state;
+            self.led_blink_counter = 0;
         }
     }
 
-    /// 设置 LED 硬件为熄灭状态
-    async fn set_led_hardware_off(&mut self) {
-        // 根据硬件连接方式，这里使用绿色状态（低电平）作为"熄灭"状态
-        // 实际硬件可能需要不同的控制方式
+    /// 设置 LED 颜色和亮度 (%)，亮度 0 为熄灭（PB5 浮空）
+    pub async fn set_led_pwm(&mut self, color: VbusLedColor, brightness: u8) {
         let mut vbus_led_pin = self.context.vbus_led_pin.lock().await;
-        vbus_led_pin.set_low();
+        vbus_led_pin.set_pwm(color, brightness);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_led_color_hysteresis() {
+        use VbusLedColor::*;
+
+        // 回差带内保持当前颜色
+        for voltage in [5.4, 5.5, 5.6] {
+            assert_eq!(VbusLedColor::for_voltage(voltage, Green), Green);
+            assert_eq!(VbusLedColor::for_voltage(voltage, Red), Red);
+        }
+        // 越过对应边沿才切换
+        assert_eq!(VbusLedColor::for_voltage(5.61, Green), Red);
+        assert_eq!(VbusLedColor::for_voltage(5.39, Red), Green);
+        assert_eq!(VbusLedColor::for_voltage(0.0, Red), Green);
+        assert_eq!(VbusLedColor::for_voltage(20.0, Green), Red);
+    }
+
+    #[test]
+    fn test_decide_led_modes_follow_vbus_and_pd_state() {
+        use VbusLedColor::*;
+        let decide = |voltage, state, pd, lockout| decide_led(voltage, state, pd, lockout, Green);
+
+        assert_eq!(
+            decide(20.0, VbusState::Enabled, PdStatus::Negotiated, false),
+            (Red, VbusLedMode::Solid)
+        );
+        assert_eq!(
+            decide(5.0, VbusState::Disabled, PdStatus::Negotiated, false),
+            (Green, VbusLedMode::Blinking)
+        );
+        assert_eq!(
+            decide(0.0, VbusState::Disabled, PdStatus::Detached, false),
+            (Green, VbusLedMode::Blinking)
+        );
+        assert_eq!(
+            decide(
+                5.0,
+                VbusState::Disabled,
+                PdStatus::Attached(power::CableOrientation::Normal),
+                false
+            ),
+            (Green, VbusLedMode::WaitingPd)
+        );
+        assert_eq!(
+            decide(5.0, VbusState::Disabled, PdStatus::NegotiationFailed, false),
+            (Green, VbusLedMode::FastBlinking)
+        );
+        // 锁定优先于 PD 状态，但开启状态下不显示锁定
+        assert_eq!(
+            decide(5.0, VbusState::Disabled, PdStatus::NegotiationFailed, true).1,
+            VbusLedMode::Lockout
+        );
+        assert_eq!(
+            decide(5.0, VbusState::Enabled, PdStatus::Negotiated, true).1,
+            VbusLedMode::Solid
+        );
+    }
+
+    #[test]
+    fn test_decide_led_color_boundaries_in_both_states() {
+        use VbusLedColor::*;
+
+        for state in [VbusState::Disabled, VbusState::Enabled] {
+            let color = |voltage, previous| {
+                decide_led(voltage, state, PdStatus::Negotiated, false, previous).0
+            };
+            // 阈值本身处于回差带内，保持之前的颜色
+            assert_eq!(color(VBUS_VOLTAGE_THRESHOLD, Green), Green);
+            assert_eq!(color(VBUS_VOLTAGE_THRESHOLD, Red), Red);
+            assert_eq!(color(VBUS_VOLTAGE_THRESHOLD + 0.11, Green), Red);
+            assert_eq!(color(VBUS_VOLTAGE_THRESHOLD - 0.11, Red), Green);
+        }
+    }
+
+    #[test]
+    fn test_publish_limiter_coalesces_burst() {
+        let mut limiter = PublishLimiter::new(Duration::from_millis(100));
+        let t0 = Instant::from_millis(0);
+
+        assert_eq!(limiter.poll(true, t0), Some(true));
+        // 100ms 内的突发切换被合并
+        assert_eq!(limiter.poll(false, t0 + Duration::from_millis(10)), None);
+        assert_eq!(limiter.poll(true, t0 + Duration::from_millis(20)), None);
+        assert_eq!(limiter.poll(false, t0 + Duration::from_millis(30)), None);
+        // 间隔到达后发布最终状态
+        assert_eq!(
+            limiter.poll(false, t0 + Duration::from_millis(100)),
+            Some(false)
+        );
+        // 值未变化时不重复发布
+        assert_eq!(limiter.poll(false, t0 + Duration::from_millis(500)), None);
+    }
+
+    #[test]
+    fn test_publish_limiter_burst_ending_on_published_value() {
+        let mut limiter = PublishLimiter::new(Duration::from_millis(100));
+        let t0 = Instant::from_millis(0);
+
+        assert_eq!(limiter.poll(true, t0), Some(true));
+        assert_eq!(limiter.poll(false, t0 + Duration::from_millis(10)), None);
+        // 最终状态与已发布值相同，消费者看到的仍然正确
+        assert_eq!(limiter.poll(true, t0 + Duration::from_millis(200)), None);
+    }
+
+    #[test]
+    fn test_overcurrent_filter_ignores_inrush_and_spikes() {
+        let config = OcpConfig::default();
+        let mut filter = OvercurrentFilter::default();
+        let at = Instant::from_millis;
+
+        // 浪涌窗口内的大电流不计入
+        assert!(!filter.update(&config, at(0), at(100), 10.0, 3.0));
+        // 短暂尖峰后回落，计时清零
+        assert!(!filter.update(&config, at(0), at(200), 4.0, 3.0));
+        assert!(!filter.update(&config, at(0), at(400), 2.0, 3.0));
+        // 持续超限达到 trip_time 才判定
+        assert!(!filter.update(&config, at(0), at(500), 4.0, 3.0));
+        assert!(!filter.update(&config, at(0), at(700), 4.0, 3.0));
+        assert!(filter.update(&config, at(0), at(800), 4.0, 3.0));
     }
 }